@@ -1,7 +1,7 @@
 use criterion::{
     criterion_group, criterion_main, BenchmarkId, Criterion, Throughput,
 };
-use digit_bin_index::DigitBinIndex;
+use digit_bin_index::{Config, DigitBinIndex};
 use wyrand::WyRand;
 use rand::{Rng, SeedableRng}; 
 use std::collections::HashMap; 
@@ -164,14 +164,14 @@ const VERY_LARGE_MAX: u64 = VERY_LARGE_POP + VERY_LARGE_ACQ;
 
 fn benchmark_wallenius_simulation(c: &mut Criterion) {
     let mut group = c.benchmark_group("Wallenius Simulation (Iterative Churn)");
-    group.throughput(Throughput::Elements((CHURN_COUNT + ACQUISITION_COUNT) as u64));
+    group.throughput(Throughput::Elements(CHURN_COUNT + ACQUISITION_COUNT));
 
     group.bench_function(BenchmarkId::new("DigitBinIndex (precision 3)", INITIAL_POP), |b| {
         b.iter_batched(|| {
             let mut dbi = DigitBinIndex::with_precision_and_capacity(3, MAX_CAPACITY);
             let mut rng = WyRand::from_os_rng();
-            for i in 0..INITIAL_POP { dbi.add(i as u64, rng.random_range(0.001..0.999)); }
-            (dbi, INITIAL_POP as u64, rng)
+            for i in 0..INITIAL_POP { dbi.add(i, rng.random_range(0.001..0.999)); }
+            (dbi, INITIAL_POP, rng)
         }, |(mut dbi, mut next_id, mut rng)| {
             for _ in 0..CHURN_COUNT { black_box(dbi.select_and_remove()); }
             for _ in 0..ACQUISITION_COUNT {
@@ -200,8 +200,8 @@ fn benchmark_wallenius_simulation(c: &mut Criterion) {
         b.iter_batched(|| {
             let mut dbi = DigitBinIndex::with_precision_and_capacity(5, MAX_CAPACITY);
             let mut rng = WyRand::from_os_rng();
-            for i in 0..INITIAL_POP { dbi.add(i as u64, rng.random_range(0.00001..0.99999)); }
-            (dbi, INITIAL_POP as u64, rng)
+            for i in 0..INITIAL_POP { dbi.add(i, rng.random_range(0.00001..0.99999)); }
+            (dbi, INITIAL_POP, rng)
         }, |(mut dbi, mut next_id, mut rng)| {
             for _ in 0..CHURN_COUNT { black_box(dbi.select_and_remove()); }
             for _ in 0..ACQUISITION_COUNT {
@@ -233,9 +233,9 @@ fn benchmark_wallenius_simulation(c: &mut Criterion) {
             let mut dbi = DigitBinIndex::with_precision_and_capacity(3, VERY_LARGE_MAX);
             let mut rng = WyRand::from_os_rng();
             for i in 0..VERY_LARGE_POP {
-                dbi.add(i as u64, rng.random_range(0.001..0.999));
+                dbi.add(i, rng.random_range(0.001..0.999));
             }
-            (dbi, VERY_LARGE_POP as u64, rng)
+            (dbi, VERY_LARGE_POP, rng)
         }, |(mut dbi, mut next_id, mut rng)| {
             for _ in 0..VERY_LARGE_CHURN { black_box(dbi.select_and_remove()); }
             for _ in 0..VERY_LARGE_ACQ {
@@ -267,16 +267,16 @@ fn benchmark_wallenius_simulation(c: &mut Criterion) {
 
 fn benchmark_fisher_simulation(c: &mut Criterion) {
     let mut group = c.benchmark_group("Fisher Simulation (Batch Churn)");
-    group.throughput(Throughput::Elements((CHURN_COUNT + ACQUISITION_COUNT) as u64));
+    group.throughput(Throughput::Elements(CHURN_COUNT + ACQUISITION_COUNT));
 
     group.bench_function(BenchmarkId::new("DigitBinIndex (precision 3)", INITIAL_POP), |b| {
         b.iter_batched(|| {
             let mut dbi = DigitBinIndex::with_precision_and_capacity(3, MAX_CAPACITY);
             let mut rng = WyRand::from_os_rng();
-            for i in 0..INITIAL_POP { dbi.add(i as u64, rng.random_range(0.001..0.999)); }
-            (dbi, INITIAL_POP as u64, rng)
+            for i in 0..INITIAL_POP { dbi.add(i, rng.random_range(0.001..0.999)); }
+            (dbi, INITIAL_POP, rng)
         }, |(mut dbi, mut next_id, mut rng)| {
-            black_box(dbi.select_many_and_remove(CHURN_COUNT as u64));
+            black_box(dbi.select_many_and_remove(CHURN_COUNT));
             for _ in 0..ACQUISITION_COUNT {
                 dbi.add(next_id, rng.random_range(0.001..0.999));
                 next_id += 1;
@@ -303,10 +303,10 @@ fn benchmark_fisher_simulation(c: &mut Criterion) {
         b.iter_batched(|| {
             let mut dbi = DigitBinIndex::with_precision_and_capacity(5, MAX_CAPACITY);
             let mut rng = WyRand::from_os_rng();
-            for i in 0..INITIAL_POP { dbi.add(i as u64, rng.random_range(0.00001..0.99999)); }
-            (dbi, INITIAL_POP as u64, rng)
+            for i in 0..INITIAL_POP { dbi.add(i, rng.random_range(0.00001..0.99999)); }
+            (dbi, INITIAL_POP, rng)
         }, |(mut dbi, mut next_id, mut rng)| {
-            black_box(dbi.select_many_and_remove(CHURN_COUNT as u64));
+            black_box(dbi.select_many_and_remove(CHURN_COUNT));
             for _ in 0..ACQUISITION_COUNT {
                 dbi.add(next_id, rng.random_range(0.00001..0.99999));
                 next_id += 1;
@@ -336,11 +336,11 @@ fn benchmark_fisher_simulation(c: &mut Criterion) {
             let mut dbi = DigitBinIndex::with_precision_and_capacity(3, VERY_LARGE_MAX);
             let mut rng = WyRand::from_os_rng();
             for i in 0..VERY_LARGE_POP {
-                dbi.add(i as u64, rng.random_range(0.001..0.999));
+                dbi.add(i, rng.random_range(0.001..0.999));
             }
-            (dbi, VERY_LARGE_POP as u64, rng)
+            (dbi, VERY_LARGE_POP, rng)
         }, |(mut dbi, mut next_id, mut rng)| {
-            black_box(dbi.select_many_and_remove(VERY_LARGE_CHURN as u64));
+            black_box(dbi.select_many_and_remove(VERY_LARGE_CHURN));
             for _ in 0..VERY_LARGE_ACQ {
                 dbi.add(next_id, rng.random_range(0.001..0.999));
                 next_id += 1;
@@ -457,11 +457,41 @@ fn insertion_benchmark(c: &mut Criterion) {
 
 }
 
+// Single draws at increasing depth, the measurement behind the choice of a decimal radix.
+fn draw_by_precision_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Draw by Precision");
+    let mut rng = WyRand::from_os_rng();
+    for precision in [2u8, 3, 4, 6] {
+        let mut index = DigitBinIndex::with_precision_and_capacity(precision, INITIAL_POP);
+        for i in 0..INITIAL_POP { index.add(i, rng.random_range(0.001..0.999)); }
+        group.bench_function(BenchmarkId::new(format!("DigitBinIndex (precision {precision})"), INITIAL_POP), |b| {
+            b.iter(|| black_box(index.select_with_rng(&mut rng)))
+        });
+    }
+    group.finish();
+}
+
+// Single draws at a fixed precision, with the tree laid out in different radices.
+fn draw_by_radix_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Draw by Radix");
+    let mut rng = WyRand::from_os_rng();
+    for radix in [2u16, 10, 16, 256] {
+        let mut index = DigitBinIndex::with_config(Config::new().precision(6).radix(radix).capacity(INITIAL_POP));
+        for i in 0..INITIAL_POP { index.add(i, rng.random_range(0.001..0.999)); }
+        group.bench_function(BenchmarkId::new(format!("DigitBinIndex (radix {radix})"), INITIAL_POP), |b| {
+            b.iter(|| black_box(index.select_with_rng(&mut rng)))
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
         benches, 
         benchmark_wallenius_simulation, 
         benchmark_fisher_simulation,
-        insertion_benchmark
+        insertion_benchmark,
+        draw_by_precision_benchmark,
+        draw_by_radix_benchmark
 );
 criterion_main!(benches);
 
@@ -0,0 +1,151 @@
+#ifndef DIGIT_BIN_INDEX_H
+#define DIGIT_BIN_INDEX_H
+
+/* Generated by cbindgen from the `ffi` feature. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A data structure that organizes weighted items into bins based on their
+// decimal digits to enable fast weighted random selection and updates.
+//
+// This structure is a specialized radix tree optimized for sequential sampling
+// (like in Wallenius' distribution). It makes a deliberate engineering trade-off:
+// it sacrifices a small, controllable amount of precision by binning items,
+// but in return, it achieves O(P) performance for its core operations, where P
+// is the configured precision. This is significantly faster than the O(log N)
+// performance of general-purpose structures like a Fenwick Tree for its
+// ideal use case.
+//
+// # Examples
+//
+// ```
+// use digit_bin_index::DigitBinIndex;
+// let mut index = DigitBinIndex::with_precision_and_capacity(3, 100);
+// ```
+typedef struct DigitBinIndex DigitBinIndex;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an index with the default precision.
+struct DigitBinIndex *dbi_new(void);
+
+// Creates an index with a specific precision, from 1 to 9. Returns `NULL` if the
+// precision is out of range.
+struct DigitBinIndex *dbi_with_precision(uint8_t precision);
+
+// Creates an index that stores a count per ID, for cohorts of identical items, with a
+// precision from 1 to 9. Returns `NULL` if the precision is out of range.
+struct DigitBinIndex *dbi_counted(uint8_t precision);
+
+// Releases an index. Passing `NULL` does nothing.
+//
+// # Safety
+//
+// `index` must be `NULL` or come from `dbi_new`, `dbi_with_precision` or
+// `dbi_counted`, and must not be used afterwards.
+void dbi_free(struct DigitBinIndex *index);
+
+// Adds an item. Returns `false`, and adds nothing, if the weight cannot be stored
+// (outside `(0, 1)`, or below the precision).
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index.
+bool dbi_add(struct DigitBinIndex *index, uint64_t id, double weight);
+
+// Adds `count` identical copies of an item and returns how many were stored, 0 if the
+// weight cannot be stored. A counted index keeps the copies as a single count.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index.
+uint64_t dbi_add_with_count(struct DigitBinIndex *index,
+                            uint64_t id,
+                            double weight,
+                            uint64_t count);
+
+// Removes an item that was added with the given weight. Returns whether it was found.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index.
+bool dbi_remove(struct DigitBinIndex *index, uint64_t id, double weight);
+
+// Selects an item by weight without removal, writing its ID and bin weight to the
+// outputs. Returns `false`, and writes nothing, if the index is empty or `index` or
+// `out_id` is `NULL`.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index, and `out_id` must be `NULL` or writable.
+// `out_weight` may be `NULL`.
+bool dbi_select(struct DigitBinIndex *index, uint64_t *out_id, double *out_weight);
+
+// Selects and removes an item by weight, like `dbi_select`.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index, and `out_id` must be `NULL` or writable.
+// `out_weight` may be `NULL`.
+bool dbi_select_and_remove(struct DigitBinIndex *index, uint64_t *out_id, double *out_weight);
+
+// Takes one copy by weight, like `dbi_select_and_remove`, and also writes the number of
+// copies of its ID that remain.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index, and `out_id` must be `NULL` or writable.
+// `out_weight` and `out_remaining` may be `NULL`.
+bool dbi_select_and_decrement(struct DigitBinIndex *index,
+                              uint64_t *out_id,
+                              double *out_weight,
+                              uint64_t *out_remaining);
+
+// Selects `n` items by weight without removal, writing their IDs and bin weights to
+// the outputs. Returns `false`, and writes nothing, if the index holds fewer than `n`
+// items or `index` or `out_ids` is `NULL`.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index, and `out_ids` must be `NULL` or have room
+// for `n` IDs. `out_weights` may be `NULL`, or must have room for `n` weights.
+bool dbi_select_many(struct DigitBinIndex *index,
+                     uint64_t n,
+                     uint64_t *out_ids,
+                     double *out_weights);
+
+// Selects and removes `n` unique items by weight, like `dbi_select_many`.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index, and `out_ids` must be `NULL` or have room
+// for `n` IDs. `out_weights` may be `NULL`, or must have room for `n` weights.
+bool dbi_select_many_and_remove(struct DigitBinIndex *index,
+                                uint64_t n,
+                                uint64_t *out_ids,
+                                double *out_weights);
+
+// Returns the number of items, or 0 for `NULL`.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index.
+uint64_t dbi_count(const struct DigitBinIndex *index);
+
+// Returns the sum of the bin weights of all items, or 0 for `NULL`.
+//
+// # Safety
+//
+// `index` must be `NULL` or a live index.
+double dbi_total_weight(const struct DigitBinIndex *index);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DIGIT_BIN_INDEX_H */
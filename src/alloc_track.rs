@@ -0,0 +1,126 @@
+//! Allocation counting for benchmark and test builds (feature `alloc-track`).
+//!
+//! Install [`CountingAllocator`] as the global allocator of a test or bench
+//! binary and wrap operations in [`measure`] to see how many allocations they
+//! performed. Counters are kept per thread, so measurements are not disturbed
+//! by tests running in parallel.
+//!
+//! # Examples
+//!
+//! ```
+//! use digit_bin_index::alloc_track::{self, CountingAllocator};
+//! use digit_bin_index::DigitBinIndex;
+//! use std::alloc::System;
+//!
+//! #[global_allocator]
+//! static ALLOC: CountingAllocator<System> = CountingAllocator::new(System);
+//!
+//! let mut index = DigitBinIndex::small(3);
+//! for id in 0..1000 {
+//!     index.add(id, 0.001 * (id % 999 + 1) as f64);
+//! }
+//!
+//! let (_, stats) = alloc_track::measure(|| index.select_and_remove());
+//! assert_eq!(stats.allocations, 0);
+//! let (_, stats) = alloc_track::measure(|| index.select());
+//! assert_eq!(stats.allocations, 0);
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    static DEALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    static REALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    static BYTES_ALLOCATED: Cell<u64> = const { Cell::new(0) };
+}
+
+fn bump(counter: &'static std::thread::LocalKey<Cell<u64>>, by: u64) {
+    // `try_with` because the allocator can be called while thread locals are being torn down.
+    let _ = counter.try_with(|c| c.set(c.get().wrapping_add(by)));
+}
+
+fn read(counter: &'static std::thread::LocalKey<Cell<u64>>) -> u64 {
+    counter.try_with(Cell::get).unwrap_or(0)
+}
+
+/// A global allocator wrapper that counts allocations made by the current thread.
+#[derive(Debug, Default)]
+pub struct CountingAllocator<A> {
+    inner: A,
+}
+
+impl<A> CountingAllocator<A> {
+    /// Wraps an allocator, typically `std::alloc::System`.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        bump(&ALLOCATIONS, 1);
+        bump(&BYTES_ALLOCATED, layout.size() as u64);
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        bump(&ALLOCATIONS, 1);
+        bump(&BYTES_ALLOCATED, layout.size() as u64);
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        bump(&DEALLOCATIONS, 1);
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        bump(&REALLOCATIONS, 1);
+        bump(&BYTES_ALLOCATED, new_size.saturating_sub(layout.size()) as u64);
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Allocation counters for the current thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub reallocations: u64,
+    /// Bytes requested by allocations plus the growth of reallocations.
+    pub bytes_allocated: u64,
+}
+
+impl AllocStats {
+    /// Counts made since `earlier`.
+    pub fn since(&self, earlier: &AllocStats) -> AllocStats {
+        AllocStats {
+            allocations: self.allocations.wrapping_sub(earlier.allocations),
+            deallocations: self.deallocations.wrapping_sub(earlier.deallocations),
+            reallocations: self.reallocations.wrapping_sub(earlier.reallocations),
+            bytes_allocated: self.bytes_allocated.wrapping_sub(earlier.bytes_allocated),
+        }
+    }
+}
+
+/// Returns the running counters for the current thread.
+///
+/// All counters stay at zero unless a [`CountingAllocator`] is installed as the global allocator.
+pub fn snapshot() -> AllocStats {
+    AllocStats {
+        allocations: read(&ALLOCATIONS),
+        deallocations: read(&DEALLOCATIONS),
+        reallocations: read(&REALLOCATIONS),
+        bytes_allocated: read(&BYTES_ALLOCATED),
+    }
+}
+
+/// Runs `f` and reports the allocations it made on the current thread.
+pub fn measure<R>(f: impl FnOnce() -> R) -> (R, AllocStats) {
+    let before = snapshot();
+    let result = f();
+    let stats = snapshot().since(&before);
+    (result, stats)
+}
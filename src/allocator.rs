@@ -0,0 +1,173 @@
+//! A weighted traffic allocator for online experiments.
+//!
+//! [`Allocator`] assigns users to arms (variants of an A/B test, backends of a
+//! load balancer, ...) in proportion to the arms' weights. Assignment is a pure
+//! function of the user's hash and the current weights, so a user keeps seeing
+//! the same arm until the weights change, and it never takes a lock: the arms
+//! are published through an `ArcSwap` like in [`SharedSampler`](crate::SharedSampler).
+//! Changing an arm rebuilds the (small) index and publishes it atomically.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+use rand::SeedableRng;
+use wyrand::WyRand;
+
+use crate::DigitBinIndex;
+
+/// Precision of the internal index: arm weights are resolved to about one part in a million.
+const PRECISION: u8 = 6;
+/// Weight given to the heaviest arm internally; the others are scaled relative to it.
+const HEAVIEST: f64 = 0.9;
+
+/// One published version of the arms.
+#[derive(Debug)]
+struct ArmTable {
+    index: DigitBinIndex,
+    weights: BTreeMap<u64, f64>,
+    /// Assignment counters, shared between versions so no count is lost on a swap.
+    counters: HashMap<u64, Arc<AtomicU64>>,
+}
+
+/// Assignment statistics for one arm, as returned by [`Allocator::stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArmStats {
+    pub arm: u64,
+    /// The weight the arm was registered with.
+    pub weight: f64,
+    /// The arm's configured share of traffic, `weight / sum of weights`.
+    pub share: f64,
+    /// The number of assignments made to the arm since it was registered.
+    pub assignments: u64,
+}
+
+/// Deterministic, weighted assignment of users to arms.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::Allocator;
+///
+/// let allocator = Allocator::new();
+/// allocator.set_arm(0, 70.0); // control
+/// allocator.set_arm(1, 30.0); // treatment
+///
+/// let arm = allocator.assign(0xDEAD_BEEF).unwrap();
+/// assert_eq!(allocator.assign(0xDEAD_BEEF), Some(arm)); // sticky per user
+///
+/// for user in 0..10_000u64 {
+///     allocator.assign(user);
+/// }
+/// let stats = allocator.stats();
+/// assert!((stats[0].share - 0.7).abs() < 1e-9);
+/// let treated = stats[1].assignments as f64 / 10_002.0;
+/// assert!((treated - 0.3).abs() < 0.03);
+/// ```
+#[derive(Debug)]
+pub struct Allocator {
+    table: ArcSwap<ArmTable>,
+    /// Serializes writers; readers only ever load `table`.
+    writer: Mutex<()>,
+}
+
+impl Allocator {
+    /// Creates an allocator without arms.
+    pub fn new() -> Self {
+        Allocator {
+            table: ArcSwap::from_pointee(ArmTable {
+                index: DigitBinIndex::with_precision(PRECISION),
+                weights: BTreeMap::new(),
+                counters: HashMap::new(),
+            }),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Registers an arm, or changes the weight of an existing one.
+    ///
+    /// Weights are relative and need not sum to anything in particular. An arm with a
+    /// non-positive or non-finite weight is removed instead.
+    pub fn set_arm(&self, arm: u64, weight: f64) {
+        self.update(|weights| {
+            if weight > 0.0 && weight.is_finite() {
+                weights.insert(arm, weight);
+            } else {
+                weights.remove(&arm);
+            }
+        });
+    }
+
+    /// Removes an arm and its statistics. Returns whether the arm existed.
+    pub fn remove_arm(&self, arm: u64) -> bool {
+        let mut existed = false;
+        self.update(|weights| existed = weights.remove(&arm).is_some());
+        existed
+    }
+
+    /// Applies `change` to a copy of the weights and publishes the rebuilt table.
+    fn update(&self, change: impl FnOnce(&mut BTreeMap<u64, f64>)) {
+        let _writer = self.writer.lock().expect("a thread panicked while updating the allocator");
+        let current = self.table.load();
+        let mut weights = current.weights.clone();
+        change(&mut weights);
+
+        let heaviest = weights.values().copied().fold(0.0, f64::max);
+        let mut index = DigitBinIndex::with_precision(PRECISION);
+        let mut counters = HashMap::with_capacity(weights.len());
+        for (&arm, &weight) in &weights {
+            // Arms too light to register at this precision still get the smallest bin.
+            index.add(arm, (weight / heaviest * HEAVIEST).max(1e-6));
+            let counter = current.counters.get(&arm).cloned().unwrap_or_default();
+            counters.insert(arm, counter);
+        }
+        self.table.store(Arc::new(ArmTable { index, weights, counters }));
+    }
+
+    /// Assigns a user to an arm.
+    ///
+    /// The choice is drawn by weight from an RNG seeded with `user_hash`, so the same
+    /// hash always gets the same arm for a given set of arms and weights. Pass a
+    /// well-mixed hash of the user's identity (and of the experiment, to decorrelate
+    /// experiments).
+    ///
+    /// # Returns
+    ///
+    /// The assigned arm, or `None` if there are no arms.
+    pub fn assign(&self, user_hash: u64) -> Option<u64> {
+        let table = self.table.load();
+        let (arm, _) = table.index.select_with_rng(&mut WyRand::seed_from_u64(user_hash))?;
+        if let Some(counter) = table.counters.get(&arm) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        Some(arm)
+    }
+
+    /// Returns the registered arms and their weights, in arm order.
+    pub fn arms(&self) -> Vec<(u64, f64)> {
+        self.table.load().weights.iter().map(|(&arm, &weight)| (arm, weight)).collect()
+    }
+
+    /// Returns the weight, configured share, and assignment count of every arm, in arm order.
+    pub fn stats(&self) -> Vec<ArmStats> {
+        let table = self.table.load();
+        let total: f64 = table.weights.values().sum();
+        table
+            .weights
+            .iter()
+            .map(|(&arm, &weight)| ArmStats {
+                arm,
+                weight,
+                share: weight / total,
+                assignments: table.counters[&arm].load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,123 @@
+//! An index paired with an exact-weight map that it keeps in sync.
+//!
+//! A common way to use a `DigitBinIndex` is next to a `HashMap` holding each
+//! ID's exact weight: the index drives the draws, and the map answers "what is
+//! this ID's weight?" and supplies the weight that `remove` and `update_weight`
+//! need. [`CachedIndex`] owns both and updates them together, so they cannot
+//! drift apart. Each ID holds one weight at a time.
+
+use std::collections::HashMap;
+
+use crate::{Config, DigitBinIndex};
+
+/// A `DigitBinIndex` together with a map of the exact weight of every ID in it.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{CachedIndex, Config};
+///
+/// let mut index = CachedIndex::new(Config::new().precision(2));
+/// index.insert(1, 0.257);
+/// index.insert(2, 0.5);
+/// assert_eq!(index.weight(1), Some(0.257));
+///
+/// index.insert(1, 0.75); // moves ID 1 to its new bin
+/// assert_eq!(index.remove(2), Some(0.5));
+/// assert_eq!(index.select_and_remove(), Some((1, 0.75)));
+/// assert!(index.weights().is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedIndex {
+    index: DigitBinIndex,
+    weights: HashMap<u64, f64>,
+}
+
+impl CachedIndex {
+    /// Creates an empty index built from `config`.
+    pub fn new(config: Config) -> Self {
+        CachedIndex { index: DigitBinIndex::with_config(config), weights: HashMap::new() }
+    }
+
+    /// Takes over an existing weight map and indexes its entries.
+    ///
+    /// Entries whose weight the index cannot store (outside `(0, 1)`, or truncated to zero
+    /// at the configured precision) are dropped from the map.
+    pub fn from_weights(config: Config, mut weights: HashMap<u64, f64>) -> Self {
+        let mut index = DigitBinIndex::with_config(config);
+        weights.retain(|&id, &mut weight| index.add_with_count(id, weight, 1) > 0);
+        CachedIndex { index, weights }
+    }
+
+    /// Adds an ID, or changes its weight if it is already present.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the index cannot store the weight, in which case nothing changes.
+    pub fn insert(&mut self, id: u64, weight: f64) -> bool {
+        let stored = match self.weights.get(&id) {
+            Some(&old) => self.index.update_weight(id, old, weight),
+            None => self.index.add_with_count(id, weight, 1) > 0,
+        };
+        if stored {
+            self.weights.insert(id, weight);
+        }
+        stored
+    }
+
+    /// Removes an ID and returns its exact weight, or `None` if it was not present.
+    pub fn remove(&mut self, id: u64) -> Option<f64> {
+        let weight = self.weights.remove(&id)?;
+        self.index.remove(id, weight);
+        Some(weight)
+    }
+
+    /// Returns the exact weight of an ID.
+    pub fn weight(&self, id: u64) -> Option<f64> {
+        self.weights.get(&id).copied()
+    }
+
+    /// Selects an ID by weight without removal, returning its exact weight.
+    pub fn select(&mut self) -> Option<(u64, f64)> {
+        let (id, _) = self.index.select()?;
+        Some((id, self.weights[&id]))
+    }
+
+    /// Selects and removes an ID by weight, returning its exact weight.
+    pub fn select_and_remove(&mut self) -> Option<(u64, f64)> {
+        let (id, _) = self.index.select_and_remove()?;
+        Some((id, self.weights.remove(&id).expect("selected IDs are in the weight map")))
+    }
+
+    /// Selects and removes `num_to_draw` unique IDs by weight, returning their exact
+    /// weights. See [`DigitBinIndex::select_many_and_remove`].
+    pub fn select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        let selected = self.index.select_many_and_remove(num_to_draw)?;
+        Some(
+            selected
+                .into_iter()
+                .map(|(id, _)| (id, self.weights.remove(&id).expect("selected IDs are in the weight map")))
+                .collect(),
+        )
+    }
+
+    /// Returns the number of IDs.
+    pub fn count(&self) -> u64 {
+        self.index.count()
+    }
+
+    /// Returns the index, for draws and queries that do not change it.
+    pub fn index(&self) -> &DigitBinIndex {
+        &self.index
+    }
+
+    /// Returns the exact weights, keyed by ID.
+    pub fn weights(&self) -> &HashMap<u64, f64> {
+        &self.weights
+    }
+
+    /// Splits the pair into the index and the weight map.
+    pub fn into_parts(self) -> (DigitBinIndex, HashMap<u64, f64>) {
+        (self.index, self.weights)
+    }
+}
@@ -0,0 +1,222 @@
+//! A sharded index for concurrent mutation and selection.
+//!
+//! `ConcurrentDigitBinIndex` splits its items over a fixed number of shards by
+//! ID. Each shard is an ordinary `DigitBinIndex` behind its own `Mutex`, and
+//! publishes its total mass in an atomic after every change. A draw first picks
+//! a shard in proportion to those masses without taking any lock, then locks
+//! just that shard to draw within it. Threads working on different shards never
+//! wait for each other, and all methods take `&self`.
+//!
+//! When nothing is being written, draws are exactly proportional to weight.
+//! While other threads are writing, a draw may pick its shard from masses that
+//! are a moment out of date; the item is then still drawn by weight within the
+//! shard, from its state at the time of locking. A draw that keeps landing on
+//! shards emptied under it gives up after a bounded number of picks and returns
+//! `None`, like a draw from an empty index.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use rand::{Rng, SeedableRng};
+use wyrand::WyRand;
+
+use crate::{Config, DigitBinIndex};
+
+/// How many shard picks a single draw makes before giving up.
+const MAX_DRAW_ATTEMPTS: usize = 64;
+
+thread_local! {
+    static THREAD_RNG: RefCell<WyRand> = RefCell::new(WyRand::from_os_rng());
+}
+
+#[derive(Debug)]
+struct Shard {
+    index: Mutex<DigitBinIndex>,
+    /// The shard's total mass in scaled units, refreshed under the lock after every change.
+    mass: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Shard {
+    fn lock(&self) -> MutexGuard<'_, DigitBinIndex> {
+        self.index.lock().expect("a thread panicked while mutating a shard")
+    }
+
+    /// Publishes the shard's mass and count after a change made through `index`.
+    fn publish(&self, index: &DigitBinIndex) {
+        self.mass.store(index.total_mass(), Ordering::Release);
+        self.count.store(index.count(), Ordering::Release);
+    }
+}
+
+/// A thread-safe index that supports concurrent `add`, `remove` and `select_and_remove`.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::ConcurrentDigitBinIndex;
+/// use std::thread;
+///
+/// let index = ConcurrentDigitBinIndex::new(8);
+/// thread::scope(|scope| {
+///     for worker in 0..4u64 {
+///         let index = &index;
+///         scope.spawn(move || {
+///             for i in 0..1000 {
+///                 index.add(worker * 1000 + i, 0.5);
+///             }
+///             for _ in 0..500 {
+///                 index.select_and_remove().unwrap();
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(index.count(), 2000);
+/// ```
+#[derive(Debug)]
+pub struct ConcurrentDigitBinIndex {
+    shards: Box<[Shard]>,
+    scale: f64,
+}
+
+impl ConcurrentDigitBinIndex {
+    /// Creates an index with `shards` shards of the default configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn new(shards: usize) -> Self {
+        Self::with_config(Config::new(), shards)
+    }
+
+    /// Creates an index with `shards` shards, each built from `config`.
+    ///
+    /// A memory budget in `config` applies to each shard separately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0, or if the configured precision is invalid.
+    pub fn with_config(config: Config, shards: usize) -> Self {
+        assert!(shards > 0, "A concurrent index needs at least one shard.");
+        let shards = (0..shards)
+            .map(|_| Shard {
+                index: Mutex::new(DigitBinIndex::with_config(config.clone())),
+                mass: AtomicU64::new(0),
+                count: AtomicU64::new(0),
+            })
+            .collect();
+        ConcurrentDigitBinIndex { shards, scale: 10f64.powi(config.precision as i32) }
+    }
+
+    /// The shard that owns an ID. IDs are mixed first so that consecutive IDs spread out.
+    fn shard(&self, id: u64) -> &Shard {
+        let mixed = (id ^ (id >> 32)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        &self.shards[((mixed >> 32) % self.shards.len() as u64) as usize]
+    }
+
+    /// Adds an item. See [`DigitBinIndex::add`].
+    pub fn add(&self, id: u64, weight: f64) {
+        let shard = self.shard(id);
+        let mut index = shard.lock();
+        index.add(id, weight);
+        shard.publish(&index);
+    }
+
+    /// Removes an item. See [`DigitBinIndex::remove`].
+    pub fn remove(&self, id: u64, weight: f64) -> bool {
+        let shard = self.shard(id);
+        let mut index = shard.lock();
+        let removed = index.remove(id, weight);
+        shard.publish(&index);
+        removed
+    }
+
+    /// Selects a single item by weight without removal, using this thread's RNG.
+    pub fn select(&self) -> Option<(u64, f64)> {
+        THREAD_RNG.with(|rng| self.select_with_rng(&mut *rng.borrow_mut()))
+    }
+
+    /// Selects a single item by weight without removal, using the given RNG.
+    pub fn select_with_rng<R: Rng>(&self, rng: &mut R) -> Option<(u64, f64)> {
+        self.draw(rng, |index, rng| index.select_with_rng(rng))
+    }
+
+    /// Selects and removes a single item by weight, using this thread's RNG.
+    pub fn select_and_remove(&self) -> Option<(u64, f64)> {
+        THREAD_RNG.with(|rng| self.select_and_remove_with_rng(&mut *rng.borrow_mut()))
+    }
+
+    /// Selects and removes a single item by weight, using the given RNG.
+    pub fn select_and_remove_with_rng<R: Rng>(&self, rng: &mut R) -> Option<(u64, f64)> {
+        self.draw(rng, |index, rng| index.select_and_remove_with_rng(rng))
+    }
+
+    /// Picks a shard by its published mass, then draws within it under its lock.
+    /// If the shard was emptied in the meantime, the pick is repeated. A shard whose select
+    /// fails while its republished mass stays the same would fail again, so it is left out
+    /// of the rest of this draw, and the picks stop after `MAX_DRAW_ATTEMPTS`, returning
+    /// `None`, so that a draw racing constant churn still ends.
+    pub(crate) fn draw<R: Rng>(
+        &self,
+        rng: &mut R,
+        mut select: impl FnMut(&mut DigitBinIndex, &mut R) -> Option<(u64, f64)>,
+    ) -> Option<(u64, f64)> {
+        let mut skipped = vec![false; self.shards.len()];
+        for _ in 0..MAX_DRAW_ATTEMPTS {
+            let masses: Vec<u64> = self
+                .shards
+                .iter()
+                .zip(&skipped)
+                .map(|(shard, &skipped)| if skipped { 0 } else { shard.mass.load(Ordering::Acquire) })
+                .collect();
+            let total: u64 = masses.iter().sum();
+            if total == 0 {
+                return None;
+            }
+            let mut target = rng.random_range(0..total);
+            let mut chosen = masses.len() - 1;
+            for (i, &mass) in masses.iter().enumerate() {
+                if target < mass {
+                    chosen = i;
+                    break;
+                }
+                target -= mass;
+            }
+            let shard = &self.shards[chosen];
+            let mut index = shard.lock();
+            let selected = select(&mut index, rng);
+            shard.publish(&index);
+            if selected.is_some() {
+                return selected;
+            }
+            skipped[chosen] = shard.mass.load(Ordering::Acquire) == masses[chosen];
+        }
+        None
+    }
+
+    /// Returns the number of items, summed over the shards' published counts.
+    pub fn count(&self) -> u64 {
+        self.shards.iter().map(|shard| shard.count.load(Ordering::Acquire)).sum()
+    }
+
+    /// Returns the total weight, summed over the shards' published masses.
+    pub fn total_weight(&self) -> f64 {
+        let mass: u64 = self.shards.iter().map(|shard| shard.mass.load(Ordering::Acquire)).sum();
+        mass as f64 / self.scale
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Consumes the index and returns its shards.
+    pub fn into_shards(self) -> Vec<DigitBinIndex> {
+        self.shards
+            .into_vec()
+            .into_iter()
+            .map(|shard| shard.index.into_inner().expect("a thread panicked while mutating a shard"))
+            .collect()
+    }
+}
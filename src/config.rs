@@ -0,0 +1,188 @@
+//! Consolidated construction options for a `DigitBinIndex`.
+//!
+//! [`Config`] collects every construction-time choice in one place and is
+//! consumed by [`DigitBinIndex::with_config`](crate::DigitBinIndex::with_config).
+//! The shorthand constructors (`new`, `with_precision`, `small`, ...) remain
+//! available and are equivalent to the corresponding `Config`.
+
+use crate::{DEFAULT_PRECISION, DEFAULT_RADIX};
+
+/// The bin storage used by an index. See the backend constructors on
+/// [`DigitBinIndex`](crate::DigitBinIndex) for the trade-offs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// `Vec<u32>` bins.
+    #[default]
+    Small,
+    /// `RoaringBitmap` bins.
+    Medium,
+    /// `RoaringTreemap` bins.
+    Large,
+    /// `CountedBin` bins.
+    Counted,
+    /// Chosen from the expected number of items, as by
+    /// [`with_precision_and_capacity`](crate::DigitBinIndex::with_precision_and_capacity).
+    Auto { capacity: u64 },
+}
+
+/// Which child a draw descends into when its target lies exactly on the boundary
+/// between two children, that is, at the end of one child's mass and the start of
+/// the next. See [`set_tie_break`](crate::DigitBinIndex::set_tie_break).
+///
+/// The choice does not change the selection probabilities, only which targets map to
+/// which items, so it matters when replaying targets or RNG streams against another
+/// implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TieBreak {
+    /// The earlier child: each child owns the targets `(start, end]`, and targets are
+    /// drawn from `1..=total`, like a Fenwick tree search for the first prefix sum that
+    /// reaches the target.
+    FirstChild,
+    /// The later child: each child owns the targets `[start, end)`, and targets are
+    /// drawn from `0..total`.
+    #[default]
+    LastChild,
+    /// Either side, chosen by a fair coin per draw (per batch for batch draws).
+    Random,
+}
+
+/// Builder for a customized [`DigitBinIndex`](crate::DigitBinIndex).
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{Backend, Config, DigitBinIndex};
+///
+/// let config = Config::new()
+///     .precision(4)
+///     .backend(Backend::Medium)
+///     .memory_budget(Some(1 << 20))
+///     .id_registry(true);
+/// let index = DigitBinIndex::with_config(config);
+/// assert_eq!(index.precision(), 4);
+/// assert!(matches!(index, DigitBinIndex::Medium(_)));
+/// assert_eq!(index.memory_budget(), Some(1 << 20));
+/// assert!(index.has_id_registry());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub(crate) precision: u8,
+    pub(crate) radix: u16,
+    pub(crate) backend: Backend,
+    pub(crate) memory_budget: Option<usize>,
+    pub(crate) id_registry: bool,
+    pub(crate) exact_weights: bool,
+    pub(crate) tie_break: TieBreak,
+    pub(crate) auto_repair: bool,
+}
+
+impl Config {
+    /// The configuration of [`DigitBinIndex::new`](crate::DigitBinIndex::new): default
+    /// precision, a decimal tree, `Small` bins, no memory budget, no ID registry, binned
+    /// weights, and [`TieBreak::LastChild`], without automatic mass repair.
+    #[must_use]
+    pub fn new() -> Self {
+        Config {
+            precision: DEFAULT_PRECISION,
+            radix: DEFAULT_RADIX,
+            backend: Backend::Small,
+            memory_budget: None,
+            id_registry: false,
+            exact_weights: false,
+            tie_break: TieBreak::LastChild,
+            auto_repair: false,
+        }
+    }
+
+    /// Sets the number of decimal places for binning (1 to 9).
+    #[must_use]
+    pub fn precision(mut self, precision: u8) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets the branching base of the tree (2 to 256, 10 by default).
+    ///
+    /// Weights are binned at the configured precision whatever the radix; the radix only
+    /// decides how the scaled weights are split into digits on the way down the tree. A
+    /// wider radix makes the tree shallower, so descents visit fewer nodes, at the cost of
+    /// wider nodes to scan. Precision 6 takes six levels in base 10, five in base 16 and
+    /// three in base 256.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{Config, DigitBinIndex};
+    ///
+    /// let mut index = DigitBinIndex::with_config(Config::new().precision(6).radix(256));
+    /// assert!(index.add(1, 0.123456));
+    /// assert_eq!(index.radix(), 256);
+    /// assert_eq!(index.quantize(0.1234567), Some(0.123456));
+    /// ```
+    #[must_use]
+    pub fn radix(mut self, radix: u16) -> Self {
+        self.radix = radix;
+        self
+    }
+
+    /// Sets the bin storage.
+    #[must_use]
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Chooses the backend from the expected number of items. Shorthand for
+    /// `backend(Backend::Auto { capacity })`.
+    #[must_use]
+    pub fn capacity(self, capacity: u64) -> Self {
+        self.backend(Backend::Auto { capacity })
+    }
+
+    /// Sets the memory budget for the tree structure. See
+    /// [`set_memory_budget`](crate::DigitBinIndex::set_memory_budget).
+    #[must_use]
+    pub fn memory_budget(mut self, max_bytes: Option<usize>) -> Self {
+        self.memory_budget = max_bytes;
+        self
+    }
+
+    /// Turns the ID registry on or off. See
+    /// [`enable_id_registry`](crate::DigitBinIndex::enable_id_registry).
+    #[must_use]
+    pub fn id_registry(mut self, enabled: bool) -> Self {
+        self.id_registry = enabled;
+        self
+    }
+
+    /// Turns exact weights on or off. Turning them on also turns on the ID registry. See
+    /// [`enable_exact_weights`](crate::DigitBinIndex::enable_exact_weights).
+    #[must_use]
+    pub fn exact_weights(mut self, enabled: bool) -> Self {
+        self.exact_weights = enabled;
+        self
+    }
+
+    /// Sets how draws resolve targets on a boundary between children. See
+    /// [`set_tie_break`](crate::DigitBinIndex::set_tie_break).
+    #[must_use]
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Turns automatic mass repair on or off. See
+    /// [`set_auto_repair`](crate::DigitBinIndex::set_auto_repair).
+    #[must_use]
+    pub fn auto_repair(mut self, enabled: bool) -> Self {
+        self.auto_repair = enabled;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
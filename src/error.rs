@@ -0,0 +1,104 @@
+//! Error type for the fallible operations of `DigitBinIndex`.
+
+use std::fmt;
+
+/// Errors reported by fallible `DigitBinIndex` operations.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum DigitBinIndexError {
+    /// A group asked for more winners than it has members in the index.
+    InfeasibleQuota { group: usize, requested: u64, available: u64 },
+    /// The same ID was listed in more than one group.
+    OverlappingGroups { id: u64 },
+    /// A plan or snapshot was made at a different precision than the index has.
+    PrecisionMismatch { expected: u8, found: u8 },
+    /// A planned item is no longer in the bin the plan recorded for it.
+    StalePlan { id: u64 },
+    /// An insertion would grow the tree beyond its memory budget.
+    MemoryBudgetExceeded { budget: usize, required: usize },
+    /// Reading or writing a snapshot failed.
+    Io { kind: std::io::ErrorKind, message: String },
+    /// A snapshot section does not match its stored checksum.
+    ChecksumMismatch { section: &'static str, expected: u32, found: u32 },
+    /// A snapshot is malformed.
+    CorruptSnapshot { reason: String },
+    /// A snapshot was written in a format version this build cannot read.
+    UnsupportedVersion { version: u16 },
+    /// A node's stored item count or mass does not add up from its contents.
+    InconsistentMass { stored_count: u64, stored_mass: u64, actual_count: u64, actual_mass: u64 },
+    /// A weight is not a number in the open interval `(0, 1)`.
+    InvalidWeight { weight: f64 },
+    /// A weight is valid but truncates to zero at the index's precision.
+    WeightBelowResolution { weight: f64, precision: u8 },
+    /// A precision outside 1 to 9 was requested.
+    PrecisionOutOfRange { precision: u8 },
+    /// A radix outside 2 to 256 was requested.
+    RadixOutOfRange { radix: u16 },
+    /// The ID is already in the bin, and the backend stores each ID at most once per bin.
+    DuplicateId { id: u64 },
+    /// The ID is larger than the backend can store (`u32::MAX` for `Small` and `Medium`).
+    IdOutOfRange { id: u64, max: u64 },
+    /// The ID is not in the index.
+    NotFound { id: u64 },
+    /// The ID is in the index, but not with the given weight.
+    WeightMismatch { id: u64, given: f64, stored: f64 },
+}
+
+impl fmt::Display for DigitBinIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DigitBinIndexError::InfeasibleQuota { group, requested, available } => write!(
+                f,
+                "group {} requests {} items but only {} are in the index",
+                group, requested, available
+            ),
+            DigitBinIndexError::OverlappingGroups { id } => write!(f, "ID {} belongs to more than one group", id),
+            DigitBinIndexError::PrecisionMismatch { expected, found } => {
+                write!(f, "expected precision {} but the index has precision {}", expected, found)
+            }
+            DigitBinIndexError::StalePlan { id } => write!(f, "planned ID {} is no longer in its bin", id),
+            DigitBinIndexError::MemoryBudgetExceeded { budget, required } => {
+                write!(f, "insertion needs {} bytes of tree memory but the budget is {}", required, budget)
+            }
+            DigitBinIndexError::Io { message, .. } => write!(f, "I/O error: {}", message),
+            DigitBinIndexError::ChecksumMismatch { section, expected, found } => write!(
+                f,
+                "checksum mismatch in {} section: stored {:08x}, computed {:08x}",
+                section, expected, found
+            ),
+            DigitBinIndexError::CorruptSnapshot { reason } => write!(f, "corrupt snapshot: {}", reason),
+            DigitBinIndexError::UnsupportedVersion { version } => write!(f, "unsupported snapshot version {}", version),
+            DigitBinIndexError::InconsistentMass { stored_count, stored_mass, actual_count, actual_mass } => write!(
+                f,
+                "a node stores {} items of mass {} but holds {} items of mass {}",
+                stored_count, stored_mass, actual_count, actual_mass
+            ),
+            DigitBinIndexError::InvalidWeight { weight } => write!(f, "weight {} is not in (0, 1)", weight),
+            DigitBinIndexError::WeightBelowResolution { weight, precision } => {
+                write!(f, "weight {} is zero at precision {}", weight, precision)
+            }
+            DigitBinIndexError::PrecisionOutOfRange { precision } => {
+                write!(f, "precision {} is not between 1 and {}", precision, crate::MAX_PRECISION)
+            }
+            DigitBinIndexError::RadixOutOfRange { radix } => {
+                write!(f, "radix {} is not between {} and {}", radix, crate::MIN_RADIX, crate::MAX_RADIX)
+            }
+            DigitBinIndexError::DuplicateId { id } => write!(f, "ID {} is already in its bin", id),
+            DigitBinIndexError::IdOutOfRange { id, max } => {
+                write!(f, "ID {} is larger than {}, the largest ID this backend stores", id, max)
+            }
+            DigitBinIndexError::NotFound { id } => write!(f, "ID {} is not in the index", id),
+            DigitBinIndexError::WeightMismatch { id, given, stored } => {
+                write!(f, "ID {} is stored with weight {}, not {}", id, stored, given)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DigitBinIndexError {}
+
+impl From<std::io::Error> for DigitBinIndexError {
+    fn from(err: std::io::Error) -> Self {
+        DigitBinIndexError::Io { kind: err.kind(), message: err.to_string() }
+    }
+}
@@ -0,0 +1,149 @@
+//! An index over integer frequencies instead of probabilities.
+//!
+//! Many datasets come as counts (people per cell, events per bucket) rather
+//! than weights in `(0, 1)`. [`FrequencyIndex`] takes such `u64` frequencies
+//! directly. Internally a frequency is a fixed-point value like any other weight:
+//! with a resolution of 1, the frequency *is* the scaled weight, so totals are
+//! exact integer sums and nothing is divided by a total and multiplied back.
+//! Frequencies with more digits than the precision are binned by their leading
+//! digits, by raising the resolution to a power of ten.
+
+use crate::{DigitBinIndex, MAX_PRECISION};
+
+/// A weighted index whose items carry integer frequencies.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::FrequencyIndex;
+///
+/// let mut index = FrequencyIndex::new(1_000_000);
+/// index.add(1, 250_000);
+/// index.add(2, 750_000);
+/// assert_eq!(index.resolution(), 1);
+/// assert_eq!(index.total_frequency(), 1_000_000);
+///
+/// let (id, frequency) = index.select_and_remove().unwrap();
+/// assert_eq!(frequency, if id == 1 { 250_000 } else { 750_000 });
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrequencyIndex {
+    index: DigitBinIndex,
+    /// The frequency represented by one scaled unit, a power of ten.
+    resolution: u64,
+    scale: f64,
+}
+
+impl FrequencyIndex {
+    /// Creates an index for frequencies up to `max_frequency`, at the smallest precision
+    /// that holds them exactly, but at most 9 digits.
+    ///
+    /// Frequencies beyond 9 digits are binned by their leading 9 digits.
+    pub fn new(max_frequency: u64) -> Self {
+        let digits = max_frequency.checked_ilog10().map_or(1, |log| log + 1) as u8;
+        Self::with_precision(digits.min(MAX_PRECISION as u8), max_frequency)
+    }
+
+    /// Creates an index for frequencies up to `max_frequency`, binned by their leading
+    /// `precision` digits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `precision` is not between 1 and 9.
+    pub fn with_precision(precision: u8, max_frequency: u64) -> Self {
+        let index = DigitBinIndex::with_precision(precision);
+        let limit = 10u64.pow(precision as u32);
+        let mut resolution = 1u64;
+        while max_frequency / resolution >= limit {
+            resolution *= 10;
+        }
+        FrequencyIndex { index, resolution, scale: limit as f64 }
+    }
+
+    /// Returns the frequency represented by one unit of the index. Frequencies are
+    /// truncated to a multiple of it, and frequencies below it are not stored.
+    pub fn resolution(&self) -> u64 {
+        self.resolution
+    }
+
+    /// Returns the underlying index, whose weights are the frequencies divided by
+    /// `resolution * 10^precision`.
+    pub fn index(&self) -> &DigitBinIndex {
+        &self.index
+    }
+
+    /// The scaled weight of a frequency, or `None` if it is not storable.
+    fn to_scaled(&self, frequency: u64) -> Option<u64> {
+        let scaled = frequency / self.resolution;
+        (scaled > 0 && (scaled as f64) < self.scale).then_some(scaled)
+    }
+
+    fn to_frequency(&self, (id, weight): (u64, f64)) -> (u64, u64) {
+        (id, (weight * self.scale).round() as u64 * self.resolution)
+    }
+
+    /// Adds an item with the given frequency.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the frequency is below the resolution or above the maximum the index
+    /// was created for, in which case nothing is added.
+    pub fn add(&mut self, id: u64, frequency: u64) -> bool {
+        match self.to_scaled(frequency) {
+            Some(scaled) => self.index.insert_scaled(id, scaled, 1) > 0,
+            None => false,
+        }
+    }
+
+    /// Removes an item that was added with the given frequency.
+    pub fn remove(&mut self, id: u64, frequency: u64) -> bool {
+        self.to_scaled(frequency).is_some_and(|scaled| self.index.remove_scaled(id, scaled))
+    }
+
+    /// Turns on the ID registry of the underlying index, which
+    /// [`frequency_of`](Self::frequency_of) looks items up in.
+    /// See [`DigitBinIndex::enable_id_registry`].
+    pub fn enable_id_registry(&mut self) {
+        self.index.enable_id_registry();
+    }
+
+    /// Returns the stored frequency of an item, truncated to the resolution, or `None` if
+    /// the item is absent or the ID registry is off.
+    pub fn frequency_of(&self, id: u64) -> Option<u64> {
+        self.index.get_weight(id).map(|weight| self.to_frequency((id, weight)).1)
+    }
+
+    /// Selects an item with probability proportional to its frequency.
+    pub fn select(&mut self) -> Option<(u64, u64)> {
+        self.index.select().map(|item| self.to_frequency(item))
+    }
+
+    /// Selects and removes an item with probability proportional to its frequency.
+    pub fn select_and_remove(&mut self) -> Option<(u64, u64)> {
+        self.index.select_and_remove().map(|item| self.to_frequency(item))
+    }
+
+    /// Selects `num_to_draw` items by frequency. See [`DigitBinIndex::select_many`].
+    pub fn select_many(&mut self, num_to_draw: u64) -> Option<Vec<(u64, u64)>> {
+        let selected = self.index.select_many(num_to_draw)?;
+        Some(selected.into_iter().map(|item| self.to_frequency(item)).collect())
+    }
+
+    /// Selects and removes `num_to_draw` items by frequency.
+    /// See [`DigitBinIndex::select_many_and_remove`].
+    pub fn select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, u64)>> {
+        let selected = self.index.select_many_and_remove(num_to_draw)?;
+        Some(selected.into_iter().map(|item| self.to_frequency(item)).collect())
+    }
+
+    /// Returns the number of items.
+    pub fn count(&self) -> u64 {
+        self.index.count()
+    }
+
+    /// Returns the sum of the stored frequencies. It is computed from the exact integer
+    /// total, so it does not suffer from floating-point rounding.
+    pub fn total_frequency(&self) -> u128 {
+        self.index.total_mass() as u128 * self.resolution as u128
+    }
+}
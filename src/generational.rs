@@ -0,0 +1,146 @@
+//! Generational handles that guard against stale references to recycled IDs.
+//!
+//! A `GenerationalIndex` hands out [`Handle`]s made of a slot and a generation.
+//! Slots are stored as IDs in an ordinary `DigitBinIndex` and are recycled
+//! once their item leaves the index, but every reuse bumps the slot's
+//! generation. Mutations through a handle from an earlier generation are
+//! rejected instead of silently affecting the slot's new occupant.
+
+use crate::DigitBinIndex;
+
+/// A reference to an item in a [`GenerationalIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Handle {
+    pub slot: u32,
+    pub generation: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Slot {
+    generation: u32,
+    /// The weight the current occupant was added with, or `None` if the slot is free.
+    weight: Option<f64>,
+}
+
+/// A weighted index addressed by generational handles.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::GenerationalIndex;
+///
+/// let mut index = GenerationalIndex::new();
+/// let old = index.insert(0.5).unwrap();
+/// assert!(index.remove(old));
+///
+/// // The slot is recycled, but the stale handle no longer matches.
+/// let new = index.insert(0.3).unwrap();
+/// assert_eq!(new.slot, old.slot);
+/// assert!(!index.remove(old));
+/// assert!(index.contains(new));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GenerationalIndex {
+    index: DigitBinIndex,
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+}
+
+impl Default for GenerationalIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GenerationalIndex {
+    /// Creates an empty index with the default precision.
+    pub fn new() -> Self {
+        Self::from_index(DigitBinIndex::new())
+    }
+
+    /// Creates an empty index with the given precision.
+    pub fn with_precision(precision: u8) -> Self {
+        Self::from_index(DigitBinIndex::with_precision(precision))
+    }
+
+    fn from_index(index: DigitBinIndex) -> Self {
+        Self { index, slots: Vec::new(), free: Vec::new() }
+    }
+
+    /// Adds an item and returns its handle, or `None` if the weight is invalid.
+    pub fn insert(&mut self, weight: f64) -> Option<Handle> {
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(Slot::default());
+                (self.slots.len() - 1) as u32
+            }
+        };
+        if self.index.add_with_count(slot as u64, weight, 1) == 0 {
+            self.free.push(slot);
+            return None;
+        }
+        let entry = &mut self.slots[slot as usize];
+        entry.weight = Some(weight);
+        Some(Handle { slot, generation: entry.generation })
+    }
+
+    /// Returns true if the handle refers to an item currently in the index.
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.weight(handle).is_some()
+    }
+
+    /// Returns the weight of the item the handle refers to, if the handle is current.
+    pub fn weight(&self, handle: Handle) -> Option<f64> {
+        let entry = self.slots.get(handle.slot as usize)?;
+        if entry.generation != handle.generation {
+            return None;
+        }
+        entry.weight
+    }
+
+    /// Removes the item the handle refers to. Returns false for stale or unknown handles.
+    pub fn remove(&mut self, handle: Handle) -> bool {
+        let Some(weight) = self.weight(handle) else {
+            return false;
+        };
+        if !self.index.remove(handle.slot as u64, weight) {
+            return false;
+        }
+        self.release(handle.slot);
+        true
+    }
+
+    /// Selects a single item without removal.
+    pub fn select(&mut self) -> Option<(Handle, f64)> {
+        let (slot, weight) = self.index.select()?;
+        let slot = slot as u32;
+        Some((Handle { slot, generation: self.slots[slot as usize].generation }, weight))
+    }
+
+    /// Selects and removes a single item. The returned handle is already stale.
+    pub fn select_and_remove(&mut self) -> Option<(Handle, f64)> {
+        let (slot, weight) = self.index.select_and_remove()?;
+        let slot = slot as u32;
+        let handle = Handle { slot, generation: self.slots[slot as usize].generation };
+        self.release(slot);
+        Some((handle, weight))
+    }
+
+    /// Returns the number of items currently in the index.
+    pub fn count(&self) -> u64 {
+        self.index.count()
+    }
+
+    /// Returns the sum of all weights in the index.
+    pub fn total_weight(&self) -> f64 {
+        self.index.total_weight()
+    }
+
+    fn release(&mut self, slot: u32) {
+        let entry = &mut self.slots[slot as usize];
+        entry.weight = None;
+        entry.generation = entry.generation.wrapping_add(1);
+        self.free.push(slot);
+    }
+}
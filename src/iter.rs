@@ -0,0 +1,359 @@
+//! Borrowed iteration over the bins and members of an index.
+//!
+//! The types here hide the bin storage (`Vec<u32>`, Roaring bitmaps, counted
+//! bins) behind opaque iterators, so code that walks an index does not depend
+//! on the `roaring` crate or on which backend was chosen.
+
+use rand::Rng;
+use roaring::{RoaringBitmap, RoaringTreemap};
+
+use crate::{Arena, CountedBin, DigitBin, DigitBinIndex, DigitBinIndexGeneric, Node, NodeContent};
+
+/// Iterator over the non-empty bins of a [`DigitBinIndexGeneric`], in ascending weight order.
+///
+/// Yields each bin's per-item weight together with the bin itself.
+#[derive(Debug, Clone)]
+pub struct GenericBins<'a, B: DigitBin> {
+    stack: Vec<(&'a Node<B>, u64)>,
+    arena: &'a Arena<B>,
+    scale: f64,
+    radix: u64,
+}
+
+impl<'a, B: DigitBin> GenericBins<'a, B> {
+    pub(crate) fn new(index: &'a DigitBinIndexGeneric<B>, scale: f64) -> Self {
+        Self { stack: vec![(&index.root, 0)], arena: &index.arena, scale, radix: index.radix() as u64 }
+    }
+}
+
+impl<'a, B: DigitBin> Iterator for GenericBins<'a, B> {
+    type Item = (f64, &'a B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, prefix)) = self.stack.pop() {
+            match &node.content {
+                NodeContent::Bin(bin) if !bin.is_empty() => return Some((prefix as f64 / self.scale, bin)),
+                NodeContent::Bin(_) => {}
+                &NodeContent::DigitIndex(block) => {
+                    // Push in reverse so the lowest digit is visited first.
+                    for (digit, child) in self.arena.block(block).iter().enumerate().rev() {
+                        if let Some(child) = child {
+                            self.stack.push((child, prefix * self.radix + digit as u64));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the IDs stored in one bin of a [`DigitBinIndex`](crate::DigitBinIndex).
+///
+/// IDs stored with a multiplicity are yielded once per copy.
+#[derive(Clone)]
+pub struct Members<'a>(MembersInner<'a>);
+
+#[derive(Clone)]
+enum MembersInner<'a> {
+    Small(<Vec<u32> as DigitBin>::Ids<'a>),
+    Medium(<RoaringBitmap as DigitBin>::Ids<'a>),
+    Large(Box<<RoaringTreemap as DigitBin>::Ids<'a>>),
+    Counted(<CountedBin as DigitBin>::Ids<'a>),
+}
+
+impl<'a> Members<'a> {
+    pub(crate) fn small(bin: &'a Vec<u32>) -> Self {
+        Members(MembersInner::Small(bin.ids()))
+    }
+    pub(crate) fn medium(bin: &'a RoaringBitmap) -> Self {
+        Members(MembersInner::Medium(bin.ids()))
+    }
+    pub(crate) fn large(bin: &'a RoaringTreemap) -> Self {
+        Members(MembersInner::Large(Box::new(bin.ids())))
+    }
+    pub(crate) fn counted(bin: &'a CountedBin) -> Self {
+        Members(MembersInner::Counted(bin.ids()))
+    }
+}
+
+impl Iterator for Members<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match &mut self.0 {
+            MembersInner::Small(ids) => ids.next(),
+            MembersInner::Medium(ids) => ids.next(),
+            MembersInner::Large(ids) => ids.next(),
+            MembersInner::Counted(ids) => ids.next(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Members<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Members").finish_non_exhaustive()
+    }
+}
+
+/// Iterator over the non-empty bins of a [`DigitBinIndex`](crate::DigitBinIndex), in ascending weight order.
+///
+/// Yields each bin's per-item weight, its item count, and an iterator over its members.
+#[derive(Debug, Clone)]
+pub struct Bins<'a>(BinsInner<'a>);
+
+#[derive(Debug, Clone)]
+enum BinsInner<'a> {
+    Small(GenericBins<'a, Vec<u32>>),
+    Medium(GenericBins<'a, RoaringBitmap>),
+    Large(GenericBins<'a, RoaringTreemap>),
+    Counted(GenericBins<'a, CountedBin>),
+}
+
+impl<'a> Bins<'a> {
+    pub(crate) fn small(bins: GenericBins<'a, Vec<u32>>) -> Self {
+        Bins(BinsInner::Small(bins))
+    }
+    pub(crate) fn medium(bins: GenericBins<'a, RoaringBitmap>) -> Self {
+        Bins(BinsInner::Medium(bins))
+    }
+    pub(crate) fn large(bins: GenericBins<'a, RoaringTreemap>) -> Self {
+        Bins(BinsInner::Large(bins))
+    }
+    pub(crate) fn counted(bins: GenericBins<'a, CountedBin>) -> Self {
+        Bins(BinsInner::Counted(bins))
+    }
+}
+
+impl<'a> Iterator for Bins<'a> {
+    type Item = (f64, u64, Members<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        fn view<'a, B: DigitBin>(
+            (weight, bin): (f64, &'a B),
+            wrap: fn(B::Ids<'a>) -> MembersInner<'a>,
+        ) -> (f64, u64, Members<'a>) {
+            (weight, bin.len() as u64, Members(wrap(bin.ids())))
+        }
+        match &mut self.0 {
+            BinsInner::Small(bins) => bins.next().map(|b| view(b, MembersInner::Small)),
+            BinsInner::Medium(bins) => bins.next().map(|b| view(b, MembersInner::Medium)),
+            BinsInner::Large(bins) => bins.next().map(|b| view(b, |ids| MembersInner::Large(Box::new(ids)))),
+            BinsInner::Counted(bins) => bins.next().map(|b| view(b, MembersInner::Counted)),
+        }
+    }
+}
+
+/// Iterator over every `(id, weight)` item of a [`DigitBinIndex`](crate::DigitBinIndex), in ascending weight order.
+///
+/// The length is known up front from the root's item count, so this is an [`ExactSizeIterator`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    bins: Bins<'a>,
+    current: Option<(f64, Members<'a>)>,
+    remaining: usize,
+}
+
+impl<'a> Iter<'a> {
+    pub(crate) fn new(bins: Bins<'a>, len: u64) -> Self {
+        Self { bins, current: None, remaining: len as usize }
+    }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (u64, f64);
+
+    fn next(&mut self) -> Option<(u64, f64)> {
+        loop {
+            if let Some((weight, members)) = &mut self.current {
+                if let Some(id) = members.next() {
+                    self.remaining -= 1;
+                    return Some((id, *weight));
+                }
+            }
+            let (weight, _, members) = self.bins.next()?;
+            self.current = Some((weight, members));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// Owning iterator over the items of a [`DigitBinIndexGeneric`], in ascending weight order.
+///
+/// The tree is taken apart as the iteration reaches it: each bin is released as soon as
+/// its IDs have been buffered, so the whole population is never copied at once.
+#[derive(Debug)]
+pub struct GenericIntoIter<B: DigitBin> {
+    stack: Vec<(Node<B>, u64)>,
+    arena: Arena<B>,
+    bin: std::vec::IntoIter<u64>,
+    weight: f64,
+    scale: f64,
+    radix: u64,
+    remaining: usize,
+}
+
+impl<B: DigitBin> GenericIntoIter<B> {
+    pub(crate) fn new(root: Node<B>, arena: Arena<B>, scale: f64) -> Self {
+        let remaining = root.content_count as usize;
+        let radix = arena.width as u64;
+        Self { stack: vec![(root, 0)], arena, bin: Vec::new().into_iter(), weight: 0.0, scale, radix, remaining }
+    }
+}
+
+impl<B: DigitBin> Iterator for GenericIntoIter<B> {
+    type Item = (u64, f64);
+
+    fn next(&mut self) -> Option<(u64, f64)> {
+        loop {
+            if let Some(id) = self.bin.next() {
+                self.remaining -= 1;
+                return Some((id, self.weight));
+            }
+            let (node, prefix) = self.stack.pop()?;
+            match node.content {
+                NodeContent::Bin(bin) => {
+                    self.bin = bin.ids().collect::<Vec<_>>().into_iter();
+                    self.weight = prefix as f64 / self.scale;
+                }
+                NodeContent::DigitIndex(block) => {
+                    // Push in reverse so the lowest digit is visited first.
+                    for digit in (0..self.arena.width).rev() {
+                        if let Some(child) = self.arena.take(block, digit) {
+                            self.stack.push((child, prefix * self.radix + digit as u64));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<B: DigitBin> ExactSizeIterator for GenericIntoIter<B> {}
+
+/// Owning iterator over the items of a [`DigitBinIndex`](crate::DigitBinIndex), in ascending
+/// weight order, returned by its `into_iter`.
+///
+/// Bins are released as the iteration passes them. See [`GenericIntoIter`].
+#[derive(Debug)]
+pub struct IntoIter(IntoIterInner);
+
+#[derive(Debug)]
+enum IntoIterInner {
+    Small(GenericIntoIter<Vec<u32>>),
+    Medium(GenericIntoIter<RoaringBitmap>),
+    Large(GenericIntoIter<RoaringTreemap>),
+    Counted(GenericIntoIter<CountedBin>),
+}
+
+impl IntoIter {
+    pub(crate) fn small(items: GenericIntoIter<Vec<u32>>) -> Self {
+        IntoIter(IntoIterInner::Small(items))
+    }
+    pub(crate) fn medium(items: GenericIntoIter<RoaringBitmap>) -> Self {
+        IntoIter(IntoIterInner::Medium(items))
+    }
+    pub(crate) fn large(items: GenericIntoIter<RoaringTreemap>) -> Self {
+        IntoIter(IntoIterInner::Large(items))
+    }
+    pub(crate) fn counted(items: GenericIntoIter<CountedBin>) -> Self {
+        IntoIter(IntoIterInner::Counted(items))
+    }
+}
+
+impl Iterator for IntoIter {
+    type Item = (u64, f64);
+
+    fn next(&mut self) -> Option<(u64, f64)> {
+        match &mut self.0 {
+            IntoIterInner::Small(items) => items.next(),
+            IntoIterInner::Medium(items) => items.next(),
+            IntoIterInner::Large(items) => items.next(),
+            IntoIterInner::Counted(items) => items.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            IntoIterInner::Small(items) => items.size_hint(),
+            IntoIterInner::Medium(items) => items.size_hint(),
+            IntoIterInner::Large(items) => items.size_hint(),
+            IntoIterInner::Counted(items) => items.size_hint(),
+        }
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+/// Owning iterator over the items withdrawn by [`DigitBinIndex::drain`](crate::DigitBinIndex::drain).
+///
+/// The withdrawn tree is taken apart lazily, like by [`IntoIter`].
+#[derive(Debug)]
+pub struct Drain(IntoIter);
+
+impl Drain {
+    pub(crate) fn new(items: IntoIter) -> Self {
+        Self(items)
+    }
+}
+
+impl Iterator for Drain {
+    type Item = (u64, f64);
+
+    fn next(&mut self) -> Option<(u64, f64)> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Drain {}
+
+/// Owning iterator over the items of a [`DigitBinIndex`], in weighted random order,
+/// returned by [`DigitBinIndex::shuffle_iter`].
+///
+/// Each step is a [`select_and_remove_with_rng`](DigitBinIndex::select_and_remove_with_rng)
+/// on the index it owns, so the items are produced lazily and only as many draws are
+/// made as are consumed.
+#[derive(Debug, Clone)]
+pub struct ShuffleIter<R> {
+    index: DigitBinIndex,
+    rng: R,
+}
+
+impl<R: Rng> ShuffleIter<R> {
+    pub(crate) fn new(index: DigitBinIndex, rng: R) -> Self {
+        ShuffleIter { index, rng }
+    }
+
+    /// Stops the shuffle and returns the index with the items not yet yielded.
+    pub fn into_inner(self) -> DigitBinIndex {
+        self.index
+    }
+}
+
+impl<R: Rng> Iterator for ShuffleIter<R> {
+    type Item = (u64, f64);
+
+    fn next(&mut self) -> Option<(u64, f64)> {
+        self.index.select_and_remove_with_rng(&mut self.rng)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.index.count() as usize;
+        (len, Some(len))
+    }
+}
+
+impl<R: Rng> ExactSizeIterator for ShuffleIter<R> {}
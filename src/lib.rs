@@ -10,36 +10,228 @@
 use wyrand::WyRand;
 use rand::{distr::{Distribution, Uniform}, Rng, SeedableRng}; 
 use roaring::{RoaringBitmap, RoaringTreemap};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+mod error;
+pub use error::DigitBinIndexError;
+
+mod config;
+pub use config::{Backend, Config, TieBreak};
+
+mod shared;
+pub use shared::SharedSampler;
+
+mod concurrent;
+pub use concurrent::ConcurrentDigitBinIndex;
+
+mod allocator;
+pub use allocator::{Allocator, ArmStats};
+
+mod generational;
+pub use generational::{GenerationalIndex, Handle};
+
+mod iter;
+pub use iter::{Bins, Drain, GenericBins, GenericIntoIter, IntoIter, Iter, Members, ShuffleIter};
+
+mod lottery;
+pub use lottery::{DuplicatePolicy, LotteryDecision, LotteryDraw, LotteryOutcome, LotteryRules};
+
+mod sliced;
+pub use sliced::{BatchDraw, BulkLoad, Slice};
+
+mod frequency;
+pub use frequency::FrequencyIndex;
+
+mod cached;
+pub use cached::CachedIndex;
+
+mod map;
+pub use map::DigitBinMap;
+
+mod sampler;
+pub use sampler::Sampler;
+
+pub mod snapshot;
+
+mod state;
+
+pub mod wallenius;
+
+#[cfg(feature = "alloc-track")]
+pub mod alloc_track;
 
 // The default precision to use if none is specified in the constructor.
 const DEFAULT_PRECISION: u8 = 3;
 const MAX_PRECISION: usize = 9;
+// The default branching base of the tree, and the range of bases it can be built with.
+const DEFAULT_RADIX: u16 = 10;
+const MIN_RADIX: u16 = 2;
+const MAX_RADIX: u16 = 256;
+// The most digits a scaled weight can have: 10^9 - 1 takes 30 digits in base 2.
+const MAX_DEPTH: usize = 30;
+
+/// The digits of a scaled weight in the index's radix, most significant first.
+type Digits = [u8; MAX_DEPTH];
 
 /// Trait for types that can be used as leaf bins in a `DigitBinIndex`.
 ///
 /// Implement this trait for any container you want to use for storing IDs in the leaf nodes.
-/// Provided implementations: [`Vec<u32>`], [`RoaringBitmap`].
+/// Provided implementations: [`Vec<u32>`], [`RoaringBitmap`], [`RoaringTreemap`] and
+/// [`CountedBin`].
+///
+/// Since 0.5.0 an implementation must also provide [`ids`](Self::ids) and its iterator type,
+/// which the index uses to enumerate bins; the other methods added since then have defaults
+/// built on `insert`, `remove` and `ids`.
 pub trait DigitBin: Clone + Default {
+    /// The largest ID the bin can store. Inserting a larger ID panics.
+    const MAX_ID: u64 = u64::MAX;
     fn insert(&mut self, id: u64);
+    /// Inserts `count` copies of `id` and returns how many copies were actually stored.
+    /// Set-like bins store at most one copy of each ID. The default inserts one copy at a
+    /// time and stops once an insert no longer grows the bin.
+    fn insert_count(&mut self, id: u64, count: u64) -> u64 {
+        let before = self.len();
+        for _ in 0..count {
+            let len = self.len();
+            self.insert(id);
+            if self.len() == len {
+                break;
+            }
+        }
+        (self.len() - before) as u64
+    }
+    /// Returns the number of copies of `id` stored in this bin.
+    fn count_of(&self, id: u64) -> u64 {
+        self.ids().filter(|&stored| stored == id).count() as u64
+    }
+    /// Inserts every ID in the range and returns how many were actually stored.
+    fn insert_range(&mut self, ids: std::ops::Range<u64>) -> u64 {
+        ids.map(|id| self.insert_count(id, 1)).sum()
+    }
     fn remove(&mut self, id: u64) -> bool;
+    /// Removes every copy of `id` and returns how many were removed.
+    fn remove_all(&mut self, id: u64) -> u64 {
+        let mut removed = 0;
+        while self.remove(id) {
+            removed += 1;
+        }
+        removed
+    }
+    /// Iterator over the IDs stored in the bin, yielding an ID once per stored copy.
+    type Ids<'a>: Iterator<Item = u64> where Self: 'a;
+    fn ids(&self) -> Self::Ids<'_>;
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool;
     fn get_random(&self, rng: &mut impl rand::Rng) -> Option<u64>;
     fn get_random_and_remove(&mut self, rng: &mut impl rand::Rng) -> Option<u64>;
+    /// Calls `f` with each stored ID and a number of copies, covering every copy in the bin.
+    fn for_each_count(&self, mut f: impl FnMut(u64, u64)) {
+        for id in self.ids() {
+            f(id, 1);
+        }
+    }
+    /// Moves every ID of `other` into this bin.
+    fn merge(&mut self, other: Self) {
+        other.for_each_count(|id, copies| {
+            self.insert_count(id, copies);
+        });
+    }
+    /// Returns the number of stored copies whose ID is not in `excluded`.
+    fn count_excluding(&self, excluded: &RoaringTreemap) -> u64 {
+        self.ids().filter(|&id| !excluded.contains(id)).count() as u64
+    }
+    /// Returns the ID at `position` in the bin's member order, the order of [`ids`](Self::ids).
+    fn nth_id(&self, position: u64) -> Option<u64> {
+        self.ids().nth(position as usize)
+    }
+    /// Returns the position in the bin's member order of the `n`-th (0-based) stored copy
+    /// whose ID is not in `excluded`.
+    fn nth_position_excluding(&self, n: u64, excluded: &RoaringTreemap) -> Option<u64> {
+        let (position, _) = self.ids().enumerate().filter(|&(_, id)| !excluded.contains(id)).nth(n as usize)?;
+        Some(position as u64)
+    }
+    /// Estimated heap memory, in bytes, held by the bin's contents.
+    fn heap_size(&self) -> usize {
+        self.len() * std::mem::size_of::<u64>()
+    }
+    /// Releases memory the bin holds beyond what its contents need.
+    fn shrink_to_fit(&mut self) {}
+    /// Appends the bin's contents to a snapshot. The default writes little-endian
+    /// `(id u64, copies u64)` pairs.
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.for_each_count(|id, copies| {
+            out.extend_from_slice(&id.to_le_bytes());
+            out.extend_from_slice(&copies.to_le_bytes());
+        });
+    }
+    /// Rebuilds a bin from the bytes written by [`encode`](Self::encode), or returns
+    /// `None` if they are malformed: out-of-range IDs, zero copies or more copies in
+    /// total than a `u64` counts.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if !bytes.len().is_multiple_of(16) {
+            return None;
+        }
+        let mut bin = Self::default();
+        let mut total = 0u64;
+        for pair in bytes.chunks_exact(16) {
+            let id = u64::from_le_bytes(pair[..8].try_into().ok()?);
+            let copies = u64::from_le_bytes(pair[8..].try_into().ok()?);
+            if id > Self::MAX_ID || copies == 0 {
+                return None;
+            }
+            total = total.checked_add(copies)?;
+            bin.insert_count(id, copies);
+        }
+        Some(bin)
+    }
+}
+
+/// Converts an ID for a bin of `u32`s, panicking instead of truncating it.
+fn u32_id(id: u64) -> u32 {
+    u32::try_from(id).unwrap_or_else(|_| panic!("ID {id} does not fit in a u32; use the Large or Counted backend for larger IDs"))
 }
 
 impl DigitBin for Vec<u32> {
-    fn insert(&mut self, id: u64) { self.push(id as u32); }
+    const MAX_ID: u64 = u32::MAX as u64;
+    fn insert(&mut self, id: u64) { self.push(u32_id(id)); }
+    // One entry per copy: `CountedBin` is the bin that stores a cohort as a single count.
+    fn insert_count(&mut self, id: u64, count: u64) -> u64 {
+        self.extend(std::iter::repeat_n(u32_id(id), count as usize));
+        count
+    }
+    fn count_of(&self, id: u64) -> u64 {
+        u32::try_from(id).map_or(0, |id| self.iter().filter(|&&x| x == id).count() as u64)
+    }
+    fn insert_range(&mut self, ids: std::ops::Range<u64>) -> u64 {
+        if ids.is_empty() {
+            return 0;
+        }
+        u32_id(ids.end - 1);
+        let before = self.len();
+        self.extend(ids.map(|id| id as u32));
+        (self.len() - before) as u64
+    }
+    type Ids<'a> = std::iter::Map<std::slice::Iter<'a, u32>, fn(&u32) -> u64>;
+    fn ids(&self) -> Self::Ids<'_> { self.iter().map(|&x| x as u64) }
     fn remove(&mut self, id: u64) -> bool {
-        if let Some(pos) = self.iter().position(|&x| x == id as u32) {
+        let Ok(id) = u32::try_from(id) else { return false };
+        if let Some(pos) = self.iter().position(|&x| x == id) {
             self.swap_remove(pos);
             true
         } else {
             false
         }
     }
+    fn remove_all(&mut self, id: u64) -> u64 {
+        let Ok(id) = u32::try_from(id) else { return 0 };
+        let before = self.len();
+        self.retain(|&x| x != id);
+        (before - self.len()) as u64
+    }
     fn len(&self) -> usize { self.len() }
     fn is_empty(&self) -> bool { self.is_empty() }
+    fn nth_id(&self, position: u64) -> Option<u64> { self.get(position as usize).map(|&x| x as u64) }
     fn get_random(&self, rng: &mut impl rand::Rng) -> Option<u64> {
         if self.is_empty() { None } else { Some(self[rng.random_range(0..self.len())] as u64) }
     }
@@ -49,13 +241,56 @@ impl DigitBin for Vec<u32> {
             Some(self.swap_remove(pos) as u64)
         }
     }
+    fn merge(&mut self, other: Self) {
+        if self.is_empty() { *self = other } else { self.extend(other) }
+    }
+    fn heap_size(&self) -> usize { self.capacity() * std::mem::size_of::<u32>() }
+    fn shrink_to_fit(&mut self) { Vec::shrink_to_fit(self) }
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.reserve(self.len() * 4);
+        for id in self {
+            out.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if !bytes.len().is_multiple_of(4) {
+            return None;
+        }
+        Some(bytes.chunks_exact(4).map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]])).collect())
+    }
 }
 
 impl DigitBin for RoaringBitmap {
-    fn insert(&mut self, id: u64) { self.insert(id as u32); }
-    fn remove(&mut self, id: u64) -> bool { self.remove(id as u32) }
+    const MAX_ID: u64 = u32::MAX as u64;
+    fn insert(&mut self, id: u64) { self.insert(u32_id(id)); }
+    fn insert_count(&mut self, id: u64, count: u64) -> u64 {
+        (count > 0 && self.insert(u32_id(id))) as u64
+    }
+    fn count_of(&self, id: u64) -> u64 { u32::try_from(id).is_ok_and(|id| self.contains(id)) as u64 }
+    fn insert_range(&mut self, ids: std::ops::Range<u64>) -> u64 {
+        if ids.is_empty() { 0 } else { self.insert_range(u32_id(ids.start)..=u32_id(ids.end - 1)) }
+    }
+    type Ids<'a> = std::iter::Map<roaring::bitmap::Iter<'a>, fn(u32) -> u64>;
+    fn ids(&self) -> Self::Ids<'_> { self.iter().map(u64::from) }
+    fn remove(&mut self, id: u64) -> bool { u32::try_from(id).is_ok_and(|id| self.remove(id)) }
     fn len(&self) -> usize { self.len() as usize }
     fn is_empty(&self) -> bool { self.is_empty() }
+    fn nth_id(&self, position: u64) -> Option<u64> {
+        self.select(u32::try_from(position).ok()?).map(u64::from)
+    }
+    fn nth_position_excluding(&self, n: u64, excluded: &RoaringTreemap) -> Option<u64> {
+        // Step past the ranks of the excluded members at or before the candidate position.
+        let mut position = n;
+        if let Some((_, low)) = excluded.bitmaps().find(|&(high, _)| high == 0) {
+            for id in &(self & low) {
+                if self.rank(id) - 1 > position {
+                    break;
+                }
+                position += 1;
+            }
+        }
+        (position < self.len()).then_some(position)
+    }
     fn get_random(&self, rng: &mut impl rand::Rng) -> Option<u64> {
         if self.is_empty() { None } else {
             let idx = rng.random_range(0..self.len() as u32);
@@ -70,16 +305,45 @@ impl DigitBin for RoaringBitmap {
             selected.map(|v| v as u64)
         }
     }
+    fn merge(&mut self, other: Self) { *self |= other; }
+    fn heap_size(&self) -> usize { self.serialized_size() }
+    fn shrink_to_fit(&mut self) { self.optimize(); }
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.serialize_into(out).expect("writing to a Vec cannot fail");
+    }
+    fn decode(mut bytes: &[u8]) -> Option<Self> {
+        let bin = Self::deserialize_from(&mut bytes).ok()?;
+        bytes.is_empty().then_some(bin)
+    }
 }
 
 impl DigitBin for RoaringTreemap {
     fn insert(&mut self, id: u64) { self.insert(id); }
+    fn insert_count(&mut self, id: u64, count: u64) -> u64 {
+        (count > 0 && self.insert(id)) as u64
+    }
+    fn count_of(&self, id: u64) -> u64 { self.contains(id) as u64 }
+    fn insert_range(&mut self, ids: std::ops::Range<u64>) -> u64 { self.insert_range(ids) }
+    type Ids<'a> = roaring::treemap::Iter<'a>;
+    fn ids(&self) -> Self::Ids<'_> { self.iter() }
     fn remove(&mut self, id: u64) -> bool { self.remove(id) }
     fn len(&self) -> usize { self.len() as usize }
     fn is_empty(&self) -> bool { self.is_empty() }
+    fn nth_id(&self, position: u64) -> Option<u64> { self.select(position) }
+    fn nth_position_excluding(&self, n: u64, excluded: &RoaringTreemap) -> Option<u64> {
+        // Step past the ranks of the excluded members at or before the candidate position.
+        let mut position = n;
+        for id in &(self & excluded) {
+            if self.rank(id) - 1 > position {
+                break;
+            }
+            position += 1;
+        }
+        (position < self.len()).then_some(position)
+    }
     fn get_random(&self, rng: &mut impl rand::Rng) -> Option<u64> {
         if self.is_empty() { None } else {
-            let idx = rng.random_range(0..self.len() as u64);
+            let idx = rng.random_range(0..self.len());
             self.select(idx)
         }
     }
@@ -91,22 +355,277 @@ impl DigitBin for RoaringTreemap {
             selected
         }
     }
+    fn count_excluding(&self, excluded: &RoaringTreemap) -> u64 { self.len() - self.intersection_len(excluded) }
+    fn merge(&mut self, other: Self) { *self |= other; }
+    fn heap_size(&self) -> usize { self.serialized_size() }
+    fn shrink_to_fit(&mut self) { self.optimize(); }
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.serialize_into(out).expect("writing to a Vec cannot fail");
+    }
+    fn decode(mut bytes: &[u8]) -> Option<Self> {
+        let bin = Self::deserialize_from(&mut bytes).ok()?;
+        bytes.is_empty().then_some(bin)
+    }
+}
+
+/// A leaf bin that stores a multiplicity count per ID instead of one entry per individual.
+///
+/// Use this when an ID represents a cohort of identical individuals: adding an ID with a
+/// count of one million costs a single entry, and every removal decrements the count by one.
+/// `len()` reports the total number of copies, so selection remains weighted by cohort size.
+/// Lookups by ID go through a map, so adding, counting and removing a given ID take constant
+/// time, and prefix sums over the counts find the copy at a position in logarithmic time.
+#[derive(Debug, Clone, Default)]
+pub struct CountedBin {
+    entries: Vec<(u64, u64)>,
+    /// The position in `entries` of every ID.
+    positions: std::collections::HashMap<u64, usize>,
+    /// A Fenwick tree over the counts in `entries`: slot `i` holds the sum of the counts
+    /// at positions `i + 1 - lowbit(i + 1)..=i`.
+    sums: Vec<u64>,
+    total: u64,
+}
+
+impl CountedBin {
+    /// Adds `delta` to the count at `pos` in the prefix sums. Decrements pass as wrapped
+    /// negatives; every sum stays a real count, so the wrapping cancels out.
+    fn add_to_sums(&mut self, pos: usize, delta: u64) {
+        let mut i = pos + 1;
+        while i <= self.sums.len() {
+            self.sums[i - 1] = self.sums[i - 1].wrapping_add(delta);
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Returns the sum of the counts at positions below `end`.
+    fn prefix_sum(&self, mut end: usize) -> u64 {
+        let mut sum = 0;
+        while end > 0 {
+            sum += self.sums[end - 1];
+            end &= end - 1;
+        }
+        sum
+    }
+
+    /// Appends a new entry for an ID that is not in the bin.
+    fn push_entry(&mut self, id: u64, count: u64) {
+        let i = self.entries.len() + 1;
+        let covered = self.prefix_sum(i - 1) - self.prefix_sum(i - (i & i.wrapping_neg()));
+        self.positions.insert(id, self.entries.len());
+        self.entries.push((id, count));
+        self.sums.push(covered + count);
+    }
+
+    /// Removes the entry at `pos` by swapping the last entry into its place.
+    fn swap_remove_entry(&mut self, pos: usize) -> (u64, u64) {
+        let last = self.entries.len() - 1;
+        if pos != last {
+            self.add_to_sums(pos, self.entries[last].1.wrapping_sub(self.entries[pos].1));
+        }
+        // Only the last slot covers the last position, so dropping it removes that count.
+        self.sums.pop();
+        let removed = self.entries.swap_remove(pos);
+        self.positions.remove(&removed.0);
+        if let Some(&(moved, _)) = self.entries.get(pos) {
+            self.positions.insert(moved, pos);
+        }
+        removed
+    }
+
+    /// Returns the position in `entries` holding the `copy`-th copy (0-based) of the bin.
+    fn position_of_copy(&self, mut copy: u64) -> usize {
+        debug_assert!(copy < self.total, "copy index out of range");
+        let mut pos = 0;
+        let mut step = if self.sums.is_empty() { 0 } else { 1 << self.sums.len().ilog2() };
+        while step > 0 {
+            let next = pos + step;
+            if next <= self.sums.len() && self.sums[next - 1] <= copy {
+                copy -= self.sums[next - 1];
+                pos = next;
+            }
+            step /= 2;
+        }
+        pos
+    }
+
+    fn decrement_at(&mut self, pos: usize) -> u64 {
+        let (id, count) = &mut self.entries[pos];
+        let id = *id;
+        *count -= 1;
+        let emptied = *count == 0;
+        self.add_to_sums(pos, 1u64.wrapping_neg());
+        if emptied {
+            self.swap_remove_entry(pos);
+        }
+        self.total -= 1;
+        id
+    }
+
+    /// Rebuilds a bin from its `(id, copies)` entries, or returns `None` if an ID repeats,
+    /// a count is zero or the counts overflow a `u64`.
+    #[cfg(feature = "serde")]
+    fn from_entries(entries: Vec<(u64, u64)>) -> Option<Self> {
+        let mut bin = CountedBin::default();
+        for (id, count) in entries {
+            if count == 0 || bin.positions.contains_key(&id) {
+                return None;
+            }
+            bin.total = bin.total.checked_add(count)?;
+            bin.push_entry(id, count);
+        }
+        Some(bin)
+    }
+}
+
+// Only the entries are serialized; the ID map and prefix sums are rebuilt from them.
+#[cfg(feature = "serde")]
+impl serde::Serialize for CountedBin {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        #[serde(rename = "CountedBin")]
+        struct Entries<'a> {
+            entries: &'a [(u64, u64)],
+        }
+        Entries { entries: &self.entries }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CountedBin {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "CountedBin")]
+        struct Entries {
+            entries: Vec<(u64, u64)>,
+        }
+        let Entries { entries } = Entries::deserialize(deserializer)?;
+        CountedBin::from_entries(entries).ok_or_else(|| serde::de::Error::custom("invalid counted bin entries"))
+    }
 }
 
-// Helper to create an array of Option<T>
-fn new_children_array<B: DigitBin>() -> Box<[Option<Node<B>>; 10]> {
-    // This is a standard way to initialize an array of non-Copy types.
-    let data: [Option<Node<B>>; 10] = Default::default();
-    Box::new(data)
+impl DigitBin for CountedBin {
+    fn insert(&mut self, id: u64) { self.insert_count(id, 1); }
+    fn insert_count(&mut self, id: u64, count: u64) -> u64 {
+        if count == 0 {
+            return 0;
+        }
+        self.total = self.total.checked_add(count).expect("the copies in a bin overflow a u64");
+        match self.positions.get(&id) {
+            Some(&pos) => {
+                self.entries[pos].1 += count;
+                self.add_to_sums(pos, count);
+            }
+            None => self.push_entry(id, count),
+        }
+        count
+    }
+    fn count_of(&self, id: u64) -> u64 {
+        self.positions.get(&id).map_or(0, |&pos| self.entries[pos].1)
+    }
+    fn insert_range(&mut self, ids: std::ops::Range<u64>) -> u64 {
+        let added = ids.end.saturating_sub(ids.start);
+        if self.entries.is_empty() {
+            // Fresh bin: the IDs are known to be distinct, so skip the duplicate lookups. Every
+            // count is one, so each prefix-sum slot holds the size of the range it covers.
+            self.positions.extend(ids.clone().zip(0..));
+            self.entries.extend(ids.map(|id| (id, 1)));
+            self.sums.extend((1..=self.entries.len()).map(|i| (i & i.wrapping_neg()) as u64));
+            self.total += added;
+        } else {
+            ids.for_each(|id| { self.insert_count(id, 1); });
+        }
+        added
+    }
+    type Ids<'a> = std::iter::FlatMap<
+        std::slice::Iter<'a, (u64, u64)>,
+        std::iter::RepeatN<u64>,
+        fn(&(u64, u64)) -> std::iter::RepeatN<u64>,
+    >;
+    fn ids(&self) -> Self::Ids<'_> {
+        self.entries.iter().flat_map(|&(id, count)| std::iter::repeat_n(id, count as usize))
+    }
+    fn remove(&mut self, id: u64) -> bool {
+        if let Some(&pos) = self.positions.get(&id) {
+            self.decrement_at(pos);
+            true
+        } else {
+            false
+        }
+    }
+    fn remove_all(&mut self, id: u64) -> u64 {
+        let Some(&pos) = self.positions.get(&id) else { return 0 };
+        let (_, count) = self.swap_remove_entry(pos);
+        self.total -= count;
+        count
+    }
+    fn len(&self) -> usize { self.total as usize }
+    fn is_empty(&self) -> bool { self.total == 0 }
+    fn nth_id(&self, position: u64) -> Option<u64> {
+        (position < self.total).then(|| self.entries[self.position_of_copy(position)].0)
+    }
+    fn nth_position_excluding(&self, mut n: u64, excluded: &RoaringTreemap) -> Option<u64> {
+        let mut before = 0;
+        for &(id, count) in &self.entries {
+            if !excluded.contains(id) {
+                if n < count {
+                    return Some(before + n);
+                }
+                n -= count;
+            }
+            before += count;
+        }
+        None
+    }
+    fn get_random(&self, rng: &mut impl rand::Rng) -> Option<u64> {
+        if self.is_empty() { None } else {
+            let pos = self.position_of_copy(rng.random_range(0..self.total));
+            Some(self.entries[pos].0)
+        }
+    }
+    fn get_random_and_remove(&mut self, rng: &mut impl rand::Rng) -> Option<u64> {
+        if self.is_empty() { None } else {
+            let pos = self.position_of_copy(rng.random_range(0..self.total));
+            Some(self.decrement_at(pos))
+        }
+    }
+    fn for_each_count(&self, mut f: impl FnMut(u64, u64)) {
+        for &(id, count) in &self.entries {
+            f(id, count);
+        }
+    }
+    fn merge(&mut self, other: Self) {
+        if self.is_empty() {
+            *self = other;
+        } else {
+            for (id, count) in other.entries {
+                self.insert_count(id, count);
+            }
+        }
+    }
+    fn heap_size(&self) -> usize {
+        self.entries.capacity() * std::mem::size_of::<(u64, u64)>()
+            + self.positions.capacity() * (std::mem::size_of::<(u64, usize)>() + 1)
+            + self.sums.capacity() * std::mem::size_of::<u64>()
+    }
+    fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        self.positions.shrink_to_fit();
+        self.sums.shrink_to_fit();
+    }
 }
 
 /// The content of a node, which is either more nodes or a leaf with individuals.
+///
+/// An internal node names its block of children in the index's node arena instead of
+/// owning them; walk the tree with [`DigitBinIndexGeneric::children`]. A bin sits behind
+/// an [`Arc`], shared between clones and copied on its first change.
 #[derive(Debug, Clone)]
 pub enum NodeContent<B: DigitBin> {
-    /// An internal node that contains children for the next digit (0-9).
-    DigitIndex(Box<[Option<Node<B>>; 10]>),
+    /// An internal node: the number of the block holding its children, one slot per
+    /// digit of the index's radix (ten for the default decimal tree).
+    DigitIndex(u32),
     /// A leaf node that contains a bin of IDs for individuals in this bin.
-    Bin(B),
+    Bin(Arc<B>),
 }
 
 /// A node within the DigitBinIndex tree.
@@ -121,16 +640,265 @@ pub struct Node<B: DigitBin> {
 }
 
 impl<B: DigitBin> Node<B> {
-    /// Creates a new, empty internal node.
-    fn new_internal() -> Self {
+    /// Creates a new, empty leaf node.
+    fn new_leaf() -> Self {
         Self {
-            content: NodeContent::DigitIndex(new_children_array()), 
+            content: NodeContent::Bin(Arc::default()),
             accumulated_value: 0u64,
             content_count: 0,
         }
     }
 }
 
+/// The blocks of children of a tree's internal nodes, stored back to back in one vector.
+///
+/// Block `b` holds slots `b * width..(b + 1) * width`, one per digit, so a descent
+/// indexes into a single allocation instead of following a pointer per level, and the
+/// siblings scanned for a draw target are contiguous.
+#[derive(Debug, Clone)]
+struct Arena<B: DigitBin> {
+    slots: Vec<Option<Node<B>>>,
+    width: usize,
+}
+
+impl<B: DigitBin> Arena<B> {
+    fn new(radix: u16) -> Self {
+        Arena { slots: Vec::new(), width: radix as usize }
+    }
+
+    /// The children in a block.
+    fn block(&self, block: u32) -> &[Option<Node<B>>] {
+        let start = block as usize * self.width;
+        &self.slots[start..start + self.width]
+    }
+
+    fn block_mut(&mut self, block: u32) -> &mut [Option<Node<B>>] {
+        let start = block as usize * self.width;
+        &mut self.slots[start..start + self.width]
+    }
+
+    /// The children of an internal node, or `None` for a bin.
+    fn children(&self, node: &Node<B>) -> Option<&[Option<Node<B>>]> {
+        match node.content {
+            NodeContent::DigitIndex(block) => Some(self.block(block)),
+            NodeContent::Bin(_) => None,
+        }
+    }
+
+    /// Appends an empty block. Blocks are not freed one by one: the arena is rebuilt by
+    /// `compact` and replaced whenever the tree is emptied.
+    fn alloc(&mut self) -> u32 {
+        let block = (self.slots.len() / self.width) as u32;
+        self.slots.resize_with(self.slots.len() + self.width, || None);
+        block
+    }
+
+    /// Creates a new, empty internal node.
+    fn new_internal(&mut self) -> Node<B> {
+        Node { content: NodeContent::DigitIndex(self.alloc()), accumulated_value: 0, content_count: 0 }
+    }
+
+    /// Takes a child out of its slot, so that it can be changed while the arena is
+    /// borrowed for the child's own children. [`put`](Self::put) returns it.
+    fn take(&mut self, block: u32, digit: usize) -> Option<Node<B>> {
+        self.block_mut(block)[digit].take()
+    }
+
+    fn put(&mut self, block: u32, digit: usize, child: Node<B>) {
+        self.block_mut(block)[digit] = Some(child);
+    }
+
+    /// Like `take`, but creates the child if the slot is empty: a bin if `leaf` is set,
+    /// an internal node otherwise.
+    fn take_or_new(&mut self, block: u32, digit: usize, leaf: bool) -> Node<B> {
+        match self.take(block, digit) {
+            Some(child) => child,
+            None if leaf => Node::new_leaf(),
+            None => self.new_internal(),
+        }
+    }
+
+    /// Runs `f` on a child and puts it back, or returns `None` if the slot is empty.
+    fn with_child<T>(&mut self, block: u32, digit: usize, f: impl FnOnce(&mut Self, &mut Node<B>) -> T) -> Option<T> {
+        let mut child = self.take(block, digit)?;
+        let result = f(self, &mut child);
+        self.put(block, digit, child);
+        Some(result)
+    }
+}
+
+/// How [`DigitBinIndex::merge_from`] resolves an incoming item whose ID is already indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConflictPolicy {
+    /// The incoming weight replaces the stored one.
+    Replace,
+    /// The stored weight is kept and the incoming weight is ignored.
+    Skip,
+    /// The incoming weight is added to the stored one.
+    Sum,
+}
+
+/// Summary of the changes applied by [`DigitBinIndex::merge_from`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MergeReport {
+    /// IDs that were not indexed before and have been added.
+    pub added: u64,
+    /// IDs that were moved to a new bin.
+    pub updated: u64,
+    /// IDs absent from the incoming data that have been removed.
+    pub removed: u64,
+    /// IDs whose bin did not change.
+    pub unchanged: u64,
+    /// Incoming IDs whose resulting weight is invalid (zero after scaling, or 1.0 and above),
+    /// which are not present in the index after the merge, or whose new bin would exceed the
+    /// memory budget, which keep their current placement or are not added.
+    pub rejected: u64,
+}
+
+/// How [`DigitBinIndex::merge_replica`] resolves an ID that both replicas changed differently.
+///
+/// A removed ID counts as having weight 0, so `Heavier` lets a reweight win over a
+/// removal and `Lighter` lets the removal win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReplicaPolicy {
+    /// Keep this index's version.
+    Ours,
+    /// Take the other replica's version.
+    Theirs,
+    /// Keep whichever version has the larger total weight.
+    Heavier,
+    /// Keep whichever version has the smaller total weight.
+    Lighter,
+}
+
+/// One ID that both replicas changed differently, and how it was resolved.
+/// Weights are the ID's total weight over all its copies, 0 if absent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplicaConflict {
+    pub id: u64,
+    pub base: f64,
+    pub ours: f64,
+    pub theirs: f64,
+    pub resolved: f64,
+}
+
+/// Summary of [`DigitBinIndex::merge_replica`]: the changes applied to this index, and
+/// every conflict that needed the policy.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplicaMergeReport {
+    pub applied: MergeReport,
+    pub conflicts: Vec<ReplicaConflict>,
+}
+
+/// Everything needed to verify one draw made by [`DigitBinIndex::select_audited`] after the fact.
+///
+/// Masses are in scaled units (weights multiplied by 10^precision) and count only the items
+/// outside the draw's exclusions. Given the index state before the draw, walking the bins in
+/// ascending weight order and subtracting each bin's eligible mass from `target` stops at the
+/// bin at `path`; `position` then identifies the member within the bin.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DrawAudit {
+    pub id: u64,
+    pub weight: f64,
+    /// Mass of the eligible items at the time of the draw.
+    pub total_mass: u64,
+    /// The uniform RNG target in `0..total_mass` that selected the bin.
+    pub target: u64,
+    /// The digits leading from the root to the chosen bin, in the index's radix.
+    pub path: Vec<u8>,
+    /// Mass of the chosen bin's eligible members at the time of the draw.
+    pub bin_mass: u64,
+    /// Number of eligible members in the chosen bin at the time of the draw.
+    pub bin_count: u64,
+    /// The position of the chosen item within the bin's member order, drawn uniformly
+    /// among the eligible members.
+    pub position: u64,
+    /// Whether the chosen item was removed from the index.
+    pub removed: bool,
+}
+
+/// The removals recorded since [`DigitBinIndex::mark`], as returned by [`DigitBinIndex::removed_since_mark`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemovedSinceMark {
+    /// The IDs of all removed items.
+    pub ids: RoaringTreemap,
+    /// The removed copies per bin, in ascending weight order.
+    pub bins: Vec<RemovedBin>,
+}
+
+/// The removals from one bin since the last mark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemovedBin {
+    pub weight: f64,
+    /// The number of removed copies.
+    pub count: u64,
+    /// The removed mass, `weight * count`.
+    pub mass: f64,
+}
+
+/// A batch draw computed by [`DigitBinIndex::plan_select_many`] but not yet applied.
+///
+/// The plan is plain data, so it can be reviewed, logged or persisted before being
+/// passed to [`DigitBinIndex::apply`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DrawPlan {
+    /// The precision of the index the plan was drawn from.
+    pub precision: u8,
+    /// The chosen items, in draw order.
+    pub draws: Vec<PlannedDraw>,
+}
+
+/// One chosen item of a [`DrawPlan`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlannedDraw {
+    pub id: u64,
+    /// The bin holding the item, as its weight scaled by 10^precision.
+    pub bin: u64,
+    pub weight: f64,
+}
+
+/// Structure and memory statistics of an index, as returned by [`DigitBinIndex::stats`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexStats {
+    pub precision: u8,
+    /// The branching base of the tree.
+    pub radix: u16,
+    /// The number of stored items, counting copies.
+    pub items: u64,
+    /// The number of nodes at each depth; entry 0 is the root, the last entry the bins.
+    /// With the default radix of 10 there are `precision + 1` entries.
+    pub nodes_per_depth: Vec<usize>,
+    /// The number of bins holding at least one item.
+    pub occupied_bins: usize,
+    /// The number of bins left empty by removals, still allocated.
+    pub empty_bins: usize,
+    /// The item count of the fullest bin.
+    pub largest_bin: u64,
+    /// Occupied bins by item count: entry `i` counts the bins holding `2^i` to `2^(i+1) - 1` items.
+    pub bin_size_histogram: Vec<usize>,
+    /// Estimated bytes used by the tree nodes.
+    pub tree_bytes: usize,
+    /// Estimated bytes used by the bin contents. For Roaring bitmaps this is their
+    /// serialized size, which closely tracks their in-memory size.
+    pub bin_bytes: usize,
+}
+
+impl IndexStats {
+    /// The estimated total memory footprint, `tree_bytes + bin_bytes`.
+    pub fn total_bytes(&self) -> usize {
+        self.tree_bytes + self.bin_bytes
+    }
+}
+
 /// A data structure that organizes weighted items into bins based on their
 /// decimal digits to enable fast weighted random selection and updates.
 ///
@@ -149,10 +917,12 @@ impl<B: DigitBin> Node<B> {
 /// let mut index = DigitBinIndex::with_precision_and_capacity(3, 100);
 /// ```
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DigitBinIndex {
     Small(DigitBinIndexGeneric<Vec<u32>>),
     Medium(DigitBinIndexGeneric<RoaringBitmap>),
     Large(DigitBinIndexGeneric<RoaringTreemap>),
+    Counted(DigitBinIndexGeneric<CountedBin>),
 }
 
 impl DigitBinIndex {
@@ -181,24 +951,127 @@ impl DigitBinIndex {
     /// // Uses Vec<u32> because capacity is small
     /// ```
     pub fn with_precision_and_capacity(precision: u8, capacity: u64) -> Self {
-        let max_bins = 10u64.pow(precision as u32);
-        if capacity / max_bins > 1_000_000_000 {
-            // Heuristic: Use RoaringTreemap if average bin size (capacity / 10^precision) exceeds threshold
-            DigitBinIndex::Large(DigitBinIndexGeneric::<RoaringTreemap>::with_precision(precision))
-        }
-        else if capacity / max_bins > 1_000 {
-            // Heuristic: Use RoaringBitmap if average bin size (capacity / 10^precision) exceeds threshold
-            DigitBinIndex::Medium(DigitBinIndexGeneric::<RoaringBitmap>::with_precision(precision))
-        } else {
-            // Heuristic: Use Vec<u32> for small average bin sizes
-            DigitBinIndex::Small(DigitBinIndexGeneric::<Vec<u32>>::with_precision(precision))
+        Self::with_config(Config::new().precision(precision).capacity(capacity))
+    }
+
+    /// Creates a new DigitBinIndex from a [`Config`].
+    ///
+    /// This is the general constructor: every other constructor is shorthand for a
+    /// particular configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The precision, backend, and other construction options.
+    ///
+    /// # Returns
+    ///
+    /// A new, empty `DigitBinIndex` set up as described by `config`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configured precision is 0 or greater than 9, or the radix is not
+    /// between 2 and 256.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{Config, DigitBinIndex};
+    ///
+    /// let index = DigitBinIndex::with_config(Config::new().precision(2).capacity(10_000_000));
+    /// assert!(matches!(index, DigitBinIndex::Medium(_)));
+    /// ```
+    pub fn with_config(config: Config) -> Self {
+        let (precision, radix) = (config.precision, config.radix);
+        let backend = match config.backend {
+            Backend::Auto { capacity } => {
+                let max_bins = 10u64.pow(precision as u32);
+                if capacity / max_bins > 1_000_000_000 {
+                    // Heuristic: Use RoaringTreemap if average bin size (capacity / 10^precision) exceeds threshold
+                    Backend::Large
+                } else if capacity / max_bins > 1_000 {
+                    // Heuristic: Use RoaringBitmap if average bin size (capacity / 10^precision) exceeds threshold
+                    Backend::Medium
+                } else {
+                    // Heuristic: Use Vec<u32> for small average bin sizes
+                    Backend::Small
+                }
+            }
+            backend => backend,
+        };
+        let mut index = match backend {
+            Backend::Medium => DigitBinIndex::Medium(DigitBinIndexGeneric::with_precision_and_radix(precision, radix)),
+            Backend::Large => DigitBinIndex::Large(DigitBinIndexGeneric::with_precision_and_radix(precision, radix)),
+            Backend::Counted => DigitBinIndex::Counted(DigitBinIndexGeneric::with_precision_and_radix(precision, radix)),
+            Backend::Small | Backend::Auto { .. } => DigitBinIndex::Small(DigitBinIndexGeneric::with_precision_and_radix(precision, radix)),
+        };
+        index.set_memory_budget(config.memory_budget);
+        if config.id_registry {
+            index.enable_id_registry();
+        }
+        if config.exact_weights {
+            index.enable_exact_weights();
+        }
+        index.set_tie_break(config.tie_break);
+        index.set_auto_repair(config.auto_repair);
+        index
+    }
+
+    /// Creates a DigitBinIndex from a histogram of weights and counts.
+    ///
+    /// Each `(weight, count)` entry is materialized as `count` items with synthetic,
+    /// contiguous IDs, inserted directly into the leaf bin with one traversal per entry.
+    /// IDs are assigned consecutively from 0 in histogram order, so the entry at position
+    /// `i` owns the IDs starting at the sum of all earlier counts. Entries with invalid
+    /// weights still reserve their ID range but add no items. The backend is chosen as in
+    /// [`with_precision_and_capacity`](Self::with_precision_and_capacity) from the total count,
+    /// except that `Large` is used whenever the IDs would not fit in a `u32`.
+    ///
+    /// # Arguments
+    ///
+    /// * `precision` - The number of decimal places for binning (1 to 9).
+    /// * `histogram` - A slice of `(weight, count)` pairs.
+    ///
+    /// # Returns
+    ///
+    /// A new `DigitBinIndex` holding every counted item.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `precision` is 0 or greater than 9, or if the counts add up to more than
+    /// `u64::MAX`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// // IDs 0..1000 get weight 0.1, IDs 1000..1500 get weight 0.2.
+    /// let index = DigitBinIndex::from_counts(3, &[(0.1, 1000), (0.2, 500)]);
+    /// assert_eq!(index.count(), 1500);
+    /// assert!((index.total_weight() - 200.0).abs() < 1e-9);
+    /// ```
+    pub fn from_counts(precision: u8, histogram: &[(f64, u64)]) -> Self {
+        let capacity = histogram
+            .iter()
+            .try_fold(0u64, |total, &(_, count)| total.checked_add(count))
+            .expect("the histogram counts overflow the u64 ID space");
+        if capacity > u32::MAX as u64 + 1 {
+            // The last IDs would not fit in the u32 bins of the other backends.
+            return DigitBinIndex::Large(DigitBinIndexGeneric::from_counts(precision, histogram));
+        }
+        match Self::with_precision_and_capacity(precision, capacity) {
+            DigitBinIndex::Small(_) => DigitBinIndex::Small(DigitBinIndexGeneric::from_counts(precision, histogram)),
+            DigitBinIndex::Medium(_) => DigitBinIndex::Medium(DigitBinIndexGeneric::from_counts(precision, histogram)),
+            DigitBinIndex::Large(_) => DigitBinIndex::Large(DigitBinIndexGeneric::from_counts(precision, histogram)),
+            DigitBinIndex::Counted(_) => DigitBinIndex::Counted(DigitBinIndexGeneric::from_counts(precision, histogram)),
         }
     }
 
     /// Creates a new DigitBinIndex with Vec<u32> bins and the specified precision.
     ///
     /// Optimized for small to medium-sized problems (average <= 1,000 items per bin).
-    /// Provides the fastest O(1) select_and_remove performance but truncates u64 IDs to u32.
+    /// Provides the fastest O(1) select_and_remove performance but only holds IDs up to
+    /// `u32::MAX`; wider IDs are refused, never truncated.
     ///
     /// # Arguments
     ///
@@ -227,7 +1100,8 @@ impl DigitBinIndex {
     /// Creates a new DigitBinIndex with RoaringBitmap bins and the specified precision.
     ///
     /// Optimized for large-scale problems (average > 1,000 items per bin) where IDs fit within u32.
-    /// Provides excellent memory compression and fast set operations but truncates u64 IDs to u32.
+    /// Provides excellent memory compression and fast set operations but only holds IDs up
+    /// to `u32::MAX`; wider IDs are refused, never truncated.
     ///
     /// # Arguments
     ///
@@ -282,15 +1156,47 @@ impl DigitBinIndex {
         DigitBinIndex::Large(DigitBinIndexGeneric::<RoaringTreemap>::with_precision(precision))
     }
 
-    /// Creates a new `DigitBinIndex` instance with the default precision.
+    /// Creates a new DigitBinIndex with CountedBin bins and the specified precision.
     ///
-    /// The default precision is set to 3 decimal places, which provides a good balance
-    /// between accuracy and performance for most use cases. For custom precision, use
-    /// [`with_precision`](Self::with_precision).
+    /// Designed for populations made of large cohorts of identical individuals. Each bin
+    /// stores a count per ID rather than one entry per individual, so an ID added with
+    /// [`add_with_count`](Self::add_with_count) represents that many copies at the memory
+    /// cost of one. Selection with removal decrements the count of the chosen ID.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A new `DigitBinIndex` instance.
+    /// * `precision` - The number of decimal places for binning (1 to 9).
+    ///
+    /// # Returns
+    ///
+    /// A new `DigitBinIndex` instance with CountedBin bins.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `precision` is 0 or greater than 9.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::counted(3);
+    /// index.add_with_count(1, 0.5, 1_000_000);
+    /// assert_eq!(index.count(), 1_000_000);
+    /// ```
+    pub fn counted(precision: u8) -> Self {
+        DigitBinIndex::Counted(DigitBinIndexGeneric::<CountedBin>::with_precision(precision))
+    }
+
+    /// Creates a new `DigitBinIndex` instance with the default precision.
+    ///
+    /// The default precision is set to 3 decimal places, which provides a good balance
+    /// between accuracy and performance for most use cases. For custom precision, use
+    /// [`with_precision`](Self::with_precision).
+    ///
+    /// # Returns
+    ///
+    /// A new `DigitBinIndex` instance.
     ///
     /// # Examples
     ///
@@ -301,7 +1207,7 @@ impl DigitBinIndex {
     /// assert_eq!(index.precision(), 3);
     /// ```    
     pub fn new() -> Self {
-        DigitBinIndex::Small(DigitBinIndexGeneric::<Vec<u32>>::new())
+        Self::with_config(Config::new())
     }
 
     /// Creates a new `DigitBinIndex` instance with the specified precision.
@@ -331,22 +1237,73 @@ impl DigitBinIndex {
     /// assert_eq!(index.precision(), 4);
     /// ```
     pub fn with_precision(precision: u8) -> Self {
-        DigitBinIndex::Small(DigitBinIndexGeneric::<Vec<u32>>::with_precision(precision))
+        Self::with_config(Config::new().precision(precision))
+    }
+
+    /// Creates a new DigitBinIndex from a [`Config`], returning an error instead of panicking.
+    ///
+    /// # Returns
+    ///
+    /// The index, or [`DigitBinIndexError::PrecisionOutOfRange`] if the configured precision
+    /// is 0 or greater than 9, or [`DigitBinIndexError::RadixOutOfRange`] if the radix is not
+    /// between 2 and 256.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{Config, DigitBinIndex, DigitBinIndexError};
+    ///
+    /// assert!(DigitBinIndex::try_with_config(Config::new().precision(9)).is_ok());
+    /// assert_eq!(
+    ///     DigitBinIndex::try_with_config(Config::new().precision(12)).unwrap_err(),
+    ///     DigitBinIndexError::PrecisionOutOfRange { precision: 12 }
+    /// );
+    /// assert_eq!(
+    ///     DigitBinIndex::try_with_config(Config::new().radix(1)).unwrap_err(),
+    ///     DigitBinIndexError::RadixOutOfRange { radix: 1 }
+    /// );
+    /// ```
+    pub fn try_with_config(config: Config) -> Result<Self, DigitBinIndexError> {
+        if !(1..=MAX_PRECISION as u8).contains(&config.precision) {
+            return Err(DigitBinIndexError::PrecisionOutOfRange { precision: config.precision });
+        }
+        if !(MIN_RADIX..=MAX_RADIX).contains(&config.radix) {
+            return Err(DigitBinIndexError::RadixOutOfRange { radix: config.radix });
+        }
+        Ok(Self::with_config(config))
+    }
+
+    /// Creates a new DigitBinIndex with the given precision, returning an error instead of
+    /// panicking. See [`try_with_config`](Self::try_with_config).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// assert_eq!(DigitBinIndex::try_with_precision(4).unwrap().precision(), 4);
+    /// assert!(DigitBinIndex::try_with_precision(0).is_err());
+    /// ```
+    pub fn try_with_precision(precision: u8) -> Result<Self, DigitBinIndexError> {
+        Self::try_with_config(Config::new().precision(precision))
     }
 
     /// Adds an item with the given ID and weight to the index.
     ///
     /// The weight is rescaled to the index's precision and binned accordingly.
     /// If the weight is non-positive or becomes zero after scaling, the item is not added.
+    /// Neither is an ID above `u32::MAX` in the `Small` and `Medium` backends; use
+    /// [`try_add`](Self::try_add) to learn why an item was skipped.
     ///
     /// # Arguments
     ///
-    /// * `individual_id` - The unique ID of the item to add (u32).
+    /// * `id` - The unique ID of the item to add.
     /// * `weight` - The positive weight (probability) of the item.
     ///
     /// # Returns
     ///
-    /// `true` if the item was successfully added, `false` otherwise (e.g., invalid weight).
+    /// `true` if the item was added, `false` otherwise (an invalid weight, an ID the
+    /// backend cannot hold, or an ID already in its bin in a set-based backend).
     ///
     /// # Examples
     ///
@@ -354,270 +1311,424 @@ impl DigitBinIndex {
     /// use digit_bin_index::DigitBinIndex;
     ///
     /// let mut index = DigitBinIndex::new();
-    /// let added = index.add(1, 0.5);
+    /// assert!(index.add(1, 0.5));
+    /// assert!(!index.add(1 << 40, 0.5));
     /// assert_eq!(index.count(), 1);
-    /// ```    
-    pub fn add(&mut self, id: u64, weight: f64) {
+    /// ```
+    pub fn add(&mut self, id: u64, weight: f64) -> bool {
         match self {
             DigitBinIndex::Small(index) => index.add(id, weight),
             DigitBinIndex::Medium(index) => index.add(id, weight),
             DigitBinIndex::Large(index) => index.add(id, weight),
+            DigitBinIndex::Counted(index) => index.add(id, weight),
         }
     }
 
-    /// Adds multiple items to the index in a highly optimized batch operation.
-    ///
-    /// This method is significantly faster than calling `add` in a loop for large
-    /// collections of items. It works by pre-processing the input, grouping items
-    /// by their shared weight, and then propagating each group through the tree in
-    /// a single pass. This minimizes cache misses and reduces function call overhead.
+    /// Adds `count` identical copies of an item with the given ID and weight.
     ///
-    /// Weights are rescaled to the index's precision and binned accordingly.
-    /// Items with non-positive weights or weights that become zero after scaling
-    /// will be ignored.
+    /// With the `Counted` backend the copies are stored as a single count, and each
+    /// selection with removal decrements it. The `Small` backend stores the copies as
+    /// repeated entries, so adding a cohort there takes memory and time proportional to
+    /// `count`, four bytes per copy; use the `Counted` backend for large cohorts. The
+    /// set-based `Medium` and `Large` backends store at most one copy of each ID.
     ///
     /// # Arguments
     ///
-    /// * `items` - A slice of `(id, weight)` tuples to add to the index.
+    /// * `id` - The ID of the cohort to add.
+    /// * `weight` - The weight (probability) of each individual copy.
+    /// * `count` - The number of copies to add.
+    ///
+    /// # Returns
+    ///
+    /// The number of copies actually stored (0 if the weight is invalid or the ID does
+    /// not fit the backend).
     ///
     /// # Examples
     ///
     /// ```
     /// use digit_bin_index::DigitBinIndex;
     ///
-    /// let mut index = DigitBinIndex::new();
-    /// let items_to_add = vec![(1, 0.1), (2, 0.2), (3, 0.1)];
-    /// index.add_many(&items_to_add);
-    ///
-    /// assert_eq!(index.count(), 3);
-    /// // The total weight should be 0.1 + 0.2 + 0.1 = 0.4
-    /// assert!((index.total_weight() - 0.4).abs() < f64::EPSILON);
+    /// let mut index = DigitBinIndex::counted(3);
+    /// assert_eq!(index.add_with_count(7, 0.25, 4), 4);
+    /// assert_eq!(index.count(), 4);
+    /// assert_eq!(index.total_weight(), 1.0);
     /// ```
-    pub fn add_many(&mut self, items: &[(u64, f64)]) {
+    pub fn add_with_count(&mut self, id: u64, weight: f64, count: u64) -> u64 {
         match self {
-            DigitBinIndex::Small(index) => index.add_many(items),
-            DigitBinIndex::Medium(index) => index.add_many(items),
-            DigitBinIndex::Large(index) => index.add_many(items),
+            DigitBinIndex::Small(index) => index.add_with_count(id, weight, count),
+            DigitBinIndex::Medium(index) => index.add_with_count(id, weight, count),
+            DigitBinIndex::Large(index) => index.add_with_count(id, weight, count),
+            DigitBinIndex::Counted(index) => index.add_with_count(id, weight, count),
         }
     }
 
-    /// Removes an item with the given ID and weight from the index.
+    /// Adds every ID in a contiguous range with the same weight.
     ///
-    /// The weight must match the one used during addition (after rescaling).
-    /// If the item is not found in the corresponding bin, no removal occurs.
+    /// The whole range is inserted into its bin with a single traversal and a single
+    /// accumulation update per node, using `insert_range` on the Roaring backends, so a
+    /// cohort of millions costs one O(P) operation instead of millions of adds.
     ///
     /// # Arguments
     ///
-    /// * `individual_id` - The ID of the item to remove.
-    /// * `weight` - The weight of the item (must match the added weight).
+    /// * `ids` - The half-open range of IDs to add.
+    /// * `weight` - The weight (probability) shared by every ID in the range.
+    ///
+    /// # Returns
+    ///
+    /// The number of items actually stored (0 if the weight is invalid, or if the range
+    /// reaches past `u32::MAX` in the `Small` and `Medium` backends). Set-based backends
+    /// do not store IDs that are already present in the bin.
     ///
     /// # Examples
     ///
     /// ```
     /// use digit_bin_index::DigitBinIndex;
     ///
-    /// let mut index = DigitBinIndex::new();
-    /// index.add(1, 0.5);
-    /// index.remove(1, 0.5);
-    /// assert_eq!(index.count(), 0);
+    /// let mut index = DigitBinIndex::medium(3);
+    /// assert_eq!(index.add_range(1_000..1_000_000, 0.25), 999_000);
+    /// assert_eq!(index.count(), 999_000);
+    /// assert!(index.remove(5_000, 0.25));
     /// ```
-    pub fn remove(&mut self, id: u64, weight: f64) -> bool {
+    pub fn add_range(&mut self, ids: std::ops::Range<u64>, weight: f64) -> u64 {
         match self {
-            DigitBinIndex::Small(index) => index.remove(id, weight),
-            DigitBinIndex::Medium(index) => index.remove(id, weight),
-            DigitBinIndex::Large(index) => index.remove(id, weight),
+            DigitBinIndex::Small(index) => index.add_range(ids, weight),
+            DigitBinIndex::Medium(index) => index.add_range(ids, weight),
+            DigitBinIndex::Large(index) => index.add_range(ids, weight),
+            DigitBinIndex::Counted(index) => index.add_range(ids, weight),
         }
     }
 
-    /// Removes multiple items from the index in a highly optimized batch operation.
+    /// Adds an item, returning an error when it cannot be stored.
     ///
-    /// This method is significantly faster than calling `remove` in a loop. It
-    /// groups the items to be removed by their weight path and traverses the tree
-    /// only once per group, performing aggregated updates on the way up.
+    /// Behaves like [`add`](Self::add), but reports why an item was not added where
+    /// plain `add` skips it silently:
     ///
-    /// The `(id, weight)` pairs must match items that are currently in the index.
-    /// If a given pair is not found, it is silently ignored.
+    /// * [`InvalidWeight`](DigitBinIndexError::InvalidWeight) if the weight is not in `(0, 1)`,
+    /// * [`WeightBelowResolution`](DigitBinIndexError::WeightBelowResolution) if it truncates
+    ///   to zero at the index's precision,
+    /// * [`IdOutOfRange`](DigitBinIndexError::IdOutOfRange) if the ID does not fit in the
+    ///   `u32` IDs of the `Small` and `Medium` backends (where `add` panics instead),
+    /// * [`DuplicateId`](DigitBinIndexError::DuplicateId) if the `Medium` or `Large` backend
+    ///   already holds the ID in that bin (the other backends store repeated copies),
+    /// * [`MemoryBudgetExceeded`](DigitBinIndexError::MemoryBudgetExceeded) if the insertion
+    ///   would need new tree nodes beyond the budget set with
+    ///   [`set_memory_budget`](Self::set_memory_budget).
+    ///
+    /// Nothing is added when an error is returned.
     ///
     /// # Arguments
     ///
-    /// * `items` - A slice of `(id, weight)` tuples to remove from the index.
+    /// * `id` - The ID of the item to add.
+    /// * `weight` - The weight (probability) of the item.
     ///
     /// # Examples
     ///
     /// ```
-    /// use digit_bin_index::DigitBinIndex;
+    /// use digit_bin_index::{DigitBinIndex, DigitBinIndexError};
     ///
-    /// let mut index = DigitBinIndex::new();
-    /// let items_to_add = vec![(1, 0.1), (2, 0.2), (3, 0.1), (4, 0.3)];
-    /// index.add_many(&items_to_add);
-    /// assert_eq!(index.count(), 4);
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// index.add(1, 0.5);
+    /// index.set_memory_budget(Some(index.memory_usage()));
     ///
-    /// let items_to_remove = vec![(2, 0.2), (3, 0.1)];
-    /// index.remove_many(&items_to_remove);
+    /// // Same bin: no new nodes are needed.
+    /// assert!(index.try_add(2, 0.5).is_ok());
+    /// // A new bin would grow the tree past the budget.
+    /// assert!(matches!(index.try_add(3, 0.25), Err(DigitBinIndexError::MemoryBudgetExceeded { .. })));
+    /// assert_eq!(index.count(), 2);
     ///
-    /// assert_eq!(index.count(), 2); // Items 1 and 4 should remain
-    /// // The total weight should be 0.1 + 0.3 = 0.4
-    /// assert!((index.total_weight() - 0.4).abs() < f64::EPSILON);
+    /// assert_eq!(index.try_add(4, 1.5), Err(DigitBinIndexError::InvalidWeight { weight: 1.5 }));
+    /// assert_eq!(index.try_add(4, 0.0001), Err(DigitBinIndexError::WeightBelowResolution { weight: 0.0001, precision: 3 }));
     /// ```
-    pub fn remove_many(&mut self, items: &[(u64, f64)]) -> bool {
+    pub fn try_add(&mut self, id: u64, weight: f64) -> Result<(), DigitBinIndexError> {
         match self {
-            DigitBinIndex::Small(index) => index.remove_many(items),
-            DigitBinIndex::Medium(index) => index.remove_many(items),
-            DigitBinIndex::Large(index) => index.remove_many(items),
+            DigitBinIndex::Small(index) => index.try_add(id, weight),
+            DigitBinIndex::Medium(index) => index.try_add(id, weight),
+            DigitBinIndex::Large(index) => index.try_add(id, weight),
+            DigitBinIndex::Counted(index) => index.try_add(id, weight),
         }
-    }    
+    }
 
-    /// Selects a single item randomly based on weights without removal.
+    /// Sets or clears a cap on the memory used by the tree structure.
     ///
-    /// Performs weighted random selection. Returns `None` if the index is empty.
+    /// The budget covers the tree nodes, the part of the index that grows with the
+    /// precision rather than with the number of items. Once set, insertions that need new
+    /// nodes beyond the budget fail: [`try_add`](Self::try_add) returns an error, while
+    /// `add`, `add_many`, `add_range` and `merge_from` skip the affected items. Insertions
+    /// into existing bins always succeed. Use [`coarsen_to`](Self::coarsen_to) to free room.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// An `Option` containing the selected item's ID and its (rescaled) weight.
+    /// * `max_bytes` - The budget in bytes, or `None` for no limit.
     ///
     /// # Examples
     ///
     /// ```
     /// use digit_bin_index::DigitBinIndex;
     ///
-    /// let mut index = DigitBinIndex::new();
-    /// index.add(1, 0.5);
-    /// if let Some((id, weight)) = index.select() {
-    ///     assert_eq!(id, 1);
-    ///     assert_eq!(weight, 0.5);
+    /// let mut index = DigitBinIndex::with_precision(5);
+    /// index.set_memory_budget(Some(64 * 1024));
+    /// for id in 0..100_000 {
+    ///     index.add(id, (id % 99_999 + 1) as f64 / 100_000.0);
     /// }
+    /// assert!(index.memory_usage() <= 64 * 1024);
+    /// assert!(index.count() < 100_000);
     /// ```
-    pub fn select(&mut self) -> Option<(u64, f64)> {
+    pub fn set_memory_budget(&mut self, max_bytes: Option<usize>) {
         match self {
-            DigitBinIndex::Small(index) => index.select(),
-            DigitBinIndex::Medium(index) => index.select(),
-            DigitBinIndex::Large(index) => index.select(),
+            DigitBinIndex::Small(index) => index.set_memory_budget(max_bytes),
+            DigitBinIndex::Medium(index) => index.set_memory_budget(max_bytes),
+            DigitBinIndex::Large(index) => index.set_memory_budget(max_bytes),
+            DigitBinIndex::Counted(index) => index.set_memory_budget(max_bytes),
         }
     }
 
-    /// Selects a single item randomly and removes it from the index.
-    ///
-    /// Combines selection and removal in one operation. Returns `None` if empty.
+    /// Returns the memory budget set with [`set_memory_budget`](Self::set_memory_budget), if any.
+    pub fn memory_budget(&self) -> Option<usize> {
+        match self {
+            DigitBinIndex::Small(index) => index.memory_budget(),
+            DigitBinIndex::Medium(index) => index.memory_budget(),
+            DigitBinIndex::Large(index) => index.memory_budget(),
+            DigitBinIndex::Counted(index) => index.memory_budget(),
+        }
+    }
+
+    /// Sets which child a draw descends into when its target lies exactly on the boundary
+    /// between two children.
+    ///
+    /// A draw picks a target in the total mass (in units of `10^-precision`) and descends
+    /// into the child whose share of the mass holds it. A target equal to the end of one
+    /// child's share is also the start of the next one's; `tie_break` decides which of
+    /// the two owns it. This applies to [`select`](Self::select),
+    /// [`select_and_remove`](Self::select_and_remove), [`select_at`](Self::select_at),
+    /// [`select_many_with_replacement`](Self::select_many_with_replacement) and their
+    /// `_with_rng` variants. Batch draws without replacement always use
+    /// [`TieBreak::LastChild`].
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// An `Option` containing the selected item's ID and weight.
+    /// * `tie_break` - The boundary rule. The default is [`TieBreak::LastChild`].
     ///
     /// # Examples
     ///
     /// ```
-    /// use digit_bin_index::DigitBinIndex;
-    ///
-    /// let mut index = DigitBinIndex::new();
-    /// index.add(1, 0.5);
-    /// if let Some((id, _)) = index.select_and_remove() {
-    ///     assert_eq!(id, 1);
-    /// }
-    /// assert_eq!(index.count(), 0);
+    /// use digit_bin_index::{DigitBinIndex, TieBreak};
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let mut index = DigitBinIndex::with_precision(1);
+    /// index.add(1, 0.2);
+    /// index.add(2, 0.3);
+    /// let mut rng = StdRng::seed_from_u64(7);
+    /// // Target 2 is where ID 1's share [0, 2) ends and ID 2's share [2, 5) starts.
+    /// assert_eq!(index.select_at(2, &mut rng), Some((2, 0.3)));
+    ///
+    /// index.set_tie_break(TieBreak::FirstChild); // shares are now (0, 2] and (2, 5]
+    /// assert_eq!(index.select_at(2, &mut rng), Some((1, 0.2)));
     /// ```
-    pub fn select_and_remove(&mut self) -> Option<(u64, f64)> {
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
         match self {
-            DigitBinIndex::Small(index) => index.select_and_remove(),
-            DigitBinIndex::Medium(index) => index.select_and_remove(),
-            DigitBinIndex::Large(index) => index.select_and_remove(),
+            DigitBinIndex::Small(index) => index.set_tie_break(tie_break),
+            DigitBinIndex::Medium(index) => index.set_tie_break(tie_break),
+            DigitBinIndex::Large(index) => index.set_tie_break(tie_break),
+            DigitBinIndex::Counted(index) => index.set_tie_break(tie_break),
         }
     }
 
-    /// Selects multiple unique items randomly based on weights without removal.
-    ///
-    /// Uses rejection sampling to ensure uniqueness. Returns `None` if `num_to_draw`
-    /// exceeds the number of items in the index.
-    ///
-    /// # Arguments
+    /// Returns the boundary rule set with [`set_tie_break`](Self::set_tie_break).
+    pub fn tie_break(&self) -> TieBreak {
+        match self {
+            DigitBinIndex::Small(index) => index.tie_break(),
+            DigitBinIndex::Medium(index) => index.tie_break(),
+            DigitBinIndex::Large(index) => index.tie_break(),
+            DigitBinIndex::Counted(index) => index.tie_break(),
+        }
+    }
+
+    /// Checks that the item count and mass stored in every node of the tree add up from
+    /// the node's contents.
     ///
-    /// * `num_to_draw` - The number of unique items to select.
+    /// Counts and masses are exact integers kept up to date by every operation, so this
+    /// only fails if the tree was modified directly, through the public `root` field of the
+    /// backend index. An inconsistent tree makes draws come back empty (or biased), so
+    /// long-running simulations can call this periodically, or draw with
+    /// [`try_select`](Self::try_select), to fail loudly instead.
     ///
     /// # Returns
     ///
-    /// An `Option` containing a vector of selected (ID, weight) pairs.
+    /// [`DigitBinIndexError::InconsistentMass`] for the first inconsistent node, from the
+    /// root down.
     ///
     /// # Examples
     ///
     /// ```
-    /// use digit_bin_index::DigitBinIndex;
+    /// use digit_bin_index::{DigitBinIndex, DigitBinIndexError};
     ///
-    /// let mut index = DigitBinIndex::new();
-    /// index.add(1, 0.3);
-    /// index.add(2, 0.7);
-    /// if let Some(selected) = index.select_many(2) {
-    ///     assert_eq!(selected.len(), 2);
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_many(&[(1, 0.25), (2, 0.5)]);
+    /// assert_eq!(index.check_mass(), Ok(()));
+    ///
+    /// if let DigitBinIndex::Small(inner) = &mut index {
+    ///     inner.root.accumulated_value = 0;
     /// }
+    /// assert_eq!(
+    ///     index.check_mass(),
+    ///     Err(DigitBinIndexError::InconsistentMass { stored_count: 2, stored_mass: 0, actual_count: 2, actual_mass: 75 })
+    /// );
+    /// assert!(index.repair_mass());
+    /// assert_eq!(index.total_weight(), 0.75);
     /// ```
-    pub fn select_many(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+    pub fn check_mass(&self) -> Result<(), DigitBinIndexError> {
         match self {
-            DigitBinIndex::Small(index) => index.select_many(num_to_draw),
-            DigitBinIndex::Medium(index) => index.select_many(num_to_draw),
-            DigitBinIndex::Large(index) => index.select_many(num_to_draw),
+            DigitBinIndex::Small(index) => index.check_mass(),
+            DigitBinIndex::Medium(index) => index.check_mass(),
+            DigitBinIndex::Large(index) => index.check_mass(),
+            DigitBinIndex::Counted(index) => index.check_mass(),
         }
     }
 
-    /// Selects multiple unique items randomly and removes them from the index.
+    /// Recomputes the item count and mass of every node from the bins, which are taken
+    /// as the truth.
     ///
-    /// Selects and removes in batch. Returns `None` if `num_to_draw` exceeds item count.
+    /// # Returns
     ///
-    /// # Arguments
+    /// `true` if any node was wrong.
+    pub fn repair_mass(&mut self) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.repair_mass(),
+            DigitBinIndex::Medium(index) => index.repair_mass(),
+            DigitBinIndex::Large(index) => index.repair_mass(),
+            DigitBinIndex::Counted(index) => index.repair_mass(),
+        }
+    }
+
+    /// Sets whether [`try_select`](Self::try_select) and
+    /// [`try_select_and_remove`](Self::try_select_and_remove) repair an inconsistent tree
+    /// with [`repair_mass`](Self::repair_mass) and draw again, instead of returning the error.
+    pub fn set_auto_repair(&mut self, enabled: bool) {
+        match self {
+            DigitBinIndex::Small(index) => index.set_auto_repair(enabled),
+            DigitBinIndex::Medium(index) => index.set_auto_repair(enabled),
+            DigitBinIndex::Large(index) => index.set_auto_repair(enabled),
+            DigitBinIndex::Counted(index) => index.set_auto_repair(enabled),
+        }
+    }
+
+    /// Returns whether automatic mass repair is on.
+    pub fn auto_repair(&self) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.auto_repair(),
+            DigitBinIndex::Medium(index) => index.auto_repair(),
+            DigitBinIndex::Large(index) => index.auto_repair(),
+            DigitBinIndex::Counted(index) => index.auto_repair(),
+        }
+    }
+
+    /// Selects a single item without removal, reporting an inconsistent tree as an error.
     ///
-    /// * `num_to_draw` - The number of unique items to select and remove.
+    /// [`select`](Self::select) never panics on an inconsistent tree, but it cannot tell
+    /// an empty index from a broken one: both return `None`. This returns `Ok(None)` only
+    /// for an empty index. When the draw fails on an index that holds items, the tree is
+    /// checked with [`check_mass`](Self::check_mass), and either the error is returned or,
+    /// with [auto repair](Self::set_auto_repair) on, the tree is repaired and the draw
+    /// retried.
     ///
     /// # Returns
     ///
-    /// An `Option` containing a vector of selected (ID, weight) pairs.
+    /// The selected (ID, weight), `None` if the index is empty, or
+    /// [`DigitBinIndexError::InconsistentMass`].
     ///
     /// # Examples
     ///
     /// ```
-    /// use digit_bin_index::DigitBinIndex;
+    /// use digit_bin_index::{Config, DigitBinIndex};
     ///
-    /// let mut index = DigitBinIndex::new();
-    /// index.add(1, 0.3);
-    /// index.add(2, 0.7);
-    /// if let Some(selected) = index.select_many_and_remove(2) {
-    ///     assert_eq!(selected.len(), 2);
+    /// let mut index = DigitBinIndex::with_config(Config::new().precision(2).auto_repair(true));
+    /// index.add(1, 0.25);
+    /// if let DigitBinIndex::Small(inner) = &mut index {
+    ///     inner.root.accumulated_value = 0;
     /// }
-    /// assert_eq!(index.count(), 0);
+    /// assert_eq!(index.select(), None);
+    /// assert_eq!(index.try_select(), Ok(Some((1, 0.25))));
+    /// assert_eq!(index.check_mass(), Ok(()));
     /// ```
-    pub fn select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+    pub fn try_select(&mut self) -> Result<Option<(u64, f64)>, DigitBinIndexError> {
+        let rng = &mut WyRand::from_os_rng();
         match self {
-            DigitBinIndex::Small(index) => index.select_many_and_remove(num_to_draw),
-            DigitBinIndex::Medium(index) => index.select_many_and_remove(num_to_draw),
-            DigitBinIndex::Large(index) => index.select_many_and_remove(num_to_draw),
+            DigitBinIndex::Small(index) => index.try_select_and_optionally_remove_with_rng(false, rng),
+            DigitBinIndex::Medium(index) => index.try_select_and_optionally_remove_with_rng(false, rng),
+            DigitBinIndex::Large(index) => index.try_select_and_optionally_remove_with_rng(false, rng),
+            DigitBinIndex::Counted(index) => index.try_select_and_optionally_remove_with_rng(false, rng),
         }
     }
 
-    /// Returns the total number of items currently in the index.
+    /// Like [`try_select`](Self::try_select), but removes the selected item.
+    pub fn try_select_and_remove(&mut self) -> Result<Option<(u64, f64)>, DigitBinIndexError> {
+        let rng = &mut WyRand::from_os_rng();
+        match self {
+            DigitBinIndex::Small(index) => index.try_select_and_optionally_remove_with_rng(true, rng),
+            DigitBinIndex::Medium(index) => index.try_select_and_optionally_remove_with_rng(true, rng),
+            DigitBinIndex::Large(index) => index.try_select_and_optionally_remove_with_rng(true, rng),
+            DigitBinIndex::Counted(index) => index.try_select_and_optionally_remove_with_rng(true, rng),
+        }
+    }
+
+    /// Returns the estimated memory, in bytes, used by the tree structure.
     ///
-    /// # Returns
+    /// This is the quantity limited by the memory budget. It does not include the IDs
+    /// stored in the bins.
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            DigitBinIndex::Small(index) => index.memory_usage(),
+            DigitBinIndex::Medium(index) => index.memory_usage(),
+            DigitBinIndex::Large(index) => index.memory_usage(),
+            DigitBinIndex::Counted(index) => index.memory_usage(),
+        }
+    }
+
+    /// Returns a stable 128-bit hash of the index's logical contents.
     ///
-    /// The count of items as a `u32`.
+    /// The hash covers the precision, the non-empty bins, and the IDs (with their number
+    /// of copies) in each bin. It does not depend on the backend, on insertion order, or on
+    /// any internal layout, and it is the same on every platform and in every process, so
+    /// it can be compared across machines for consensus checks or used as a cache key.
     ///
     /// # Examples
     ///
     /// ```
     /// use digit_bin_index::DigitBinIndex;
     ///
-    /// let mut index = DigitBinIndex::new();
-    /// assert_eq!(index.count(), 0);
+    /// let mut a = DigitBinIndex::small(3);
+    /// a.add_many(&[(1, 0.25), (2, 0.5)]);
+    /// let mut b = DigitBinIndex::medium(3);
+    /// b.add(2, 0.5);
+    /// b.add(1, 0.25);
+    /// assert_eq!(a.state_hash(), b.state_hash());
+    ///
+    /// b.remove(1, 0.25);
+    /// assert_ne!(a.state_hash(), b.state_hash());
     /// ```
-    pub fn count(&self) -> u64 {
+    pub fn state_hash(&self) -> u128 {
         match self {
-            DigitBinIndex::Small(index) => index.count(),
-            DigitBinIndex::Medium(index) => index.count(),
-            DigitBinIndex::Large(index) => index.count(),
+            DigitBinIndex::Small(index) => index.state_hash(),
+            DigitBinIndex::Medium(index) => index.state_hash(),
+            DigitBinIndex::Large(index) => index.state_hash(),
+            DigitBinIndex::Counted(index) => index.state_hash(),
         }
     }
 
-    /// Returns the sum of all weights in the index.
+    /// Adds multiple items to the index in a highly optimized batch operation.
     ///
-    /// This represents the total accumulated probability mass.
+    /// This method is significantly faster than calling `add` in a loop for large
+    /// collections of items. It works by pre-processing the input, grouping items
+    /// by their shared weight, and then propagating each group through the tree in
+    /// a single pass. This minimizes cache misses and reduces function call overhead.
     ///
-    /// # Returns
+    /// Weights are rescaled to the index's precision and binned accordingly.
+    /// Items with non-positive weights or weights that become zero after scaling
+    /// will be ignored, as will IDs above `u32::MAX` in the `Small` and `Medium` backends.
     ///
-    /// The total weight as a `f64`.
+    /// # Arguments
+    ///
+    /// * `items` - A slice of `(id, weight)` tuples to add to the index.
     ///
     /// # Examples
     ///
@@ -625,25 +1736,2066 @@ impl DigitBinIndex {
     /// use digit_bin_index::DigitBinIndex;
     ///
     /// let mut index = DigitBinIndex::new();
-    /// index.add(1, 0.5);
-    /// assert_eq!(index.total_weight(), 0.5);
+    /// let items_to_add = vec![(1, 0.1), (2, 0.2), (3, 0.1)];
+    /// index.add_many(&items_to_add);
+    ///
+    /// assert_eq!(index.count(), 3);
+    /// // The total weight should be 0.1 + 0.2 + 0.1 = 0.4
+    /// assert!((index.total_weight() - 0.4).abs() < f64::EPSILON);
     /// ```
-    pub fn total_weight(&self) -> f64 {
+    pub fn add_many(&mut self, items: &[(u64, f64)]) {
         match self {
-            DigitBinIndex::Small(index) => index.total_weight(),
-            DigitBinIndex::Medium(index) => index.total_weight(),
-            DigitBinIndex::Large(index) => index.total_weight(),
+            DigitBinIndex::Small(index) => index.add_many(items),
+            DigitBinIndex::Medium(index) => index.add_many(items),
+            DigitBinIndex::Large(index) => index.add_many(items),
+            DigitBinIndex::Counted(index) => index.add_many(items),
         }
     }
 
-    /// Prints detailed statistics about the index's structure, memory usage,
-    /// and data distribution.
-    pub fn print_stats(&self) {
-        println!("DigitBinIndex Statistics:");
-        println!("=========================");
-        match self {
-            DigitBinIndex::Small(idx) => {
-                println!("- Index Type: Small (Vec<u32>)");
+    /// Removes an item with the given ID and weight from the index.
+    ///
+    /// The weight must match the one used during addition (after rescaling).
+    /// If the item is not found in the corresponding bin, no removal occurs.
+    ///
+    /// # Arguments
+    ///
+    /// * `individual_id` - The ID of the item to remove.
+    /// * `weight` - The weight of the item (must match the added weight).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// index.remove(1, 0.5);
+    /// assert_eq!(index.count(), 0);
+    /// ```
+    pub fn remove(&mut self, id: u64, weight: f64) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.remove(id, weight),
+            DigitBinIndex::Medium(index) => index.remove(id, weight),
+            DigitBinIndex::Large(index) => index.remove(id, weight),
+            DigitBinIndex::Counted(index) => index.remove(id, weight),
+        }
+    }
+
+    /// Removes an item with the given ID and weight, returning an error when nothing is removed.
+    ///
+    /// Behaves like [`remove`](Self::remove), but tells the reasons for a failed removal
+    /// apart: an invalid weight ([`InvalidWeight`](DigitBinIndexError::InvalidWeight) or
+    /// [`WeightBelowResolution`](DigitBinIndexError::WeightBelowResolution)), an ID that is
+    /// not in the index at all ([`NotFound`](DigitBinIndexError::NotFound)), or an ID that
+    /// is stored in a different bin ([`WeightMismatch`](DigitBinIndexError::WeightMismatch),
+    /// which carries the stored binned weight). Looking up the stored weight scans every item,
+    /// at `O(N)`, unless the [ID registry](Self::enable_id_registry) is on, but only happens
+    /// on failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the item to remove.
+    /// * `weight` - The weight of the item (must match the added weight after binning).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, DigitBinIndexError};
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add(1, 0.5);
+    /// assert_eq!(
+    ///     index.try_remove(1, 0.25),
+    ///     Err(DigitBinIndexError::WeightMismatch { id: 1, given: 0.25, stored: 0.5 })
+    /// );
+    /// assert_eq!(index.try_remove(2, 0.5), Err(DigitBinIndexError::NotFound { id: 2 }));
+    /// assert_eq!(index.try_remove(1, 0.501), Ok(()));
+    /// assert_eq!(index.count(), 0);
+    /// ```
+    pub fn try_remove(&mut self, id: u64, weight: f64) -> Result<(), DigitBinIndexError> {
+        match self {
+            DigitBinIndex::Small(index) => index.try_remove(id, weight),
+            DigitBinIndex::Medium(index) => index.try_remove(id, weight),
+            DigitBinIndex::Large(index) => index.try_remove(id, weight),
+            DigitBinIndex::Counted(index) => index.try_remove(id, weight),
+        }
+    }
+
+    /// Removes one copy of an item given only its ID.
+    ///
+    /// The item's bin is looked up in the ID registry (see
+    /// [`enable_id_registry`](Self::enable_id_registry)), which this turns on if it is off:
+    /// the first call then costs `O(N)` to build the registry, and every later lookup is a
+    /// constant-time hash map probe, with the registry kept up to date by all updates. If
+    /// the ID is stored in several bins, one of them is chosen.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the item to remove.
+    ///
+    /// # Returns
+    ///
+    /// The binned weight of the removed copy, or `None` if the ID is not in the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.enable_id_registry();
+    /// index.add_many(&[(1, 0.25), (2, 0.5)]);
+    /// assert_eq!(index.remove_by_id(2), Some(0.5));
+    /// assert_eq!(index.remove_by_id(2), None);
+    /// assert_eq!(index.count(), 1);
+    /// ```
+    pub fn remove_by_id(&mut self, id: u64) -> Option<f64> {
+        match self {
+            DigitBinIndex::Small(index) => index.remove_by_id(id),
+            DigitBinIndex::Medium(index) => index.remove_by_id(id),
+            DigitBinIndex::Large(index) => index.remove_by_id(id),
+            DigitBinIndex::Counted(index) => index.remove_by_id(id),
+        }
+    }
+
+    /// Returns whether an item with the given ID is in the index.
+    ///
+    /// This is a constant-time lookup in the ID registry, and needs it: without the
+    /// registry (see [`enable_id_registry`](Self::enable_id_registry)) it returns `false`
+    /// for every ID rather than scanning all items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.enable_id_registry();
+    /// index.add_many(&[(1, 0.25), (2, 0.5)]);
+    /// index.select_many_and_remove(1);
+    /// assert!(index.contains(1) != index.contains(2));
+    /// assert!(!index.contains(3));
+    /// ```
+    pub fn contains(&self, id: u64) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.contains(id),
+            DigitBinIndex::Medium(index) => index.contains(id),
+            DigitBinIndex::Large(index) => index.contains(id),
+            DigitBinIndex::Counted(index) => index.contains(id),
+        }
+    }
+
+    /// Returns the binned weight of the item with the given ID.
+    ///
+    /// The result is the weight of the bin holding the item, i.e. the added weight
+    /// truncated to the index's precision. If the ID is stored in several bins, one of
+    /// them is reported. Like [`contains`](Self::contains), this is a constant-time lookup
+    /// in the ID registry and needs it.
+    ///
+    /// # Returns
+    ///
+    /// The binned weight, or `None` if the ID is not in the index or the ID registry is off.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.enable_id_registry();
+    /// index.add(1, 0.257);
+    /// assert_eq!(index.get_weight(1), Some(0.25));
+    /// assert_eq!(index.get_weight(2), None);
+    /// ```
+    pub fn get_weight(&self, id: u64) -> Option<f64> {
+        match self {
+            DigitBinIndex::Small(index) => index.get_weight(id),
+            DigitBinIndex::Medium(index) => index.get_weight(id),
+            DigitBinIndex::Large(index) => index.get_weight(id),
+            DigitBinIndex::Counted(index) => index.get_weight(id),
+        }
+    }
+
+    /// Turns on the ID registry, a reverse map from each ID to the bin that holds it.
+    ///
+    /// The registry is filled from the current contents and kept up to date by every
+    /// later insertion, removal, and move, so that [`remove_by_id`](Self::remove_by_id)
+    /// no longer has to scan the bins. It costs a hash map entry per stored ID and a
+    /// little extra work per update, which is why it is off by default; `remove_by_id` and
+    /// `advance_to` turn it on when they need it, and [`contains`](Self::contains) and
+    /// [`get_weight`](Self::get_weight) need it. Building it costs `O(N)`. Calling this
+    /// again rebuilds it. Snapshots and serialized indexes record whether it is on, and
+    /// restoring them rebuilds it from the items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(7, 0.3);
+    /// assert!(!index.has_id_registry());
+    /// index.enable_id_registry();
+    /// assert!(index.has_id_registry());
+    /// assert_eq!(index.remove_by_id(7), Some(0.3));
+    /// ```
+    pub fn enable_id_registry(&mut self) {
+        match self {
+            DigitBinIndex::Small(index) => index.enable_id_registry(),
+            DigitBinIndex::Medium(index) => index.enable_id_registry(),
+            DigitBinIndex::Large(index) => index.enable_id_registry(),
+            DigitBinIndex::Counted(index) => index.enable_id_registry(),
+        }
+    }
+
+    /// Returns whether the ID registry is enabled.
+    pub fn has_id_registry(&self) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.has_id_registry(),
+            DigitBinIndex::Medium(index) => index.has_id_registry(),
+            DigitBinIndex::Large(index) => index.has_id_registry(),
+            DigitBinIndex::Counted(index) => index.has_id_registry(),
+        }
+    }
+
+    /// Makes selections return the exact weights items were added with.
+    ///
+    /// Draws are still driven by the bins, but [`select`](Self::select),
+    /// [`select_and_remove`](Self::select_and_remove), the `select_many` family and
+    /// [`get_weight`](Self::get_weight) then report the `f64` that was passed to `add`,
+    /// `add_many`, `add_range` or `update_weight` (or to `at_tick`), instead of the bin
+    /// weight it was truncated to. The weights are kept in the ID registry, which is turned
+    /// on if needed. Items that were already in the index, or that were placed by weights
+    /// computed internally (e.g. by `merge_from` or `coarsen_to`), report their bin weight.
+    /// Exact weights are kept by snapshots and serialized indexes.
+    ///
+    /// Either weight can be passed back to [`remove`](Self::remove), since both fall in the
+    /// same bin.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add(1, 0.257);
+    /// assert_eq!(index.select(), Some((1, 0.25)));
+    ///
+    /// index.enable_exact_weights();
+    /// index.update_weight(1, 0.257, 0.3141);
+    /// assert_eq!(index.select(), Some((1, 0.3141)));
+    /// assert_eq!(index.select_and_remove(), Some((1, 0.3141)));
+    /// ```
+    pub fn enable_exact_weights(&mut self) {
+        match self {
+            DigitBinIndex::Small(index) => index.enable_exact_weights(),
+            DigitBinIndex::Medium(index) => index.enable_exact_weights(),
+            DigitBinIndex::Large(index) => index.enable_exact_weights(),
+            DigitBinIndex::Counted(index) => index.enable_exact_weights(),
+        }
+    }
+
+    /// Returns whether exact weights are enabled.
+    pub fn has_exact_weights(&self) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.has_exact_weights(),
+            DigitBinIndex::Medium(index) => index.has_exact_weights(),
+            DigitBinIndex::Large(index) => index.has_exact_weights(),
+            DigitBinIndex::Counted(index) => index.has_exact_weights(),
+        }
+    }
+
+    /// Registers a weight change for an item, to be applied when the clock reaches `tick`.
+    ///
+    /// Changes are queued, not applied: nothing happens to the index until
+    /// [`advance_to`](Self::advance_to) is called with a tick at or past `tick`. This lets
+    /// discrete-time models schedule future changes inside the index instead of keeping a
+    /// separate event queue in sync. A change registered for a tick the clock has already
+    /// passed is applied by the next `advance_to` call.
+    ///
+    /// When applied, the item is located by ID alone through the ID registry, so no old
+    /// weight is needed; this turns the registry on if it is off, at a one-time `O(N)` cost. A non-positive or otherwise invalid `new_weight` removes
+    /// the item, and an ID that is not in the index is added with `new_weight`.
+    ///
+    /// # Arguments
+    ///
+    /// * `tick` - The simulation tick at which the change takes effect.
+    /// * `id` - The ID of the item to change.
+    /// * `new_weight` - The weight the item has from `tick` on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// index.at_tick(3, 1, 0.25);
+    /// index.at_tick(5, 1, 0.0); // leaves the population at tick 5
+    /// index.at_tick(5, 2, 0.5); // joins it at tick 5
+    ///
+    /// assert_eq!(index.advance_to(2), 0);
+    /// assert_eq!(index.get_weight(1), Some(0.5));
+    /// assert_eq!(index.advance_to(4), 1);
+    /// assert_eq!(index.get_weight(1), Some(0.25));
+    /// assert_eq!(index.advance_to(5), 2);
+    /// assert!(!index.contains(1) && index.contains(2));
+    /// assert_eq!(index.clock(), 5);
+    /// ```
+    pub fn at_tick(&mut self, tick: u64, id: u64, new_weight: f64) {
+        match self {
+            DigitBinIndex::Small(index) => index.at_tick(tick, id, new_weight),
+            DigitBinIndex::Medium(index) => index.at_tick(tick, id, new_weight),
+            DigitBinIndex::Large(index) => index.at_tick(tick, id, new_weight),
+            DigitBinIndex::Counted(index) => index.at_tick(tick, id, new_weight),
+        }
+    }
+
+    /// Advances the clock to `tick`, applying every change registered with
+    /// [`at_tick`](Self::at_tick) for a tick up to and including it.
+    ///
+    /// Changes are applied in tick order, and in registration order within a tick. The
+    /// clock never moves backwards; advancing to an earlier tick only applies changes that
+    /// are overdue. Each change looks its item up in the ID registry, which `at_tick` has
+    /// turned on, at a hash map probe plus `O(P)` to move the item.
+    ///
+    /// # Returns
+    ///
+    /// The number of changes that modified the index. Changes that found nothing to do
+    /// (such as removing an absent item) are discarded without being counted.
+    pub fn advance_to(&mut self, tick: u64) -> usize {
+        match self {
+            DigitBinIndex::Small(index) => index.advance_to(tick),
+            DigitBinIndex::Medium(index) => index.advance_to(tick),
+            DigitBinIndex::Large(index) => index.advance_to(tick),
+            DigitBinIndex::Counted(index) => index.advance_to(tick),
+        }
+    }
+
+    /// Returns the highest tick passed to [`advance_to`](Self::advance_to), or 0.
+    pub fn clock(&self) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.clock(),
+            DigitBinIndex::Medium(index) => index.clock(),
+            DigitBinIndex::Large(index) => index.clock(),
+            DigitBinIndex::Counted(index) => index.clock(),
+        }
+    }
+
+    /// Returns the number of registered changes that have not been applied yet.
+    pub fn pending_changes(&self) -> usize {
+        match self {
+            DigitBinIndex::Small(index) => index.pending_changes(),
+            DigitBinIndex::Medium(index) => index.pending_changes(),
+            DigitBinIndex::Large(index) => index.pending_changes(),
+            DigitBinIndex::Counted(index) => index.pending_changes(),
+        }
+    }
+
+    /// Moves an item to a new weight in a single traversal.
+    ///
+    /// This is equivalent to `remove(id, old_weight)` followed by `add(id, new_weight)`,
+    /// but walks the shared part of both paths only once and is all-or-nothing: if the
+    /// item is not found under `old_weight`, or `new_weight` is invalid, the index is
+    /// left unchanged. With the `Counted` backend one copy is moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the item to move.
+    /// * `old_weight` - The weight the item was added with.
+    /// * `new_weight` - The weight to move it to.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the item was moved (or already had the new weight's bin), `false` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.2);
+    /// assert!(!index.update_weight(1, 0.3, 0.6)); // wrong old weight: nothing changes
+    /// assert!(index.update_weight(1, 0.2, 0.6));
+    /// assert_eq!(index.total_weight(), 0.6);
+    /// assert!(index.remove(1, 0.6));
+    /// ```
+    pub fn update_weight(&mut self, id: u64, old_weight: f64, new_weight: f64) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.update_weight(id, old_weight, new_weight),
+            DigitBinIndex::Medium(index) => index.update_weight(id, old_weight, new_weight),
+            DigitBinIndex::Large(index) => index.update_weight(id, old_weight, new_weight),
+            DigitBinIndex::Counted(index) => index.update_weight(id, old_weight, new_weight),
+        }
+    }
+
+    /// Returns a copy of the index in which some items carry new weights.
+    ///
+    /// This is for scenario analysis on a shared baseline ("the same population, but these
+    /// agents are high-risk"): the baseline is left untouched, and the copy is the cloned
+    /// tree with only the listed items moved, which is much cheaper than rebuilding it from
+    /// `(id, weight)` pairs. Subtrees are not shared between the two, so each copy takes as
+    /// much memory as the baseline. Every copy of a listed ID moves to its new weight, and
+    /// the other items keep theirs.
+    ///
+    /// # Arguments
+    ///
+    /// * `overrides` - `(id, new weight)` pairs. IDs not in the index, and weights the
+    ///   index cannot store, are ignored. If an ID is listed twice, the last weight wins.
+    ///
+    /// # Returns
+    ///
+    /// The modified copy, with the same backend and settings as the original.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut baseline = DigitBinIndex::with_precision(2);
+    /// baseline.enable_id_registry();
+    /// baseline.add_range(0..1_000, 0.01);
+    ///
+    /// let high_risk: Vec<(u64, f64)> = (0..10).map(|id| (id, 0.5)).collect();
+    /// let scenario = baseline.clone_with_overrides(&high_risk);
+    /// assert_eq!(scenario.get_weight(3), Some(0.5));
+    /// assert!((scenario.total_weight() - (990.0 * 0.01 + 10.0 * 0.5)).abs() < 1e-9);
+    /// assert_eq!(baseline.get_weight(3), Some(0.01));
+    /// ```
+    pub fn clone_with_overrides(&self, overrides: &[(u64, f64)]) -> DigitBinIndex {
+        match self {
+            DigitBinIndex::Small(index) => DigitBinIndex::Small(index.clone_with_overrides(overrides)),
+            DigitBinIndex::Medium(index) => DigitBinIndex::Medium(index.clone_with_overrides(overrides)),
+            DigitBinIndex::Large(index) => DigitBinIndex::Large(index.clone_with_overrides(overrides)),
+            DigitBinIndex::Counted(index) => DigitBinIndex::Counted(index.clone_with_overrides(overrides)),
+        }
+    }
+
+    /// Removes multiple items from the index in a highly optimized batch operation.
+    ///
+    /// This method is significantly faster than calling `remove` in a loop. It
+    /// groups the items to be removed by their weight path and traverses the tree
+    /// only once per group, performing aggregated updates on the way up.
+    ///
+    /// The `(id, weight)` pairs must match items that are currently in the index.
+    /// If a given pair is not found, it is silently ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - A slice of `(id, weight)` tuples to remove from the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// let items_to_add = vec![(1, 0.1), (2, 0.2), (3, 0.1), (4, 0.3)];
+    /// index.add_many(&items_to_add);
+    /// assert_eq!(index.count(), 4);
+    ///
+    /// let items_to_remove = vec![(2, 0.2), (3, 0.1)];
+    /// index.remove_many(&items_to_remove);
+    ///
+    /// assert_eq!(index.count(), 2); // Items 1 and 4 should remain
+    /// // The total weight should be 0.1 + 0.3 = 0.4
+    /// assert!((index.total_weight() - 0.4).abs() < f64::EPSILON);
+    /// ```
+    pub fn remove_many(&mut self, items: &[(u64, f64)]) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.remove_many(items),
+            DigitBinIndex::Medium(index) => index.remove_many(items),
+            DigitBinIndex::Large(index) => index.remove_many(items),
+            DigitBinIndex::Counted(index) => index.remove_many(items),
+        }
+    }    
+
+    /// Reconciles the index with a fresh snapshot of `(id, weight)` pairs in a single pass.
+    ///
+    /// IDs present in `items` but not in the index are added, IDs present in both are
+    /// resolved by `on_conflict`, and IDs in the index that do not appear in `items` are
+    /// removed. Duplicate IDs within `items` are resolved with the same policy. Weights are
+    /// compared after binning, so an item whose new weight falls in its current bin is left
+    /// untouched. An ID stored in several bins is taken out of all of them, and its copies
+    /// move to the new bin together; the policy combines the new weight with the lightest
+    /// of its current bins.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The complete, refreshed population as `(id, weight)` pairs.
+    /// * `on_conflict` - How to combine a new weight with the weight already stored for an ID.
+    ///
+    /// # Returns
+    ///
+    /// A [`MergeReport`] counting the added, updated, removed, unchanged and rejected IDs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{ConflictPolicy, DigitBinIndex};
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.1), (2, 0.2), (3, 0.3)]);
+    ///
+    /// // ID 1 gets a new weight, ID 2 disappears, ID 4 is new.
+    /// let report = index.merge_from(vec![(1, 0.4), (3, 0.3), (4, 0.2)], ConflictPolicy::Replace);
+    /// assert_eq!((report.added, report.updated, report.removed, report.unchanged), (1, 1, 1, 1));
+    /// assert_eq!(index.count(), 3);
+    /// assert!((index.total_weight() - 0.9).abs() < 1e-9);
+    /// ```
+    pub fn merge_from<I: IntoIterator<Item = (u64, f64)>>(&mut self, items: I, on_conflict: ConflictPolicy) -> MergeReport {
+        match self {
+            DigitBinIndex::Small(index) => index.merge_from(items, on_conflict),
+            DigitBinIndex::Medium(index) => index.merge_from(items, on_conflict),
+            DigitBinIndex::Large(index) => index.merge_from(items, on_conflict),
+            DigitBinIndex::Counted(index) => index.merge_from(items, on_conflict),
+        }
+    }
+
+    /// Removes one copy of each ID in a set, without knowing their weights.
+    ///
+    /// The IDs are matched to their bins first, from the ID registry if it is on (see
+    /// [`enable_id_registry`](Self::enable_id_registry)), otherwise in a single pass over
+    /// the bins. The removals are then applied like [`remove_many`](Self::remove_many),
+    /// updating each affected path once, so removing the losers of a tournament costs one
+    /// walk of the tree rather than one per ID. IDs that are not in the index are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The IDs to remove.
+    ///
+    /// # Returns
+    ///
+    /// The number of IDs removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.1), (2, 0.2), (3, 0.3)]);
+    ///
+    /// let losers: RoaringTreemap = [1, 3, 9].into_iter().collect();
+    /// assert_eq!(index.remove_ids(&losers), 2);
+    /// assert_eq!(index.iter().collect::<Vec<_>>(), vec![(2, 0.2)]);
+    /// ```
+    pub fn remove_ids(&mut self, ids: &RoaringTreemap) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.remove_ids(ids),
+            DigitBinIndex::Medium(index) => index.remove_ids(ids),
+            DigitBinIndex::Large(index) => index.remove_ids(ids),
+            DigitBinIndex::Counted(index) => index.remove_ids(ids),
+        }
+    }
+
+    /// Merges the changes another replica made to a shared base into this index.
+    ///
+    /// `self` and `theirs` are copies of `base` that were mutated independently. The merge
+    /// is three-way: for every ID it compares the two versions against the base, where a
+    /// version is the set of bins (and copies) holding the ID. A change made on one side
+    /// only is taken as is, so additions are unioned and removals subtract. Where both
+    /// sides changed the same ID differently, `policy` decides, and the case is reported.
+    ///
+    /// With [`ReplicaPolicy::Heavier`] or [`ReplicaPolicy::Lighter`] the result does not
+    /// depend on which replica merges which, so two workers that merge each other's state
+    /// end up with the same contents (equal [`state_hash`](Self::state_hash)).
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The common ancestor both replicas started from.
+    /// * `theirs` - The other replica.
+    /// * `policy` - How to resolve IDs changed differently on both sides.
+    ///
+    /// # Returns
+    ///
+    /// A report of the changes applied to `self` and the conflicts that were resolved, or
+    /// [`DigitBinIndexError::PrecisionMismatch`] if the three indexes do not share a precision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, ReplicaPolicy};
+    ///
+    /// let mut base = DigitBinIndex::with_precision(2);
+    /// base.add_many(&[(1, 0.1), (2, 0.2), (3, 0.3)]);
+    ///
+    /// let mut ours = base.clone();
+    /// ours.add(4, 0.4);
+    /// ours.update_weight(3, 0.3, 0.5);
+    /// let mut theirs = base.clone();
+    /// theirs.remove(1, 0.1);
+    /// theirs.update_weight(3, 0.3, 0.7);
+    ///
+    /// let report = ours.merge_replica(&base, &theirs, ReplicaPolicy::Heavier).unwrap();
+    /// assert_eq!(report.conflicts.len(), 1); // ID 3 was reweighted on both sides
+    /// assert_eq!(ours.iter().collect::<Vec<_>>(), vec![(2, 0.2), (4, 0.4), (3, 0.7)]);
+    /// ```
+    pub fn merge_replica(&mut self, base: &DigitBinIndex, theirs: &DigitBinIndex, policy: ReplicaPolicy) -> Result<ReplicaMergeReport, DigitBinIndexError> {
+        for other in [base, theirs] {
+            if other.precision() != self.precision() {
+                return Err(DigitBinIndexError::PrecisionMismatch { expected: other.precision(), found: self.precision() });
+            }
+        }
+        let (base, theirs) = (base.placements(), theirs.placements());
+        Ok(match self {
+            DigitBinIndex::Small(index) => index.merge_replica(&base, &theirs, policy),
+            DigitBinIndex::Medium(index) => index.merge_replica(&base, &theirs, policy),
+            DigitBinIndex::Large(index) => index.merge_replica(&base, &theirs, policy),
+            DigitBinIndex::Counted(index) => index.merge_replica(&base, &theirs, policy),
+        })
+    }
+
+    /// Moves every item of another index into this one.
+    ///
+    /// When both indexes use the same backend, the trees are merged node by node: matching
+    /// bins are merged as a whole and subtrees missing on one side are moved over, so the
+    /// cost depends on the number of bins rather than the number of items. Otherwise the
+    /// items of `other` are inserted bin by bin. This is the fast way to combine indexes
+    /// that were built in parallel, e.g. one per shard of the input.
+    ///
+    /// An ID present in both indexes keeps both entries; set-like backends (`Medium`,
+    /// `Large`) store an ID at most once per bin. Changes scheduled with `at_tick` in
+    /// `other` carry over. The memory budget of this index is not applied to the merge.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The index to merge in. It must have the same precision.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or [`DigitBinIndexError::PrecisionMismatch`] if the precisions differ, in
+    /// which case nothing is merged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut shards: Vec<DigitBinIndex> = (0..4u64)
+    ///     .map(|shard| (shard * 1000..(shard + 1) * 1000).map(|id| (id, 0.25)).collect())
+    ///     .collect();
+    /// let mut index = shards.pop().unwrap();
+    /// for shard in shards {
+    ///     index.merge(shard).unwrap();
+    /// }
+    /// assert_eq!(index.count(), 4000);
+    /// assert!((index.total_weight() - 1000.0).abs() < 1e-9);
+    /// ```
+    pub fn merge(&mut self, other: DigitBinIndex) -> Result<(), DigitBinIndexError> {
+        if other.precision() != self.precision() {
+            return Err(DigitBinIndexError::PrecisionMismatch { expected: other.precision(), found: self.precision() });
+        }
+        match (self, other) {
+            (DigitBinIndex::Small(index), DigitBinIndex::Small(other)) => index.merge(other),
+            (DigitBinIndex::Medium(index), DigitBinIndex::Medium(other)) => index.merge(other),
+            (DigitBinIndex::Large(index), DigitBinIndex::Large(other)) => index.merge(other),
+            (DigitBinIndex::Counted(index), DigitBinIndex::Counted(other)) => index.merge(other),
+            (index, other) => {
+                for (id, bins) in other.placements() {
+                    for (scaled, copies) in bins {
+                        index.insert_scaled(id, scaled, copies);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a branch of the index that shares its tree with this one.
+    ///
+    /// The tree is copy-on-write: the node arena and the bins are shared between branches.
+    /// A branch's first change copies the arena, whose size is bounded by the precision
+    /// rather than by the number of items, and after that a change copies only the bin it
+    /// touches the first time. Taking a snapshot therefore costs next to nothing whatever
+    /// the size of the index, and what-if scenarios can be forked from one
+    /// population many times over. Draws without removal through `&self`, such as
+    /// [`select_with_rng`](Self::select_with_rng), copy nothing. The optional ID registry
+    /// and exact-weight map are not shared and are copied with the snapshot.
+    ///
+    /// This is the same as `clone`, which is just as cheap.
+    ///
+    /// # Returns
+    ///
+    /// An independent index with the same items and settings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut population = DigitBinIndex::with_precision(3);
+    /// population.add_range(0..1_000_000, 0.5);
+    ///
+    /// let mut scenario = population.snapshot();
+    /// scenario.select_many_and_remove(1_000).unwrap();
+    /// assert_eq!(scenario.count(), 999_000);
+    /// assert_eq!(population.count(), 1_000_000);
+    /// ```
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Returns a new index holding the items of both indexes, leaving them unchanged.
+    ///
+    /// The result uses this index's backend and settings. See [`merge`](Self::merge).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let a: DigitBinIndex = [(1, 0.2), (2, 0.3)].into_iter().collect();
+    /// let b: DigitBinIndex = [(3, 0.5)].into_iter().collect();
+    /// let both = a.union(&b).unwrap();
+    /// assert_eq!((a.count(), b.count(), both.count()), (2, 1, 3));
+    /// ```
+    pub fn union(&self, other: &DigitBinIndex) -> Result<DigitBinIndex, DigitBinIndexError> {
+        let mut union = self.clone();
+        union.merge(other.clone())?;
+        Ok(union)
+    }
+
+    fn placements(&self) -> Placements {
+        match self {
+            DigitBinIndex::Small(index) => index.placements(),
+            DigitBinIndex::Medium(index) => index.placements(),
+            DigitBinIndex::Large(index) => index.placements(),
+            DigitBinIndex::Counted(index) => index.placements(),
+        }
+    }
+
+    /// Removes entire bins that match a structural predicate.
+    ///
+    /// The predicate is called once per non-empty bin with the bin's per-item weight and
+    /// its item count. Every bin for which it returns `true` is emptied in one pass. This
+    /// is useful for denoising heavy-tailed populations, e.g. dropping all sparsely
+    /// populated weights before an expensive phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - Called as `predicate(bin_weight, count)`; return `true` to prune the bin.
+    ///
+    /// # Returns
+    ///
+    /// The removed (ID, weight) pairs, in ascending weight order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.1), (2, 0.1), (3, 0.1), (4, 0.5), (5, 0.9)]);
+    ///
+    /// // Drop every bin with fewer than two members.
+    /// let removed = index.prune_bins(|_, count| count < 2);
+    /// assert_eq!(removed, vec![(4, 0.5), (5, 0.9)]);
+    /// assert_eq!(index.count(), 3);
+    /// ```
+    pub fn prune_bins(&mut self, predicate: impl FnMut(f64, u64) -> bool) -> Vec<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.prune_bins(predicate),
+            DigitBinIndex::Medium(index) => index.prune_bins(predicate),
+            DigitBinIndex::Large(index) => index.prune_bins(predicate),
+            DigitBinIndex::Counted(index) => index.prune_bins(predicate),
+        }
+    }
+
+    /// Releases the memory left behind by removals.
+    ///
+    /// Removing items never frees nodes, so that a bin emptied and refilled during a
+    /// simulation does not churn allocations. After a long run of removals, `compact` drops
+    /// every subtree whose count has reached zero and shrinks the remaining bins to their
+    /// contents, so the memory comes back and later draws and walks skip the dead branches.
+    /// The node arena is rebuilt with only the live nodes, laid out depth first. Bins
+    /// shared with a [`snapshot`](Self::snapshot) are left as they are, since copying them
+    /// would take memory rather than free it.
+    ///
+    /// # Returns
+    ///
+    /// The number of tree nodes released.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// for id in 0..1_000 {
+    ///     index.add(id, 0.001 + id as f64 / 1_001.0);
+    /// }
+    /// let before = index.memory_usage();
+    /// index.select_many_and_remove(990).unwrap();
+    /// assert_eq!(index.memory_usage(), before);
+    ///
+    /// assert!(index.compact() > 0);
+    /// assert!(index.memory_usage() < before / 10);
+    /// assert_eq!(index.count(), 10);
+    /// ```
+    pub fn compact(&mut self) -> usize {
+        match self {
+            DigitBinIndex::Small(index) => index.compact(),
+            DigitBinIndex::Medium(index) => index.compact(),
+            DigitBinIndex::Large(index) => index.compact(),
+            DigitBinIndex::Counted(index) => index.compact(),
+        }
+    }
+
+    /// Returns up to `k` items from the heaviest bins, heaviest first.
+    ///
+    /// The tree is walked from its highest digits down and the walk stops as soon as `k`
+    /// items are collected, so looking at the top of a large population only touches the
+    /// bins it returns. Items in the same bin are in the bin's member order, and items
+    /// stored with several copies are returned once per copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - The maximum number of items to return.
+    ///
+    /// # Returns
+    ///
+    /// The (ID, weight) pairs with binned weights, in descending weight order. Fewer than
+    /// `k` are returned if the index holds fewer items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.1), (2, 0.9), (3, 0.5), (4, 0.05)]);
+    /// assert_eq!(index.heaviest(2), vec![(2, 0.9), (3, 0.5)]);
+    /// assert_eq!(index.heaviest(9).len(), 4);
+    /// ```
+    pub fn heaviest(&self, k: usize) -> Vec<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.heaviest(k),
+            DigitBinIndex::Medium(index) => index.heaviest(k),
+            DigitBinIndex::Large(index) => index.heaviest(k),
+            DigitBinIndex::Counted(index) => index.heaviest(k),
+        }
+    }
+
+    /// Returns up to `k` items from the lightest bins, lightest first.
+    ///
+    /// The counterpart of [`heaviest`](Self::heaviest), walking the tree from its lowest
+    /// digits up. Combined with [`remove_many`](Self::remove_many) it culls the weakest
+    /// part of a population without sorting it.
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - The maximum number of items to return.
+    ///
+    /// # Returns
+    ///
+    /// The (ID, weight) pairs with binned weights, in ascending weight order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.1), (2, 0.9), (3, 0.5), (4, 0.05)]);
+    /// let culled = index.lightest(2);
+    /// assert_eq!(culled, vec![(4, 0.05), (1, 0.1)]);
+    ///
+    /// index.remove_many(&culled);
+    /// assert_eq!(index.count(), 2);
+    /// ```
+    pub fn lightest(&self, k: usize) -> Vec<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.lightest(k),
+            DigitBinIndex::Medium(index) => index.lightest(k),
+            DigitBinIndex::Large(index) => index.lightest(k),
+            DigitBinIndex::Counted(index) => index.lightest(k),
+        }
+    }
+
+    /// Selects a single item randomly based on weights without removal.
+    ///
+    /// Performs weighted random selection. Returns `None` if the index is empty.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected item's ID and its (rescaled) weight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// if let Some((id, weight)) = index.select() {
+    ///     assert_eq!(id, 1);
+    ///     assert_eq!(weight, 0.5);
+    /// }
+    /// ```
+    pub fn select(&mut self) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select(),
+            DigitBinIndex::Medium(index) => index.select(),
+            DigitBinIndex::Large(index) => index.select(),
+            DigitBinIndex::Counted(index) => index.select(),
+        }
+    }
+
+    /// Selects a single item randomly and removes it from the index.
+    ///
+    /// Combines selection and removal in one operation. Returns `None` if empty.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected item's ID and weight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// if let Some((id, _)) = index.select_and_remove() {
+    ///     assert_eq!(id, 1);
+    /// }
+    /// assert_eq!(index.count(), 0);
+    /// ```
+    pub fn select_and_remove(&mut self) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_and_remove(),
+            DigitBinIndex::Medium(index) => index.select_and_remove(),
+            DigitBinIndex::Large(index) => index.select_and_remove(),
+            DigitBinIndex::Counted(index) => index.select_and_remove(),
+        }
+    }
+
+    /// Selects a single copy randomly and removes it, reporting how many copies of the
+    /// selected ID remain in its bin.
+    ///
+    /// This is the natural draw for the `Counted` backend, where an ID stands for a cohort
+    /// of identical individuals. For the other backends the remaining count is the number
+    /// of duplicate entries left, which is normally 0.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected item's ID, its weight, and the remaining count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::counted(3);
+    /// index.add_with_count(1, 0.5, 3);
+    /// assert_eq!(index.select_and_decrement(), Some((1, 0.5, 2)));
+    /// assert_eq!(index.count(), 2);
+    /// ```
+    pub fn select_and_decrement(&mut self) -> Option<(u64, f64, u64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_and_decrement(),
+            DigitBinIndex::Medium(index) => index.select_and_decrement(),
+            DigitBinIndex::Large(index) => index.select_and_decrement(),
+            DigitBinIndex::Counted(index) => index.select_and_decrement(),
+        }
+    }
+
+    /// Selects multiple unique items randomly based on weights without removal.
+    ///
+    /// The draw targets are spread over the tree in proportion to weight, and a target that
+    /// lands in a subtree with no items left to give is redrawn among the subtrees that still
+    /// have some, so no target is rejected and the cost stays `O(k * P)` however close `k`
+    /// gets to the item count. This is the batch (Fisher's) draw, for any `k`: it never
+    /// switches to [`select_many_sequential`](Self::select_many_sequential), which is a
+    /// separate sampler for the distribution of sequential draws. Returns `None` if
+    /// `num_to_draw` exceeds the number of items in the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a vector of selected (ID, weight) pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.3);
+    /// index.add(2, 0.7);
+    /// if let Some(selected) = index.select_many(2) {
+    ///     assert_eq!(selected.len(), 2);
+    /// }
+    /// ```
+    pub fn select_many(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many(num_to_draw),
+        }
+    }
+
+    /// Selects multiple unique items randomly and removes them from the index.
+    ///
+    /// Selects and removes in batch. Returns `None` if `num_to_draw` exceeds item count.
+    /// Within a bin, the members to take are drawn uniformly at random from those still
+    /// available, as in [`select`](Self::select), so neither ID order nor insertion order
+    /// affects which individuals are chosen.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select and remove.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a vector of selected (ID, weight) pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.3);
+    /// index.add(2, 0.7);
+    /// if let Some(selected) = index.select_many_and_remove(2) {
+    ///     assert_eq!(selected.len(), 2);
+    /// }
+    /// assert_eq!(index.count(), 0);
+    /// ```
+    pub fn select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_and_remove(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many_and_remove(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many_and_remove(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many_and_remove(num_to_draw),
+        }
+    }
+
+    /// Selects a single item without removal through a shared reference, using the given RNG.
+    ///
+    /// Unlike [`select`](Self::select), this does not need mutable access, so many threads
+    /// can draw from the same index concurrently, each with its own RNG.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - The random number generator to draw from.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight), or `None` if the index is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// let mut rng = StdRng::seed_from_u64(7);
+    /// assert_eq!(index.select_with_rng(&mut rng), Some((1, 0.5)));
+    /// assert_eq!(index.count(), 1);
+    /// ```
+    pub fn select_with_rng<R: Rng>(&self, rng: &mut R) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_with_rng(rng),
+            DigitBinIndex::Medium(index) => index.select_with_rng(rng),
+            DigitBinIndex::Large(index) => index.select_with_rng(rng),
+            DigitBinIndex::Counted(index) => index.select_with_rng(rng),
+        }
+    }
+
+    /// Returns the index as a [`rand::distr::Distribution`] of (ID, weight) pairs.
+    ///
+    /// The sampler draws without removal, with a caller-supplied RNG, so the index can be
+    /// used wherever the `rand` ecosystem expects a distribution, for example with
+    /// `sample_iter`.
+    ///
+    /// # Returns
+    ///
+    /// A [`Sampler`] borrowing the index, or `None` if the index is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::distr::Distribution;
+    /// use rand::SeedableRng;
+    /// use wyrand::WyRand;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// assert!(index.sampler().is_none());
+    /// index.add(7, 0.5);
+    ///
+    /// let mut rng = WyRand::seed_from_u64(1);
+    /// let draws: Vec<(u64, f64)> = index.sampler().unwrap().sample_iter(&mut rng).take(3).collect();
+    /// assert_eq!(draws, vec![(7, 0.5); 3]);
+    /// ```
+    pub fn sampler(&self) -> Option<Sampler<'_>> {
+        Sampler::new(self)
+    }
+
+    /// Selects the item that owns a given target, without removal.
+    ///
+    /// This is the descent that [`select`](Self::select) performs after drawing its target,
+    /// so it can replay targets produced elsewhere, for example by another implementation
+    /// being compared against this one. Which item owns a target on a boundary between two
+    /// children follows [`tie_break`](Self::tie_break). The RNG picks the member within the
+    /// bin reached, and the side of a boundary under [`TieBreak::Random`].
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - A position in the total mass, in units of `10^-precision`: in
+    ///   `0..total` under [`TieBreak::LastChild`], in `1..=total` under
+    ///   [`TieBreak::FirstChild`], and in `0..=total` under [`TieBreak::Random`].
+    /// * `rng` - The random number generator to draw from.
+    ///
+    /// # Returns
+    ///
+    /// The selected (ID, weight), or `None` if the target is outside the total mass.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add(1, 0.25);
+    /// index.add(2, 0.5);
+    /// let mut rng = StdRng::seed_from_u64(7);
+    /// assert_eq!(index.select_at(24, &mut rng), Some((1, 0.25)));
+    /// assert_eq!(index.select_at(25, &mut rng), Some((2, 0.5)));
+    /// assert_eq!(index.select_at(75, &mut rng), None);
+    /// ```
+    pub fn select_at<R: Rng>(&self, target: u64, rng: &mut R) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_at(target, rng),
+            DigitBinIndex::Medium(index) => index.select_at(target, rng),
+            DigitBinIndex::Large(index) => index.select_at(target, rng),
+            DigitBinIndex::Counted(index) => index.select_at(target, rng),
+        }
+    }
+
+    /// Draws `num_to_draw` items independently by weight, with replacement.
+    ///
+    /// Every draw sees the full index, so the same item can be drawn several times, as in
+    /// a bootstrap resample. This is equivalent to calling [`select`](Self::select)
+    /// `num_to_draw` times, but the targets are routed down the tree together, so each
+    /// node is visited once per batch instead of once per draw. The index is not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of draws.
+    ///
+    /// # Returns
+    ///
+    /// The drawn (ID, weight) pairs, grouped by bin in ascending weight order, or `None` if
+    /// the index is empty and `num_to_draw` is not 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.2);
+    /// index.add(2, 0.6);
+    /// let resample = index.select_many_with_replacement(10_000).unwrap();
+    /// assert_eq!(resample.len(), 10_000);
+    /// let ones = resample.iter().filter(|&&(id, _)| id == 1).count();
+    /// assert!((ones as f64 / 10_000.0 - 0.25).abs() < 0.03);
+    /// assert_eq!(index.count(), 2);
+    /// ```
+    pub fn select_many_with_replacement(&self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.select_many_with_replacement_with_rng(num_to_draw, &mut WyRand::from_os_rng())
+    }
+
+    /// Like [`select_many_with_replacement`](Self::select_many_with_replacement), but
+    /// draws from the given RNG.
+    pub fn select_many_with_replacement_with_rng<R: Rng>(&self, num_to_draw: u64, rng: &mut R) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_with_replacement_with_rng(num_to_draw, rng),
+            DigitBinIndex::Medium(index) => index.select_many_with_replacement_with_rng(num_to_draw, rng),
+            DigitBinIndex::Large(index) => index.select_many_with_replacement_with_rng(num_to_draw, rng),
+            DigitBinIndex::Counted(index) => index.select_many_with_replacement_with_rng(num_to_draw, rng),
+        }
+    }
+
+    /// Selects and removes an exact number of winners from each of several groups.
+    ///
+    /// Each group is a set of IDs paired with its target count, typically produced by an
+    /// external allocation step. Within a group, winners are drawn by weight without
+    /// replacement, as in [`select_many_and_remove`](Self::select_many_and_remove). The
+    /// request is validated up front: groups must be disjoint and each must have at least
+    /// as many members in the index as its quota, otherwise nothing is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `groups` - A slice of `(member IDs, quota)` pairs.
+    ///
+    /// # Returns
+    ///
+    /// The winners of each group, in the order the groups were given, or a
+    /// [`DigitBinIndexError`] describing why the quotas cannot be met.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, DigitBinIndexError};
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.2), (2, 0.4), (3, 0.6), (4, 0.8)]);
+    /// let urban: RoaringTreemap = [1, 2].into_iter().collect();
+    /// let rural: RoaringTreemap = [3, 4].into_iter().collect();
+    ///
+    /// let winners = index.select_with_quotas(&[(urban.clone(), 1), (rural.clone(), 2)]).unwrap();
+    /// assert_eq!(winners[0].len(), 1);
+    /// assert_eq!(winners[1].len(), 2);
+    /// assert_eq!(index.count(), 1);
+    ///
+    /// let err = index.select_with_quotas(&[(rural, 1)]).unwrap_err();
+    /// assert_eq!(err, DigitBinIndexError::InfeasibleQuota { group: 0, requested: 1, available: 0 });
+    /// ```
+    pub fn select_with_quotas(&mut self, groups: &[(RoaringTreemap, u64)]) -> Result<Vec<Vec<(u64, f64)>>, DigitBinIndexError> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_with_quotas(groups),
+            DigitBinIndex::Medium(index) => index.select_with_quotas(groups),
+            DigitBinIndex::Large(index) => index.select_with_quotas(groups),
+            DigitBinIndex::Counted(index) => index.select_with_quotas(groups),
+        }
+    }
+
+    /// Draws a bootstrap resample of a given size from each of several strata.
+    ///
+    /// Within each stratum, items are drawn independently and with replacement, with
+    /// probability proportional to their weight among the stratum's members, as in
+    /// [`select_many_with_replacement`](Self::select_many_with_replacement). This is the
+    /// resampling step of survey-weighted analyses, done in one pass over the index and
+    /// without copying it. The index is not changed. Strata must be disjoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `strata` - A slice of `(member IDs, resample size)` pairs.
+    /// * `rng` - The random number generator to draw from.
+    ///
+    /// # Returns
+    ///
+    /// The resample of each stratum, in the order the strata were given, or a
+    /// [`DigitBinIndexError`]: `OverlappingGroups` if an ID is in two strata, or
+    /// `InfeasibleQuota` if a non-zero size is requested from a stratum with no members in
+    /// the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::SeedableRng;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.1), (2, 0.3), (3, 0.5), (4, 0.5)]);
+    ///
+    /// let urban: RoaringTreemap = [1, 2].into_iter().collect();
+    /// let rural: RoaringTreemap = [3, 4].into_iter().collect();
+    /// let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    /// let resamples = index.stratified_bootstrap(&[(urban, 1_000), (rural, 10)], &mut rng).unwrap();
+    /// assert_eq!((resamples[0].len(), resamples[1].len()), (1_000, 10));
+    /// let ones = resamples[0].iter().filter(|&&(id, _)| id == 1).count();
+    /// assert!((ones as f64 / 1_000.0 - 0.25).abs() < 0.06);
+    /// assert!(resamples[1].iter().all(|&(id, _)| id == 3 || id == 4));
+    /// ```
+    pub fn stratified_bootstrap<R: Rng>(
+        &self,
+        strata: &[(RoaringTreemap, u64)],
+        rng: &mut R,
+    ) -> Result<Vec<Vec<(u64, f64)>>, DigitBinIndexError> {
+        match self {
+            DigitBinIndex::Small(index) => index.stratified_bootstrap(strata, rng),
+            DigitBinIndex::Medium(index) => index.stratified_bootstrap(strata, rng),
+            DigitBinIndex::Large(index) => index.stratified_bootstrap(strata, rng),
+            DigitBinIndex::Counted(index) => index.stratified_bootstrap(strata, rng),
+        }
+    }
+
+    /// Adds an item tagged with a group (a stratum such as an age band or a region).
+    ///
+    /// Grouped items are part of the index like any other, so global draws include them,
+    /// and in addition each group keeps its own total weight and can be drawn from on its
+    /// own, in time proportional to the precision. An ID belongs to one group at a time:
+    /// adding it with another group moves all its copies there. The tag is dropped when the
+    /// ID's last copy is removed. Groups use the ID registry, which this turns on.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The item's ID.
+    /// * `weight` - The item's weight, in `(0, 1)`.
+    /// * `group` - The item's group.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the item was stored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_with_group(1, 0.2, 0);
+    /// index.add_with_group(2, 0.3, 1);
+    /// index.add_with_group(3, 0.4, 1);
+    /// index.add(4, 0.5); // no group
+    /// assert_eq!(index.group_of(2), Some(1));
+    /// assert_eq!(index.group_weight(1), 0.7);
+    ///
+    /// let (id, _) = index.select_from_group(0).unwrap();
+    /// assert_eq!(id, 1);
+    /// assert_eq!(index.remove_by_id(3), Some(0.4));
+    /// assert_eq!(index.group_weight(1), 0.3);
+    /// ```
+    pub fn add_with_group(&mut self, id: u64, weight: f64, group: u16) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.add_with_group(id, weight, group),
+            DigitBinIndex::Medium(index) => index.add_with_group(id, weight, group),
+            DigitBinIndex::Large(index) => index.add_with_group(id, weight, group),
+            DigitBinIndex::Counted(index) => index.add_with_group(id, weight, group),
+        }
+    }
+
+    /// Returns the group of an ID, or `None` if it has none or is not in the index.
+    pub fn group_of(&self, id: u64) -> Option<u16> {
+        match self {
+            DigitBinIndex::Small(index) => index.group_of(id),
+            DigitBinIndex::Medium(index) => index.group_of(id),
+            DigitBinIndex::Large(index) => index.group_of(id),
+            DigitBinIndex::Counted(index) => index.group_of(id),
+        }
+    }
+
+    /// Returns the total weight of a group's items, as binned, or 0 for an empty group.
+    ///
+    /// Like [`total_weight`](Self::total_weight), the total is kept up to date by every
+    /// insertion and removal, so this does not scan the group.
+    pub fn group_weight(&self, group: u16) -> f64 {
+        match self {
+            DigitBinIndex::Small(index) => index.group_weight(group),
+            DigitBinIndex::Medium(index) => index.group_weight(group),
+            DigitBinIndex::Large(index) => index.group_weight(group),
+            DigitBinIndex::Counted(index) => index.group_weight(group),
+        }
+    }
+
+    /// Returns the number of items in a group, counting copies.
+    pub fn group_count(&self, group: u16) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.group_count(group),
+            DigitBinIndex::Medium(index) => index.group_count(group),
+            DigitBinIndex::Large(index) => index.group_count(group),
+            DigitBinIndex::Counted(index) => index.group_count(group),
+        }
+    }
+
+    /// Selects an item of a group with probability proportional to its weight within the
+    /// group, without removal.
+    ///
+    /// # Returns
+    ///
+    /// The selected (ID, weight), or `None` if the group is empty.
+    pub fn select_from_group(&self, group: u16) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_from_group(group),
+            DigitBinIndex::Medium(index) => index.select_from_group(group),
+            DigitBinIndex::Large(index) => index.select_from_group(group),
+            DigitBinIndex::Counted(index) => index.select_from_group(group),
+        }
+    }
+
+    /// Like [`select_from_group`](Self::select_from_group), but removes the selected item.
+    pub fn select_from_group_and_remove(&mut self, group: u16) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_from_group_and_remove(group),
+            DigitBinIndex::Medium(index) => index.select_from_group_and_remove(group),
+            DigitBinIndex::Large(index) => index.select_from_group_and_remove(group),
+            DigitBinIndex::Counted(index) => index.select_from_group_and_remove(group),
+        }
+    }
+
+    /// Selects and removes an exact number of items from each of several groups.
+    ///
+    /// This is [`select_with_quotas`](Self::select_with_quotas) for groups tagged with
+    /// [`add_with_group`](Self::add_with_group): within a group, items are drawn by weight
+    /// without replacement, one after another. The quotas are validated up front, and if
+    /// any cannot be met nothing is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `quotas` - A slice of `(group, quota)` pairs.
+    ///
+    /// # Returns
+    ///
+    /// The items drawn for each quota, in the order the quotas were given, or
+    /// [`DigitBinIndexError::InfeasibleQuota`] naming the position of the first quota that
+    /// exceeds what its group holds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, DigitBinIndexError};
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// for id in 0..100 {
+    ///     index.add_with_group(id, 0.5, (id % 4) as u16);
+    /// }
+    /// let drawn = index.select_many_stratified(&[(0, 10), (3, 5)]).unwrap();
+    /// assert!(drawn[0].iter().all(|&(id, _)| id % 4 == 0));
+    /// assert_eq!((drawn[0].len(), drawn[1].len()), (10, 5));
+    /// assert_eq!(index.group_count(0), 15);
+    ///
+    /// let err = index.select_many_stratified(&[(1, 25), (2, 26)]).unwrap_err();
+    /// assert_eq!(err, DigitBinIndexError::InfeasibleQuota { group: 1, requested: 26, available: 25 });
+    /// ```
+    pub fn select_many_stratified(&mut self, quotas: &[(u16, u64)]) -> Result<Vec<Vec<(u64, f64)>>, DigitBinIndexError> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_stratified(quotas),
+            DigitBinIndex::Medium(index) => index.select_many_stratified(quotas),
+            DigitBinIndex::Large(index) => index.select_many_stratified(quotas),
+            DigitBinIndex::Counted(index) => index.select_many_stratified(quotas),
+        }
+    }
+
+    /// Returns the next item of a deterministic, weight-proportional schedule.
+    ///
+    /// Instead of drawing at random, this interleaves items using smooth weighted
+    /// round-robin down the tree: every node picks among its children in proportion to
+    /// their total weight, so each bin is visited in proportion to its total weight, and
+    /// successive visits to a bin cycle through its items in order. Over any window of
+    /// calls every item therefore appears in proportion to its weight, with picks spread
+    /// out evenly. A call touches only the nodes on one path, costing `O(precision)` plus
+    /// a positional lookup in the bin. The schedule state lives in the index and adapts
+    /// when items are added or removed.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the scheduled (ID, weight), or `None` if the index is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(1);
+    /// index.add(1, 0.1);
+    /// index.add(2, 0.3);
+    /// let picks: Vec<u64> = (0..4).map(|_| index.next_scheduled().unwrap().0).collect();
+    /// assert_eq!(picks, vec![2, 1, 2, 2]);
+    /// ```
+    pub fn next_scheduled(&mut self) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.next_scheduled(),
+            DigitBinIndex::Medium(index) => index.next_scheduled(),
+            DigitBinIndex::Large(index) => index.next_scheduled(),
+            DigitBinIndex::Counted(index) => index.next_scheduled(),
+        }
+    }
+
+    /// Selects and removes a single item, drawing from the supplied RNG.
+    ///
+    /// Identical to [`select_and_remove`](Self::select_and_remove) except for the source
+    /// of randomness, so that a seeded RNG makes a run reproducible.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - The random number generator to draw from.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the removed (ID, weight), or `None` if the index is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let mut a = DigitBinIndex::new();
+    /// a.add_many(&[(1, 0.2), (2, 0.4), (3, 0.6)]);
+    /// let mut b = a.clone();
+    ///
+    /// let mut rng_a = StdRng::seed_from_u64(42);
+    /// let mut rng_b = StdRng::seed_from_u64(42);
+    /// for _ in 0..3 {
+    ///     assert_eq!(a.select_and_remove_with_rng(&mut rng_a), b.select_and_remove_with_rng(&mut rng_b));
+    /// }
+    /// ```
+    pub fn select_and_remove_with_rng<R: Rng>(&mut self, rng: &mut R) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_and_remove_with_rng(rng),
+            DigitBinIndex::Medium(index) => index.select_and_remove_with_rng(rng),
+            DigitBinIndex::Large(index) => index.select_and_remove_with_rng(rng),
+            DigitBinIndex::Counted(index) => index.select_and_remove_with_rng(rng),
+        }
+    }
+
+    /// Selects an item by weight and multiplies its weight, instead of removing it.
+    ///
+    /// This is the step of a Pólya urn or another reinforcement process: the drawn item
+    /// moves to the bin of `weight * multiplier` in the same call, so a multiplier above 1
+    /// makes it more likely to be drawn again and one below 1 less likely. The product is
+    /// binned like a fresh `add`, so with binned weights a long chain of multiplications
+    /// drifts downwards by up to one bin per step;
+    /// [exact weights](Self::enable_exact_weights) multiply the weight the item was added
+    /// with instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `multiplier` - The factor to apply to the drawn item's weight.
+    ///
+    /// # Returns
+    ///
+    /// The drawn ID and the weight it has now, or `None` if the index is empty. If the new
+    /// weight cannot be stored (it reaches 1, or truncates to zero), or moving the item
+    /// would exceed the memory budget, the item keeps its weight, which is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// index.add(1, 0.1);
+    /// assert_eq!(index.select_and_reweight(2.0), Some((1, 0.2)));
+    /// assert_eq!(index.select_and_reweight(2.0), Some((1, 0.4)));
+    /// assert_eq!(index.select_and_reweight(5.0), Some((1, 0.4))); // 2.0 is not a valid weight
+    /// assert_eq!(index.count(), 1);
+    /// assert_eq!(index.total_weight(), 0.4);
+    /// ```
+    pub fn select_and_reweight(&mut self, multiplier: f64) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_and_reweight(multiplier),
+            DigitBinIndex::Medium(index) => index.select_and_reweight(multiplier),
+            DigitBinIndex::Large(index) => index.select_and_reweight(multiplier),
+            DigitBinIndex::Counted(index) => index.select_and_reweight(multiplier),
+        }
+    }
+
+    /// Like [`select_and_reweight`](Self::select_and_reweight), but draws from the given RNG.
+    pub fn select_and_reweight_with_rng<R: Rng>(&mut self, multiplier: f64, rng: &mut R) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_and_reweight_with_rng(multiplier, rng),
+            DigitBinIndex::Medium(index) => index.select_and_reweight_with_rng(multiplier, rng),
+            DigitBinIndex::Large(index) => index.select_and_reweight_with_rng(multiplier, rng),
+            DigitBinIndex::Counted(index) => index.select_and_reweight_with_rng(multiplier, rng),
+        }
+    }
+
+    /// Selects multiple unique items without removal, drawing from the supplied RNG.
+    ///
+    /// Identical to [`select_many`](Self::select_many) except for the source of randomness.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select.
+    /// * `rng` - The random number generator to draw from.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight) pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.2), (2, 0.4), (3, 0.6)]);
+    /// let first = index.select_many_with_rng(2, &mut StdRng::seed_from_u64(7));
+    /// let again = index.select_many_with_rng(2, &mut StdRng::seed_from_u64(7));
+    /// assert_eq!(first, again);
+    /// assert_eq!(index.count(), 3);
+    /// ```
+    pub fn select_many_with_rng<R: Rng>(&mut self, num_to_draw: u64, rng: &mut R) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_with_rng(num_to_draw, rng),
+            DigitBinIndex::Medium(index) => index.select_many_with_rng(num_to_draw, rng),
+            DigitBinIndex::Large(index) => index.select_many_with_rng(num_to_draw, rng),
+            DigitBinIndex::Counted(index) => index.select_many_with_rng(num_to_draw, rng),
+        }
+    }
+
+    /// Selects and removes multiple unique items, drawing from the supplied RNG.
+    ///
+    /// Identical to [`select_many_and_remove`](Self::select_many_and_remove) except for the
+    /// source of randomness.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select and remove.
+    /// * `rng` - The random number generator to draw from.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the removed (ID, weight) pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::{rngs::StdRng, SeedableRng};
+    ///
+    /// let mut a = DigitBinIndex::new();
+    /// a.add_many(&[(1, 0.2), (2, 0.4), (3, 0.6), (4, 0.8)]);
+    /// let mut b = a.clone();
+    /// let drawn = a.select_many_and_remove_with_rng(2, &mut StdRng::seed_from_u64(3));
+    /// assert_eq!(drawn, b.select_many_and_remove_with_rng(2, &mut StdRng::seed_from_u64(3)));
+    /// assert_eq!(a.count(), 2);
+    /// ```
+    pub fn select_many_and_remove_with_rng<R: Rng>(&mut self, num_to_draw: u64, rng: &mut R) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_and_remove_with_rng(num_to_draw, rng),
+            DigitBinIndex::Medium(index) => index.select_many_and_remove_with_rng(num_to_draw, rng),
+            DigitBinIndex::Large(index) => index.select_many_and_remove_with_rng(num_to_draw, rng),
+            DigitBinIndex::Counted(index) => index.select_many_and_remove_with_rng(num_to_draw, rng),
+        }
+    }
+
+    /// Selects multiple unique items without removal, with the distribution of sequential draws.
+    ///
+    /// This is a separate sampler, not a faster path of [`select_many`](Self::select_many):
+    /// the result has the distribution of `num_to_draw` calls to
+    /// [`select_and_remove`](Self::select_and_remove) (Wallenius' draw), rather than the
+    /// batch distribution of `select_many` (Fisher's draw), but the index is left
+    /// unchanged. Choose it by the distribution the model needs. See
+    /// [`select_many_sequential_and_remove`](Self::select_many_sequential_and_remove) for
+    /// how it is drawn.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight) pairs, or `None` if `num_to_draw`
+    /// exceeds the item count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.2), (2, 0.4), (3, 0.6)]);
+    /// let selected = index.select_many_sequential(2).unwrap();
+    /// assert_eq!(selected.len(), 2);
+    /// assert_ne!(selected[0].0, selected[1].0);
+    /// assert_eq!(index.count(), 3);
+    /// ```
+    pub fn select_many_sequential(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_sequential(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many_sequential(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many_sequential(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many_sequential(num_to_draw),
+        }
+    }
+
+    /// Selects and removes multiple unique items in one pass, with the distribution of
+    /// sequential draws.
+    ///
+    /// This draws the same distribution as calling [`select_and_remove`](Self::select_and_remove)
+    /// `num_to_draw` times, but without walking the tree per item. Like
+    /// [`select_many_sequential`](Self::select_many_sequential) it is a separate sampler from
+    /// [`select_many_and_remove`](Self::select_many_and_remove), which keeps the batch
+    /// distribution for every batch size. Every item is given an
+    /// exponential clock that rings at a rate equal to its weight, and the items whose
+    /// clocks ring first are taken. The clocks are kept per bin, so the draw costs
+    /// `O(k log bins)` plus one pass over the tree, and never rejects a draw, which makes
+    /// it well suited to taking a large share of the population. Which members of a bin
+    /// are taken is uniform.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select and remove.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight) pairs, in ascending weight order,
+    /// or `None` if `num_to_draw` exceeds the item count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// index.add_range(0..500_000, 0.9);
+    /// index.add_range(500_000..1_000_000, 0.001);
+    ///
+    /// let selected = index.select_many_sequential_and_remove(600_000).unwrap();
+    /// assert_eq!(selected.len(), 600_000);
+    /// assert_eq!(index.count(), 400_000);
+    /// ```
+    pub fn select_many_sequential_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_sequential_and_remove(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many_sequential_and_remove(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many_sequential_and_remove(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many_sequential_and_remove(num_to_draw),
+        }
+    }
+
+    /// Selects multiple unique items without removal, in the order that sequential draws
+    /// would pick them.
+    ///
+    /// The batch draws return their items grouped by bin, which loses the order in which
+    /// they were chosen. This draws like [`select_many_sequential`](Self::select_many_sequential)
+    /// and keeps that order: the first item is distributed like a single
+    /// [`select`](Self::select), the second like a select among the rest, and so on, so
+    /// the `k`-th item can stand for the `k`-th event of a simulation.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight) pairs in draw order, or `None` if
+    /// `num_to_draw` exceeds the item count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// index.add(1, 0.999);
+    /// index.add_range(2..10, 0.001);
+    /// let selected = index.select_many_ordered(3).unwrap();
+    /// assert_eq!(selected.len(), 3);
+    /// assert_eq!(index.count(), 9);
+    /// ```
+    pub fn select_many_ordered(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_ordered(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many_ordered(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many_ordered(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many_ordered(num_to_draw),
+        }
+    }
+
+    /// Selects and removes multiple unique items, in the order that repeated calls to
+    /// [`select_and_remove`](Self::select_and_remove) would pick them.
+    ///
+    /// This is [`select_many_sequential_and_remove`](Self::select_many_sequential_and_remove)
+    /// with the items in draw order rather than grouped by bin, at the cost of one extra
+    /// pass over the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select and remove.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight) pairs in draw order, or `None` if
+    /// `num_to_draw` exceeds the item count.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// index.add_range(0..1000, 0.001);
+    /// index.add(1000, 0.999);
+    /// // The heavy item is almost always the first one drawn.
+    /// let selected = index.select_many_ordered_and_remove(10).unwrap();
+    /// assert_eq!(selected.len(), 10);
+    /// assert_eq!(index.count(), 991);
+    /// ```
+    pub fn select_many_ordered_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_ordered_and_remove(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many_ordered_and_remove(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many_ordered_and_remove(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many_ordered_and_remove(num_to_draw),
+        }
+    }
+
+    /// Suggests how many sequential draws (Wallenius') can be replaced by one batch draw
+    /// (Fisher's) while keeping the error within `target_bias`.
+    ///
+    /// While draws are made one at a time, every removal shifts the probabilities of the
+    /// items that remain; a batch draw of `k` items ignores those shifts. The shift after
+    /// `k` draws is about the fraction of the total weight they remove, which is
+    /// `k / n_eff` on average, where `n_eff = (Σw)² / Σw²` is the effective number of
+    /// items (Kish's effective sample size). With even weights `n_eff` is the item count;
+    /// when a few heavy items dominate the mass, it is much smaller, and so is the batch.
+    /// The suggestion is `target_bias * n_eff`, at least 1 and at most the item count.
+    /// Recompute it between batches, since draws change the skew.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_bias` - The tolerated relative shift in selection probabilities, e.g.
+    ///   `0.01` for 1%.
+    ///
+    /// # Returns
+    ///
+    /// The suggested batch size, or 0 if the index is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_range(0..10_000, 0.1);
+    /// assert_eq!(index.suggest_batch_size(0.01), 100);
+    ///
+    /// // A tenth as many heavy items now carry most of the mass, so batches must shrink.
+    /// index.add_range(10_000..11_000, 0.9);
+    /// assert!(index.suggest_batch_size(0.01) < 50);
+    /// ```
+    pub fn suggest_batch_size(&self, target_bias: f64) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.suggest_batch_size(target_bias),
+            DigitBinIndex::Medium(index) => index.suggest_batch_size(target_bias),
+            DigitBinIndex::Large(index) => index.suggest_batch_size(target_bias),
+            DigitBinIndex::Counted(index) => index.suggest_batch_size(target_bias),
+        }
+    }
+
+    /// Selects multiple items like [`select_many`](Self::select_many), spreading the work
+    /// across threads.
+    ///
+    /// The draw is split along the tree: the targets are distributed over the top levels as
+    /// in `select_many`, and the subtrees that received targets are then sampled in
+    /// parallel on the rayon thread pool, each with its own RNG. The result has the same
+    /// distribution as `select_many`. It pays off for large `num_to_draw` spread over many
+    /// bins; for small draws the sequential version is faster.
+    ///
+    /// Requires the `rayon` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of items to select.
+    ///
+    /// # Returns
+    ///
+    /// The selected `(id, weight)` items, grouped by subtree, or `None` if `num_to_draw`
+    /// exceeds the number of items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// let items: Vec<(u64, f64)> = (0..100_000).map(|id| (id, (id % 997 + 1) as f64 / 1000.0)).collect();
+    /// index.add_many(&items);
+    ///
+    /// let drawn = index.par_select_many(20_000).unwrap();
+    /// assert_eq!(drawn.len(), 20_000);
+    /// assert_eq!(index.count(), 100_000);
+    ///
+    /// let removed = index.par_select_many_and_remove(20_000).unwrap();
+    /// let unique: std::collections::HashSet<u64> = removed.iter().map(|&(id, _)| id).collect();
+    /// assert_eq!(unique.len(), 20_000);
+    /// assert_eq!(index.count(), 80_000);
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn par_select_many(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.par_select_many(num_to_draw),
+            DigitBinIndex::Medium(index) => index.par_select_many(num_to_draw),
+            DigitBinIndex::Large(index) => index.par_select_many(num_to_draw),
+            DigitBinIndex::Counted(index) => index.par_select_many(num_to_draw),
+        }
+    }
+
+    /// Selects and removes multiple unique items like
+    /// [`select_many_and_remove`](Self::select_many_and_remove), spreading the work across
+    /// threads as [`par_select_many`](Self::par_select_many) does.
+    ///
+    /// Requires the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn par_select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.par_select_many_and_remove(num_to_draw),
+            DigitBinIndex::Medium(index) => index.par_select_many_and_remove(num_to_draw),
+            DigitBinIndex::Large(index) => index.par_select_many_and_remove(num_to_draw),
+            DigitBinIndex::Counted(index) => index.par_select_many_and_remove(num_to_draw),
+        }
+    }
+
+    /// Selects multiple unique items without removal and also returns their IDs as a bitmap.
+    ///
+    /// Behaves like [`select_many`](Self::select_many), but additionally collects the chosen
+    /// IDs into a [`RoaringTreemap`] so that downstream set algebra (intersecting with
+    /// eligibility sets, subtracting from other groups) can stay in compressed-bitmap form.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight) pairs and the bitmap of their IDs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.3);
+    /// index.add(2, 0.7);
+    /// let (selected, ids) = index.select_many_with_bitmap(2).unwrap();
+    /// assert_eq!(selected.len(), 2);
+    /// assert!(ids.contains(1) && ids.contains(2));
+    /// ```
+    pub fn select_many_with_bitmap(&mut self, num_to_draw: u64) -> Option<(Vec<(u64, f64)>, RoaringTreemap)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_with_bitmap(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many_with_bitmap(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many_with_bitmap(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many_with_bitmap(num_to_draw),
+        }
+    }
+
+    /// Selects and removes multiple unique items and also returns their IDs as a bitmap.
+    ///
+    /// Behaves like [`select_many_and_remove`](Self::select_many_and_remove), but
+    /// additionally collects the removed IDs into a [`RoaringTreemap`].
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select and remove.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the removed (ID, weight) pairs and the bitmap of their IDs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.3), (2, 0.7), (3, 0.5)]);
+    /// let (_, removed) = index.select_many_and_remove_with_bitmap(2).unwrap();
+    ///
+    /// let eligible: RoaringTreemap = [1, 2].into_iter().collect();
+    /// let removed_eligible = &removed & &eligible;
+    /// assert!(removed_eligible.len() >= 1);
+    /// assert_eq!(index.count(), 1);
+    /// ```
+    pub fn select_many_and_remove_with_bitmap(&mut self, num_to_draw: u64) -> Option<(Vec<(u64, f64)>, RoaringTreemap)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_and_remove_with_bitmap(num_to_draw),
+            DigitBinIndex::Medium(index) => index.select_many_and_remove_with_bitmap(num_to_draw),
+            DigitBinIndex::Large(index) => index.select_many_and_remove_with_bitmap(num_to_draw),
+            DigitBinIndex::Counted(index) => index.select_many_and_remove_with_bitmap(num_to_draw),
+        }
+    }
+
+    /// Returns the total number of items currently in the index.
+    ///
+    /// # Returns
+    ///
+    /// The count of items as a `u32`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// assert_eq!(index.count(), 0);
+    /// ```
+    pub fn count(&self) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.count(),
+            DigitBinIndex::Medium(index) => index.count(),
+            DigitBinIndex::Large(index) => index.count(),
+            DigitBinIndex::Counted(index) => index.count(),
+        }
+    }
+
+    /// Returns the sum of all weights in the index.
+    ///
+    /// This represents the total accumulated probability mass.
+    ///
+    /// # Returns
+    ///
+    /// The total weight as a `f64`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// assert_eq!(index.total_weight(), 0.5);
+    /// ```
+    pub fn total_weight(&self) -> f64 {
+        match self {
+            DigitBinIndex::Small(index) => index.total_weight(),
+            DigitBinIndex::Medium(index) => index.total_weight(),
+            DigitBinIndex::Large(index) => index.total_weight(),
+            DigitBinIndex::Counted(index) => index.total_weight(),
+        }
+    }
+
+    /// The total weight in scaled units, as kept at the root.
+    fn total_mass(&self) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.root.accumulated_value,
+            DigitBinIndex::Medium(index) => index.root.accumulated_value,
+            DigitBinIndex::Large(index) => index.root.accumulated_value,
+            DigitBinIndex::Counted(index) => index.root.accumulated_value,
+        }
+    }
+
+    /// Adds `count` copies of an item whose weight is already scaled.
+    fn insert_scaled(&mut self, id: u64, scaled: u64, count: u64) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.insert_scaled(id, scaled, count),
+            DigitBinIndex::Medium(index) => index.insert_scaled(id, scaled, count),
+            DigitBinIndex::Large(index) => index.insert_scaled(id, scaled, count),
+            DigitBinIndex::Counted(index) => index.insert_scaled(id, scaled, count),
+        }
+    }
+
+    /// Removes one copy of an item whose weight is already scaled.
+    fn remove_scaled(&mut self, id: u64, scaled: u64) -> bool {
+        match self {
+            DigitBinIndex::Small(index) => index.remove_scaled(id, scaled),
+            DigitBinIndex::Medium(index) => index.remove_scaled(id, scaled),
+            DigitBinIndex::Large(index) => index.remove_scaled(id, scaled),
+            DigitBinIndex::Counted(index) => index.remove_scaled(id, scaled),
+        }
+    }
+
+    /// Prints detailed statistics about the index's structure, memory usage,
+    /// and data distribution.
+    pub fn print_stats(&self) {
+        println!("DigitBinIndex Statistics:");
+        println!("=========================");
+        match self {
+            DigitBinIndex::Small(idx) => {
+                println!("- Index Type: Small (Vec<u32>)");
                 idx.print_stats_generic();
             },
             DigitBinIndex::Medium(idx) => {
@@ -654,843 +3806,7770 @@ impl DigitBinIndex {
                 println!("- Index Type: Large (RoaringTreemap)");
                 idx.print_stats_generic();
             },
+            DigitBinIndex::Counted(idx) => {
+                println!("- Index Type: Counted (CountedBin)");
+                idx.print_stats_generic();
+            },
+        }
+    }
+
+    /// Returns statistics about the tree structure and its memory use.
+    ///
+    /// Unlike [`print_stats`](Self::print_stats), the figures are returned as data, so they
+    /// can be compared across precisions or logged. Use them to see how items collide in
+    /// bins (`bin_size_histogram`, `largest_bin`) and how wide the tree is at each level
+    /// (`nodes_per_depth`) when tuning the precision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::medium(2);
+    /// index.add_range(0..100, 0.25);
+    /// index.add_many(&[(100, 0.5), (101, 0.55), (102, 0.55)]);
+    /// let stats = index.stats();
+    /// assert_eq!(stats.items, 103);
+    /// assert_eq!(stats.nodes_per_depth, vec![1, 2, 3]);
+    /// assert_eq!((stats.occupied_bins, stats.largest_bin), (3, 100));
+    /// // One bin of 1 item, one of 2-3, and one of 64-127.
+    /// assert_eq!(stats.bin_size_histogram, vec![1, 1, 0, 0, 0, 0, 1]);
+    /// assert!(stats.total_bytes() > stats.tree_bytes);
+    /// ```
+    pub fn stats(&self) -> IndexStats {
+        match self {
+            DigitBinIndex::Small(index) => index.stats(),
+            DigitBinIndex::Medium(index) => index.stats(),
+            DigitBinIndex::Large(index) => index.stats(),
+            DigitBinIndex::Counted(index) => index.stats(),
+        }
+    }
+
+    /// Selects a single item by weight and returns an audit record of the draw.
+    ///
+    /// The record ([`DrawAudit`]) captures the RNG target, the digit path to the chosen
+    /// bin, the bin's eligible mass and size at the time of the draw, and the position drawn
+    /// within the bin — enough to verify each draw independently. IDs in `exclusions` are
+    /// never chosen: every bin's mass counts only its members outside `exclusions`, so the
+    /// draw is exactly by weight among the remaining items, however much is excluded.
+    ///
+    /// # Arguments
+    ///
+    /// * `exclusions` - IDs that must not be chosen (may be empty).
+    /// * `with_removal` - Whether to remove the chosen item from the index.
+    ///
+    /// # Returns
+    ///
+    /// The audit record, or `None` if no item outside `exclusions` has a positive weight.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_many(&[(1, 0.25), (2, 0.5)]);
+    /// let exclusions: RoaringTreemap = [1].into_iter().collect();
+    ///
+    /// let audit = index.select_audited(&exclusions, true).unwrap();
+    /// assert_eq!(audit.id, 2);
+    /// assert_eq!(audit.path, vec![5, 0]);
+    /// assert_eq!(audit.total_mass, 50);
+    /// assert_eq!(index.count(), 1);
+    /// ```
+    pub fn select_audited(&mut self, exclusions: &RoaringTreemap, with_removal: bool) -> Option<DrawAudit> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_audited(exclusions, with_removal),
+            DigitBinIndex::Medium(index) => index.select_audited(exclusions, with_removal),
+            DigitBinIndex::Large(index) => index.select_audited(exclusions, with_removal),
+            DigitBinIndex::Counted(index) => index.select_audited(exclusions, with_removal),
+        }
+    }
+
+    /// Runs a weighted multi-winner lottery.
+    ///
+    /// Winners are drawn one at a time with [`select_audited`](Self::select_audited), so
+    /// each draw is proportional to weight among the entries still in play. Entrants in
+    /// the rules' exclusion set are never drawn. A drawn entrant rejected by the
+    /// eligibility check is excluded and the draw is repeated, up to the re-draw limit.
+    /// Under [`DuplicatePolicy::SingleWin`] a winner's other entries are excluded too, so
+    /// the same ID cannot win twice; under [`DuplicatePolicy::MultipleWins`] they stay in.
+    /// With `remove_winners`, each winning entry is removed from the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `k` - The number of winners to draw.
+    /// * `rules` - Exclusions, duplicate policy, eligibility check, and re-draw limit.
+    ///
+    /// # Returns
+    ///
+    /// The winners and an audit trail with one record per draw, ineligible ones included.
+    /// If no eligible entry is left or the re-draw limit is hit, the outcome holds fewer
+    /// than `k` winners and is marked `exhausted`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, LotteryDecision, LotteryRules};
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_many(&[(1, 0.5), (2, 0.3), (3, 0.2), (4, 0.9)]);
+    ///
+    /// // Entrant 4 is disqualified up front and entrant 1 fails the eligibility check.
+    /// let rules = LotteryRules::new().exclude([4]).eligibility(|id, _| id != 1);
+    /// let outcome = index.lottery(2, rules);
+    ///
+    /// let mut winners: Vec<u64> = outcome.winners.iter().map(|&(id, _)| id).collect();
+    /// winners.sort();
+    /// assert_eq!(winners, vec![2, 3]);
+    /// assert!(!outcome.exhausted);
+    /// assert!(outcome.trail.iter().all(|draw| draw.audit.id != 4));
+    /// assert!(outcome.trail.iter().all(|draw| (draw.decision == LotteryDecision::Ineligible) == (draw.audit.id == 1)));
+    /// ```
+    pub fn lottery(&mut self, k: usize, rules: LotteryRules<'_>) -> LotteryOutcome {
+        match self {
+            DigitBinIndex::Small(index) => index.lottery(k, rules),
+            DigitBinIndex::Medium(index) => index.lottery(k, rules),
+            DigitBinIndex::Large(index) => index.lottery(k, rules),
+            DigitBinIndex::Counted(index) => index.lottery(k, rules),
+        }
+    }
+
+    /// Selects a single item by weight from the complement of an ID set, without removal.
+    ///
+    /// Only items whose ID is not in `excluded` can be chosen, with probability proportional
+    /// to their weight among those eligible. Each bin's mass is corrected for its excluded
+    /// members before drawing, so this is exact and needs neither retries nor a second index
+    /// (e.g. to "choose among the untreated").
+    ///
+    /// # Arguments
+    ///
+    /// * `excluded` - The IDs that must not be chosen.
+    ///
+    /// # Returns
+    ///
+    /// The selected (ID, weight), or `None` if no eligible item remains.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.9), (2, 0.1)]);
+    /// let treated: RoaringTreemap = [1].into_iter().collect();
+    /// assert_eq!(index.select_not_in(&treated), Some((2, 0.1)));
+    ///
+    /// let everyone: RoaringTreemap = [1, 2].into_iter().collect();
+    /// assert_eq!(index.select_not_in(&everyone), None);
+    /// ```
+    pub fn select_not_in(&self, excluded: &RoaringTreemap) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_not_in(excluded),
+            DigitBinIndex::Medium(index) => index.select_not_in(excluded),
+            DigitBinIndex::Large(index) => index.select_not_in(excluded),
+            DigitBinIndex::Counted(index) => index.select_not_in(excluded),
+        }
+    }
+
+    /// Selects and removes a single item by weight from the complement of an ID set.
+    ///
+    /// See [`select_not_in`](Self::select_not_in); the chosen item is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.9), (2, 0.1)]);
+    /// let treated: RoaringTreemap = [1].into_iter().collect();
+    /// assert_eq!(index.select_and_remove_not_in(&treated), Some((2, 0.1)));
+    /// assert_eq!(index.count(), 1);
+    /// ```
+    pub fn select_and_remove_not_in(&mut self, excluded: &RoaringTreemap) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_and_remove_not_in(excluded),
+            DigitBinIndex::Medium(index) => index.select_and_remove_not_in(excluded),
+            DigitBinIndex::Large(index) => index.select_and_remove_not_in(excluded),
+            DigitBinIndex::Counted(index) => index.select_and_remove_not_in(excluded),
+        }
+    }
+
+    /// Selects a single item by weight, treating the IDs in `excluded` as unavailable.
+    ///
+    /// The same draw as [`select_not_in`](Self::select_not_in), under the name that pairs
+    /// with [`select_many_excluding`](Self::select_many_excluding). The index is not changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.9), (2, 0.1)]);
+    /// let infected: RoaringTreemap = [1].into_iter().collect();
+    /// assert_eq!(index.select_excluding(&infected), Some((2, 0.1)));
+    /// assert_eq!(index.count(), 2);
+    /// ```
+    pub fn select_excluding(&self, excluded: &RoaringTreemap) -> Option<(u64, f64)> {
+        self.select_not_in(excluded)
+    }
+
+    /// Selects multiple unique items by weight from the complement of an ID set, without
+    /// removal.
+    ///
+    /// The items are drawn one after another as [`select_and_remove_not_in`](Self::select_and_remove_not_in)
+    /// would draw them (Wallenius' draw), but the index is left unchanged: `excluded` and the
+    /// items already drawn are simply unavailable to the following draws. This expresses
+    /// constraints such as "not already infected this tick" without mutating the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to select.
+    /// * `excluded` - The IDs that must not be chosen.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the selected (ID, weight) pairs, or `None` if fewer than
+    /// `num_to_draw` eligible items remain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use roaring::RoaringTreemap;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.9), (2, 0.1), (3, 0.5), (4, 0.5)]);
+    /// let infected: RoaringTreemap = [1, 3].into_iter().collect();
+    /// let mut selected = index.select_many_excluding(2, &infected).unwrap();
+    /// selected.sort_by_key(|&(id, _)| id);
+    /// assert_eq!(selected, vec![(2, 0.1), (4, 0.5)]);
+    /// assert_eq!(index.select_many_excluding(3, &infected), None);
+    /// assert_eq!(index.count(), 4);
+    /// ```
+    pub fn select_many_excluding(&self, num_to_draw: u64, excluded: &RoaringTreemap) -> Option<Vec<(u64, f64)>> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_many_excluding(num_to_draw, excluded),
+            DigitBinIndex::Medium(index) => index.select_many_excluding(num_to_draw, excluded),
+            DigitBinIndex::Large(index) => index.select_many_excluding(num_to_draw, excluded),
+            DigitBinIndex::Counted(index) => index.select_many_excluding(num_to_draw, excluded),
+        }
+    }
+
+    /// Selects a single item by weight among those whose weight lies in `[min, max]`,
+    /// without removal.
+    ///
+    /// The bounds are compared with the binned weights, so `0.57` and `0.575` both count
+    /// as `0.57` at precision 2. Items in range are chosen with probability proportional
+    /// to their weight among those in range. The draw walks the tree once to total the
+    /// mass in range and once to find the target, skipping every subtree that lies
+    /// entirely outside the range, so it needs no filtering and no retries.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - The smallest weight that may be chosen.
+    /// * `max` - The largest weight that may be chosen.
+    ///
+    /// # Returns
+    ///
+    /// The selected (ID, weight), or `None` if no item has a weight in range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_many(&[(1, 0.05), (2, 0.3), (3, 0.9)]);
+    /// assert_eq!(index.select_in_range(0.1, 0.5), Some((2, 0.3)));
+    /// assert_eq!(index.select_in_range(0.3, 0.3), Some((2, 0.3)));
+    /// assert_eq!(index.select_in_range(0.31, 0.89), None);
+    /// ```
+    pub fn select_in_range(&self, min: f64, max: f64) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_in_range(min, max),
+            DigitBinIndex::Medium(index) => index.select_in_range(min, max),
+            DigitBinIndex::Large(index) => index.select_in_range(min, max),
+            DigitBinIndex::Counted(index) => index.select_in_range(min, max),
+        }
+    }
+
+    /// Selects and removes a single item by weight among those whose weight lies in
+    /// `[min, max]`.
+    ///
+    /// See [`select_in_range`](Self::select_in_range); the chosen item is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_many(&[(1, 0.05), (2, 0.3), (3, 0.9)]);
+    /// assert_eq!(index.select_in_range_and_remove(0.0, 0.5).map(|(_, w)| w < 0.5), Some(true));
+    /// assert_eq!(index.count(), 2);
+    /// ```
+    pub fn select_in_range_and_remove(&mut self, min: f64, max: f64) -> Option<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.select_in_range_and_remove(min, max),
+            DigitBinIndex::Medium(index) => index.select_in_range_and_remove(min, max),
+            DigitBinIndex::Large(index) => index.select_in_range_and_remove(min, max),
+            DigitBinIndex::Counted(index) => index.select_in_range_and_remove(min, max),
+        }
+    }
+
+    /// Starts tracking removals, discarding anything recorded since an earlier mark.
+    ///
+    /// From now on every removal — by `remove`, selection with removal, pruning, draining,
+    /// or any other operation — is recorded until the next call to `mark`. Moving an item
+    /// with `update_weight` or `merge_from` is not a removal. Tracking is off until the
+    /// first call, so indexes that never call `mark` pay nothing for it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.2), (2, 0.2), (3, 0.5)]);
+    /// index.remove(3, 0.5);
+    ///
+    /// index.mark();
+    /// index.remove(1, 0.2);
+    /// index.remove(2, 0.2);
+    /// let tick = index.removed_since_mark().unwrap();
+    /// assert_eq!(tick.ids.iter().collect::<Vec<_>>(), vec![1, 2]);
+    /// assert_eq!(tick.bins.len(), 1);
+    /// assert_eq!(tick.bins[0].count, 2);
+    /// assert!((tick.bins[0].mass - 0.4).abs() < 1e-9);
+    /// ```
+    pub fn mark(&mut self) {
+        match self {
+            DigitBinIndex::Small(index) => index.mark(),
+            DigitBinIndex::Medium(index) => index.mark(),
+            DigitBinIndex::Large(index) => index.mark(),
+            DigitBinIndex::Counted(index) => index.mark(),
+        }
+    }
+
+    /// Returns the removals recorded since the last [`mark`](Self::mark).
+    ///
+    /// # Returns
+    ///
+    /// The removed IDs as a bitmap plus the removed count and mass per bin, or `None` if
+    /// `mark` has never been called.
+    pub fn removed_since_mark(&self) -> Option<RemovedSinceMark> {
+        match self {
+            DigitBinIndex::Small(index) => index.removed_since_mark(),
+            DigitBinIndex::Medium(index) => index.removed_since_mark(),
+            DigitBinIndex::Large(index) => index.removed_since_mark(),
+            DigitBinIndex::Counted(index) => index.removed_since_mark(),
+        }
+    }
+
+    /// Plans a batch draw of unique items without modifying the index.
+    ///
+    /// This is the first phase of a two-phase draw: the returned [`DrawPlan`] lists the
+    /// chosen IDs and their bins, and can be reviewed or persisted before it is passed
+    /// to [`apply`](Self::apply). Items are chosen by weight without replacement, like
+    /// [`select_many_and_remove`](Self::select_many_and_remove) on a copy of the index.
+    /// Copies of an ID (a cohort added with [`add_with_count`](Self::add_with_count))
+    /// are separate items, so a plan may list the same ID more than once.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_to_draw` - The number of unique items to choose.
+    ///
+    /// # Returns
+    ///
+    /// The plan, or `None` if the index holds fewer than `num_to_draw` items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.2), (2, 0.4), (3, 0.6)]);
+    /// let plan = index.plan_select_many(2).unwrap();
+    /// assert_eq!(plan.draws.len(), 2);
+    /// assert_eq!(index.count(), 3);
+    ///
+    /// let removed = index.apply(&plan).unwrap();
+    /// assert_eq!(removed.len(), 2);
+    /// assert_eq!(index.count(), 1);
+    /// ```
+    pub fn plan_select_many(&self, num_to_draw: u64) -> Option<DrawPlan> {
+        match self {
+            DigitBinIndex::Small(index) => index.plan_select_many(num_to_draw),
+            DigitBinIndex::Medium(index) => index.plan_select_many(num_to_draw),
+            DigitBinIndex::Large(index) => index.plan_select_many(num_to_draw),
+            DigitBinIndex::Counted(index) => index.plan_select_many(num_to_draw),
+        }
+    }
+
+    /// Applies a [`DrawPlan`], removing its items from the index.
+    ///
+    /// The plan is validated against the current state first: it must come from an index
+    /// with the same precision, and every planned item must still be in its bin. If any
+    /// check fails nothing is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - A plan produced by [`plan_select_many`](Self::plan_select_many).
+    ///
+    /// # Returns
+    ///
+    /// The removed (ID, weight) pairs in plan order, or a [`DigitBinIndexError`] if the
+    /// plan no longer matches the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, DigitBinIndexError};
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// let plan = index.plan_select_many(1).unwrap();
+    /// index.remove(1, 0.5);
+    /// assert_eq!(index.apply(&plan), Err(DigitBinIndexError::StalePlan { id: 1 }));
+    /// ```
+    pub fn apply(&mut self, plan: &DrawPlan) -> Result<Vec<(u64, f64)>, DigitBinIndexError> {
+        match self {
+            DigitBinIndex::Small(index) => index.apply(plan),
+            DigitBinIndex::Medium(index) => index.apply(plan),
+            DigitBinIndex::Large(index) => index.apply(plan),
+            DigitBinIndex::Counted(index) => index.apply(plan),
+        }
+    }
+
+    /// Returns an iterator over the non-empty bins, in ascending weight order.
+    ///
+    /// Each bin is yielded as its per-item weight, its item count, and a [`Members`]
+    /// iterator over its IDs. The iterators are opaque, so callers do not depend on the
+    /// bin storage of the chosen backend.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.2), (2, 0.2), (3, 0.7)]);
+    /// let bins: Vec<(f64, u64, Vec<u64>)> = index
+    ///     .bins()
+    ///     .map(|(weight, count, members)| (weight, count, members.collect()))
+    ///     .collect();
+    /// assert_eq!(bins, vec![(0.2, 2, vec![1, 2]), (0.7, 1, vec![3])]);
+    /// ```
+    pub fn bins(&self) -> Bins<'_> {
+        match self {
+            DigitBinIndex::Small(index) => Bins::small(index.bins()),
+            DigitBinIndex::Medium(index) => Bins::medium(index.bins()),
+            DigitBinIndex::Large(index) => Bins::large(index.bins()),
+            DigitBinIndex::Counted(index) => Bins::counted(index.bins()),
+        }
+    }
+
+    /// Returns the bin weight that `weight` is stored under.
+    ///
+    /// This is the weight truncated to the precision, exactly as [`add`](Self::add) bins
+    /// it, so a model can be checked against the index's rounding without reproducing it.
+    ///
+    /// # Arguments
+    ///
+    /// * `weight` - Any weight.
+    ///
+    /// # Returns
+    ///
+    /// The bin weight, or `None` if the weight cannot be stored (outside `(0, 1)`, or below
+    /// the precision).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let index = DigitBinIndex::with_precision(2);
+    /// assert_eq!(index.quantize(0.257), Some(0.25));
+    /// assert_eq!(index.quantize(0.009), None);
+    /// ```
+    pub fn quantize(&self, weight: f64) -> Option<f64> {
+        match self {
+            DigitBinIndex::Small(index) => index.quantize(weight),
+            DigitBinIndex::Medium(index) => index.quantize(weight),
+            DigitBinIndex::Large(index) => index.quantize(weight),
+            DigitBinIndex::Counted(index) => index.quantize(weight),
+        }
+    }
+
+    /// Returns the number of items in the bin that `weight` is stored under.
+    ///
+    /// Any weight in the bin can be passed; it is [quantized](Self::quantize) first.
+    /// Copies of an ID count separately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_many(&[(1, 0.251), (2, 0.259), (3, 0.3)]);
+    /// assert_eq!(index.bin_count(0.25), 2);
+    /// assert_eq!(index.bin_count(0.5), 0);
+    /// ```
+    pub fn bin_count(&self, weight: f64) -> u64 {
+        match self {
+            DigitBinIndex::Small(index) => index.bin_count(weight),
+            DigitBinIndex::Medium(index) => index.bin_count(weight),
+            DigitBinIndex::Large(index) => index.bin_count(weight),
+            DigitBinIndex::Counted(index) => index.bin_count(weight),
+        }
+    }
+
+    /// Returns the IDs that share the bin `weight` is stored under.
+    ///
+    /// Any weight in the bin can be passed; it is [quantized](Self::quantize) first.
+    ///
+    /// # Returns
+    ///
+    /// A [`Members`] iterator over the bin's IDs, or `None` if the bin holds no items.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(2);
+    /// index.add_many(&[(1, 0.251), (2, 0.259), (3, 0.3)]);
+    /// let members: Vec<u64> = index.bin_members(0.257).unwrap().collect();
+    /// assert_eq!(members, vec![1, 2]);
+    /// assert!(index.bin_members(0.5).is_none());
+    /// ```
+    pub fn bin_members(&self, weight: f64) -> Option<Members<'_>> {
+        match self {
+            DigitBinIndex::Small(index) => index.bin_at(weight).map(Members::small),
+            DigitBinIndex::Medium(index) => index.bin_at(weight).map(Members::medium),
+            DigitBinIndex::Large(index) => index.bin_at(weight).map(Members::large),
+            DigitBinIndex::Counted(index) => index.bin_at(weight).map(Members::counted),
+        }
+    }
+
+    /// Returns an iterator over all `(id, weight)` items, in ascending weight order.
+    ///
+    /// Items stored with several copies are yielded once per copy. The iterator knows its
+    /// length up front, so `len()` matches [`count`](Self::count) before iteration starts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.7), (2, 0.2)]);
+    /// let mut items = index.iter();
+    /// assert_eq!(items.len(), 2);
+    /// assert_eq!(items.next(), Some((2, 0.2)));
+    /// assert_eq!(items.len(), 1);
+    /// assert_eq!(items.collect::<Vec<_>>(), vec![(1, 0.7)]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(self.bins(), self.count())
+    }
+
+    /// Removes every item from the index and returns them as an owning iterator.
+    ///
+    /// The items are yielded in ascending weight order. The tree is detached from the
+    /// index right away and taken apart as the iterator advances, so handing a large
+    /// population on to another stage does not copy it. The index keeps its precision,
+    /// backend and settings and can be refilled immediately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.7), (2, 0.2)]);
+    /// let withdrawn: Vec<(u64, f64)> = index.drain().collect();
+    /// assert_eq!(withdrawn, vec![(2, 0.2), (1, 0.7)]);
+    /// assert_eq!(index.count(), 0);
+    /// ```
+    pub fn drain(&mut self) -> Drain {
+        Drain::new(match self {
+            DigitBinIndex::Small(index) => IntoIter::small(index.drain()),
+            DigitBinIndex::Medium(index) => IntoIter::medium(index.drain()),
+            DigitBinIndex::Large(index) => IntoIter::large(index.drain()),
+            DigitBinIndex::Counted(index) => IntoIter::counted(index.drain()),
+        })
+    }
+
+    /// Consumes the index, yielding every item exactly once in weighted random order.
+    ///
+    /// The order is a full sequential (Wallenius') permutation: the first item is drawn
+    /// like [`select_and_remove`](Self::select_and_remove), the second like a draw among
+    /// the rest, and so on. Items are drawn lazily as the iterator advances, so taking
+    /// only the first few costs only those draws, and
+    /// [`ShuffleIter::into_inner`] hands back the items not yet yielded.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - The random number generator driving the draws.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    /// use rand::SeedableRng;
+    /// use wyrand::WyRand;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.1), (2, 0.2), (3, 0.9)]);
+    ///
+    /// let order: Vec<u64> = index.shuffle_iter(WyRand::seed_from_u64(7)).map(|(id, _)| id).collect();
+    /// assert_eq!(order.len(), 3);
+    /// let mut sorted = order.clone();
+    /// sorted.sort();
+    /// assert_eq!(sorted, vec![1, 2, 3]);
+    /// ```
+    pub fn shuffle_iter<R: Rng>(self, rng: R) -> ShuffleIter<R> {
+        ShuffleIter::new(self, rng)
+    }
+
+    /// Lowers the precision of the index in place.
+    ///
+    /// Bins below the new depth are merged into their ancestor (bitmaps are unioned and
+    /// masses summed), without rebuilding the tree. Each item's weight is truncated to
+    /// the new precision, exactly as if it had been added at that precision. Items whose
+    /// weight truncates to zero can no longer be represented and are removed. Use this to
+    /// reclaim memory when a long simulation no longer needs fine granularity.
+    ///
+    /// # Arguments
+    ///
+    /// * `precision` - The new, lower precision. Passing the current precision is a no-op.
+    ///
+    /// # Returns
+    ///
+    /// The (ID, weight) pairs removed because their weight is below the new resolution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `precision` is 0 or greater than the current precision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// index.add_many(&[(1, 0.123), (2, 0.129), (3, 0.005)]);
+    /// let dropped = index.coarsen_to(1);
+    /// assert_eq!(dropped, vec![(3, 0.005)]);
+    /// assert_eq!(index.precision(), 1);
+    /// assert_eq!(index.count(), 2);
+    /// assert!((index.total_weight() - 0.2).abs() < 1e-9);
+    /// assert!(index.remove(2, 0.1));
+    /// ```
+    pub fn coarsen_to(&mut self, precision: u8) -> Vec<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.coarsen_to(precision),
+            DigitBinIndex::Medium(index) => index.coarsen_to(precision),
+            DigitBinIndex::Large(index) => index.coarsen_to(precision),
+            DigitBinIndex::Counted(index) => index.coarsen_to(precision),
+        }
+    }
+
+    /// Changes the precision of the index in place, in either direction.
+    ///
+    /// Lowering the precision is the same as [`coarsen_to`](Self::coarsen_to). Raising it
+    /// rebuilds the tree at the finer precision from the items already in the index.
+    /// Binning has truncated their weights, so the finer bins can only be filled from the
+    /// weights the items were added with if [exact weights](Self::enable_exact_weights)
+    /// are on; otherwise every item keeps its current (coarse) weight, and only items
+    /// added or updated afterwards use the extra digits. The backend, settings, removal
+    /// tracking and scheduled changes are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_precision` - The new precision (1 to 9).
+    ///
+    /// # Returns
+    ///
+    /// The (ID, weight) pairs removed because their weight is below the new resolution;
+    /// always empty when the precision is raised.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_precision` is 0 or greater than 9.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(1);
+    /// index.enable_exact_weights();
+    /// index.add_many(&[(1, 0.123), (2, 0.189)]);
+    /// assert!((index.total_weight() - 0.2).abs() < 1e-9);
+    ///
+    /// index.rebin_to_precision(3);
+    /// assert_eq!(index.precision(), 3);
+    /// assert!((index.total_weight() - 0.312).abs() < 1e-9);
+    /// ```
+    pub fn rebin_to_precision(&mut self, new_precision: u8) -> Vec<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.rebin_to_precision(new_precision),
+            DigitBinIndex::Medium(index) => index.rebin_to_precision(new_precision),
+            DigitBinIndex::Large(index) => index.rebin_to_precision(new_precision),
+            DigitBinIndex::Counted(index) => index.rebin_to_precision(new_precision),
+        }
+    }
+
+    /// Multiplies the weight of every item by `factor` in one pass.
+    ///
+    /// Each bin is moved as a whole to the bin of its scaled weight, merging with any bin
+    /// already there, and the counts and masses are rebuilt along the way, so renormalizing
+    /// a population costs one walk over its bins instead of a removal and an addition per
+    /// item. With [exact weights](Self::enable_exact_weights), items are rescaled from their
+    /// exact weights and binned like a fresh `add`. Items whose new weight cannot be stored
+    /// (1 or more, or below the resolution) are removed. The memory budget is not applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `factor` - The positive multiplier applied to every weight.
+    ///
+    /// # Returns
+    ///
+    /// The (ID, weight) pairs removed because their new weight cannot be stored, with the
+    /// weights they had before scaling.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not positive and finite.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::with_precision(3);
+    /// index.add_many(&[(1, 0.1), (2, 0.25), (3, 0.6)]);
+    /// assert_eq!(index.scale_all_weights(2.0), vec![(3, 0.6)]);
+    /// assert!((index.total_weight() - 0.7).abs() < 1e-9);
+    /// assert!(index.remove(2, 0.5));
+    /// ```
+    pub fn scale_all_weights(&mut self, factor: f64) -> Vec<(u64, f64)> {
+        match self {
+            DigitBinIndex::Small(index) => index.scale_all_weights(factor),
+            DigitBinIndex::Medium(index) => index.scale_all_weights(factor),
+            DigitBinIndex::Large(index) => index.scale_all_weights(factor),
+            DigitBinIndex::Counted(index) => index.scale_all_weights(factor),
+        }
+    }
+
+    /// Writes a checksummed binary snapshot of the index.
+    ///
+    /// The snapshot records the backend, the precision and every bin with its members,
+    /// each bin in its backend's compact form (the portable roaring format for the
+    /// `Medium` and `Large` backends). Loading places every bin into the tree whole, so
+    /// a prebuilt index loads much faster than it is built item by item. It also records
+    /// the settings, the `next_scheduled` state, pending `at_tick` changes, tracked
+    /// removals, exact weights and group tags, so the restored index behaves like this one.
+    /// The header, the bins and that state each carry a CRC-32, and a whole-file CRC-32
+    /// is appended, so that [`load_from`](Self::load_from) can detect corruption.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination, e.g. a `File` or a `Vec<u8>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.2), (2, 0.7)]);
+    /// let mut bytes = Vec::new();
+    /// index.save_to(&mut bytes).unwrap();
+    ///
+    /// let restored = DigitBinIndex::load_from(bytes.as_slice()).unwrap();
+    /// assert_eq!(restored.count(), 2);
+    /// assert_eq!(restored.iter().collect::<Vec<_>>(), index.iter().collect::<Vec<_>>());
+    /// ```
+    pub fn save_to<W: std::io::Write>(&self, writer: W) -> Result<(), DigitBinIndexError> {
+        snapshot::write(self, writer)
+    }
+
+    /// Restores an index from a snapshot written by [`save_to`](Self::save_to), verifying all checksums.
+    ///
+    /// The snapshot is read as a stream, one bin at a time, and the checksums are computed
+    /// on the way, so memory beyond the restored index is bounded by its largest bin.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The source of the snapshot.
+    ///
+    /// # Returns
+    ///
+    /// The restored index, or a [`DigitBinIndexError`] if the snapshot cannot be read,
+    /// is malformed, or fails a checksum (`ChecksumMismatch`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, DigitBinIndexError};
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// let mut bytes = Vec::new();
+    /// index.save_to(&mut bytes).unwrap();
+    ///
+    /// bytes[22] ^= 0xff;
+    /// let err = DigitBinIndex::load_from(bytes.as_slice()).unwrap_err();
+    /// assert!(matches!(err, DigitBinIndexError::ChecksumMismatch { .. }));
+    /// ```
+    pub fn load_from<R: std::io::Read>(reader: R) -> Result<Self, DigitBinIndexError> {
+        snapshot::read(reader, true)
+    }
+
+    /// Restores an index from a snapshot without verifying checksums.
+    ///
+    /// Structural checks still apply, but corruption inside the data may go unnoticed.
+    /// Use this only for trusted snapshots when load time matters.
+    pub fn load_from_unverified<R: std::io::Read>(reader: R) -> Result<Self, DigitBinIndexError> {
+        snapshot::read(reader, false)
+    }
+
+    /// Writes the index to `writer` in the binary snapshot format.
+    ///
+    /// The same as [`save_to`](Self::save_to), under the name that pairs with
+    /// [`read_from`](Self::read_from).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::medium(3);
+    /// index.add_range(0..1_000_000, 0.25);
+    /// let mut bytes = Vec::new();
+    /// index.write_to(&mut bytes).unwrap();
+    ///
+    /// let restored = DigitBinIndex::read_from(bytes.as_slice()).unwrap();
+    /// assert_eq!(restored.count(), 1_000_000);
+    /// ```
+    pub fn write_to<W: std::io::Write>(&self, writer: W) -> Result<(), DigitBinIndexError> {
+        self.save_to(writer)
+    }
+
+    /// Streams an index from `reader`, verifying all checksums.
+    ///
+    /// The same as [`load_from`](Self::load_from): bins are decoded one at a time as the
+    /// bytes arrive, so a large snapshot can be loaded straight from a file or a socket.
+    pub fn read_from<R: std::io::Read>(reader: R) -> Result<Self, DigitBinIndexError> {
+        Self::load_from(reader)
+    }
+
+    /// Returns the precision (number of decimal places) used for binning.
+    pub fn precision(&self) -> u8 {
+        match self {
+            DigitBinIndex::Small(idx) => idx.precision,
+            DigitBinIndex::Medium(idx) => idx.precision,
+            DigitBinIndex::Large(idx) => idx.precision,
+            DigitBinIndex::Counted(idx) => idx.precision,
+        }
+    }
+
+    /// Returns the branching base of the tree, set with [`Config::radix`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{Config, DigitBinIndex};
+    ///
+    /// assert_eq!(DigitBinIndex::new().radix(), 10);
+    /// assert_eq!(DigitBinIndex::with_config(Config::new().radix(16)).radix(), 16);
+    /// ```
+    pub fn radix(&self) -> u16 {
+        match self {
+            DigitBinIndex::Small(idx) => idx.radix,
+            DigitBinIndex::Medium(idx) => idx.radix,
+            DigitBinIndex::Large(idx) => idx.radix,
+            DigitBinIndex::Counted(idx) => idx.radix,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a DigitBinIndex {
+    type Item = (u64, f64);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for DigitBinIndex {
+    type Item = (u64, f64);
+    type IntoIter = IntoIter;
+
+    /// Consumes the index, yielding its items in ascending weight order and releasing
+    /// each bin once it has been passed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::DigitBinIndex;
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add_many(&[(1, 0.7), (2, 0.2), (3, 0.7)]);
+    /// index.select_and_remove();
+    /// let rest: Vec<(u64, f64)> = index.into_iter().collect();
+    /// assert_eq!(rest.len(), 2);
+    /// assert!(rest.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    /// ```
+    fn into_iter(self) -> IntoIter {
+        match self {
+            DigitBinIndex::Small(index) => IntoIter::small(index.into_iter()),
+            DigitBinIndex::Medium(index) => IntoIter::medium(index.into_iter()),
+            DigitBinIndex::Large(index) => IntoIter::large(index.into_iter()),
+            DigitBinIndex::Counted(index) => IntoIter::counted(index.into_iter()),
+        }
+    }
+}
+
+impl Default for DigitBinIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Adds the items with the grouped bulk path of [`add_many`](DigitBinIndex::add_many).
+impl Extend<(u64, f64)> for DigitBinIndex {
+    fn extend<I: IntoIterator<Item = (u64, f64)>>(&mut self, items: I) {
+        let items: Vec<(u64, f64)> = items.into_iter().collect();
+        self.add_many(&items);
+    }
+}
+
+/// Builds an index with the default precision, choosing the backend from the number of
+/// items as [`with_precision_and_capacity`](DigitBinIndex::with_precision_and_capacity) does.
+/// If any ID does not fit in a `u32`, the `Large` backend is used so no ID is truncated.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::DigitBinIndex;
+///
+/// let index: DigitBinIndex = (0..1000).map(|id| (id, 0.25)).collect();
+/// assert_eq!(index.count(), 1000);
+/// assert_eq!(index.total_weight(), 250.0);
+/// ```
+impl FromIterator<(u64, f64)> for DigitBinIndex {
+    fn from_iter<I: IntoIterator<Item = (u64, f64)>>(items: I) -> Self {
+        let items: Vec<(u64, f64)> = items.into_iter().collect();
+        let config = Config::new().precision(DEFAULT_PRECISION);
+        let config = if items.iter().any(|&(id, _)| id > u32::MAX as u64) {
+            config.backend(Backend::Large)
+        } else {
+            config.capacity(items.len() as u64)
+        };
+        let mut index = Self::with_config(config);
+        index.add_many(&items);
+        index
+    }
+}
+
+/// A data structure that organizes weighted items into bins based on their
+/// decimal digits to enable fast weighted random selection and updates.
+///
+/// This structure is a specialized radix tree optimized for sequential sampling
+/// (like in Wallenius' distribution). It makes a deliberate engineering trade-off:
+/// it sacrifices a small, controllable amount of precision by binning items,
+/// but in return, it achieves O(P) performance for its core operations, where P
+/// is the configured precision. This is significantly faster than the O(log N)
+/// performance of general-purpose structures like a Fenwick Tree for its
+/// ideal use case.
+///
+/// # Type Parameters
+///
+/// * `B` - The bin container type for leaf nodes. Must implement the [`DigitBin`] trait.
+///   Common choices are [`Vec<u32>`] for maximum speed with small bins, or [`RoaringBitmap`]
+///   for memory efficiency with large, sparse bins.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::DigitBinIndexGeneric;
+/// // Use Vec<u32> for leaf bins
+/// let mut index = DigitBinIndexGeneric::<Vec<u32>>::new();
+/// // Or use RoaringBitmap for leaf bins
+/// // let mut index = DigitBinIndexGeneric::<roaring::RoaringBitmap>::new();
+/// ```
+///
+/// With the `serde` feature, an index serializes as its tree, its precision and the
+/// state that [`save_to`](DigitBinIndex::save_to) records besides the items.
+#[derive(Debug, Clone)]
+pub struct DigitBinIndexGeneric<B: DigitBin> {
+    /// The root node of the tree.
+    pub root: Node<B>,
+    /// The blocks of children below the root, shared between clones until one of them
+    /// changes the tree.
+    arena: Arc<Arena<B>>,
+    /// The precision (number of decimal places) used for binning.
+    pub precision: u8,
+    /// The scaling factor (10^precision) as f64 for conversions.
+    scale: f64,
+    /// The branching base of the tree: each level splits on one digit of the scaled weight
+    /// written in this base.
+    radix: u16,
+    /// The number of levels above the bins: the digits of the largest scaled weight,
+    /// 10^precision - 1, in base `radix`.
+    depth: u8,
+    /// Smooth weighted round-robin state per node (keyed by depth and digit prefix) for `next_scheduled`.
+    schedule: BTreeMap<(u8, u64), ScheduleSlot>,
+    /// Weight changes registered with `at_tick`, keyed by the tick they are due.
+    timeline: BTreeMap<u64, Vec<(u64, f64)>>,
+    /// The last tick passed to `advance_to`.
+    clock: u64,
+    /// Number of nodes in the tree, including the root.
+    nodes: usize,
+    /// Optional cap, in bytes, on the estimated memory of the tree structure.
+    memory_budget: Option<usize>,
+    /// Which child owns a draw target on a boundary between two children.
+    tie_break: TieBreak,
+    /// Whether `try_` draws repair inconsistent counts and masses instead of failing.
+    auto_repair: bool,
+    /// Removals recorded since the last `mark`, if tracking is on.
+    removals: Option<RemovalTracker>,
+    /// Where each ID is stored, if the registry is enabled.
+    registry: Option<IdRegistry>,
+}
+
+/// Removals recorded since a `mark`: the IDs, and the number of removed copies per bin.
+/// Bins are keyed by the bit pattern of their weight, which orders like the weight itself.
+#[derive(Debug, Clone, Default)]
+struct RemovalTracker {
+    ids: RoaringTreemap,
+    bins: BTreeMap<u64, u64>,
+}
+
+/// Where each ID is stored, so that items can be removed by ID alone.
+#[derive(Debug, Clone, Default)]
+struct IdRegistry {
+    /// ID -> `(scaled weight, copies)` for every bin holding it; almost always a single entry.
+    bins: std::collections::HashMap<u64, Vec<(u64, u64)>>,
+    /// The weight each `(ID, scaled weight)` was last added with, if exact weights are on.
+    exact: Option<std::collections::HashMap<(u64, u64), f64>>,
+    /// Group tags and per-group pools, once an item has been added with a group.
+    groups: Option<Groups>,
+}
+
+/// The group of every tagged ID, and per group an index of its members, so that draws
+/// within a group and group weights do not need to scan the bins.
+#[derive(Debug, Clone)]
+struct Groups {
+    precision: u8,
+    radix: u16,
+    of: std::collections::HashMap<u64, u16>,
+    pools: std::collections::HashMap<u16, DigitBinIndexGeneric<CountedBin>>,
+}
+
+impl Groups {
+    fn new(precision: u8, radix: u16, of: std::collections::HashMap<u64, u16>) -> Self {
+        Groups { precision, radix, of, pools: std::collections::HashMap::new() }
+    }
+
+    fn add(&mut self, individual_id: u64, scaled: u64, copies: u64) {
+        if let Some(&group) = self.of.get(&individual_id) {
+            let (precision, radix) = (self.precision, self.radix);
+            let pool = self.pools.entry(group).or_insert_with(|| DigitBinIndexGeneric::with_precision_and_radix(precision, radix));
+            pool.insert_scaled(individual_id, scaled, copies);
+        }
+    }
+
+    fn remove(&mut self, individual_id: u64, scaled: u64, copies: u64) {
+        let Some(group) = self.of.get(&individual_id) else {
+            return;
+        };
+        if let Some(pool) = self.pools.get_mut(group) {
+            for _ in 0..copies {
+                pool.remove_scaled(individual_id, scaled);
+            }
+            if pool.count() == 0 {
+                self.pools.remove(group);
+            }
+        }
+    }
+}
+
+impl IdRegistry {
+    fn remember(&mut self, individual_id: u64, scaled: u64, copies: u64) {
+        if copies == 0 {
+            return;
+        }
+        let bins = self.bins.entry(individual_id).or_default();
+        match bins.iter_mut().find(|(bin, _)| *bin == scaled) {
+            Some((_, stored)) => *stored += copies,
+            None => bins.push((scaled, copies)),
+        }
+        if let Some(groups) = &mut self.groups {
+            groups.add(individual_id, scaled, copies);
+        }
+    }
+
+    fn forget(&mut self, individual_id: u64, scaled: u64) {
+        self.forget_copies(individual_id, scaled, 1);
+    }
+
+    fn forget_copies(&mut self, individual_id: u64, scaled: u64, copies: u64) {
+        let Some(bins) = self.bins.get_mut(&individual_id) else {
+            return;
+        };
+        if let Some(pos) = bins.iter().position(|&(bin, _)| bin == scaled) {
+            let copies = copies.min(bins[pos].1);
+            if let Some(groups) = &mut self.groups {
+                groups.remove(individual_id, scaled, copies);
+            }
+            bins[pos].1 -= copies;
+            if bins[pos].1 == 0 {
+                bins.swap_remove(pos);
+                if let Some(exact) = &mut self.exact {
+                    exact.remove(&(individual_id, scaled));
+                }
+            }
+        }
+        if bins.is_empty() {
+            self.bins.remove(&individual_id);
+            if let Some(groups) = &mut self.groups {
+                groups.of.remove(&individual_id);
+            }
+        }
+    }
+
+    /// Moves one copy of an ID between bins, after which the new bin holds `held` copies.
+    /// The ID keeps its group even if the moved copy was its last one.
+    fn relocate(&mut self, individual_id: u64, old_scaled: u64, new_scaled: u64, held: u64) {
+        let group = self.groups.as_ref().and_then(|groups| groups.of.get(&individual_id).copied());
+        self.forget(individual_id, old_scaled);
+        if let (Some(group), Some(groups)) = (group, &mut self.groups) {
+            groups.of.insert(individual_id, group);
+        }
+        let known = self.bins.get(&individual_id).and_then(|bins| bins.iter().find(|&&(bin, _)| bin == new_scaled));
+        let known = known.map_or(0, |&(_, copies)| copies);
+        self.remember(individual_id, new_scaled, held - known);
+    }
+
+    /// Puts an ID in a group, moving the copies it already has out of its previous group.
+    fn set_group(&mut self, individual_id: u64, group: u16, precision: u8, radix: u16) {
+        let groups = self.groups.get_or_insert_with(|| Groups::new(precision, radix, Default::default()));
+        if groups.of.get(&individual_id) == Some(&group) {
+            return;
+        }
+        let placements = self.bins.get(&individual_id).map(Vec::as_slice).unwrap_or_default();
+        for &(scaled, copies) in placements {
+            groups.remove(individual_id, scaled, copies);
+        }
+        groups.of.insert(individual_id, group);
+        for &(scaled, copies) in placements {
+            groups.add(individual_id, scaled, copies);
+        }
+    }
+
+    fn holds(&self, individual_id: u64, scaled: u64) -> bool {
+        self.bins.get(&individual_id).is_some_and(|bins| bins.iter().any(|&(bin, _)| bin == scaled))
+    }
+}
+
+/// 128-bit FNV-1a over little-endian words, used by `state_hash`. Its output is fixed by
+/// the algorithm alone, so it does not vary between platforms, builds, or processes.
+struct StateHasher(u128);
+
+impl StateHasher {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        StateHasher(Self::OFFSET)
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u128).wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+/// ID -> the bins holding it, as sorted `(scaled weight, copies)` pairs.
+type Placements = std::collections::HashMap<u64, Vec<(u64, u64)>>;
+
+/// Round-robin state of one node: the SWRR current weights of its children and, for a
+/// bin, the position of its next item.
+#[derive(Debug, Clone, Default)]
+struct ScheduleSlot {
+    current: Vec<i128>,
+    cursor: u64,
+}
+
+impl<B: DigitBin> IntoIterator for DigitBinIndexGeneric<B> {
+    type Item = (u64, f64);
+    type IntoIter = GenericIntoIter<B>;
+
+    /// Consumes the index, yielding its items in ascending weight order.
+    fn into_iter(self) -> GenericIntoIter<B> {
+        GenericIntoIter::new(self.root, Arc::unwrap_or_clone(self.arena), self.scale)
+    }
+}
+
+impl<B: DigitBin> Default for DigitBinIndexGeneric<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: DigitBin> DigitBinIndexGeneric<B> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_precision(DEFAULT_PRECISION)
+    }
+
+    #[must_use]
+    pub fn with_precision(precision: u8) -> Self {
+        Self::with_precision_and_radix(precision, DEFAULT_RADIX)
+    }
+
+    /// Creates an index whose tree branches on digits in base `radix` (2 to 256) instead
+    /// of base 10. See [`Config::radix`].
+    #[must_use]
+    pub fn with_precision_and_radix(precision: u8, radix: u16) -> Self {
+        assert!(precision > 0, "Precision must be at least 1.");
+        assert!(precision <= MAX_PRECISION as u8, "Precision cannot be larger than {}.", MAX_PRECISION);
+        assert!((MIN_RADIX..=MAX_RADIX).contains(&radix), "Radix must be between {} and {}.", MIN_RADIX, MAX_RADIX);
+        let (root, arena) = Self::empty_tree(radix);
+        Self {
+            root,
+            arena,
+            precision,
+            scale: 10f64.powi(precision as i32),
+            radix,
+            depth: Self::depth_for(precision, radix),
+            schedule: BTreeMap::new(),
+            timeline: BTreeMap::new(),
+            clock: 0,
+            nodes: 1,
+            memory_budget: None,
+            tie_break: TieBreak::LastChild,
+            auto_repair: false,
+            removals: None,
+            registry: None,
+        }        
+    }
+
+    /// An empty root and the arena for its children.
+    fn empty_tree(radix: u16) -> (Node<B>, Arc<Arena<B>>) {
+        let mut arena = Arena::new(radix);
+        (arena.new_internal(), Arc::new(arena))
+    }
+
+    /// Replaces the tree with an empty one in the index's radix, returning the old root
+    /// and arena.
+    fn replace_tree(&mut self) -> (Node<B>, Arena<B>) {
+        let (root, arena) = Self::empty_tree(self.radix);
+        let root = std::mem::replace(&mut self.root, root);
+        let arena = std::mem::replace(&mut self.arena, arena);
+        self.nodes = 1;
+        (root, Arc::unwrap_or_clone(arena))
+    }
+
+    /// The root and the arena, ready to change; the arena is copied first if a clone
+    /// still shares it.
+    fn tree_mut(&mut self) -> (&mut Node<B>, &mut Arena<B>) {
+        (&mut self.root, Arc::make_mut(&mut self.arena))
+    }
+
+    /// The children of an internal node of this index's tree, one slot per digit, or
+    /// `None` for a bin.
+    pub fn children(&self, node: &Node<B>) -> Option<&[Option<Node<B>>]> {
+        self.arena.children(node)
+    }
+
+    /// The number of base-`radix` digits of the largest scaled weight at `precision`.
+    fn depth_for(precision: u8, radix: u16) -> u8 {
+        let mut rest = 10u64.pow(precision as u32) - 1;
+        let mut depth = 0;
+        while rest > 0 {
+            rest /= radix as u64;
+            depth += 1;
+        }
+        depth
+    }
+
+    /// The branching base of the tree. See [`DigitBinIndex::radix`].
+    pub fn radix(&self) -> u16 {
+        self.radix
+    }
+
+    /// Converts a f64 weight to the digits of its scaled value in the index's radix, and returns the scaled u64 value.
+    /// Returns None if the weight is invalid (non-positive or zero after scaling).
+    fn weight_to_digits(&self, weight: f64, digits: &mut Digits) -> Option<u64> {
+        if weight <= 0.0 || weight >= 1.0 {
+            return None;
+        }
+
+        let scaled = (weight * self.scale) as u64;
+        if scaled == 0 {
+            return None;
+        }
+
+        self.scaled_to_digits(scaled, digits);
+        Some(scaled)
+    }
+
+    /// Like `weight_to_digits`, but tells an invalid weight from one that truncates to zero.
+    fn checked_weight_to_digits(&self, weight: f64, digits: &mut Digits) -> Result<u64, DigitBinIndexError> {
+        if !(weight > 0.0 && weight < 1.0) {
+            return Err(DigitBinIndexError::InvalidWeight { weight });
+        }
+        self.weight_to_digits(weight, digits)
+            .ok_or(DigitBinIndexError::WeightBelowResolution { weight, precision: self.precision })
+    }
+
+    /// Splits an already scaled weight into its `depth` digits in the index's radix.
+    fn scaled_to_digits(&self, scaled: u64, digits: &mut Digits) {
+        let mut temp = scaled;
+        for i in (0..self.depth as usize).rev() {
+            digits[i] = (temp % self.radix as u64) as u8;
+            temp /= self.radix as u64;
+        }
+    }
+
+    /// Returns the leaf node holding items of the given scaled weight, if it exists.
+    fn leaf(&self, scaled: u64) -> Option<&Node<B>> {
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        let mut node = &self.root;
+        for &digit in &digits[..self.depth as usize] {
+            node = self.arena.children(node)?[digit as usize].as_ref()?;
+        }
+        Some(node)
+    }
+
+    // --- Standard Functions ---
+
+    pub fn add(&mut self, individual_id: u64, weight: f64) -> bool {
+        self.add_with_count(individual_id, weight, 1) > 0
+    }
+
+    /// Adds `count` copies of an individual and returns how many copies the bin stored.
+    pub fn add_with_count(&mut self, individual_id: u64, weight: f64, count: u64) -> u64 {
+        if individual_id > B::MAX_ID {
+            return 0;
+        }
+        let mut digits = [0u8; MAX_DEPTH];
+        if let Some(scaled) = self.weight_to_digits(weight, &mut digits) {
+            let stored = self.insert_at(scaled, &digits, |bin| bin.insert_count(individual_id, count)).unwrap_or(0);
+            self.remember(individual_id, scaled, stored);
+            self.record_exact(individual_id, scaled, weight);
+            return stored;
+        }
+        0
+    }
+
+    /// Adds an individual, reporting why it cannot be stored. See [`DigitBinIndex::try_add`].
+    pub fn try_add(&mut self, individual_id: u64, weight: f64) -> Result<(), DigitBinIndexError> {
+        let mut digits = [0u8; MAX_DEPTH];
+        let scaled = self.checked_weight_to_digits(weight, &mut digits)?;
+        if individual_id > B::MAX_ID {
+            return Err(DigitBinIndexError::IdOutOfRange { id: individual_id, max: B::MAX_ID });
+        }
+        let stored = self.insert_at(scaled, &digits, |bin| bin.insert_count(individual_id, 1))?;
+        if stored == 0 {
+            return Err(DigitBinIndexError::DuplicateId { id: individual_id });
+        }
+        self.remember(individual_id, scaled, stored);
+        self.record_exact(individual_id, scaled, weight);
+        Ok(())
+    }
+
+    /// Removes an individual, reporting why nothing was removed. See [`DigitBinIndex::try_remove`].
+    pub fn try_remove(&mut self, individual_id: u64, weight: f64) -> Result<(), DigitBinIndexError> {
+        let scaled = self.checked_weight_to_digits(weight, &mut [0u8; MAX_DEPTH])?;
+        if self.remove_scaled(individual_id, scaled) {
+            return Ok(());
+        }
+        match self.locate(individual_id) {
+            Some(stored) => {
+                Err(DigitBinIndexError::WeightMismatch { id: individual_id, given: weight, stored: stored as f64 / self.scale })
+            }
+            None => Err(DigitBinIndexError::NotFound { id: individual_id }),
+        }
+    }
+
+    /// The single entry point for insertions: enforces the memory budget, then fills the leaf.
+    fn insert_at(
+        &mut self,
+        scaled: u64,
+        digits: &Digits,
+        insert: impl FnOnce(&mut B) -> u64,
+    ) -> Result<u64, DigitBinIndexError> {
+        let missing = self.missing_nodes(digits);
+        if let Some(budget) = self.memory_budget {
+            let required = (self.nodes + missing) * self.node_bytes();
+            if missing > 0 && required > budget {
+                return Err(DigitBinIndexError::MemoryBudgetExceeded { budget, required });
+            }
+        }
+        self.nodes += missing;
+        let depth = self.depth;
+        let (root, arena) = self.tree_mut();
+        Ok(Self::insert_recurse(root, arena, scaled, digits, 1, depth, insert))
+    }
+
+    /// Returns true if placing an item at `scaled` stays within the memory budget.
+    fn fits_budget(&self, scaled: u64) -> bool {
+        let Some(budget) = self.memory_budget else { return true };
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        let missing = self.missing_nodes(&digits);
+        missing == 0 || (self.nodes + missing) * self.node_bytes() <= budget
+    }
+
+    /// Estimated size of one node: its slot in the parent's block plus the block it takes
+    /// in the arena.
+    fn node_bytes(&self) -> usize {
+        self.radix as usize * std::mem::size_of::<Option<Node<B>>>()
+    }
+
+    /// Counts the nodes an insertion along `digits` would have to create.
+    fn missing_nodes(&self, digits: &Digits) -> usize {
+        let mut node = &self.root;
+        for (depth, &digit) in digits[..self.depth as usize].iter().enumerate() {
+            let Some(children) = self.arena.children(node) else { return 0 };
+            match &children[digit as usize] {
+                Some(child) => node = child,
+                None => return self.depth as usize - depth,
+            }
+        }
+        0
+    }
+
+    /// Recounts the nodes after the tree has been restructured.
+    fn recount_nodes(&mut self) {
+        fn count<B: DigitBin>(node: &Node<B>, arena: &Arena<B>) -> usize {
+            match arena.children(node) {
+                None => 1,
+                Some(children) => 1 + children.iter().flatten().map(|child| count(child, arena)).sum::<usize>(),
+            }
+        }
+        self.nodes = count(&self.root, &self.arena);
+    }
+
+    /// Whether there is nothing to draw from: no items, or (in an inconsistent tree) no mass.
+    fn is_massless(&self) -> bool {
+        self.root.content_count == 0 || self.root.accumulated_value == 0
+    }
+
+    /// Checks that every node's count and mass add up from its contents.
+    /// See [`DigitBinIndex::check_mass`].
+    pub fn check_mass(&self) -> Result<(), DigitBinIndexError> {
+        fn check<B: DigitBin>(node: &Node<B>, arena: &Arena<B>, prefix: u64, radix: u64) -> Result<(), DigitBinIndexError> {
+            let (actual_count, actual_mass) = match &node.content {
+                NodeContent::Bin(bin) => (bin.len() as u64, prefix * bin.len() as u64),
+                &NodeContent::DigitIndex(block) => arena
+                    .block(block)
+                    .iter()
+                    .flatten()
+                    .fold((0, 0), |(count, mass), child| (count + child.content_count, mass + child.accumulated_value)),
+            };
+            if (actual_count, actual_mass) != (node.content_count, node.accumulated_value) {
+                return Err(DigitBinIndexError::InconsistentMass {
+                    stored_count: node.content_count,
+                    stored_mass: node.accumulated_value,
+                    actual_count,
+                    actual_mass,
+                });
+            }
+            if let Some(children) = arena.children(node) {
+                for (digit, child) in children.iter().enumerate() {
+                    if let Some(child) = child {
+                        check(child, arena, prefix * radix + digit as u64, radix)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        check(&self.root, &self.arena, 0, self.radix as u64)
+    }
+
+    /// Recomputes every node's count and mass from the bins. See [`DigitBinIndex::repair_mass`].
+    pub fn repair_mass(&mut self) -> bool {
+        fn repair<B: DigitBin>(node: &mut Node<B>, arena: &mut Arena<B>, prefix: u64, radix: u64) -> bool {
+            let mut changed = false;
+            let (count, mass) = match &node.content {
+                NodeContent::Bin(bin) => (bin.len() as u64, prefix * bin.len() as u64),
+                &NodeContent::DigitIndex(block) => {
+                    let (mut count, mut mass) = (0, 0);
+                    for digit in 0..arena.width {
+                        arena.with_child(block, digit, |arena, child| {
+                            changed |= repair(child, arena, prefix * radix + digit as u64, radix);
+                            count += child.content_count;
+                            mass += child.accumulated_value;
+                        });
+                    }
+                    (count, mass)
+                }
+            };
+            changed |= (count, mass) != (node.content_count, node.accumulated_value);
+            node.content_count = count;
+            node.accumulated_value = mass;
+            changed
+        }
+        let radix = self.radix as u64;
+        let (root, arena) = self.tree_mut();
+        repair(root, arena, 0, radix)
+    }
+
+    /// See [`DigitBinIndex::set_auto_repair`].
+    pub fn set_auto_repair(&mut self, enabled: bool) {
+        self.auto_repair = enabled;
+    }
+
+    pub fn auto_repair(&self) -> bool {
+        self.auto_repair
+    }
+
+    /// A single draw that reports an inconsistent tree instead of coming back empty.
+    /// See [`DigitBinIndex::try_select`].
+    pub fn try_select_and_optionally_remove_with_rng<R: Rng>(
+        &mut self,
+        with_removal: bool,
+        rng: &mut R,
+    ) -> Result<Option<(u64, f64)>, DigitBinIndexError> {
+        if self.root.content_count == 0 && self.root.accumulated_value == 0 {
+            return Ok(None);
+        }
+        if let Some(selected) = self.select_and_optionally_remove_with_rng(with_removal, rng) {
+            return Ok(Some(selected));
+        }
+        // A consistent index with items always yields one.
+        match self.check_mass() {
+            Ok(()) => Ok(None),
+            Err(error) if !self.auto_repair => Err(error),
+            Err(_) => {
+                self.repair_mass();
+                Ok(self.select_and_optionally_remove_with_rng(with_removal, rng))
+            }
+        }
+    }
+
+    /// Sets or clears the memory budget, in bytes, for the tree structure.
+    pub fn set_memory_budget(&mut self, max_bytes: Option<usize>) {
+        self.memory_budget = max_bytes;
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// See [`DigitBinIndex::set_tie_break`].
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = tie_break;
+    }
+
+    pub fn tie_break(&self) -> TieBreak {
+        self.tie_break
+    }
+
+    /// Whether a draw gives boundary targets to the earlier child, resolving
+    /// `TieBreak::Random` with a coin flip.
+    fn boundary_to_earlier<R: Rng>(&self, rng: &mut R) -> bool {
+        match self.tie_break {
+            TieBreak::FirstChild => true,
+            TieBreak::LastChild => false,
+            TieBreak::Random => rng.random(),
+        }
+    }
+
+    /// Draws a target in the total mass: from `1..=total` if boundaries go to the
+    /// earlier child, from `0..total` otherwise.
+    fn draw_target<R: Rng>(total: u64, to_earlier: bool, rng: &mut R) -> u64 {
+        if to_earlier {
+            rng.random_range(1..=total)
+        } else {
+            rng.random_range(0..total)
+        }
+    }
+
+    /// Finds the child owning `target` and returns its position and the target relative to
+    /// it. A child owns `[start, end)` of its parent's mass, or `(start, end]` when
+    /// `to_earlier` is set.
+    fn child_at(children: &[Option<Node<B>>], target: u64, to_earlier: bool) -> Option<(usize, u64)> {
+        let mut start = 0;
+        for (i, child) in children.iter().enumerate() {
+            let Some(child) = child else {
+                continue;
+            };
+            let end = start + child.accumulated_value;
+            let owned = if to_earlier { start < target && target <= end } else { target < end };
+            if owned {
+                return Some((i, target - start));
+            }
+            start = end;
+        }
+        None
+    }
+
+    /// Estimated memory, in bytes, used by the tree structure (excluding bin contents).
+    pub fn memory_usage(&self) -> usize {
+        self.nodes * self.node_bytes()
+    }
+
+    /// Stable hash of the logical contents. See [`DigitBinIndex::state_hash`].
+    pub fn state_hash(&self) -> u128 {
+        let mut hasher = StateHasher::new();
+        hasher.write_u64(self.precision as u64);
+        let mut entries = Vec::new();
+        self.for_each_bin(|scaled, bin| {
+            if bin.is_empty() {
+                return;
+            }
+            // Bins may keep their IDs in any order, so normalise to sorted (id, copies) runs.
+            entries.clear();
+            bin.for_each_count(|id, copies| entries.push((id, copies)));
+            entries.sort_unstable();
+            entries.dedup_by(|next, run| {
+                let same = next.0 == run.0;
+                if same {
+                    run.1 += next.1;
+                }
+                same
+            });
+            hasher.write_u64(scaled);
+            hasher.write_u64(entries.len() as u64);
+            for &(id, copies) in &entries {
+                hasher.write_u64(id);
+                hasher.write_u64(copies);
+            }
+        });
+        hasher.0
+    }
+
+    /// Walks to (creating as needed) the leaf for `digits` and lets `insert` fill its bin.
+    /// `insert` returns the number of copies it stored, which every node on the path adds to its stats.
+    fn insert_recurse(
+        node: &mut Node<B>,
+        arena: &mut Arena<B>,
+        scaled: u64,
+        digits: &Digits,
+        current_depth: u8,
+        max_depth: u8,
+        insert: impl FnOnce(&mut B) -> u64,
+    ) -> u64 {
+        // Walks down by slot number rather than by recursion, so that no node is moved out
+        // of the arena on the way, then adds the stored copies along the path.
+        let levels = (max_depth + 1).saturating_sub(current_depth) as usize;
+        let added = match &mut node.content {
+            NodeContent::Bin(bin) if levels == 0 => insert(Arc::make_mut(bin)),
+            &mut NodeContent::DigitIndex(mut block) if levels > 0 => {
+                let mut path = [0usize; MAX_DEPTH];
+                for (level, &digit) in digits[current_depth as usize - 1..][..levels].iter().enumerate() {
+                    let slot = block as usize * arena.width + digit as usize;
+                    path[level] = slot;
+                    let leaf = level + 1 == levels;
+                    if arena.slots[slot].is_none() {
+                        // Create the child if it doesn't exist.
+                        let child = if leaf { Node::new_leaf() } else { arena.new_internal() };
+                        arena.slots[slot] = Some(child);
+                    }
+                    if !leaf {
+                        let Some(Node { content: NodeContent::DigitIndex(next), .. }) = arena.slots[slot] else { return 0 };
+                        block = next;
+                    }
+                }
+                let Some(Node { content: NodeContent::Bin(bin), .. }) = &mut arena.slots[path[levels - 1]] else { return 0 };
+                let added = insert(Arc::make_mut(bin));
+                for &slot in &path[..levels] {
+                    if let Some(child) = &mut arena.slots[slot] {
+                        child.content_count += added;
+                        child.accumulated_value += scaled * added;
+                    }
+                }
+                added
+            }
+            _ => 0,
+        };
+        node.content_count += added;
+        node.accumulated_value += scaled * added;
+        added
+    }
+
+    /// Inserts a contiguous range of IDs sharing one weight with a single traversal.
+    fn insert_range_scaled(&mut self, ids: std::ops::Range<u64>, scaled: u64) -> u64 {
+        if ids.is_empty() || ids.end - 1 > B::MAX_ID {
+            return 0;
+        }
+        if self.registry.is_some() {
+            // The registry needs to know which IDs were actually stored.
+            return ids.map(|id| self.insert_scaled(id, scaled, 1)).sum();
+        }
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        self.insert_at(scaled, &digits, |bin| bin.insert_range(ids)).unwrap_or(0)
+    }
+
+    /// Adds a contiguous range of IDs with one weight and returns how many were stored.
+    pub fn add_range(&mut self, ids: std::ops::Range<u64>, weight: f64) -> u64 {
+        let mut digits = [0u8; MAX_DEPTH];
+        match self.weight_to_digits(weight, &mut digits) {
+            Some(scaled) => {
+                let stored = self.insert_range_scaled(ids.clone(), scaled);
+                if self.has_exact_weights() {
+                    ids.for_each(|id| self.record_exact(id, scaled, weight));
+                }
+                stored
+            }
+            None => 0,
+        }
+    }
+
+    /// Builds an index from a histogram of `(weight, count)` pairs, assigning consecutive IDs.
+    pub fn from_counts(precision: u8, histogram: &[(f64, u64)]) -> Self {
+        let mut index = Self::with_precision(precision);
+        let mut digits = [0u8; MAX_DEPTH];
+        let mut next_id = 0u64;
+        for &(weight, count) in histogram {
+            let end = next_id.checked_add(count).expect("the histogram counts overflow the u64 ID space");
+            let ids = next_id..end;
+            next_id = end;
+            if let Some(scaled) = index.weight_to_digits(weight, &mut digits) {
+                index.insert_range_scaled(ids, scaled);
+            }
+        }
+        index
+    }
+
+    /// Adds multiple items to the index in a highly optimized batch operation.
+    ///
+    /// This method is significantly faster than calling `add` in a loop for large
+    /// collections of items. It works by first pre-processing the input items,
+    /// grouping them by their shared weight path (e.g., all items with weight 0.123...).
+    /// It then traverses the tree once per group, rather than once per item,
+    /// drastically reducing function call overhead and improving CPU cache performance
+    /// by performing aggregated updates at each node.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - A slice of `(individual_id, weight)` tuples to add to the index.
+    ///
+    pub fn add_many(&mut self, items: &[(u64, f64)]) {
+        if items.is_empty() {
+            return;
+        }
+
+        let mut digits = [0u8; MAX_DEPTH];
+        let mut binned: Vec<(u64, u64)> = items
+            .iter()
+            .filter(|&&(id, _)| id <= B::MAX_ID)
+            .filter_map(|&(id, weight)| Some((self.weight_to_digits(weight, &mut digits)?, id)))
+            .collect();
+        // A stable sort keeps each bin's IDs in input order.
+        binned.sort_by_key(|&(scaled, _)| scaled);
+
+        let track = self.registry.is_some();
+        let mut stored = Vec::new();
+        for group in binned.chunk_by(|a, b| a.0 == b.0) {
+            let scaled = group[0].0;
+            self.scaled_to_digits(scaled, &mut digits);
+            let _ = self.insert_at(scaled, &digits, |bin| {
+                group
+                    .iter()
+                    .map(|&(_, id)| {
+                        let copies = bin.insert_count(id, 1);
+                        if track {
+                            stored.push((id, copies));
+                        }
+                        copies
+                    })
+                    .sum()
+            });
+            for (id, copies) in stored.drain(..) {
+                self.remember(id, scaled, copies);
+            }
+        }
+        if self.has_exact_weights() {
+            for &(id, weight) in items {
+                if let Some(scaled) = self.weight_to_digits(weight, &mut digits) {
+                    self.record_exact(id, scaled, weight);
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, individual_id: u64, weight: f64) -> bool{
+        let mut digits = [0u8; MAX_DEPTH];
+        if let Some(scaled) = self.weight_to_digits(weight, &mut digits) {
+            let depth = self.depth;
+            let (root, arena) = self.tree_mut();
+            if Self::remove_recurse(root, arena, individual_id, scaled, &digits, 1, depth) {
+                self.log_removal(individual_id, scaled as f64 / self.scale);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Records a removal with the ID registry and the removal tracker, if they are on.
+    fn log_removal(&mut self, individual_id: u64, weight: f64) {
+        if let Some(registry) = &mut self.registry {
+            registry.forget(individual_id, (weight * self.scale).round() as u64);
+        }
+        self.track_removal(individual_id, weight);
+    }
+
+    /// Records a removal with the removal tracker only.
+    fn track_removal(&mut self, individual_id: u64, weight: f64) {
+        if let Some(tracker) = &mut self.removals {
+            tracker.ids.insert(individual_id);
+            *tracker.bins.entry(weight.to_bits()).or_insert(0) += 1;
+        }
+    }
+
+    /// Records the removal of `copies` copies from one bin with the removal tracker only.
+    fn track_removals(&mut self, individual_id: u64, scaled: u64, copies: u64) {
+        if let (true, Some(tracker)) = (copies > 0, &mut self.removals) {
+            tracker.ids.insert(individual_id);
+            *tracker.bins.entry((scaled as f64 / self.scale).to_bits()).or_insert(0) += copies;
+        }
+    }
+
+    /// Starts (or restarts) tracking removals.
+    pub fn mark(&mut self) {
+        self.removals = Some(RemovalTracker::default());
+    }
+
+    /// Removals since the last `mark`. See [`DigitBinIndex::removed_since_mark`].
+    pub fn removed_since_mark(&self) -> Option<RemovedSinceMark> {
+        let tracker = self.removals.as_ref()?;
+        let bins = tracker
+            .bins
+            .iter()
+            .map(|(&bits, &count)| {
+                let weight = f64::from_bits(bits);
+                RemovedBin { weight, count, mass: weight * count as f64 }
+            })
+            .collect();
+        Some(RemovedSinceMark { ids: tracker.ids.clone(), bins })
+    }
+
+    /// Moves one copy of an individual from its old bin to a new one in a single traversal.
+    pub fn update_weight(&mut self, individual_id: u64, old_weight: f64, new_weight: f64) -> bool {
+        let mut digits = [0u8; MAX_DEPTH];
+        let (Some(old_scaled), Some(new_scaled)) = (
+            self.weight_to_digits(old_weight, &mut digits),
+            self.weight_to_digits(new_weight, &mut digits),
+        ) else {
+            return false;
+        };
+        let moved = self.update_scaled(individual_id, old_scaled, new_scaled);
+        if moved {
+            self.record_exact(individual_id, new_scaled, new_weight);
+        }
+        moved
+    }
+
+    /// A copy with every copy of the listed IDs moved to new weights.
+    /// See [`DigitBinIndex::clone_with_overrides`].
+    pub fn clone_with_overrides(&self, overrides: &[(u64, f64)]) -> Self {
+        let mut copy = self.clone();
+        let mut digits = [0u8; MAX_DEPTH];
+        let targets: std::collections::HashMap<u64, (u64, f64)> = overrides
+            .iter()
+            .filter_map(|&(id, weight)| Some((id, (self.weight_to_digits(weight, &mut digits)?, weight))))
+            .collect();
+        // Where the listed IDs are now: from the registry, or from one pass over the bins.
+        let mut placements = Vec::new();
+        match &self.registry {
+            Some(registry) => {
+                for &id in targets.keys() {
+                    let bins = registry.bins.get(&id).map(Vec::as_slice).unwrap_or_default();
+                    placements.extend(bins.iter().map(|&(scaled, copies)| (id, scaled, copies)));
+                }
+            }
+            None => self.for_each_bin(|scaled, bin| {
+                bin.for_each_count(|id, copies| {
+                    if targets.contains_key(&id) {
+                        placements.push((id, scaled, copies));
+                    }
+                });
+            }),
+        }
+        for (id, scaled, copies) in placements {
+            let (new_scaled, weight) = targets[&id];
+            if new_scaled != scaled {
+                for _ in 0..copies {
+                    copy.update_scaled(id, scaled, new_scaled);
+                }
+            }
+            copy.record_exact(id, new_scaled, weight);
+        }
+        copy
+    }
+
+    /// Moves one copy of an individual between two valid bins given by scaled weight.
+    fn update_scaled(&mut self, individual_id: u64, old_scaled: u64, new_scaled: u64) -> bool {
+        let mut old_digits = [0u8; MAX_DEPTH];
+        let mut new_digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(old_scaled, &mut old_digits);
+        self.scaled_to_digits(new_scaled, &mut new_digits);
+        let missing = self.missing_nodes(&new_digits);
+        if let Some(budget) = self.memory_budget {
+            if missing > 0 && (self.nodes + missing) * self.node_bytes() > budget {
+                return false;
+            }
+        }
+        let depth = self.depth;
+        let (root, arena) = self.tree_mut();
+        let moved =
+            Self::update_recurse(root, arena, individual_id, old_scaled, new_scaled, &old_digits, &new_digits, 1, depth);
+        if moved {
+            self.nodes += missing;
+            // A set-like bin that already held the ID absorbs the moved copy.
+            let held = self.registry.is_some().then(|| {
+                self.leaf(new_scaled).map_or(0, |leaf| match &leaf.content {
+                    NodeContent::Bin(bin) => bin.count_of(individual_id),
+                    NodeContent::DigitIndex(_) => 0,
+                })
+            });
+            if let (Some(held), Some(registry)) = (held, &mut self.registry) {
+                registry.relocate(individual_id, old_scaled, new_scaled, held);
+            }
+        }
+        moved
+    }
+
+    /// Follows the shared prefix of both paths, then removes from the old subtree and inserts
+    /// into the new one. Ancestors only see the change in mass (and in count, if a set-like
+    /// bin already held the ID).
+    #[allow(clippy::too_many_arguments)]
+    fn update_recurse(
+        node: &mut Node<B>,
+        arena: &mut Arena<B>,
+        individual_id: u64,
+        old_scaled: u64,
+        new_scaled: u64,
+        old_digits: &Digits,
+        new_digits: &Digits,
+        current_depth: u8,
+        max_depth: u8,
+    ) -> bool {
+        if current_depth > max_depth {
+            // Both paths end in this bin: nothing to move.
+            return matches!(&node.content, NodeContent::Bin(bin) if bin.count_of(individual_id) > 0);
+        }
+        let NodeContent::DigitIndex(block) = node.content else {
+            return false;
+        };
+        let old_digit = old_digits[current_depth as usize - 1] as usize;
+        let new_digit = new_digits[current_depth as usize - 1] as usize;
+        let Some(mut old_child) = arena.take(block, old_digit) else {
+            return false;
+        };
+        let (moved, added) = if old_digit == new_digit {
+            let moved = Self::update_recurse(
+                &mut old_child, arena, individual_id, old_scaled, new_scaled, old_digits, new_digits, current_depth + 1, max_depth,
+            );
+            (moved, 1)
+        } else {
+            (Self::remove_recurse(&mut old_child, arena, individual_id, old_scaled, old_digits, current_depth + 1, max_depth), 0)
+        };
+        arena.put(block, old_digit, old_child);
+        if !moved {
+            return false;
+        }
+        let added = if old_digit == new_digit {
+            added
+        } else {
+            let mut new_child = arena.take_or_new(block, new_digit, current_depth == max_depth);
+            let added = Self::insert_recurse(&mut new_child, arena, new_scaled, new_digits, current_depth + 1, max_depth, |bin| {
+                bin.insert_count(individual_id, 1)
+            });
+            arena.put(block, new_digit, new_child);
+            added
+        };
+        node.content_count = node.content_count - 1 + added;
+        node.accumulated_value = node.accumulated_value - old_scaled + new_scaled * added;
+        true
+    }
+
+    /// Recursive private method to handle removing individuals.
+    fn remove_recurse(
+        node: &mut Node<B>,
+        arena: &mut Arena<B>,
+        individual_id: u64,
+        scaled: u64,
+        digits: &Digits,
+        current_depth: u8,
+        max_depth: u8,
+    ) -> bool {
+        if current_depth > max_depth {
+            if let NodeContent::Bin(bin) = &mut node.content {
+                let bin = Arc::make_mut(bin);
+                let orig_len = bin.len();
+                bin.remove(individual_id);
+                if bin.len() < orig_len {
+                    node.content_count -= 1;
+                    node.accumulated_value -= scaled;
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        let digit = digits[current_depth as usize - 1] as usize;
+        if let NodeContent::DigitIndex(block) = node.content {
+            // If the child at 'digit' exists, recurse. If the recursion returns true (success)...
+            let removed = arena.with_child(block, digit, |arena, child| {
+                Self::remove_recurse(child, arena, individual_id, scaled, digits, current_depth + 1, max_depth)
+            });
+            if removed == Some(true) {
+                // ...then update this node's stats and propagate the success upwards.
+                node.content_count -= 1;
+                node.accumulated_value -= scaled;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Adds `count` copies of an individual whose weight is already scaled.
+    fn insert_scaled(&mut self, individual_id: u64, scaled: u64, count: u64) -> u64 {
+        if individual_id > B::MAX_ID {
+            return 0;
+        }
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        let stored = self.insert_at(scaled, &digits, |bin| bin.insert_count(individual_id, count)).unwrap_or(0);
+        self.remember(individual_id, scaled, stored);
+        stored
+    }
+
+    /// Removes one copy of an individual whose weight is already scaled.
+    fn remove_scaled(&mut self, individual_id: u64, scaled: u64) -> bool {
+        let removed = self.unlink_scaled(individual_id, scaled);
+        if removed {
+            self.track_removal(individual_id, scaled as f64 / self.scale);
+        }
+        removed
+    }
+
+    /// Like `remove_scaled`, but for items that are about to be reinserted, so the removal
+    /// tracker does not see it.
+    fn unlink_scaled(&mut self, individual_id: u64, scaled: u64) -> bool {
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        let depth = self.depth;
+        let (root, arena) = self.tree_mut();
+        let removed = Self::remove_recurse(root, arena, individual_id, scaled, &digits, 1, depth);
+        if let (true, Some(registry)) = (removed, &mut self.registry) {
+            registry.forget(individual_id, scaled);
+        }
+        removed
+    }
+
+    /// Removes every copy of an individual from the bin of the given scaled weight in one
+    /// step, without telling the removal tracker. Returns the number of copies removed.
+    fn unlink_all_scaled(&mut self, individual_id: u64, scaled: u64) -> u64 {
+        fn recurse<B: DigitBin>(node: &mut Node<B>, arena: &mut Arena<B>, individual_id: u64, scaled: u64, digits: &[u8]) -> u64 {
+            let removed = match (&mut node.content, digits.split_first()) {
+                (NodeContent::Bin(bin), _) => Arc::make_mut(bin).remove_all(individual_id),
+                (&mut NodeContent::DigitIndex(block), Some((&digit, rest))) => arena
+                    .with_child(block, digit as usize, |arena, child| recurse(child, arena, individual_id, scaled, rest))
+                    .unwrap_or(0),
+                (NodeContent::DigitIndex(_), None) => 0,
+            };
+            node.content_count -= removed;
+            node.accumulated_value -= removed * scaled;
+            removed
+        }
+
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        let depth = self.depth as usize;
+        let (root, arena) = self.tree_mut();
+        let removed = recurse(root, arena, individual_id, scaled, &digits[..depth]);
+        if let (true, Some(registry)) = (removed > 0, &mut self.registry) {
+            registry.forget_copies(individual_id, scaled, removed);
+        }
+        removed
+    }
+
+    /// Records stored copies with the ID registry, if it is on.
+    fn remember(&mut self, individual_id: u64, scaled: u64, copies: u64) {
+        if let Some(registry) = &mut self.registry {
+            registry.remember(individual_id, scaled, copies);
+        }
+    }
+
+    /// Turns on the ID registry, filling it from the current contents.
+    /// See [`DigitBinIndex::enable_id_registry`].
+    pub fn enable_id_registry(&mut self) {
+        let old = self.registry.take().unwrap_or_default();
+        let mut registry = IdRegistry {
+            groups: old.groups.map(|groups| Groups::new(self.precision, self.radix, groups.of)),
+            ..IdRegistry::default()
+        };
+        self.for_each_bin(|scaled, bin| bin.for_each_count(|id, copies| registry.remember(id, scaled, copies)));
+        // Exact weights and group tags survive a rebuild for the items that are still present.
+        if let Some(mut exact) = old.exact {
+            exact.retain(|&(id, scaled), _| registry.holds(id, scaled));
+            registry.exact = Some(exact);
+        }
+        if let Some(groups) = &mut registry.groups {
+            groups.of.retain(|id, _| registry.bins.contains_key(id));
+        }
+        self.registry = Some(registry);
+    }
+
+    pub fn has_id_registry(&self) -> bool {
+        self.registry.is_some()
+    }
+
+    /// Turns on exact weights, and the ID registry with them.
+    /// See [`DigitBinIndex::enable_exact_weights`].
+    pub fn enable_exact_weights(&mut self) {
+        if self.registry.is_none() {
+            self.enable_id_registry();
+        }
+        if let Some(registry) = &mut self.registry {
+            registry.exact.get_or_insert_default();
+        }
+    }
+
+    pub fn has_exact_weights(&self) -> bool {
+        self.registry.as_ref().is_some_and(|registry| registry.exact.is_some())
+    }
+
+    /// Records the weight an item was added with, if exact weights are on.
+    fn record_exact(&mut self, individual_id: u64, scaled: u64, weight: f64) {
+        if let Some(registry) = &mut self.registry {
+            let held = registry.holds(individual_id, scaled);
+            if let (true, Some(exact)) = (held, &mut registry.exact) {
+                exact.insert((individual_id, scaled), weight);
+            }
+        }
+    }
+
+    /// The weight a binned item was added with, if exact weights are on and it is known,
+    /// and otherwise its bin weight. Must be looked up before the item's removal is logged.
+    fn exact_weight(&self, individual_id: u64, weight: f64) -> f64 {
+        let exact = self.registry.as_ref().and_then(|registry| registry.exact.as_ref());
+        exact
+            .and_then(|exact| exact.get(&(individual_id, (weight * self.scale).round() as u64)))
+            .copied()
+            .unwrap_or(weight)
+    }
+
+    /// `exact_weight` for a batch, or `None` if exact weights are off.
+    fn exact_weights(&self, items: &[(u64, f64)]) -> Option<Vec<(u64, f64)>> {
+        self.registry.as_ref()?.exact.as_ref()?;
+        Some(items.iter().map(|&(id, weight)| (id, self.exact_weight(id, weight))).collect())
+    }
+
+    /// Finds a bin holding the ID in the registry, or `None` if the registry is off.
+    fn registered(&self, individual_id: u64) -> Option<u64> {
+        Some(self.registry.as_ref()?.bins.get(&individual_id)?.first()?.0)
+    }
+
+    /// Finds a bin holding the ID: from the registry if it is on, otherwise by scanning the
+    /// bins, which costs time proportional to the number of items.
+    fn locate(&self, individual_id: u64) -> Option<u64> {
+        match &self.registry {
+            Some(_) => self.registered(individual_id),
+            None => {
+                let mut found = None;
+                self.for_each_bin(|scaled, bin| {
+                    if found.is_none() && bin.count_of(individual_id) > 0 {
+                        found = Some(scaled);
+                    }
+                });
+                found
+            }
+        }
+    }
+
+    /// Removes one copy of an item given only its ID. See [`DigitBinIndex::remove_by_id`].
+    pub fn remove_by_id(&mut self, individual_id: u64) -> Option<f64> {
+        if self.registry.is_none() {
+            self.enable_id_registry();
+        }
+        let scaled = self.registered(individual_id)?;
+        self.remove_scaled(individual_id, scaled).then(|| scaled as f64 / self.scale)
+    }
+
+    /// Registers a weight change due at `tick`. See [`DigitBinIndex::at_tick`].
+    pub fn at_tick(&mut self, tick: u64, individual_id: u64, new_weight: f64) {
+        if self.registry.is_none() {
+            self.enable_id_registry();
+        }
+        self.timeline.entry(tick).or_default().push((individual_id, new_weight));
+    }
+
+    /// Applies the changes due up to `tick`. See [`DigitBinIndex::advance_to`].
+    pub fn advance_to(&mut self, tick: u64) -> usize {
+        let later = self.timeline.split_off(&(tick.saturating_add(1)));
+        let due = std::mem::replace(&mut self.timeline, later);
+        self.clock = self.clock.max(tick);
+        if !due.is_empty() && self.registry.is_none() {
+            self.enable_id_registry();
+        }
+        let mut digits = [0u8; MAX_DEPTH];
+        let mut applied = 0;
+        for (individual_id, new_weight) in due.into_values().flatten() {
+            let new_scaled = self.weight_to_digits(new_weight, &mut digits);
+            let changed = match (self.registered(individual_id), new_scaled) {
+                (Some(old_scaled), Some(new_scaled)) => self.update_scaled(individual_id, old_scaled, new_scaled),
+                (Some(old_scaled), None) => self.remove_scaled(individual_id, old_scaled),
+                (None, Some(new_scaled)) => self.insert_scaled(individual_id, new_scaled, 1) > 0,
+                (None, None) => false,
+            };
+            if let (true, Some(new_scaled)) = (changed, new_scaled) {
+                self.record_exact(individual_id, new_scaled, new_weight);
+            }
+            applied += changed as usize;
+        }
+        applied
+    }
+
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn pending_changes(&self) -> usize {
+        self.timeline.values().map(Vec::len).sum()
+    }
+
+    /// Whether the registry holds the ID. See [`DigitBinIndex::contains`].
+    pub fn contains(&self, individual_id: u64) -> bool {
+        self.registered(individual_id).is_some()
+    }
+
+    /// The binned weight of an item, from the registry. See [`DigitBinIndex::get_weight`].
+    pub fn get_weight(&self, individual_id: u64) -> Option<f64> {
+        self.registered(individual_id).map(|scaled| self.exact_weight(individual_id, scaled as f64 / self.scale))
+    }
+
+    /// Returns true if `scaled` is a weight this index can store.
+    fn is_valid_scaled(&self, scaled: u64) -> bool {
+        scaled > 0 && (scaled as f64) < self.scale
+    }
+
+    /// Calls `f` with the scaled weight and the bin of every leaf, in ascending weight order.
+    fn for_each_bin<'a>(&'a self, mut f: impl FnMut(u64, &'a B)) {
+        fn walk<'a, B: DigitBin>(
+            node: &'a Node<B>,
+            arena: &'a Arena<B>,
+            prefix: u64,
+            radix: u64,
+            f: &mut impl FnMut(u64, &'a B),
+        ) {
+            match &node.content {
+                NodeContent::Bin(bin) => f(prefix, bin),
+                &NodeContent::DigitIndex(block) => {
+                    for (digit, child) in arena.block(block).iter().enumerate() {
+                        if let Some(child) = child {
+                            walk(child, arena, prefix * radix + digit as u64, radix, f);
+                        }
+                    }
+                }
+            }
+        }
+        walk(&self.root, &self.arena, 0, self.radix as u64, &mut f);
+    }
+
+    /// Up to `k` items from the heaviest bins, heaviest first. See [`DigitBinIndex::heaviest`].
+    pub fn heaviest(&self, k: usize) -> Vec<(u64, f64)> {
+        self.extremes(k, true)
+    }
+
+    /// Up to `k` items from the lightest bins, lightest first. See [`DigitBinIndex::lightest`].
+    pub fn lightest(&self, k: usize) -> Vec<(u64, f64)> {
+        self.extremes(k, false)
+    }
+
+    /// Collects up to `k` items, walking the digits from the top or from the bottom.
+    fn extremes(&self, k: usize, from_top: bool) -> Vec<(u64, f64)> {
+        #[allow(clippy::too_many_arguments)]
+        fn walk<B: DigitBin>(
+            node: &Node<B>,
+            arena: &Arena<B>,
+            prefix: u64,
+            radix: u64,
+            k: usize,
+            from_top: bool,
+            scale: f64,
+            out: &mut Vec<(u64, f64)>,
+        ) {
+            match &node.content {
+                NodeContent::Bin(bin) => {
+                    let weight = prefix as f64 / scale;
+                    out.extend(bin.ids().take(k - out.len()).map(|id| (id, weight)));
+                }
+                &NodeContent::DigitIndex(block) => {
+                    let children = arena.block(block);
+                    let mut visit = |digit: usize, child: &Option<Node<B>>| {
+                        match child {
+                            Some(child) if out.len() < k && child.content_count > 0 => {
+                                walk(child, arena, prefix * radix + digit as u64, radix, k, from_top, scale, out)
+                            }
+                            _ => {}
+                        }
+                    };
+                    if from_top {
+                        children.iter().enumerate().rev().for_each(|(digit, child)| visit(digit, child));
+                    } else {
+                        children.iter().enumerate().for_each(|(digit, child)| visit(digit, child));
+                    }
+                }
+            }
+        }
+        let mut out = Vec::with_capacity(k.min(self.count() as usize));
+        walk(&self.root, &self.arena, 0, self.radix as u64, k, from_top, self.scale, &mut out);
+        out
+    }
+
+    /// Empties every bin for which `predicate(bin_weight, count)` holds and returns its items.
+    pub fn prune_bins(&mut self, mut predicate: impl FnMut(f64, u64) -> bool) -> Vec<(u64, f64)> {
+        #[allow(clippy::too_many_arguments)]
+        fn walk<B: DigitBin>(
+            node: &mut Node<B>,
+            arena: &mut Arena<B>,
+            prefix: u64,
+            radix: u64,
+            scale: f64,
+            predicate: &mut impl FnMut(f64, u64) -> bool,
+            removed: &mut Vec<(u64, f64)>,
+        ) -> (u64, u64) {
+            let (count, accumulated) = match &mut node.content {
+                NodeContent::Bin(bin) => {
+                    let weight = prefix as f64 / scale;
+                    if bin.is_empty() || !predicate(weight, node.content_count) {
+                        return (0, 0);
+                    }
+                    removed.extend(bin.ids().map(|id| (id, weight)));
+                    *bin = Arc::default();
+                    (node.content_count, node.accumulated_value)
+                }
+                &mut NodeContent::DigitIndex(block) => {
+                    let mut pruned = (0, 0);
+                    for digit in 0..arena.width {
+                        arena.with_child(block, digit, |arena, child| {
+                            let (count, accumulated) =
+                                walk(child, arena, prefix * radix + digit as u64, radix, scale, predicate, removed);
+                            pruned.0 += count;
+                            pruned.1 += accumulated;
+                        });
+                    }
+                    pruned
+                }
+            };
+            node.content_count -= count;
+            node.accumulated_value -= accumulated;
+            (count, accumulated)
+        }
+        let mut removed = Vec::new();
+        let (radix, scale) = (self.radix as u64, self.scale);
+        let (root, arena) = self.tree_mut();
+        walk(root, arena, 0, radix, scale, &mut predicate, &mut removed);
+        for &(id, weight) in &removed {
+            self.log_removal(id, weight);
+        }
+        removed
+    }
+
+    /// Drops empty subtrees and shrinks the bins. See [`DigitBinIndex::compact`].
+    pub fn compact(&mut self) -> usize {
+        // Copies the non-empty part of a subtree into a new arena, depth first.
+        fn copy_live<B: DigitBin>(node: &Node<B>, from: &Arena<B>, into: &mut Arena<B>) -> Node<B> {
+            let content = match &node.content {
+                NodeContent::Bin(bin) => NodeContent::Bin(Arc::clone(bin)),
+                &NodeContent::DigitIndex(block) => {
+                    let copy = into.alloc();
+                    for (digit, child) in from.block(block).iter().enumerate() {
+                        if let Some(child) = child.as_ref().filter(|child| child.content_count > 0) {
+                            let child = copy_live(child, from, into);
+                            into.put(copy, digit, child);
+                        }
+                    }
+                    NodeContent::DigitIndex(copy)
+                }
+            };
+            Node { content, accumulated_value: node.accumulated_value, content_count: node.content_count }
+        }
+        let before = self.nodes;
+        let mut arena = Arena::new(self.radix);
+        self.root = copy_live(&self.root, &self.arena, &mut arena);
+        self.arena = Arc::new(arena);
+        // With the old arena gone, bins no clone shares can be shrunk in place.
+        for node in Arc::make_mut(&mut self.arena).slots.iter_mut().flatten() {
+            if let NodeContent::Bin(bin) = &mut node.content {
+                if let Some(bin) = Arc::get_mut(bin) {
+                    bin.shrink_to_fit();
+                }
+            }
+        }
+        self.recount_nodes();
+        before - self.nodes
+    }
+
+    /// Draws one item by weight among those not in `exclusions`, recording an audit trail.
+    /// See [`DigitBinIndex::select_audited`].
+    pub fn select_audited(&mut self, exclusions: &RoaringTreemap, with_removal: bool) -> Option<DrawAudit> {
+        let mut rng = WyRand::from_os_rng();
+        let (total_mass, target, scaled, bin_count) = if exclusions.is_empty() {
+            if self.is_massless() {
+                return None;
+            }
+            let total_mass = self.root.accumulated_value;
+            let target = rng.random_range(0..total_mass);
+            let mut remaining = target;
+            let mut scaled = 0u64;
+            let mut node = &self.root;
+            while let Some(children) = self.arena.children(node) {
+                let (digit, child) = children.iter().enumerate().find_map(|(digit, child)| {
+                    let child = child.as_ref()?;
+                    if remaining < child.accumulated_value {
+                        Some((digit, child))
+                    } else {
+                        remaining -= child.accumulated_value;
+                        None
+                    }
+                })?;
+                scaled = scaled * self.radix as u64 + digit as u64;
+                node = child;
+            }
+            (total_mass, target, scaled, node.content_count)
+        } else {
+            // The same exact complement as `select_not_in_with_rng`: each bin weighs in with
+            // its eligible members only.
+            let mut bins = Vec::new();
+            let mut total_mass = 0u64;
+            self.for_each_bin(|scaled, bin| {
+                let eligible = bin.count_excluding(exclusions);
+                if scaled > 0 && eligible > 0 {
+                    bins.push((scaled, eligible));
+                    total_mass += scaled * eligible;
+                }
+            });
+            if total_mass == 0 {
+                return None;
+            }
+            let target = rng.random_range(0..total_mass);
+            let mut remaining = target;
+            let &(scaled, eligible) = bins.iter().find(|&&(scaled, eligible)| {
+                if remaining < scaled * eligible {
+                    true
+                } else {
+                    remaining -= scaled * eligible;
+                    false
+                }
+            })?;
+            (total_mass, target, scaled, eligible)
+        };
+        let NodeContent::Bin(bin) = &self.leaf(scaled)?.content else { return None };
+        let rank = rng.random_range(0..bin_count);
+        let position = if bin_count == DigitBin::len(bin.as_ref()) as u64 {
+            rank
+        } else {
+            bin.nth_position_excluding(rank, exclusions)?
+        };
+        let id = bin.nth_id(position)?;
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        let audit = DrawAudit {
+            id,
+            weight: scaled as f64 / self.scale,
+            total_mass,
+            target,
+            path: digits[..self.depth as usize].to_vec(),
+            bin_mass: scaled * bin_count,
+            bin_count,
+            position,
+            removed: with_removal,
+        };
+        if with_removal {
+            self.remove_scaled(id, scaled);
+        }
+        Some(audit)
+    }
+
+    /// Draws by weight among the items whose ID is not in `excluded`, returning the ID and scaled weight.
+    fn select_not_in_with_rng<R: Rng>(&self, excluded: &RoaringTreemap, rng: &mut R) -> Option<(u64, u64)> {
+        // Correct each bin's mass for its excluded members.
+        let mut bins = Vec::new();
+        let mut total = 0u64;
+        self.for_each_bin(|scaled, bin| {
+            let eligible = bin.count_excluding(excluded);
+            if eligible > 0 {
+                bins.push((scaled, bin, eligible));
+                total += scaled * eligible;
+            }
+        });
+        if total == 0 {
+            return None;
+        }
+        let mut target = rng.random_range(0..total);
+        let &(scaled, bin, eligible) = bins.iter().find(|&&(scaled, _, eligible)| {
+            if target < scaled * eligible {
+                true
+            } else {
+                target -= scaled * eligible;
+                false
+            }
+        })?;
+        let position = rng.random_range(0..eligible) as usize;
+        let id = bin.ids().filter(|&id| !excluded.contains(id)).nth(position)?;
+        Some((id, scaled))
+    }
+
+    /// Selects one item by weight among those with a binned weight in `[min, max]`, without
+    /// removal. See [`DigitBinIndex::select_in_range`].
+    pub fn select_in_range(&self, min: f64, max: f64) -> Option<(u64, f64)> {
+        self.select_in_range_with_rng(min, max, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_in_range_with_rng<R: Rng>(&self, min: f64, max: f64, rng: &mut R) -> Option<(u64, f64)> {
+        let (id, scaled) = self.draw_in_range(min, max, rng)?;
+        Some((id, self.exact_weight(id, scaled as f64 / self.scale)))
+    }
+
+    /// Selects and removes one item by weight among those with a binned weight in `[min, max]`.
+    pub fn select_in_range_and_remove(&mut self, min: f64, max: f64) -> Option<(u64, f64)> {
+        self.select_in_range_and_remove_with_rng(min, max, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_in_range_and_remove_with_rng<R: Rng>(&mut self, min: f64, max: f64, rng: &mut R) -> Option<(u64, f64)> {
+        let (id, scaled) = self.draw_in_range(min, max, rng)?;
+        let weight = self.exact_weight(id, scaled as f64 / self.scale);
+        self.remove_scaled(id, scaled);
+        Some((id, weight))
+    }
+
+    /// Draws by weight among the bins in `[min, max]`, returning the ID and scaled weight.
+    fn draw_in_range<R: Rng>(&self, min: f64, max: f64, rng: &mut R) -> Option<(u64, u64)> {
+        // Absorb the rounding of `weight * scale`, which is far below one scaled unit.
+        let lo = ((min * self.scale - 1e-6).ceil().max(1.0)) as u64;
+        let hi = ((max * self.scale + 1e-6).floor().min(self.scale - 1.0)) as u64;
+        if min.is_nan() || max.is_nan() || lo > hi {
+            return None;
+        }
+
+        /// The mass of the bins in `[lo, hi]` below a node covering `[prefix * span, (prefix + 1) * span)`.
+        fn mass_in<B: DigitBin>(node: &Node<B>, arena: &Arena<B>, prefix: u64, span: u64, radix: u64, lo: u64, hi: u64) -> u64 {
+            let start = prefix * span;
+            let end = start + span - 1;
+            if end < lo || start > hi {
+                return 0;
+            }
+            if lo <= start && end <= hi {
+                return node.accumulated_value;
+            }
+            match arena.children(node) {
+                Some(children) => children
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(digit, child)| {
+                        Some(mass_in(child.as_ref()?, arena, prefix * radix + digit as u64, span / radix, radix, lo, hi))
+                    })
+                    .sum(),
+                None => 0,
+            }
+        }
+
+        let radix = self.radix as u64;
+        let mut span = radix.pow(self.depth as u32);
+        let total = mass_in(&self.root, &self.arena, 0, span, radix, lo, hi);
+        if total == 0 {
+            return None;
+        }
+        let mut target = rng.random_range(0..total);
+        let mut node = &self.root;
+        let mut prefix = 0;
+        while let Some(children) = self.arena.children(node) {
+            span /= radix;
+            let (digit, child) = children.iter().enumerate().find_map(|(digit, child)| {
+                let child = child.as_ref()?;
+                let mass = mass_in(child, &self.arena, prefix * radix + digit as u64, span, radix, lo, hi);
+                if target < mass {
+                    return Some((digit, child));
+                }
+                target -= mass;
+                None
+            })?;
+            node = child;
+            prefix = prefix * radix + digit as u64;
+        }
+        match &node.content {
+            NodeContent::Bin(bin) => Some((bin.get_random(rng)?, prefix)),
+            NodeContent::DigitIndex(_) => None,
+        }
+    }
+
+    /// Draws up to `k` winners under `rules`. See [`DigitBinIndex::lottery`].
+    pub fn lottery(&mut self, k: usize, mut rules: LotteryRules<'_>) -> LotteryOutcome {
+        let mut excluded = std::mem::take(&mut rules.excluded);
+        let mut outcome = LotteryOutcome::default();
+        let mut redraws = 0u64;
+        while outcome.winners.len() < k {
+            let Some(mut audit) = self.select_audited(&excluded, false) else {
+                outcome.exhausted = true;
+                break;
+            };
+            let eligible = rules.eligible.as_mut().is_none_or(|eligible| eligible(audit.id, audit.weight));
+            if !eligible {
+                excluded.insert(audit.id);
+                outcome.trail.push(LotteryDraw { audit, decision: LotteryDecision::Ineligible });
+                redraws += 1;
+                if redraws > rules.max_redraws {
+                    outcome.exhausted = true;
+                    break;
+                }
+                continue;
+            }
+            if rules.remove_winners {
+                audit.removed = self.remove_scaled(audit.id, (audit.weight * self.scale).round() as u64);
+            }
+            if rules.duplicates == DuplicatePolicy::SingleWin {
+                excluded.insert(audit.id);
+            }
+            outcome.winners.push((audit.id, audit.weight));
+            outcome.trail.push(LotteryDraw { audit, decision: LotteryDecision::Won });
+        }
+        outcome
+    }
+
+    /// Selects one item by weight among those not in `excluded`, without removal.
+    /// See [`DigitBinIndex::select_not_in`].
+    pub fn select_not_in(&self, excluded: &RoaringTreemap) -> Option<(u64, f64)> {
+        let (id, scaled) = self.select_not_in_with_rng(excluded, &mut WyRand::from_os_rng())?;
+        Some((id, scaled as f64 / self.scale))
+    }
+
+    /// Selects and removes one item by weight among those not in `excluded`.
+    pub fn select_and_remove_not_in(&mut self, excluded: &RoaringTreemap) -> Option<(u64, f64)> {
+        let (id, scaled) = self.select_not_in_with_rng(excluded, &mut WyRand::from_os_rng())?;
+        self.remove_scaled(id, scaled);
+        Some((id, scaled as f64 / self.scale))
+    }
+
+    /// Selects `num_to_draw` unique items by weight among those not in `excluded`, without
+    /// removal. See [`DigitBinIndex::select_many_excluding`].
+    pub fn select_many_excluding(&self, num_to_draw: u64, excluded: &RoaringTreemap) -> Option<Vec<(u64, f64)>> {
+        self.select_many_excluding_with_rng(num_to_draw, excluded, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_many_excluding_with_rng<R: Rng>(
+        &self,
+        num_to_draw: u64,
+        excluded: &RoaringTreemap,
+        rng: &mut R,
+    ) -> Option<Vec<(u64, f64)>> {
+        // Eligible copies per bin; a bin's IDs are listed the first time it is drawn from,
+        // and each draw takes its copy out of that list.
+        let mut bins: Vec<(u64, &B, u64, Option<Vec<u64>>)> = Vec::new();
+        let mut total = 0u64;
+        self.for_each_bin(|scaled, bin| {
+            let eligible = bin.count_excluding(excluded);
+            if eligible > 0 {
+                bins.push((scaled, bin, eligible, None));
+                total += scaled * eligible;
+            }
+        });
+        if bins.iter().map(|&(_, _, eligible, _)| eligible).sum::<u64>() < num_to_draw {
+            return None;
+        }
+        let mut selected = Vec::with_capacity(num_to_draw as usize);
+        for _ in 0..num_to_draw {
+            let mut target = rng.random_range(0..total);
+            let (scaled, bin, eligible, ids) = bins.iter_mut().find(|(scaled, _, eligible, _)| {
+                if target < *scaled * *eligible {
+                    true
+                } else {
+                    target -= *scaled * *eligible;
+                    false
+                }
+            })?;
+            let ids = ids.get_or_insert_with(|| bin.ids().filter(|&id| !excluded.contains(id)).collect());
+            let id = ids.swap_remove(rng.random_range(0..ids.len()));
+            *eligible -= 1;
+            total -= *scaled;
+            selected.push((id, *scaled as f64 / self.scale));
+        }
+        Some(selected)
+    }
+
+    /// Chooses `num_to_draw` unique items without touching the index.
+    /// See [`DigitBinIndex::plan_select_many`].
+    pub fn plan_select_many(&self, num_to_draw: u64) -> Option<DrawPlan> {
+        if num_to_draw > self.count() {
+            return None;
+        }
+        // Draw from a scratch index holding only a copy of the tree, which shares the arena
+        // until the draw first changes it. Without a registry or tracker the draw reports bin
+        // weights and logs nothing, and none of that side state is copied.
+        let mut scratch = Self::with_precision_and_radix(self.precision, self.radix);
+        scratch.root = self.root.clone();
+        scratch.arena = Arc::clone(&self.arena);
+        let drawn = scratch.select_many_and_remove_with_rng(num_to_draw, &mut WyRand::from_os_rng())?;
+        let draws = drawn
+            .into_iter()
+            .map(|(id, weight)| {
+                let bin = (weight * self.scale).round() as u64;
+                PlannedDraw { id, bin, weight: self.exact_weight(id, weight) }
+            })
+            .collect();
+        Some(DrawPlan { precision: self.precision, draws })
+    }
+
+    /// Removes the items of a plan after checking that all of them are still in their bins.
+    /// See [`DigitBinIndex::apply`].
+    pub fn apply(&mut self, plan: &DrawPlan) -> Result<Vec<(u64, f64)>, DigitBinIndexError> {
+        if plan.precision != self.precision {
+            return Err(DigitBinIndexError::PrecisionMismatch { expected: plan.precision, found: self.precision });
+        }
+        let mut needed: std::collections::HashMap<(u64, u64), u64> = std::collections::HashMap::new();
+        for draw in &plan.draws {
+            let copies = needed.entry((draw.id, draw.bin)).or_insert(0);
+            *copies += 1;
+            let present = match self.leaf(draw.bin).map(|node| &node.content) {
+                Some(NodeContent::Bin(bin)) => bin.count_of(draw.id),
+                _ => 0,
+            };
+            if present < *copies {
+                return Err(DigitBinIndexError::StalePlan { id: draw.id });
+            }
+        }
+        Ok(plan
+            .draws
+            .iter()
+            .map(|draw| {
+                self.remove_scaled(draw.id, draw.bin);
+                (draw.id, draw.bin as f64 / self.scale)
+            })
+            .collect())
+    }
+
+    /// Borrowed iterator over the non-empty bins, in ascending weight order.
+    pub fn bins(&self) -> GenericBins<'_, B> {
+        GenericBins::new(self, self.scale)
+    }
+
+    /// Returns the bin weight `weight` is stored under. See [`DigitBinIndex::quantize`].
+    pub fn quantize(&self, weight: f64) -> Option<f64> {
+        let scaled = self.weight_to_digits(weight, &mut [0u8; MAX_DEPTH])?;
+        Some(scaled as f64 / self.scale)
+    }
+
+    /// Returns the number of items in the bin `weight` is stored under.
+    /// See [`DigitBinIndex::bin_count`].
+    pub fn bin_count(&self, weight: f64) -> u64 {
+        self.bin_at(weight).map_or(0, |bin| DigitBin::len(bin) as u64)
+    }
+
+    /// Returns the non-empty bin `weight` is stored under.
+    pub fn bin_at(&self, weight: f64) -> Option<&B> {
+        let scaled = self.weight_to_digits(weight, &mut [0u8; MAX_DEPTH])?;
+        match &self.leaf(scaled)?.content {
+            NodeContent::Bin(bin) if !bin.is_empty() => Some(bin),
+            _ => None,
+        }
+    }
+
+    /// Removes every item, returning them lazily in ascending weight order.
+    pub fn drain(&mut self) -> GenericIntoIter<B> {
+        if self.removals.is_some() {
+            let mut removed = Vec::new();
+            self.for_each_bin(|scaled, bin| removed.extend(bin.ids().map(|id| (id, scaled))));
+            for (id, scaled) in removed {
+                self.track_removal(id, scaled as f64 / self.scale);
+            }
+        }
+        if let Some(registry) = &mut self.registry {
+            registry.bins.clear();
+            if let Some(exact) = &mut registry.exact {
+                exact.clear();
+            }
+            if let Some(groups) = &mut registry.groups {
+                groups.of.clear();
+                groups.pools.clear();
+            }
+        }
+        self.schedule.clear();
+        let (root, arena) = self.replace_tree();
+        GenericIntoIter::new(root, arena, self.scale)
+    }
+
+    /// Changes the precision in place. See [`DigitBinIndex::rebin_to_precision`].
+    pub fn rebin_to_precision(&mut self, new_precision: u8) -> Vec<(u64, f64)> {
+        if new_precision <= self.precision {
+            return self.coarsen_to(new_precision);
+        }
+        let mut rebuilt = Self::with_precision_and_radix(new_precision, self.radix);
+        if self.has_exact_weights() {
+            rebuilt.enable_exact_weights();
+        } else if self.registry.is_some() {
+            rebuilt.enable_id_registry();
+        }
+        if let (Some(registry), Some(groups)) = (&mut rebuilt.registry, self.registry.as_ref().and_then(|r| r.groups.as_ref())) {
+            registry.groups = Some(Groups::new(new_precision, self.radix, groups.of.clone()));
+        }
+        let factor = 10u64.pow((new_precision - self.precision) as u32);
+        let mut digits = [0u8; MAX_DEPTH];
+        self.for_each_bin(|scaled, bin| {
+            bin.for_each_count(|id, copies| {
+                let weight = scaled as f64 / self.scale;
+                let exact = self.exact_weight(id, weight);
+                // Bin weights are scaled exactly; exact weights are binned like a fresh `add`.
+                let new_scaled = match rebuilt.weight_to_digits(exact, &mut digits) {
+                    Some(new_scaled) if exact != weight => new_scaled,
+                    _ => scaled * factor,
+                };
+                rebuilt.insert_scaled(id, new_scaled, copies);
+                if exact != weight {
+                    rebuilt.record_exact(id, new_scaled, exact);
+                }
+            });
+        });
+        rebuilt.memory_budget = self.memory_budget;
+        rebuilt.tie_break = self.tie_break;
+        rebuilt.auto_repair = self.auto_repair;
+        rebuilt.removals = self.removals.take();
+        rebuilt.timeline = std::mem::take(&mut self.timeline);
+        rebuilt.clock = self.clock;
+        *self = rebuilt;
+        Vec::new()
+    }
+
+    /// Multiplies every weight by `factor`, moving whole bins. See
+    /// [`DigitBinIndex::scale_all_weights`].
+    pub fn scale_all_weights(&mut self, factor: f64) -> Vec<(u64, f64)> {
+        assert!(factor.is_finite() && factor > 0.0, "The scaling factor must be positive and finite.");
+        let bins = self.take_bins();
+        let exact = self.registry.as_mut().and_then(|registry| registry.exact.take());
+        let mut rescaled = exact.as_ref().map(|_| std::collections::HashMap::new());
+        let mut dropped = Vec::new();
+        let mut digits = [0u8; MAX_DEPTH];
+        // Bin weights are scaled in fixed point; the tolerance absorbs products like
+        // 700 * 0.7 landing just below 490.
+        let scale = self.scale;
+        let rescale = |scaled: u64| Some((scaled as f64 * factor + 1e-6) as u64).filter(|&s| s > 0 && (s as f64) < scale);
+        for (scaled, bin) in bins {
+            let weight = scaled as f64 / self.scale;
+            let (Some(exact), Some(rescaled)) = (&exact, &mut rescaled) else {
+                match rescale(scaled) {
+                    Some(new_scaled) => {
+                        self.insert_bin(new_scaled, bin);
+                    }
+                    None => dropped.extend(bin.ids().map(|id| (id, weight))),
+                }
+                continue;
+            };
+            bin.for_each_count(|id, copies| {
+                let old = exact.get(&(id, scaled)).copied();
+                let new_scaled = match old {
+                    Some(old) => self.weight_to_digits(old * factor, &mut digits),
+                    None => rescale(scaled).inspect(|&new_scaled| self.scaled_to_digits(new_scaled, &mut digits)),
+                };
+                let Some(new_scaled) = new_scaled else {
+                    dropped.extend(std::iter::repeat_n((id, old.unwrap_or(weight)), copies as usize));
+                    return;
+                };
+                let depth = self.depth;
+                let (root, arena) = self.tree_mut();
+                Self::insert_recurse(root, arena, new_scaled, &digits, 1, depth, |into| into.insert_count(id, copies));
+                if let Some(old) = old {
+                    rescaled.insert((id, new_scaled), old * factor);
+                }
+            });
+        }
+        self.schedule.clear();
+        self.recount_nodes();
+        for &(id, weight) in &dropped {
+            self.track_removal(id, weight);
+        }
+        if let Some(registry) = &mut self.registry {
+            registry.exact = rescaled;
+            self.enable_id_registry();
+        }
+        dropped
+    }
+
+    /// Lowers the precision in place by merging the bins that share a coarse weight.
+    /// See [`DigitBinIndex::coarsen_to`].
+    pub fn coarsen_to(&mut self, precision: u8) -> Vec<(u64, f64)> {
+        assert!(precision > 0, "Precision must be at least 1.");
+        assert!(precision <= self.precision, "Precision cannot be raised by coarsening.");
+        let mut dropped = Vec::new();
+        if precision < self.precision {
+            let factor = 10u64.pow((self.precision - precision) as u32);
+            let bins = self.take_bins();
+            let old_scale = self.scale;
+            self.precision = precision;
+            self.scale = 10f64.powi(precision as i32);
+            self.depth = Self::depth_for(precision, self.radix);
+            // Bins come out in ascending order, so each coarse bin takes its members in the
+            // order of their fine bins.
+            for (scaled, bin) in bins {
+                match scaled / factor {
+                    0 => dropped.extend(bin.ids().map(|id| (id, scaled as f64 / old_scale))),
+                    coarse => self.insert_bin(coarse, bin),
+                }
+            }
+            self.schedule.clear();
+            self.recount_nodes();
+        }
+        for &(id, weight) in &dropped {
+            self.track_removal(id, weight);
+        }
+        if self.registry.is_some() {
+            self.enable_id_registry();
+        }
+        dropped
+    }
+
+    /// Takes the non-empty bins out of the tree, in ascending weight order, leaving it empty.
+    fn take_bins(&mut self) -> Vec<(u64, B)> {
+        fn take<B: DigitBin>(node: Node<B>, arena: &mut Arena<B>, prefix: u64, radix: u64, bins: &mut Vec<(u64, B)>) {
+            match node.content {
+                NodeContent::Bin(bin) if !bin.is_empty() => bins.push((prefix, Arc::unwrap_or_clone(bin))),
+                NodeContent::Bin(_) => {}
+                NodeContent::DigitIndex(block) => {
+                    for digit in 0..arena.width {
+                        if let Some(child) = arena.take(block, digit) {
+                            take(child, arena, prefix * radix + digit as u64, radix, bins);
+                        }
+                    }
+                }
+            }
+        }
+        let mut bins = Vec::new();
+        let (root, mut arena) = self.replace_tree();
+        take(root, &mut arena, 0, self.radix as u64, &mut bins);
+        bins
+    }
+
+    /// Merges a whole bin into the bin of `scaled`, creating its path as needed.
+    fn insert_bin(&mut self, scaled: u64, bin: B) {
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        self.nodes += self.missing_nodes(&digits);
+        let depth = self.depth;
+        let (root, arena) = self.tree_mut();
+        Self::insert_recurse(root, arena, scaled, &digits, 1, depth, |into| {
+            let before = into.len();
+            into.merge(bin);
+            (into.len() - before) as u64
+        });
+    }
+
+    /// Returns a branch that shares its tree with this index. See [`DigitBinIndex::snapshot`].
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Moves every item of `other` into this index by merging its bins into this tree.
+    /// Both indexes must have the same precision; their radices may differ.
+    /// See [`DigitBinIndex::merge`].
+    pub fn merge(&mut self, mut other: Self) {
+        assert_eq!(self.precision, other.precision, "Merged indexes must have the same precision.");
+        let bins = other.take_bins();
+        let other_groups = other.registry.and_then(|registry| registry.groups);
+        if let Some(other_groups) = other_groups {
+            self.enable_id_registry();
+            let registry = self.registry.as_mut().expect("the registry was just enabled");
+            let groups = registry.groups.get_or_insert_with(|| Groups::new(self.precision, self.radix, Default::default()));
+            groups.of.extend(other_groups.of);
+        }
+        for (scaled, bin) in bins {
+            self.insert_bin(scaled, bin);
+        }
+        for (tick, changes) in other.timeline {
+            self.timeline.entry(tick).or_default().extend(changes);
+        }
+        self.recount_nodes();
+        if self.registry.is_some() {
+            self.enable_id_registry();
+        }
+    }
+
+    /// Every ID's bins, as sorted `(scaled weight, copies)` pairs.
+    fn placements(&self) -> Placements {
+        let mut placements = Placements::new();
+        self.for_each_bin(|scaled, bin| {
+            bin.for_each_count(|id, copies| {
+                let bins = placements.entry(id).or_default();
+                match bins.last_mut() {
+                    Some((last, stored)) if *last == scaled => *stored += copies,
+                    _ => bins.push((scaled, copies)),
+                }
+            })
+        });
+        placements
+    }
+
+    /// Three-way merge of another replica's placements. See [`DigitBinIndex::merge_replica`].
+    fn merge_replica(&mut self, base: &Placements, theirs: &Placements, policy: ReplicaPolicy) -> ReplicaMergeReport {
+        let ours = self.placements();
+        let mass = |bins: &[(u64, u64)]| bins.iter().map(|&(scaled, copies)| scaled * copies).sum::<u64>();
+        let mut ids: Vec<u64> = ours.keys().chain(theirs.keys()).chain(base.keys()).copied().collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut report = ReplicaMergeReport::default();
+        for id in ids {
+            let o = ours.get(&id).map_or(&[][..], Vec::as_slice);
+            let t = theirs.get(&id).map_or(&[][..], Vec::as_slice);
+            let b = base.get(&id).map_or(&[][..], Vec::as_slice);
+            let target = if o == t || t == b {
+                o
+            } else if o == b {
+                t
+            } else {
+                // Ties are broken on the placements themselves, so both merge orders agree.
+                let resolved = match policy {
+                    ReplicaPolicy::Ours => o,
+                    ReplicaPolicy::Theirs => t,
+                    ReplicaPolicy::Heavier => std::cmp::max_by_key(o, t, |bins| (mass(bins), *bins)),
+                    ReplicaPolicy::Lighter => std::cmp::min_by_key(o, t, |bins| (mass(bins), *bins)),
+                };
+                let weight = |bins: &[(u64, u64)]| mass(bins) as f64 / self.scale;
+                report.conflicts.push(ReplicaConflict { id, base: weight(b), ours: weight(o), theirs: weight(t), resolved: weight(resolved) });
+                resolved
+            };
+            if target == o {
+                if !o.is_empty() {
+                    report.applied.unchanged += 1;
+                }
+                continue;
+            }
+            for &(scaled, copies) in o {
+                for _ in 0..copies {
+                    self.unlink_scaled(id, scaled);
+                }
+            }
+            if target.is_empty() {
+                for &(scaled, copies) in o {
+                    for _ in 0..copies {
+                        self.track_removal(id, scaled as f64 / self.scale);
+                    }
+                }
+                report.applied.removed += 1;
+            } else if target.iter().all(|&(scaled, copies)| self.insert_scaled(id, scaled, copies) == copies) {
+                if o.is_empty() {
+                    report.applied.added += 1;
+                } else {
+                    report.applied.updated += 1;
+                }
+            } else {
+                report.applied.rejected += 1;
+            }
+        }
+        report
+    }
+
+    /// Reconciles the index with a fresh snapshot of `(id, weight)` pairs in a single pass.
+    /// See [`DigitBinIndex::merge_from`].
+    pub fn merge_from<I: IntoIterator<Item = (u64, f64)>>(&mut self, items: I, on_conflict: ConflictPolicy) -> MergeReport {
+        use std::collections::hash_map::Entry;
+        use std::collections::HashMap;
+
+        // Current state: ID -> (scaled weight, copies) for every bin holding it.
+        let current: HashMap<u64, Vec<(u64, u64)>> = match &self.registry {
+            Some(registry) => registry.bins.clone(),
+            None => {
+                let mut current: HashMap<u64, Vec<(u64, u64)>> = HashMap::new();
+                self.for_each_bin(|scaled, bin| {
+                    bin.for_each_count(|id, copies| {
+                        let bins = current.entry(id).or_default();
+                        match bins.last_mut() {
+                            Some((last, stored)) if *last == scaled => *stored += copies,
+                            _ => bins.push((scaled, copies)),
+                        }
+                    })
+                });
+                current
+            }
+        };
+
+        // Incoming state: ID -> scaled weight, combining duplicates with the policy.
+        let mut incoming: HashMap<u64, u64> = HashMap::new();
+        for (id, weight) in items {
+            let scaled = if weight > 0.0 { (weight * self.scale) as u64 } else { 0 };
+            match incoming.entry(id) {
+                Entry::Vacant(entry) => { entry.insert(scaled); }
+                Entry::Occupied(mut entry) => match on_conflict {
+                    ConflictPolicy::Replace => { entry.insert(scaled); }
+                    ConflictPolicy::Skip => {}
+                    ConflictPolicy::Sum => *entry.get_mut() += scaled,
+                },
+            }
+        }
+
+        let mut report = MergeReport::default();
+        for (&id, bins) in current.iter().filter(|(id, _)| !incoming.contains_key(id)) {
+            for &(scaled, _) in bins {
+                let copies = self.unlink_all_scaled(id, scaled);
+                self.track_removals(id, scaled, copies);
+            }
+            report.removed += 1;
+        }
+
+        for (id, new_scaled) in incoming {
+            let Some(bins) = current.get(&id) else {
+                if self.is_valid_scaled(new_scaled) && self.insert_scaled(id, new_scaled, 1) > 0 {
+                    report.added += 1;
+                } else {
+                    report.rejected += 1;
+                }
+                continue;
+            };
+            // An ID held in several bins is combined from its lightest one.
+            let old_scaled = bins.iter().map(|&(scaled, _)| scaled).min().unwrap_or(0);
+            let target = match on_conflict {
+                ConflictPolicy::Replace => new_scaled,
+                ConflictPolicy::Skip => old_scaled,
+                ConflictPolicy::Sum => old_scaled + new_scaled,
+            };
+            if bins.len() == 1 && target == old_scaled {
+                report.unchanged += 1;
+                continue;
+            }
+            if self.is_valid_scaled(target) && !self.fits_budget(target) {
+                // The new bin would exceed the memory budget: keep the current placement.
+                report.rejected += 1;
+                continue;
+            }
+            let unlinked: Vec<(u64, u64)> = bins.iter().map(|&(scaled, _)| (scaled, self.unlink_all_scaled(id, scaled))).collect();
+            let copies = unlinked.iter().map(|&(_, copies)| copies).sum();
+            if self.is_valid_scaled(target) && self.insert_scaled(id, target, copies) > 0 {
+                report.updated += 1;
+            } else {
+                for (scaled, copies) in unlinked {
+                    self.track_removals(id, scaled, copies);
+                }
+                report.rejected += 1;
+            }
+        }
+        report
+    }
+
+    /// Removes multiple items from the index in a highly optimized batch operation.
+    ///
+    /// This method is significantly faster than calling `remove` in a loop. It
+    /// groups the items to be removed by their weight path and traverses the tree
+    /// only once per group, performing aggregated updates on the way up.
+    ///
+    /// The `(id, weight)` pairs must match items that are currently in the index.
+    /// If a given pair is not found, it is silently ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - A slice of `(id, weight)` tuples to remove from the index.
+    ///
+    pub fn remove_many(&mut self, items: &[(u64, f64)]) -> bool {
+        if items.is_empty() {
+            return false;
+        }
+
+        let mut digits = [0u8; MAX_DEPTH];
+        let mut valid = true;
+        let mut scaled_items = Vec::with_capacity(items.len());
+        for &(id, weight) in items {
+            match self.weight_to_digits(weight, &mut digits) {
+                Some(scaled) => scaled_items.push((scaled, id)),
+                None => valid = false,
+            }
+        }
+        let expected = scaled_items.len();
+        valid && self.remove_grouped(scaled_items) == expected
+    }
+
+    /// Removes one copy of each ID in the set. See [`DigitBinIndex::remove_ids`].
+    pub fn remove_ids(&mut self, ids: &RoaringTreemap) -> u64 {
+        let items: Vec<(u64, u64)> = if self.registry.is_some() {
+            ids.iter().filter_map(|id| Some((self.registered(id)?, id))).collect()
+        } else {
+            // One pass over the bins, probing whichever side of the match is smaller.
+            let mut pending = ids.clone();
+            let mut items = Vec::new();
+            self.for_each_bin(|scaled, bin| {
+                if pending.is_empty() {
+                    return;
+                }
+                let found: Vec<u64> = if pending.len() < DigitBin::len(bin) as u64 {
+                    pending.iter().filter(|&id| bin.count_of(id) > 0).collect()
+                } else {
+                    bin.ids().filter(|&id| pending.contains(id)).collect()
+                };
+                for id in found {
+                    if pending.remove(id) {
+                        items.push((scaled, id));
+                    }
+                }
+            });
+            items
+        };
+        self.remove_grouped(items) as u64
+    }
+
+    /// Removes one copy of each `(scaled weight, ID)` pair, visiting every affected node
+    /// once and updating its totals once. Returns the number of copies removed.
+    fn remove_grouped(&mut self, mut items: Vec<(u64, u64)>) -> usize {
+        #[allow(clippy::too_many_arguments)]
+        fn recurse<B: DigitBin>(
+            node: &mut Node<B>,
+            arena: &mut Arena<B>,
+            items: &[(u64, u64)],
+            depth: u8,
+            max_depth: u8,
+            radix: u64,
+            removed: &mut Vec<(u64, u64)>,
+        ) {
+            let first = removed.len();
+            match &mut node.content {
+                NodeContent::Bin(bin) => {
+                    let bin = Arc::make_mut(bin);
+                    for &(scaled, id) in items {
+                        let len = bin.len();
+                        bin.remove(id);
+                        if bin.len() < len {
+                            removed.push((scaled, id));
+                        }
+                    }
+                }
+                &mut NodeContent::DigitIndex(block) => {
+                    let place = radix.pow((max_depth - depth) as u32);
+                    let digit = |scaled: u64| (scaled / place % radix) as usize;
+                    let mut rest = items;
+                    while let Some(&(scaled, _)) = rest.first() {
+                        let end = rest.partition_point(|&(other, _)| digit(other) == digit(scaled));
+                        let (group, tail) = rest.split_at(end);
+                        arena.with_child(block, digit(scaled), |arena, child| {
+                            recurse(child, arena, group, depth + 1, max_depth, radix, removed)
+                        });
+                        rest = tail;
+                    }
+                }
+            }
+            let taken = &removed[first..];
+            node.content_count -= taken.len() as u64;
+            node.accumulated_value -= taken.iter().map(|&(scaled, _)| scaled).sum::<u64>();
+        }
+
+        if items.is_empty() {
+            return 0;
+        }
+        items.sort_unstable();
+        let mut removed = Vec::with_capacity(items.len());
+        let (depth, radix) = (self.depth, self.radix as u64);
+        let (root, arena) = self.tree_mut();
+        recurse(root, arena, &items, 1, depth, radix, &mut removed);
+        for &(scaled, id) in &removed {
+            self.log_removal(id, scaled as f64 / self.scale);
+        }
+        removed.len()
+    }
+
+    // --- Selection Functions ---
+
+    pub fn select(&mut self) -> Option<(u64, f64)> {
+        self.select_and_optionally_remove(false)
+    }
+
+    pub fn select_many(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.select_many_and_optionally_remove(num_to_draw, false)
+    }
+
+    pub fn select_and_remove(&mut self) -> Option<(u64, f64)> {
+        self.select_and_optionally_remove(true)
+    }
+
+    /// Selects and removes one copy, returning the ID, its weight and the copies that remain.
+    pub fn select_and_decrement(&mut self) -> Option<(u64, f64, u64)> {
+        let (id, weight) = self.select_and_remove()?;
+        let scaled = (weight * self.scale).round() as u64;
+        let remaining = match self.leaf(scaled).map(|node| &node.content) {
+            Some(NodeContent::Bin(bin)) => bin.count_of(id),
+            _ => 0,
+        };
+        Some((id, weight, remaining))
+    }
+
+    // Wrapper function to handle both select and select_and_remove
+    pub fn select_and_optionally_remove(&mut self, with_removal: bool) -> Option<(u64, f64)> {
+        self.select_and_optionally_remove_with_rng(with_removal, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_and_remove_with_rng<R: Rng>(&mut self, rng: &mut R) -> Option<(u64, f64)> {
+        self.select_and_optionally_remove_with_rng(true, rng)
+    }
+
+    pub fn select_and_optionally_remove_with_rng<R: Rng>(&mut self, with_removal: bool, rng: &mut R) -> Option<(u64, f64)> {
+        if !with_removal {
+            // The read-only descent leaves an arena shared with snapshots in place.
+            return self.select_with_rng(rng);
+        }
+        if self.is_massless() {
+            return None;
+        }
+        let to_earlier = self.boundary_to_earlier(rng);
+        let random_target = Self::draw_target(self.root.accumulated_value, to_earlier, rng);
+        let (depth, scale) = (self.depth, self.scale);
+        let (root, arena) = self.tree_mut();
+        let (id, weight) = Self::select_and_optionally_remove_recurse(
+            root,
+            arena,
+            random_target,
+            to_earlier,
+            1,
+            depth,
+            rng,
+            with_removal,
+            scale,
+        )?;
+        let exact = self.exact_weight(id, weight);
+        if with_removal {
+            self.log_removal(id, weight);
+        }
+        Some((id, exact))
+    }
+
+    // Helper function
+    #[allow(clippy::too_many_arguments)]
+    fn select_and_optionally_remove_recurse<R: Rng>(
+        node: &mut Node<B>,
+        arena: &mut Arena<B>,
+        target: u64,
+        to_earlier: bool,
+        current_depth: u8,
+        max_depth: u8,
+        rng: &mut R,
+        with_removal: bool,
+        scale: f64,
+    ) -> Option<(u64, f64)> {
+        // Base case: Bin node
+        if current_depth > max_depth {
+            if let NodeContent::Bin(bin) = &mut node.content {
+                if bin.is_empty() {
+                    return None;
+                }
+                let bin = Arc::make_mut(bin);
+                let scaled_weight = node.accumulated_value.checked_div(node.content_count)?;
+                let weight = scaled_weight as f64 / scale;
+                let selected_id = if with_removal {
+                    bin.get_random_and_remove(rng)?
+                } else {
+                    bin.get_random(rng)?
+                };
+                if with_removal {
+                    node.content_count -= 1;
+                    node.accumulated_value -= scaled_weight;
+                }
+                return Some((selected_id, weight));
+            }
+            return None;
+        }
+
+        // Recursive case: DigitIndex node
+        if let NodeContent::DigitIndex(block) = node.content {
+            let (i, child_target) = Self::child_at(arena.block(block), target, to_earlier)?;
+            // A failed recursion implies an empty bin was selected.
+            let (selected_id, weight) = arena.with_child(block, i, |arena, child| {
+                Self::select_and_optionally_remove_recurse(
+                    child,
+                    arena,
+                    child_target,
+                    to_earlier,
+                    current_depth + 1,
+                    max_depth,
+                    rng,
+                    with_removal,
+                    scale,
+                )
+            })??;
+            if with_removal {
+                node.content_count -= 1;
+                node.accumulated_value -= (weight * scale).round() as u64;
+            }
+            return Some((selected_id, weight));
+        }
+        None
+    } 
+
+    /// Non-destructive selection through a shared reference, drawing from the supplied RNG.
+    pub fn select_with_rng<R: Rng>(&self, rng: &mut R) -> Option<(u64, f64)> {
+        if self.is_massless() {
+            return None;
+        }
+        let to_earlier = self.boundary_to_earlier(rng);
+        let target = Self::draw_target(self.root.accumulated_value, to_earlier, rng);
+        self.descend(target, to_earlier, rng)
+    }
+
+    /// Selects the item owning `target` without removal. See [`DigitBinIndex::select_at`].
+    pub fn select_at<R: Rng>(&self, target: u64, rng: &mut R) -> Option<(u64, f64)> {
+        let total = self.root.accumulated_value;
+        let to_earlier = match self.tie_break {
+            TieBreak::FirstChild => true,
+            TieBreak::LastChild => false,
+            // The ends of the mass have only one side.
+            TieBreak::Random => target == total || (target > 0 && rng.random()),
+        };
+        self.descend(target, to_earlier, rng)
+    }
+
+    /// Follows `target` down to a bin and draws one of its members.
+    fn descend<R: Rng>(&self, target: u64, to_earlier: bool, rng: &mut R) -> Option<(u64, f64)> {
+        let (id, scaled) = self.descend_scaled(target, to_earlier, rng)?;
+        Some((id, self.exact_weight(id, scaled as f64 / self.scale)))
+    }
+
+    /// Draws an item and multiplies its weight. See [`DigitBinIndex::select_and_reweight`].
+    pub fn select_and_reweight(&mut self, multiplier: f64) -> Option<(u64, f64)> {
+        self.select_and_reweight_with_rng(multiplier, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_and_reweight_with_rng<R: Rng>(&mut self, multiplier: f64, rng: &mut R) -> Option<(u64, f64)> {
+        if self.is_massless() {
+            return None;
+        }
+        let to_earlier = self.boundary_to_earlier(rng);
+        let target = Self::draw_target(self.root.accumulated_value, to_earlier, rng);
+        let (id, old_scaled) = self.descend_scaled(target, to_earlier, rng)?;
+        let weight = self.exact_weight(id, old_scaled as f64 / self.scale);
+        let new_weight = weight * multiplier;
+        let Some(new_scaled) = self.weight_to_digits(new_weight, &mut [0u8; MAX_DEPTH]) else {
+            return Some((id, weight));
+        };
+        if new_scaled != old_scaled && !self.update_scaled(id, old_scaled, new_scaled) {
+            return Some((id, weight));
+        }
+        self.record_exact(id, new_scaled, new_weight);
+        Some((id, self.exact_weight(id, new_scaled as f64 / self.scale)))
+    }
+
+    /// Like `descend`, but returns the scaled bin weight.
+    fn descend_scaled<R: Rng>(&self, mut target: u64, to_earlier: bool, rng: &mut R) -> Option<(u64, u64)> {
+        let mut node = &self.root;
+        while let Some(children) = self.arena.children(node) {
+            let (i, child_target) = Self::child_at(children, target, to_earlier)?;
+            node = children[i].as_ref()?;
+            target = child_target;
+        }
+        match &node.content {
+            NodeContent::Bin(bin) => {
+                let scaled_weight = node.accumulated_value.checked_div(node.content_count)?;
+                Some((bin.get_random(rng)?, scaled_weight))
+            }
+            NodeContent::DigitIndex(_) => None,
+        }
+    }
+    /// Draws `num_to_draw` items independently by weight. See
+    /// [`DigitBinIndex::select_many_with_replacement`].
+    pub fn select_many_with_replacement_with_rng<R: Rng>(&self, num_to_draw: u64, rng: &mut R) -> Option<Vec<(u64, f64)>> {
+        if num_to_draw == 0 {
+            return Some(Vec::new());
+        }
+        if self.is_massless() {
+            return None;
+        }
+        let to_earlier = self.boundary_to_earlier(rng);
+        let total = self.root.accumulated_value;
+        let uniform = if to_earlier { Uniform::new_inclusive(1u64, total) } else { Uniform::new(0u64, total) };
+        let uniform = uniform.expect("Valid range for Uniform");
+        let targets: Vec<u64> = uniform.sample_iter(&mut *rng).take(num_to_draw as usize).collect();
+        let mut selected = Vec::with_capacity(num_to_draw as usize);
+        self.with_replacement_recurse(&self.root, targets, to_earlier, rng, &mut selected);
+        Some(self.exact_weights(&selected).unwrap_or(selected))
+    }
+
+    /// Routes each target to the child whose range holds it, down to the bins, where one
+    /// member is drawn per target.
+    fn with_replacement_recurse<R: Rng>(
+        &self,
+        node: &Node<B>,
+        targets: Vec<u64>,
+        to_earlier: bool,
+        rng: &mut R,
+        selected: &mut Vec<(u64, f64)>,
+    ) {
+        match &node.content {
+            NodeContent::Bin(bin) => {
+                let Some(scaled_weight) = node.accumulated_value.checked_div(node.content_count) else {
+                    return;
+                };
+                let weight = scaled_weight as f64 / self.scale;
+                selected.extend(targets.iter().filter_map(|_| bin.get_random(rng)).map(|id| (id, weight)));
+            }
+            &NodeContent::DigitIndex(block) => {
+                let children = self.arena.block(block);
+                let mut child_targets = vec![Vec::new(); children.len()];
+                for target in targets {
+                    if let Some((i, child_target)) = Self::child_at(children, target, to_earlier) {
+                        child_targets[i].push(child_target);
+                    }
+                }
+                for (child, targets) in children.iter().zip(child_targets) {
+                    if let (Some(child), false) = (child, targets.is_empty()) {
+                        self.with_replacement_recurse(child, targets, to_earlier, rng, selected);
+                    }
+                }
+            }
+        }
+    }
+
+
+    /// Selects and removes exactly `quota` items from each group, by weight within the group.
+    /// See [`DigitBinIndex::select_with_quotas`].
+    pub fn select_with_quotas(&mut self, groups: &[(RoaringTreemap, u64)]) -> Result<Vec<Vec<(u64, f64)>>, DigitBinIndexError> {
+        for (i, (members, _)) in groups.iter().enumerate() {
+            for (other, _) in &groups[i + 1..] {
+                if let Some(id) = (members & other).min() {
+                    return Err(DigitBinIndexError::OverlappingGroups { id });
+                }
+            }
+        }
+        // Gather the members present in the index into one candidate index per group.
+        let mut candidates: Vec<DigitBinIndexGeneric<RoaringTreemap>> =
+            groups.iter().map(|_| DigitBinIndexGeneric::with_precision_and_radix(self.precision, self.radix)).collect();
+        self.for_each_bin(|scaled, bin| {
+            for id in bin.ids() {
+                if let Some(group) = groups.iter().position(|(members, _)| members.contains(id)) {
+                    candidates[group].insert_scaled(id, scaled, 1);
+                }
+            }
+        });
+        for (group, ((_, quota), pool)) in groups.iter().zip(&candidates).enumerate() {
+            if *quota > pool.count() {
+                return Err(DigitBinIndexError::InfeasibleQuota { group, requested: *quota, available: pool.count() });
+            }
+        }
+
+        let mut winners = Vec::with_capacity(groups.len());
+        for ((_, quota), pool) in groups.iter().zip(&mut candidates) {
+            let selected = pool.select_many_and_remove(*quota).unwrap_or_default();
+            for &(id, weight) in &selected {
+                self.remove_scaled(id, (weight * self.scale).round() as u64);
+            }
+            winners.push(selected);
+        }
+        Ok(winners)
+    }
+
+    /// Draws a with-replacement resample of a given size from each stratum, by weight
+    /// within the stratum. See [`DigitBinIndex::stratified_bootstrap`].
+    pub fn stratified_bootstrap<R: Rng>(
+        &self,
+        strata: &[(RoaringTreemap, u64)],
+        rng: &mut R,
+    ) -> Result<Vec<Vec<(u64, f64)>>, DigitBinIndexError> {
+        for (i, (members, _)) in strata.iter().enumerate() {
+            for (other, _) in &strata[i + 1..] {
+                if let Some(id) = (members & other).min() {
+                    return Err(DigitBinIndexError::OverlappingGroups { id });
+                }
+            }
+        }
+        // Counted bins keep the copies of each member, so they are drawn as often as in `select`.
+        let mut pools: Vec<DigitBinIndexGeneric<CountedBin>> =
+            strata.iter().map(|_| DigitBinIndexGeneric::with_precision_and_radix(self.precision, self.radix)).collect();
+        self.for_each_bin(|scaled, bin| {
+            bin.for_each_count(|id, copies| {
+                if let Some(stratum) = strata.iter().position(|(members, _)| members.contains(id)) {
+                    pools[stratum].insert_scaled(id, scaled, copies);
+                }
+            });
+        });
+        strata
+            .iter()
+            .zip(&pools)
+            .enumerate()
+            .map(|(group, ((_, size), pool))| {
+                let resample = pool.select_many_with_replacement_with_rng(*size, rng).ok_or(
+                    DigitBinIndexError::InfeasibleQuota { group, requested: *size, available: 0 },
+                )?;
+                Ok(resample.into_iter().map(|(id, weight)| (id, self.exact_weight(id, weight))).collect())
+            })
+            .collect()
+    }
+
+    /// Adds an item tagged with a group. See [`DigitBinIndex::add_with_group`].
+    pub fn add_with_group(&mut self, individual_id: u64, weight: f64, group: u16) -> bool {
+        if self.weight_to_digits(weight, &mut [0u8; MAX_DEPTH]).is_none() {
+            return false;
+        }
+        if self.registry.is_none() {
+            self.enable_id_registry();
+        }
+        let (precision, radix) = (self.precision, self.radix);
+        if let Some(registry) = &mut self.registry {
+            registry.set_group(individual_id, group, precision, radix);
+        }
+        let stored = self.add_with_count(individual_id, weight, 1) > 0;
+        // A failed insertion leaves no copy to carry the tag.
+        if let Some(registry) = &mut self.registry {
+            if let (false, Some(groups)) = (registry.bins.contains_key(&individual_id), &mut registry.groups) {
+                groups.of.remove(&individual_id);
+            }
+        }
+        stored
+    }
+
+    /// The members of a group, if it has any.
+    fn group_pool(&self, group: u16) -> Option<&DigitBinIndexGeneric<CountedBin>> {
+        self.registry.as_ref()?.groups.as_ref()?.pools.get(&group)
+    }
+
+    pub fn group_of(&self, individual_id: u64) -> Option<u16> {
+        self.registry.as_ref()?.groups.as_ref()?.of.get(&individual_id).copied()
+    }
+
+    /// The total binned weight of a group's items. See [`DigitBinIndex::group_weight`].
+    pub fn group_weight(&self, group: u16) -> f64 {
+        self.group_pool(group).map_or(0.0, |pool| pool.total_weight())
+    }
+
+    /// The number of items in a group, counting copies.
+    pub fn group_count(&self, group: u16) -> u64 {
+        self.group_pool(group).map_or(0, |pool| pool.count())
+    }
+
+    /// Selects an item of a group by weight, without removal. See [`DigitBinIndex::select_from_group`].
+    pub fn select_from_group(&self, group: u16) -> Option<(u64, f64)> {
+        self.select_from_group_with_rng(group, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_from_group_with_rng<R: Rng>(&self, group: u16, rng: &mut R) -> Option<(u64, f64)> {
+        let (id, weight) = self.group_pool(group)?.select_with_rng(rng)?;
+        Some((id, self.exact_weight(id, weight)))
+    }
+
+    /// Selects and removes an item of a group by weight.
+    pub fn select_from_group_and_remove(&mut self, group: u16) -> Option<(u64, f64)> {
+        self.select_from_group_and_remove_with_rng(group, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_from_group_and_remove_with_rng<R: Rng>(&mut self, group: u16, rng: &mut R) -> Option<(u64, f64)> {
+        let (id, weight) = self.group_pool(group)?.select_with_rng(rng)?;
+        let exact = self.exact_weight(id, weight);
+        self.remove_scaled(id, (weight * self.scale).round() as u64);
+        Some((id, exact))
+    }
+
+    /// Selects and removes a quota of items from each group. See [`DigitBinIndex::select_many_stratified`].
+    pub fn select_many_stratified(&mut self, quotas: &[(u16, u64)]) -> Result<Vec<Vec<(u64, f64)>>, DigitBinIndexError> {
+        self.select_many_stratified_with_rng(quotas, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_many_stratified_with_rng<R: Rng>(
+        &mut self,
+        quotas: &[(u16, u64)],
+        rng: &mut R,
+    ) -> Result<Vec<Vec<(u64, f64)>>, DigitBinIndexError> {
+        // A group listed twice has to cover both quotas.
+        let mut requested = std::collections::HashMap::new();
+        for (position, &(group, quota)) in quotas.iter().enumerate() {
+            let earlier = requested.entry(group).or_insert(0u64);
+            let available = self.group_count(group).saturating_sub(*earlier);
+            if quota > available {
+                return Err(DigitBinIndexError::InfeasibleQuota { group: position, requested: quota, available });
+            }
+            *earlier += quota;
+        }
+        let mut winners = Vec::with_capacity(quotas.len());
+        for &(group, quota) in quotas {
+            let selected = (0..quota).filter_map(|_| self.select_from_group_and_remove_with_rng(group, rng)).collect();
+            winners.push(selected);
+        }
+        Ok(winners)
+    }
+
+    /// Deterministic smooth weighted round-robin over the bins, cycling items within each bin.
+    /// See [`DigitBinIndex::next_scheduled`].
+    pub fn next_scheduled(&mut self) -> Option<(u64, f64)> {
+        // Every node runs smooth weighted round-robin among its children, weighted by their
+        // current masses, so a call updates the slots along one path and nothing else.
+        let mut node = &self.root;
+        let (mut depth, mut prefix) = (0u8, 0u64);
+        while let Some(children) = self.arena.children(node) {
+            let slot = self.schedule.entry((depth, prefix)).or_default();
+            slot.current.resize(children.len(), 0);
+            let mut total = 0i128;
+            let mut best: Option<usize> = None;
+            for (digit, child) in children.iter().enumerate() {
+                match child {
+                    Some(child) if child.accumulated_value > 0 => {
+                        slot.current[digit] += child.accumulated_value as i128;
+                        total += child.accumulated_value as i128;
+                        if best.is_none_or(|best| slot.current[digit] > slot.current[best]) {
+                            best = Some(digit);
+                        }
+                    }
+                    // Children that have emptied start over when they return.
+                    _ => slot.current[digit] = 0,
+                }
+            }
+            let digit = best?;
+            slot.current[digit] -= total;
+            node = children[digit].as_ref()?;
+            depth += 1;
+            prefix = prefix * self.radix as u64 + digit as u64;
+        }
+        let NodeContent::Bin(bin) = &node.content else { return None };
+        let slot = self.schedule.entry((depth, prefix)).or_default();
+        let position = slot.cursor % DigitBin::len(bin.as_ref()).max(1) as u64;
+        slot.cursor = position + 1;
+        let id = bin.nth_id(position)?;
+        Some((id, prefix as f64 / self.scale))
+    }
+
+    pub fn select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.select_many_and_optionally_remove(num_to_draw, true)
+    }
+
+    // Wrapper function to handle both select_many and select_many_and_remove
+    pub fn select_many_and_optionally_remove(&mut self, num_to_draw: u64, with_removal: bool) -> Option<Vec<(u64, f64)>> {
+        self.select_many_and_optionally_remove_with_rng(num_to_draw, with_removal, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_many_with_rng<R: Rng>(&mut self, num_to_draw: u64, rng: &mut R) -> Option<Vec<(u64, f64)>> {
+        self.select_many_and_optionally_remove_with_rng(num_to_draw, false, rng)
+    }
+
+    pub fn select_many_and_remove_with_rng<R: Rng>(&mut self, num_to_draw: u64, rng: &mut R) -> Option<Vec<(u64, f64)>> {
+        self.select_many_and_optionally_remove_with_rng(num_to_draw, true, rng)
+    }
+
+    pub fn select_many_and_optionally_remove_with_rng<R: Rng>(
+        &mut self,
+        num_to_draw: u64,
+        with_removal: bool,
+        rng: &mut R,
+    ) -> Option<Vec<(u64, f64)>> {
+        if num_to_draw > self.count() || num_to_draw == 0 {
+            return if num_to_draw == 0 { Some(Vec::new()) } else { None };
+        }
+        if self.is_massless() {
+            return None;
+        }
+        let mut selected: Vec<(u64, f64)> = Vec::with_capacity(num_to_draw as usize);
+        let total_accum = self.root.accumulated_value;
+        // Create a Uniform distribution for the range [0, total_accum)
+        let uniform = Uniform::new(0u64, total_accum).expect("Valid range for Uniform");
+        // Generate num_to_draw random numbers using sample_iter
+        let passed_targets: Vec<u64> = uniform
+            .sample_iter(&mut *rng)
+            .take(num_to_draw as usize)
+            .collect();
+        let (depth, scale) = (self.depth, self.scale);
+        if with_removal {
+            let (root, arena) = self.tree_mut();
+            Self::select_many_and_optionally_remove_recurse(root, arena, &mut selected, rng, 1, depth, true, passed_targets, scale);
+        } else {
+            // Reading through a shared reference leaves an arena shared with snapshots in place.
+            Self::select_many_recurse(&self.root, &self.arena, &mut selected, rng, 1, depth, passed_targets, scale);
+        }
+        let exact = self.exact_weights(&selected);
+        if with_removal {
+            for &(id, weight) in &selected {
+                self.log_removal(id, weight);
+            }
+        }
+        let selected = exact.unwrap_or(selected);
+        if selected.len() == num_to_draw as usize {
+            Some(selected)
+        } else {
+            None // Should not happen if logic is correct
+        }
+    }
+
+    /// Draws unique items without removal, distributed like sequential draws.
+    /// See [`DigitBinIndex::select_many_sequential`].
+    pub fn select_many_sequential(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.select_many_sequential_and_optionally_remove_with_rng(num_to_draw, false, &mut WyRand::from_os_rng())
+    }
+
+    /// Draws and removes unique items in one pass, distributed like sequential draws.
+    /// See [`DigitBinIndex::select_many_sequential_and_remove`].
+    pub fn select_many_sequential_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.select_many_sequential_and_optionally_remove_with_rng(num_to_draw, true, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_many_sequential_and_optionally_remove_with_rng<R: Rng>(
+        &mut self,
+        num_to_draw: u64,
+        with_removal: bool,
+        rng: &mut R,
+    ) -> Option<Vec<(u64, f64)>> {
+        self.select_many_sequential_with_order(num_to_draw, with_removal, false, rng)
+    }
+
+    /// Draws unique items without removal, in the order sequential draws would pick them.
+    /// See [`DigitBinIndex::select_many_ordered`].
+    pub fn select_many_ordered(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.select_many_ordered_and_optionally_remove_with_rng(num_to_draw, false, &mut WyRand::from_os_rng())
+    }
+
+    /// Draws and removes unique items, in the order sequential draws would pick them.
+    /// See [`DigitBinIndex::select_many_ordered_and_remove`].
+    pub fn select_many_ordered_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.select_many_ordered_and_optionally_remove_with_rng(num_to_draw, true, &mut WyRand::from_os_rng())
+    }
+
+    pub fn select_many_ordered_and_optionally_remove_with_rng<R: Rng>(
+        &mut self,
+        num_to_draw: u64,
+        with_removal: bool,
+        rng: &mut R,
+    ) -> Option<Vec<(u64, f64)>> {
+        self.select_many_sequential_with_order(num_to_draw, with_removal, true, rng)
+    }
+
+    fn select_many_sequential_with_order<R: Rng>(
+        &mut self,
+        num_to_draw: u64,
+        with_removal: bool,
+        in_draw_order: bool,
+        rng: &mut R,
+    ) -> Option<Vec<(u64, f64)>> {
+        if num_to_draw > self.count() || num_to_draw == 0 {
+            return if num_to_draw == 0 { Some(Vec::new()) } else { None };
+        }
+        if self.is_massless() {
+            return None;
+        }
+        let mut selected = Vec::with_capacity(num_to_draw as usize);
+        self.select_many_by_clocks(num_to_draw, with_removal, in_draw_order, rng, &mut selected);
+        let exact = self.exact_weights(&selected);
+        if with_removal {
+            for &(id, weight) in &selected {
+                self.log_removal(id, weight);
+            }
+        }
+        let selected = exact.unwrap_or(selected);
+        (selected.len() == num_to_draw as usize).then_some(selected)
+    }
+
+    /// Draws a batch by racing exponential clocks over the bins.
+    ///
+    /// Every item gets an exponential clock with its weight as rate, and the items whose
+    /// clocks ring first are taken, which is successive sampling without replacement.
+    /// Clocks are only kept per bin: the first of a bin's `m` remaining clocks rings after
+    /// an exponential time at rate `m * weight`, and by memorylessness a bin's clock is
+    /// simply restarted after it rings. Nothing is ever rejected, so the cost stays
+    /// `O(k log bins)` however close `k` gets to the population size.
+    ///
+    /// The items are appended grouped by bin, unless `in_draw_order` is set, in which case
+    /// they are appended in the order their clocks rang.
+    fn select_many_by_clocks<R: Rng>(
+        &mut self,
+        num_to_draw: u64,
+        with_removal: bool,
+        in_draw_order: bool,
+        rng: &mut R,
+        selected: &mut Vec<(u64, f64)>,
+    ) {
+        /// A bin's next ring, ordered so that `BinaryHeap` pops the earliest.
+        struct Clock {
+            time: f64,
+            bin: usize,
+        }
+        impl PartialEq for Clock {
+            fn eq(&self, other: &Self) -> bool {
+                self.time.total_cmp(&other.time).is_eq()
+            }
+        }
+        impl Eq for Clock {}
+        impl PartialOrd for Clock {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Clock {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                other.time.total_cmp(&self.time)
+            }
+        }
+        fn ring<R: Rng>(rng: &mut R, rate: f64) -> f64 {
+            -(1.0 - rng.random::<f64>()).ln() / rate
+        }
+
+        // (scaled weight, items left, items taken) per non-empty bin, in ascending weight order.
+        let mut bins: Vec<(u64, u64, u64)> = Vec::new();
+        self.for_each_bin(|scaled, bin| {
+            if !bin.is_empty() {
+                bins.push((scaled, bin.len() as u64, 0));
+            }
+        });
+        let mut clocks: std::collections::BinaryHeap<Clock> = bins
+            .iter()
+            .enumerate()
+            .map(|(bin, &(scaled, left, _))| Clock { time: ring(rng, (scaled * left) as f64), bin })
+            .collect();
+        let mut rings = Vec::with_capacity(if in_draw_order { num_to_draw as usize } else { 0 });
+        for _ in 0..num_to_draw {
+            let Some(Clock { time, bin }) = clocks.pop() else { break };
+            if in_draw_order {
+                rings.push(bin);
+            }
+            let (scaled, left, taken) = &mut bins[bin];
+            *left -= 1;
+            *taken += 1;
+            if *left > 0 {
+                let rate = (*scaled * *left) as f64;
+                clocks.push(Clock { time: time + ring(rng, rate), bin });
+            }
+        }
+
+        // Take each bin's share, uniformly within the bin.
+        #[allow(clippy::too_many_arguments)]
+        fn take<B: DigitBin, R: Rng>(
+            node: &mut Node<B>,
+            arena: &mut Arena<B>,
+            prefix: u64,
+            radix: u64,
+            takes: &mut std::iter::Peekable<impl Iterator<Item = (u64, u64)>>,
+            with_removal: bool,
+            scale: f64,
+            rng: &mut R,
+            selected: &mut Vec<(u64, f64)>,
+        ) {
+            match &mut node.content {
+                NodeContent::Bin(bin) => {
+                    let Some(&(scaled, count)) = takes.peek() else { return };
+                    if scaled != prefix {
+                        return;
+                    }
+                    takes.next();
+                    let weight = scaled as f64 / scale;
+                    if with_removal {
+                        let bin = Arc::make_mut(bin);
+                        for _ in 0..count {
+                            if let Some(id) = bin.get_random_and_remove(rng) {
+                                selected.push((id, weight));
+                                node.content_count -= 1;
+                                node.accumulated_value -= scaled;
+                            }
+                        }
+                    } else {
+                        // Look the sampled positions up directly, so the cost follows the share
+                        // taken rather than the size of the bin.
+                        for position in rand::seq::index::sample(rng, bin.len(), count as usize) {
+                            if let Some(id) = bin.nth_id(position as u64) {
+                                selected.push((id, weight));
+                            }
+                        }
+                    }
+                }
+                &mut NodeContent::DigitIndex(block) => {
+                    for digit in 0..arena.width {
+                        if takes.peek().is_none() {
+                            break;
+                        }
+                        arena.with_child(block, digit, |arena, child| {
+                            take(child, arena, prefix * radix + digit as u64, radix, takes, with_removal, scale, rng, selected)
+                        });
+                    }
+                    if with_removal {
+                        let children = arena.block(block);
+                        node.content_count = children.iter().flatten().map(|c| c.content_count).sum();
+                        node.accumulated_value = children.iter().flatten().map(|c| c.accumulated_value).sum();
+                    }
+                }
+            }
+        }
+        // Where each bin's share will start among the appended items.
+        let mut starts = Vec::with_capacity(if in_draw_order { bins.len() } else { 0 });
+        if in_draw_order {
+            let mut start = 0;
+            for &(_, _, taken) in &bins {
+                starts.push(start);
+                start += taken as usize;
+            }
+        }
+        let mut takes = bins.into_iter().filter(|&(_, _, taken)| taken > 0).map(|(scaled, _, taken)| (scaled, taken)).peekable();
+        let first = selected.len();
+        let (radix, scale) = (self.radix as u64, self.scale);
+        let (root, arena) = self.tree_mut();
+        take(root, arena, 0, radix, &mut takes, with_removal, scale, rng, selected);
+        if in_draw_order {
+            // Hand a bin's items out to its rings in random order, so which member answers
+            // which ring stays uniform.
+            let mut grouped = selected.split_off(first);
+            let ends: Vec<usize> = starts.iter().skip(1).copied().chain([grouped.len()]).collect();
+            for (&start, end) in starts.iter().zip(ends) {
+                rand::seq::SliceRandom::shuffle(&mut grouped[start..end], rng);
+            }
+            for bin in rings {
+                selected.push(grouped[starts[bin]]);
+                starts[bin] += 1;
+            }
+        }
+    }
+
+    /// Distributes batch-selection targets over a node's children, redrawing targets that
+    /// land on a child whose items are all taken. Returns each child's target count and
+    /// its targets relative to the child's own range.
+    fn assign_targets<R: Rng>(
+        children: &[Option<Node<B>>],
+        passed_targets: &[u64],
+        rng: &mut R,
+    ) -> (Vec<u64>, Vec<Vec<u64>>) {
+        let original_target_count = passed_targets.len() as u64;
+        // One count and one target list per child slot.
+        let mut child_assigned = vec![0u64; children.len()];
+        let mut child_rel_targets = vec![Vec::new(); children.len()];
+        let mut assigned = 0u64;
+
+        // --- Main assignment loop ---
+        for &target in passed_targets {
+            let mut cum: u64 = 0;
+            let mut chosen_idx = None;
+            // CHANGE: Iterate over the array of Options.
+            for (i, child_option) in children.iter().enumerate() {
+                // CHANGE: Only process existing children.
+                if let Some(child) = child_option {
+                    if child.accumulated_value == 0 {
+                        continue;
+                    }
+                    if target < cum + child.accumulated_value {
+                        if child_assigned[i] < child.content_count {
+                            chosen_idx = Some(i);
+                        }
+                        break;
+                    }
+                    cum += child.accumulated_value;
+                }
+            }
+            if let Some(idx) = chosen_idx {
+                child_assigned[idx] += 1;
+                // We need to re-calculate `cum` up to the chosen index to get the relative target.
+                let start_of_child_range: u64 = children[..idx].iter().filter_map(|c| c.as_ref()).map(|c| c.accumulated_value).sum();
+                let rel_target = target - start_of_child_range;
+                child_rel_targets[idx].push(rel_target);
+                assigned += 1;
+            }
+        }
+
+        // --- Redraw the remaining targets among the children that still have room ---
+        // Redrawing over the whole range and rejecting targets in full children gives the
+        // same distribution, but takes ever more attempts as the batch nears the item count.
+        let mut remaining = original_target_count - assigned;
+        while remaining > 0 {
+            let has_room = |i: usize, child: &Node<B>| child_assigned[i] < child.content_count;
+            let open: u64 = children
+                .iter()
+                .enumerate()
+                .filter_map(|(i, child)| child.as_ref().filter(|child| has_room(i, child)))
+                .map(|child| child.accumulated_value)
+                .sum();
+            if open == 0 {
+                break;
+            }
+            let mut target = rng.random_range(0..open);
+            let chosen = children.iter().enumerate().find_map(|(i, child)| {
+                let child = child.as_ref().filter(|child| has_room(i, child))?;
+                if target < child.accumulated_value {
+                    return Some(i);
+                }
+                target -= child.accumulated_value;
+                None
+            });
+            if let Some(idx) = chosen {
+                child_assigned[idx] += 1;
+                child_rel_targets[idx].push(target);
+                remaining -= 1;
+            }
+        }
+
+        (child_assigned, child_rel_targets)
+    }
+
+    /// Recursive helper for batch selection and removal.
+    /// - node: Current subtree root.
+    /// - arena: The blocks holding the children of the subtree.
+    /// - selected: Mutable vec to collect (id, weight) from leaves.
+    /// - rng: Mutable RNG.
+    /// - current_depth: Current digit level.
+    /// - max_depth: The depth of the bins below the root.
+    /// - with_removal: Whether to remove selected items.
+    /// - passed_targets: Pre-computed relative targets from parent (in [0, accumulated value)).
+    /// - scale: The scaling factor for weight conversions.
+    #[allow(clippy::too_many_arguments)]
+    fn select_many_and_optionally_remove_recurse<R: Rng>(
+        node: &mut Node<B>,
+        arena: &mut Arena<B>,
+        selected: &mut Vec<(u64, f64)>,
+        rng: &mut R,
+        current_depth: u8,
+        max_depth: u8,
+        with_removal: bool,
+        passed_targets: Vec<u64>,
+        scale: f64,
+    ) {
+        let original_target_count = passed_targets.len() as u64;
+        if original_target_count == 0 {
+            return;
+        }
+        if !with_removal {
+            Self::select_many_recurse(node, arena, selected, rng, current_depth, max_depth, passed_targets, scale);
+            return;
+        }
+
+        if current_depth > max_depth {
+            Self::take_from_bin(node, original_target_count, scale, rng, selected);
+            return;
+        }
+
+        if let NodeContent::DigitIndex(block) = node.content {
+            let (child_assigned, mut child_rel_targets) = Self::assign_targets(arena.block(block), &passed_targets, rng);
+
+            // --- Recurse into children ---
+            for (i, &assign_count) in child_assigned.iter().enumerate() {
+                if assign_count > 0 {
+                    // We must have a child here if it was assigned targets.
+                    let rel_targets = std::mem::take(&mut child_rel_targets[i]);
+                    arena.with_child(block, i, |arena, child| {
+                        Self::select_many_and_optionally_remove_recurse(
+                            child,
+                            arena,
+                            selected,
+                            rng,
+                            current_depth + 1,
+                            max_depth,
+                            with_removal,
+                            rel_targets,
+                            scale,
+                        )
+                    });
+                }
+            }
+
+            // --- Unwind: Update this node's stats ---
+            let children = arena.block(block);
+            node.content_count = children.iter().filter_map(|c| c.as_ref()).map(|c| c.content_count).sum();
+            node.accumulated_value = children.iter().filter_map(|c| c.as_ref()).map(|c| c.accumulated_value).sum();
+        }
+    }
+
+    /// Draws and removes up to `count` members of a bin node, updating its stats.
+    fn take_from_bin<R: Rng>(node: &mut Node<B>, count: u64, scale: f64, rng: &mut R, selected: &mut Vec<(u64, f64)>) {
+        if let NodeContent::Bin(bin) = &mut node.content {
+            let bin = Arc::make_mut(bin);
+            let bin_scaled = node.accumulated_value.checked_div(node.content_count).unwrap_or(0);
+            let bin_weight = bin_scaled as f64 / scale;
+            let to_select = count.min(node.content_count);
+            let mut picked = 0u64;
+            while picked < to_select && !bin.is_empty() {
+                let id = bin.get_random_and_remove(rng).unwrap();
+                selected.push((id, bin_weight));
+                picked += 1;
+            }
+            node.content_count -= picked;
+            node.accumulated_value -= bin_scaled * picked;
+        }
+    }
+
+    /// The read-only half of `select_many_and_optionally_remove_recurse`, for draws without
+    /// removal.
+    #[allow(clippy::too_many_arguments)]
+    fn select_many_recurse<R: Rng>(
+        node: &Node<B>,
+        arena: &Arena<B>,
+        selected: &mut Vec<(u64, f64)>,
+        rng: &mut R,
+        current_depth: u8,
+        max_depth: u8,
+        passed_targets: Vec<u64>,
+        scale: f64,
+    ) {
+        if passed_targets.is_empty() {
+            return;
+        }
+        match &node.content {
+            NodeContent::Bin(bin) if current_depth > max_depth => {
+                let bin_scaled = node.accumulated_value.checked_div(node.content_count).unwrap_or(0);
+                let bin_weight = bin_scaled as f64 / scale;
+                let to_select = (passed_targets.len() as u64).min(node.content_count);
+                for _ in 0..to_select {
+                    let Some(id) = bin.get_random(rng) else { break };
+                    selected.push((id, bin_weight));
+                }
+            }
+            &NodeContent::DigitIndex(block) => {
+                let children = arena.block(block);
+                let (child_assigned, mut child_rel_targets) = Self::assign_targets(children, &passed_targets, rng);
+                for (i, child) in children.iter().enumerate() {
+                    if let (Some(child), true) = (child, child_assigned[i] > 0) {
+                        let rel_targets = std::mem::take(&mut child_rel_targets[i]);
+                        Self::select_many_recurse(child, arena, selected, rng, current_depth + 1, max_depth, rel_targets, scale);
+                    }
+                }
+            }
+            NodeContent::Bin(_) => {}
+        }
+    }
+
+    /// The number of sequential draws a single batch draw can stand in for at the given
+    /// bias. See [`DigitBinIndex::suggest_batch_size`].
+    pub fn suggest_batch_size(&self, target_bias: f64) -> u64 {
+        if self.root.content_count == 0 {
+            return 0;
+        }
+        let mut sum_of_squares = 0.0;
+        self.for_each_bin(|scaled, bin| sum_of_squares += (scaled as f64).powi(2) * bin.len() as f64);
+        let total = self.root.accumulated_value as f64;
+        let effective_size = total * total / sum_of_squares;
+        let suggested = (target_bias.max(0.0) * effective_size).floor();
+        (suggested as u64).clamp(1, self.root.content_count)
+    }
+
+    pub fn select_many_with_bitmap(&mut self, num_to_draw: u64) -> Option<(Vec<(u64, f64)>, RoaringTreemap)> {
+        let selected = self.select_many_and_optionally_remove(num_to_draw, false)?;
+        let ids = selected.iter().map(|&(id, _)| id).collect();
+        Some((selected, ids))
+    }
+
+    pub fn select_many_and_remove_with_bitmap(&mut self, num_to_draw: u64) -> Option<(Vec<(u64, f64)>, RoaringTreemap)> {
+        let selected = self.select_many_and_optionally_remove(num_to_draw, true)?;
+        let ids = selected.iter().map(|&(id, _)| id).collect();
+        Some((selected, ids))
+    }
+
+    pub fn count(&self) -> u64 {
+        self.root.content_count
+    }
+
+    pub fn total_weight(&self) -> f64 {
+        self.root.accumulated_value as f64 / self.scale
+    }
+
+    /// Prints detailed statistics about the tree: node count, bin stats, and weight stats.
+    /// Collects structure and memory statistics. See [`DigitBinIndex::stats`].
+    pub fn stats(&self) -> IndexStats {
+        fn walk<B: DigitBin>(node: &Node<B>, arena: &Arena<B>, depth: usize, stats: &mut IndexStats) {
+            stats.nodes_per_depth[depth] += 1;
+            match &node.content {
+                &NodeContent::DigitIndex(block) => {
+                    let children = arena.block(block);
+                    stats.tree_bytes += std::mem::size_of_val::<[Option<Node<B>>]>(children);
+                    for child in children.iter().flatten() {
+                        walk(child, arena, depth + 1, stats);
+                    }
+                }
+                NodeContent::Bin(bin) if bin.is_empty() => stats.empty_bins += 1,
+                NodeContent::Bin(bin) => {
+                    let size = bin.len() as u64;
+                    stats.occupied_bins += 1;
+                    stats.largest_bin = stats.largest_bin.max(size);
+                    let bucket = size.ilog2() as usize;
+                    if stats.bin_size_histogram.len() <= bucket {
+                        stats.bin_size_histogram.resize(bucket + 1, 0);
+                    }
+                    stats.bin_size_histogram[bucket] += 1;
+                    stats.bin_bytes += bin.heap_size();
+                }
+            }
+        }
+
+        let mut stats = IndexStats {
+            precision: self.precision,
+            radix: self.radix,
+            items: self.count(),
+            nodes_per_depth: vec![0; self.depth as usize + 1],
+            tree_bytes: std::mem::size_of::<Node<B>>(),
+            ..IndexStats::default()
+        };
+        walk(&self.root, &self.arena, 0, &mut stats);
+        stats
+    }
+
+    pub fn print_stats_generic(&self) {
+        // This struct holds all the metrics we want to collect.
+        struct Stats {
+            node_count: usize,
+            non_empty_node_count: usize,
+            internal_node_count: usize, // NEW: For branching factor
+            child_slots_used: usize,    // NEW: For branching factor
+            bin_count: usize,
+            empty_bin_count: usize,
+            total_bin_items: u64,
+            min_weight: Option<f64>,
+            max_weight: Option<f64>,
+            // We collect all bin sizes to calculate standard deviation later.
+            bin_sizes: Vec<usize>, 
+            // Memory estimates
+            mem_nodes: usize,
+            mem_bins: usize,
+        }
+
+        fn traverse<B: DigitBin>(
+            node: &Node<B>,
+            arena: &Arena<B>,
+            stats: &mut Stats,
+            scale: f64,
+        ) {
+            stats.node_count += 1;
+            stats.mem_nodes += std::mem::size_of::<Node<B>>();
+
+            if node.content_count > 0 {
+                stats.non_empty_node_count += 1;
+            }
+            
+            match &node.content {
+                &NodeContent::DigitIndex(block) => {
+                    let children = arena.block(block);
+                    // --- NEW: Calculate branching factor stats ---
+                    stats.internal_node_count += 1;
+                    let used_children = children.iter().filter(|c| c.is_some()).count();
+                    stats.child_slots_used += used_children;
+                    // --- END NEW ---
+
+                    // Add memory for the node's block of optional children in the arena, one per digit.
+                    stats.mem_nodes += std::mem::size_of_val::<[Option<Node<B>>]>(children);
+                    
+                    // Iterate over the options in the array
+                    // Only recurse into the children that actually exist (are Some)
+                    for child in children.iter().flatten() {
+                        traverse(child, arena, stats, scale);
+                    }
+                }
+                NodeContent::Bin(bin) => {
+                    stats.bin_count += 1;
+                    let bin_size = bin.len();
+                    stats.bin_sizes.push(bin_size);
+                    stats.total_bin_items += bin_size as u64;
+
+                    // Estimate memory for the bin's contents.
+                    // This is an approximation. For RoaringBitmap, `serialized_size()` would be more accurate.
+                    stats.mem_bins += bin_size * std::mem::size_of::<u32>();
+
+                    if bin_size == 0 {
+                        stats.empty_bin_count += 1;
+                    } else {
+                        // All items in a bin share the same weight.
+                        let scaled_weight = node.accumulated_value / node.content_count;
+                        let weight = scaled_weight as f64 / scale;
+                        stats.min_weight = Some(stats.min_weight.map_or(weight, |min| min.min(weight)));
+                        stats.max_weight = Some(stats.max_weight.map_or(weight, |max| max.max(weight)));
+                    }
+                }
+            }
+        }
+
+        let mut stats = Stats {
+            node_count: 0,
+            non_empty_node_count: 0,
+            internal_node_count: 0, // NEW
+            child_slots_used: 0,    // NEW
+            bin_count: 0,
+            empty_bin_count: 0,
+            total_bin_items: 0,
+            min_weight: None,
+            max_weight: None,
+            bin_sizes: Vec::new(),
+            mem_nodes: 0,
+            mem_bins: 0,
+        };
+
+        traverse(&self.root, &self.arena, &mut stats, self.scale);
+        
+        // --- Calculations ---
+        let fill_ratio = if stats.node_count > 0 {
+            stats.non_empty_node_count as f64 / stats.node_count as f64 * 100.0
+        } else { 0.0 };
+
+        // NEW: Calculate average branching factor
+        let avg_branching_factor = if stats.internal_node_count > 0 {
+            stats.child_slots_used as f64 / stats.internal_node_count as f64
+        } else { 0.0 };
+        
+        let avg_bin_size = if stats.bin_count > 0 {
+            stats.total_bin_items as f64 / stats.bin_count as f64
+        } else { 0.0 };
+
+        let std_dev_bin_size = if stats.bin_count > 1 {
+            let variance = stats.bin_sizes.iter()
+                .map(|&size| (size as f64 - avg_bin_size).powi(2)) // Corrected: removed the `*`
+                .sum::<f64>() / (stats.bin_count - 1) as f64;
+            variance.sqrt()
+        } else { 0.0 };
+
+        // NEW: Calculate bin size quartiles
+        let (q1_bin_size, median_bin_size, q3_bin_size) = if !stats.bin_sizes.is_empty() {
+            let mut sorted_sizes = stats.bin_sizes.clone();
+            sorted_sizes.sort_unstable();
+            let q1 = sorted_sizes.get(sorted_sizes.len() / 4).cloned().unwrap_or(0);
+            let median = sorted_sizes.get(sorted_sizes.len() / 2).cloned().unwrap_or(0);
+            let q3 = sorted_sizes.get(sorted_sizes.len() * 3 / 4).cloned().unwrap_or(0);
+            (q1, median, q3)
+        } else {
+            (0, 0, 0)
+        };
+        
+        let total_mem_mb = (stats.mem_nodes + stats.mem_bins) as f64 / (1024.0 * 1024.0);
+        let nodes_mem_mb = stats.mem_nodes as f64 / (1024.0 * 1024.0);
+        let bins_mem_mb = stats.mem_bins as f64 / (1024.0 * 1024.0);
+        
+        // NEW: Calculate average weight
+        let avg_weight = if self.count() > 0 {
+            self.total_weight() / self.count() as f64
+        } else { 0.0 };
+        
+
+        // --- Printing ---
+        println!("\n[Tree Structure]");
+        println!("- Total Nodes Created:  {}", stats.node_count);
+        println!("- Internal Nodes:       {}", stats.internal_node_count); // NEW
+        println!("- Avg Branching Factor: {:.2} / {}", avg_branching_factor, self.radix); // NEW
+        println!("- Tree Fill Ratio:      {:.2}%", fill_ratio);
+        println!("- Max Depth:            {}", self.depth);
+
+        println!("\n[Memory (Estimated)]");
+        println!("- Tree Structure:       {:.2} MB", nodes_mem_mb);
+        println!("- Leaf Bins:            {:.2} MB", bins_mem_mb);
+        println!("- Total Estimated:      {:.2} MB", total_mem_mb);
+ 
+        println!("\n[Items & Bins]");
+        println!("- Total Items:          {}", stats.total_bin_items);
+        println!("- Total Bins (Leaves):  {}", stats.bin_count);
+        println!("- Empty Bins:           {}", stats.empty_bin_count);
+        println!("- Avg Items per Bin:    {:.2}", avg_bin_size);
+        println!("- Std Dev of Bin Size:  {:.2}", std_dev_bin_size);
+        println!("- Bin Size (min/max):   {} / {}", stats.bin_sizes.iter().min().map_or(0, |v| *v), stats.bin_sizes.iter().max().map_or(0, |v| *v));
+        println!("- Bin Size (Q1/Med/Q3): {} / {} / {}", q1_bin_size, median_bin_size, q3_bin_size); // NEW
+        
+        println!("\n[Weights]");
+        println!("- Smallest Weight:      {}", stats.min_weight.map_or("-".to_string(), |v| format!("{:.prec$}", v, prec = self.precision as usize)));
+        println!("- Largest Weight:       {}", stats.max_weight.map_or("-".to_string(), |v| format!("{:.prec$}", v, prec = self.precision as usize)));
+        println!("- Average Weight:       {:.prec$}", avg_weight, prec = self.precision as usize); // NEW
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<B: DigitBin + Send + Sync> DigitBinIndexGeneric<B> {
+    /// Tree levels at which batch selection fans out across threads; deeper levels run
+    /// sequentially within each thread.
+    const PARALLEL_DEPTH: u8 = 2;
+
+    /// See [`DigitBinIndex::par_select_many`].
+    pub fn par_select_many(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.par_select_many_and_optionally_remove(num_to_draw, false)
+    }
+
+    /// See [`DigitBinIndex::par_select_many_and_remove`].
+    pub fn par_select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
+        self.par_select_many_and_optionally_remove(num_to_draw, true)
+    }
+
+    fn par_select_many_and_optionally_remove(&mut self, num_to_draw: u64, with_removal: bool) -> Option<Vec<(u64, f64)>> {
+        if num_to_draw > self.count() || num_to_draw == 0 {
+            return if num_to_draw == 0 { Some(Vec::new()) } else { None };
+        }
+        let mut rng = WyRand::from_os_rng();
+        let total_accum = self.root.accumulated_value;
+        let uniform = Uniform::new(0u64, total_accum).expect("Valid range for Uniform");
+        let targets: Vec<u64> = uniform.sample_iter(&mut rng).take(num_to_draw as usize).collect();
+        let selected = if with_removal {
+            self.par_select_many_and_remove_targets(targets, rng.random())
+        } else {
+            Self::par_select_many_read_recurse(&self.root, &self.arena, 1, self.depth, targets, self.scale, rng.random())
+        };
+        let exact = self.exact_weights(&selected);
+        if with_removal {
+            for &(id, weight) in &selected {
+                self.log_removal(id, weight);
+            }
+        }
+        let selected = exact.unwrap_or(selected);
+        (selected.len() == num_to_draw as usize).then_some(selected)
+    }
+
+    /// Draws and removes the items at `targets` in three steps: routes the targets down to
+    /// the bins, draws from each bin in parallel, then takes the drawn items off the bins'
+    /// ancestors.
+    fn par_select_many_and_remove_targets(&mut self, targets: Vec<u64>, seed: u64) -> Vec<(u64, f64)> {
+        use rayon::prelude::*;
+
+        let mut leaves = Self::par_route_targets(&self.root, &self.arena, 1, self.depth, targets, seed);
+        leaves.sort_unstable_by_key(|&(slot, _, _)| slot);
+        let scale = self.scale;
+        // The bins are distinct slots of the arena, so splitting it at each of them hands
+        // every task its own bin.
+        let mut bins = Vec::with_capacity(leaves.len());
+        let mut rest = Arc::make_mut(&mut self.arena).slots.as_mut_slice();
+        let mut offset = 0;
+        for (slot, count, seed) in leaves {
+            let (_, tail) = std::mem::take(&mut rest).split_at_mut(slot - offset);
+            let (leaf, tail) = tail.split_first_mut().expect("a routed bin is a slot of the arena");
+            rest = tail;
+            offset = slot + 1;
+            if let Some(leaf) = leaf {
+                bins.push((leaf, count, seed));
+            }
+        }
+        let parts: Vec<(u64, Vec<(u64, f64)>)> = bins
+            .into_par_iter()
+            .map(|(leaf, count, seed)| {
+                let scaled = leaf.accumulated_value.checked_div(leaf.content_count).unwrap_or(0);
+                let mut selected = Vec::new();
+                Self::take_from_bin(leaf, count, scale, &mut WyRand::seed_from_u64(seed), &mut selected);
+                (scaled, selected)
+            })
+            .collect();
+        for (scaled, picks) in &parts {
+            self.subtract_above_bin(*scaled, picks.len() as u64);
+        }
+        parts.into_iter().flat_map(|(_, picks)| picks).collect()
+    }
+
+    /// Routes batch targets down to the bins as `select_many_and_optionally_remove_recurse`
+    /// does, in parallel near the root. Returns each bin reached as its slot in the arena,
+    /// the number of items to draw from it and a seed for that draw.
+    fn par_route_targets(
+        node: &Node<B>,
+        arena: &Arena<B>,
+        current_depth: u8,
+        max_depth: u8,
+        passed_targets: Vec<u64>,
+        seed: u64,
+    ) -> Vec<(usize, u64, u64)> {
+        use rayon::prelude::*;
+
+        let mut rng = WyRand::seed_from_u64(seed);
+        let NodeContent::DigitIndex(block) = node.content else {
+            return Vec::new();
+        };
+        let children = arena.block(block);
+        let (child_assigned, child_rel_targets) = Self::assign_targets(children, &passed_targets, &mut rng);
+        let first_slot = block as usize * arena.width;
+        let tasks: Vec<(usize, &Node<B>, Vec<u64>, u64)> = children
+            .iter()
+            .zip(child_rel_targets)
+            .zip(child_assigned)
+            .enumerate()
+            .filter_map(|(digit, ((child, targets), assigned))| {
+                let child = child.as_ref().filter(|_| assigned > 0)?;
+                Some((first_slot + digit, child, targets, rng.random()))
+            })
+            .collect();
+        let route = |(slot, child, targets, seed): (usize, &Node<B>, Vec<u64>, u64)| {
+            if current_depth == max_depth {
+                vec![(slot, targets.len() as u64, seed)]
+            } else {
+                Self::par_route_targets(child, arena, current_depth + 1, max_depth, targets, seed)
+            }
+        };
+        let parts: Vec<Vec<(usize, u64, u64)>> = if current_depth > Self::PARALLEL_DEPTH {
+            tasks.into_iter().map(route).collect()
+        } else {
+            tasks.into_par_iter().map(route).collect()
+        };
+        parts.concat()
+    }
+
+    /// Takes `count` items of the bin of `scaled` off the counts and masses of the bin's
+    /// ancestors, leaving the bin itself as it is.
+    fn subtract_above_bin(&mut self, scaled: u64, count: u64) {
+        let mut digits = [0u8; MAX_DEPTH];
+        self.scaled_to_digits(scaled, &mut digits);
+        let depth = self.depth as usize;
+        let (mut node, arena) = self.tree_mut();
+        for &digit in &digits[..depth] {
+            node.content_count -= count;
+            node.accumulated_value -= count * scaled;
+            let NodeContent::DigitIndex(block) = node.content else { return };
+            let Some(child) = arena.block_mut(block)[digit as usize].as_mut() else { return };
+            node = child;
+        }
+    }
+
+    /// Like `select_many_recurse`, but recurses into the children in parallel, each with its
+    /// own RNG seeded from this level's.
+    #[allow(clippy::too_many_arguments)]
+    fn par_select_many_read_recurse(
+        node: &Node<B>,
+        arena: &Arena<B>,
+        current_depth: u8,
+        max_depth: u8,
+        passed_targets: Vec<u64>,
+        scale: f64,
+        seed: u64,
+    ) -> Vec<(u64, f64)> {
+        use rayon::prelude::*;
+
+        let mut rng = WyRand::seed_from_u64(seed);
+        let mut selected = Vec::new();
+        if current_depth > max_depth || current_depth > Self::PARALLEL_DEPTH {
+            Self::select_many_recurse(node, arena, &mut selected, &mut rng, current_depth, max_depth, passed_targets, scale);
+            return selected;
+        }
+        let Some(children) = arena.children(node) else {
+            return selected;
+        };
+        let (child_assigned, child_rel_targets) = Self::assign_targets(children, &passed_targets, &mut rng);
+        let tasks: Vec<(&Node<B>, Vec<u64>, u64)> = children
+            .iter()
+            .zip(child_rel_targets)
+            .zip(child_assigned)
+            .filter_map(|((child, targets), assigned)| {
+                let child = child.as_ref().filter(|_| assigned > 0)?;
+                Some((child, targets, rng.random()))
+            })
+            .collect();
+        let parts: Vec<Vec<(u64, f64)>> = tasks
+            .into_par_iter()
+            .map(|(child, targets, seed)| {
+                Self::par_select_many_read_recurse(child, arena, current_depth + 1, max_depth, targets, scale, seed)
+            })
+            .collect();
+        parts.concat()
+    }
+}
+
+#[cfg(feature = "python-bindings")]
+mod python {
+    use super::*;
+    use numpy::{IntoPyArray, PyArray1, PyReadonlyArray1};
+    use pyo3::exceptions::PyValueError;
+    use pyo3::prelude::*;
+    use pyo3::types::PyBytes;
+
+    /// Selected items as NumPy arrays of IDs and weights.
+    type Selection<'py> = (Bound<'py, PyArray1<u64>>, Bound<'py, PyArray1<f64>>);
+
+    fn to_arrays(py: Python<'_>, selected: Vec<(u64, f64)>) -> Selection<'_> {
+        let (ids, weights): (Vec<u64>, Vec<f64>) = selected.into_iter().unzip();
+        (ids.into_pyarray(py), weights.into_pyarray(py))
+    }
+
+    #[pyclass(name = "DigitBinIndex", module = "digit_bin_index")]
+    struct PyDigitBinIndex {
+        index: DigitBinIndex,
+    }
+
+    #[pymethods]
+    impl PyDigitBinIndex {
+        #[new]
+        fn new() -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::new(),
+            }
+        }
+
+        /// Create a DigitBinIndex with a specific precision.
+        #[staticmethod]
+        fn with_precision(precision: u64) -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::with_precision(precision.try_into().unwrap()),
+            }
+        }
+
+        /// Create a DigitBinIndex with a specific precision and expected capacity.
+        #[staticmethod]
+        fn with_precision_and_capacity(precision: u8, capacity: u64) -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::with_precision_and_capacity(precision, capacity),
+            }
+        }        
+
+        /// Create a DigitBinIndex from (weight, count) pairs with consecutive synthetic IDs.
+        #[staticmethod]
+        fn from_counts(precision: u8, histogram: Vec<(f64, u64)>) -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::from_counts(precision, &histogram),
+            }
+        }
+
+        /// Create a DigitBinIndex with Vec<u32> bins and the specified precision.
+        #[staticmethod]
+        fn small(precision: u8) -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::small(precision),
+            }
+        }
+
+        /// Create a DigitBinIndex with RoaringBitmap bins and the specified precision.
+        #[staticmethod]
+        fn medium(precision: u8) -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::medium(precision),
+            }
+        }
+
+        /// Create a DigitBinIndex with RoaringTreemap bins and the specified precision.
+        #[staticmethod]
+        fn large(precision: u8) -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::large(precision),
+            }
+        }
+
+        /// Create a DigitBinIndex with CountedBin bins and the specified precision.
+        #[staticmethod]
+        fn counted(precision: u8) -> Self {
+            PyDigitBinIndex {
+                index: DigitBinIndex::counted(precision),
+            }
+        }
+
+        fn add(&mut self, id: u64, weight: f64) -> bool {
+            self.index.add(id, weight)
+        }
+
+        fn add_with_count(&mut self, id: u64, weight: f64, count: u64) -> u64 {
+            self.index.add_with_count(id, weight, count)
+        }
+
+        fn coarsen_to(&mut self, precision: u8) -> Vec<(u64, f64)> {
+            self.index.coarsen_to(precision)
+        }
+
+        fn rebin_to_precision(&mut self, new_precision: u8) -> Vec<(u64, f64)> {
+            self.index.rebin_to_precision(new_precision)
+        }
+
+        fn scale_all_weights(&mut self, factor: f64) -> PyResult<Vec<(u64, f64)>> {
+            if !(factor.is_finite() && factor > 0.0) {
+                return Err(PyValueError::new_err("The scaling factor must be positive and finite."));
+            }
+            Ok(self.index.scale_all_weights(factor))
+        }
+
+        fn next_scheduled(&mut self) -> Option<(u64, f64)> {
+            self.index.next_scheduled()
+        }
+
+        fn add_range(&mut self, start: u64, end: u64, weight: f64) -> u64 {
+            self.index.add_range(start..end, weight)
+        }
+
+        /// Add items from equally long NumPy arrays of IDs (uint64) and weights (float64).
+        /// The GIL is released while the items are indexed.
+        fn add_many(&mut self, py: Python<'_>, ids: PyReadonlyArray1<'_, u64>, weights: PyReadonlyArray1<'_, f64>) -> PyResult<()> {
+            let (ids, weights) = (ids.as_array(), weights.as_array());
+            if ids.len() != weights.len() {
+                return Err(PyValueError::new_err("ids and weights must have the same length"));
+            }
+            let items: Vec<(u64, f64)> = ids.iter().copied().zip(weights.iter().copied()).collect();
+            let index = &mut self.index;
+            py.detach(|| index.add_many(&items));
+            Ok(())
+        }
+
+        fn remove(&mut self, id: u64, weight: f64) -> bool {
+            self.index.remove(id, weight)
+        }
+
+        fn update_weight(&mut self, id: u64, old_weight: f64, new_weight: f64) -> bool {
+            self.index.update_weight(id, old_weight, new_weight)
+        }
+
+        /// Queue a weight change for an item, applied when advance_to reaches the tick.
+        fn at_tick(&mut self, tick: u64, id: u64, new_weight: f64) {
+            self.index.at_tick(tick, id, new_weight);
+        }
+
+        /// Apply all changes queued up to and including the tick; return how many took effect.
+        fn advance_to(&mut self, tick: u64) -> usize {
+            self.index.advance_to(tick)
+        }
+
+        /// Remove one copy of an item by ID and return its binned weight, or None.
+        fn remove_by_id(&mut self, id: u64) -> Option<f64> {
+            self.index.remove_by_id(id)
+        }
+
+        /// Whether the ID registry holds the ID; always False while the registry is off.
+        fn contains(&self, id: u64) -> bool {
+            self.index.contains(id)
+        }
+
+        /// Return the binned weight of an item, or None if it is not in the index or the ID
+        /// registry is off.
+        fn get_weight(&self, id: u64) -> Option<f64> {
+            self.index.get_weight(id)
+        }
+
+        /// The bin weight a weight is stored under, or None if it cannot be stored.
+        fn quantize(&self, weight: f64) -> Option<f64> {
+            self.index.quantize(weight)
+        }
+
+        fn bin_count(&self, weight: f64) -> u64 {
+            self.index.bin_count(weight)
+        }
+
+        /// The IDs sharing the bin a weight is stored under.
+        fn bin_members(&self, weight: f64) -> Vec<u64> {
+            self.index.bin_members(weight).map_or_else(Vec::new, Iterator::collect)
+        }
+
+        /// Keep an ID -> bin map so that lookups by ID do not scan the bins.
+        fn enable_id_registry(&mut self) {
+            self.index.enable_id_registry();
+        }
+
+        fn enable_exact_weights(&mut self) {
+            self.index.enable_exact_weights();
+        }
+
+        fn has_exact_weights(&self) -> bool {
+            self.index.has_exact_weights()
+        }
+
+        fn remove_many(&mut self, items: Vec<(u64, f64)>) -> bool {
+            self.index.remove_many(&items)
+        }
+
+        /// Remove one copy of each given ID; return how many were removed.
+        fn remove_ids(&mut self, ids: Vec<u64>) -> u64 {
+            self.index.remove_ids(&ids.into_iter().collect())
+        }        
+
+        fn select(&mut self) -> Option<(u64, f64)> {
+            self.index.select()
+        }
+
+        /// Select n items, returned as NumPy arrays of IDs and weights.
+        fn select_many<'py>(&mut self, py: Python<'py>, n: u64) -> Option<Selection<'py>> {
+            let index = &mut self.index;
+            let selected = py.detach(|| index.select_many(n))?;
+            Some(to_arrays(py, selected))
+        }
+
+        fn select_and_remove(&mut self) -> Option<(u64, f64)> {
+            self.index.select_and_remove()
+        }
+
+        fn select_and_reweight(&mut self, multiplier: f64) -> Option<(u64, f64)> {
+            self.index.select_and_reweight(multiplier)
+        }
+
+        fn select_in_range(&self, min: f64, max: f64) -> Option<(u64, f64)> {
+            self.index.select_in_range(min, max)
+        }
+
+        fn select_in_range_and_remove(&mut self, min: f64, max: f64) -> Option<(u64, f64)> {
+            self.index.select_in_range_and_remove(min, max)
+        }
+
+        fn heaviest(&self, k: usize) -> Vec<(u64, f64)> {
+            self.index.heaviest(k)
+        }
+
+        fn lightest(&self, k: usize) -> Vec<(u64, f64)> {
+            self.index.lightest(k)
+        }
+
+        /// Select and remove n unique items, returned as NumPy arrays of IDs and weights.
+        fn select_many_and_remove<'py>(&mut self, py: Python<'py>, n: u64) -> Option<Selection<'py>> {
+            let index = &mut self.index;
+            let selected = py.detach(|| index.select_many_and_remove(n))?;
+            Some(to_arrays(py, selected))
+        }
+
+        /// Select and remove n unique items with the distribution of n sequential draws,
+        /// returned as NumPy arrays of IDs and weights.
+        fn select_many_sequential_and_remove<'py>(&mut self, py: Python<'py>, n: u64) -> Option<Selection<'py>> {
+            let index = &mut self.index;
+            let selected = py.detach(|| index.select_many_sequential_and_remove(n))?;
+            Some(to_arrays(py, selected))
+        }
+
+        /// Select and remove n unique items as n sequential draws would, returned as NumPy
+        /// arrays of IDs and weights in draw order.
+        fn select_many_ordered_and_remove<'py>(&mut self, py: Python<'py>, n: u64) -> Option<Selection<'py>> {
+            let index = &mut self.index;
+            let selected = py.detach(|| index.select_many_ordered_and_remove(n))?;
+            Some(to_arrays(py, selected))
+        }
+
+        /// Draw n items with replacement, returned as NumPy arrays of IDs and weights.
+        fn select_many_with_replacement<'py>(&self, py: Python<'py>, n: u64) -> Option<Selection<'py>> {
+            let index = &self.index;
+            let selected = py.detach(|| index.select_many_with_replacement(n))?;
+            Some(to_arrays(py, selected))
+        }
+
+        fn clone_with_overrides(&self, overrides: Vec<(u64, f64)>) -> Self {
+            PyDigitBinIndex {
+                index: self.index.clone_with_overrides(&overrides),
+            }
+        }
+
+        fn add_with_group(&mut self, id: u64, weight: f64, group: u16) -> bool {
+            self.index.add_with_group(id, weight, group)
+        }
+
+        fn group_weight(&self, group: u16) -> f64 {
+            self.index.group_weight(group)
+        }
+
+        fn select_from_group(&self, group: u16) -> Option<(u64, f64)> {
+            self.index.select_from_group(group)
+        }
+
+        fn select_and_decrement(&mut self) -> Option<(u64, f64, u64)> {
+            self.index.select_and_decrement()
+        }
+
+        fn total_weight(&self) -> f64 {
+            self.index.total_weight()
+        }
+
+        fn count(&self) -> u64 {
+            self.index.count()
+        }
+
+        fn print_stats(&self) {
+            self.index.print_stats();
+        }
+
+        fn __len__(&self) -> usize {
+            self.index.count() as usize
+        }
+
+        fn __repr__(&self) -> String {
+            format!(
+                "DigitBinIndex(precision={}, count={}, total_weight={:?})",
+                self.index.precision(),
+                self.index.count(),
+                self.index.total_weight()
+            )
+        }
+
+        /// The pickled state is a snapshot, as written by save_to, which keeps the
+        /// configuration and bookkeeping along with the items.
+        fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+            let mut bytes = Vec::new();
+            self.index.save_to(&mut bytes).map_err(|err| PyValueError::new_err(err.to_string()))?;
+            Ok(PyBytes::new(py, &bytes))
+        }
+
+        fn __setstate__(&mut self, state: Bound<'_, PyBytes>) -> PyResult<()> {
+            self.index = DigitBinIndex::load_from(state.as_bytes()).map_err(|err| PyValueError::new_err(err.to_string()))?;
+            Ok(())
+        }
+    }
+
+    #[pymodule]
+    fn digit_bin_index(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
+        m.add_class::<PyDigitBinIndex>()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "wasm-bindgen")]
+mod wasm {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+
+    /// The index as a JavaScript class. IDs and counts are `u64`, which JavaScript sees as
+    /// `BigInt`; weights are numbers.
+    #[wasm_bindgen(js_name = DigitBinIndex)]
+    pub struct JsDigitBinIndex {
+        index: DigitBinIndex,
+    }
+
+    /// One selected item.
+    #[wasm_bindgen]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Selected {
+        pub id: u64,
+        pub weight: f64,
+    }
+
+    /// One copy taken from a cohort, with the number of copies of its ID that remain.
+    #[wasm_bindgen]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Decremented {
+        pub id: u64,
+        pub weight: f64,
+        pub remaining: u64,
+    }
+
+    /// Selected items, as equally long arrays of IDs and weights.
+    #[wasm_bindgen]
+    pub struct Selection {
+        ids: Vec<u64>,
+        weights: Vec<f64>,
+    }
+
+    #[wasm_bindgen]
+    impl Selection {
+        /// The selected IDs, as a `BigUint64Array`.
+        #[wasm_bindgen(getter)]
+        pub fn ids(&self) -> Vec<u64> {
+            self.ids.clone()
+        }
+
+        /// The weights of the selected items, as a `Float64Array`.
+        #[wasm_bindgen(getter)]
+        pub fn weights(&self) -> Vec<f64> {
+            self.weights.clone()
+        }
+    }
+
+    impl From<Vec<(u64, f64)>> for Selection {
+        fn from(selected: Vec<(u64, f64)>) -> Self {
+            let (ids, weights) = selected.into_iter().unzip();
+            Selection { ids, weights }
+        }
+    }
+
+    impl Default for JsDigitBinIndex {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[wasm_bindgen(js_class = DigitBinIndex)]
+    impl JsDigitBinIndex {
+        /// Create an index with the default precision.
+        #[wasm_bindgen(constructor)]
+        pub fn new() -> Self {
+            JsDigitBinIndex { index: DigitBinIndex::new() }
+        }
+
+        /// Create an index with a specific precision, from 1 to 9.
+        #[wasm_bindgen(js_name = withPrecision)]
+        pub fn with_precision(precision: u8) -> Result<JsDigitBinIndex, JsError> {
+            let index = DigitBinIndex::try_with_precision(precision).map_err(|err| JsError::new(&err.to_string()))?;
+            Ok(JsDigitBinIndex { index })
+        }
+
+        /// Create an index that stores a count per ID, for cohorts of identical items.
+        pub fn counted(precision: u8) -> Result<JsDigitBinIndex, JsError> {
+            let config = Config::new().precision(precision).backend(Backend::Counted);
+            let index = DigitBinIndex::try_with_config(config).map_err(|err| JsError::new(&err.to_string()))?;
+            Ok(JsDigitBinIndex { index })
+        }
+
+        pub fn add(&mut self, id: u64, weight: f64) -> bool {
+            self.index.add(id, weight)
+        }
+
+        /// Add `count` identical copies of an item; returns how many were stored.
+        #[wasm_bindgen(js_name = addWithCount)]
+        pub fn add_with_count(&mut self, id: u64, weight: f64, count: u64) -> u64 {
+            self.index.add_with_count(id, weight, count)
+        }
+
+        pub fn remove(&mut self, id: u64, weight: f64) -> bool {
+            self.index.remove(id, weight)
+        }
+
+        pub fn select(&mut self) -> Option<Selected> {
+            let (id, weight) = self.index.select()?;
+            Some(Selected { id, weight })
+        }
+
+        #[wasm_bindgen(js_name = selectAndRemove)]
+        pub fn select_and_remove(&mut self) -> Option<Selected> {
+            let (id, weight) = self.index.select_and_remove()?;
+            Some(Selected { id, weight })
+        }
+
+        /// Take one copy by weight, reporting how many copies of its ID remain.
+        #[wasm_bindgen(js_name = selectAndDecrement)]
+        pub fn select_and_decrement(&mut self) -> Option<Decremented> {
+            let (id, weight, remaining) = self.index.select_and_decrement()?;
+            Some(Decremented { id, weight, remaining })
+        }
+
+        #[wasm_bindgen(js_name = selectMany)]
+        pub fn select_many(&mut self, n: u64) -> Option<Selection> {
+            self.index.select_many(n).map(Selection::from)
+        }
+
+        #[wasm_bindgen(js_name = selectManyAndRemove)]
+        pub fn select_many_and_remove(&mut self, n: u64) -> Option<Selection> {
+            self.index.select_many_and_remove(n).map(Selection::from)
+        }
+
+        pub fn count(&self) -> u64 {
+            self.index.count()
+        }
+
+        #[wasm_bindgen(js_name = totalWeight)]
+        pub fn total_weight(&self) -> f64 {
+            self.index.total_weight()
+        }
+    }
+}
+
+#[cfg(feature = "ffi")]
+mod ffi {
+    //! C bindings. The index is an opaque `DigitBinIndex*` owned by the caller, created with
+    //! `dbi_new`, `dbi_with_precision` or `dbi_counted` and released with `dbi_free`. The
+    //! header is `include/digit_bin_index.h`, generated with
+    //! `cbindgen --config cbindgen.toml --output include/digit_bin_index.h`.
+    //!
+    //! No entry point unwinds into C: a panic inside one is caught and reported like a
+    //! failure (`NULL`, `false` or 0). `NULL` index and output pointers are refused the same
+    //! way, before anything is changed.
+
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::slice;
+
+    /// Runs the body of an entry point, returning `failed` if it panics.
+    fn guard<T>(failed: T, body: impl FnOnce() -> T) -> T {
+        catch_unwind(AssertUnwindSafe(body)).unwrap_or(failed)
+    }
+
+    /// Moves a created index to the heap, or returns `NULL` if it could not be created.
+    fn into_handle(index: Result<DigitBinIndex, DigitBinIndexError>) -> *mut DigitBinIndex {
+        index.map_or(std::ptr::null_mut(), |index| Box::into_raw(Box::new(index)))
+    }
+
+    /// Creates an index with the default precision.
+    #[no_mangle]
+    pub extern "C" fn dbi_new() -> *mut DigitBinIndex {
+        guard(std::ptr::null_mut(), || into_handle(Ok(DigitBinIndex::new())))
+    }
+
+    /// Creates an index with a specific precision, from 1 to 9. Returns `NULL` if the
+    /// precision is out of range.
+    #[no_mangle]
+    pub extern "C" fn dbi_with_precision(precision: u8) -> *mut DigitBinIndex {
+        guard(std::ptr::null_mut(), || into_handle(DigitBinIndex::try_with_precision(precision)))
+    }
+
+    /// Creates an index that stores a count per ID, for cohorts of identical items, with a
+    /// precision from 1 to 9. Returns `NULL` if the precision is out of range.
+    #[no_mangle]
+    pub extern "C" fn dbi_counted(precision: u8) -> *mut DigitBinIndex {
+        guard(std::ptr::null_mut(), || {
+            into_handle(DigitBinIndex::try_with_config(Config::new().precision(precision).backend(Backend::Counted)))
+        })
+    }
+
+    /// Releases an index. Passing `NULL` does nothing.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or come from `dbi_new`, `dbi_with_precision` or
+    /// `dbi_counted`, and must not be used afterwards.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_free(index: *mut DigitBinIndex) {
+        if !index.is_null() {
+            guard((), || drop(Box::from_raw(index)));
+        }
+    }
+
+    /// Adds an item. Returns `false`, and adds nothing, if the weight cannot be stored
+    /// (outside `(0, 1)`, or below the precision).
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_add(index: *mut DigitBinIndex, id: u64, weight: f64) -> bool {
+        let Some(index) = index.as_mut() else { return false };
+        guard(false, || index.try_add(id, weight).is_ok())
+    }
+
+    /// Adds `count` identical copies of an item and returns how many were stored, 0 if the
+    /// weight cannot be stored. A counted index keeps the copies as a single count.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_add_with_count(index: *mut DigitBinIndex, id: u64, weight: f64, count: u64) -> u64 {
+        let Some(index) = index.as_mut() else { return 0 };
+        guard(0, || index.add_with_count(id, weight, count))
+    }
+
+    /// Removes an item that was added with the given weight. Returns whether it was found.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_remove(index: *mut DigitBinIndex, id: u64, weight: f64) -> bool {
+        let Some(index) = index.as_mut() else { return false };
+        guard(false, || index.remove(id, weight))
+    }
+
+    /// Writes one selected item to the outputs, if there is one. `out_id` has been checked.
+    unsafe fn write_selected(selected: Option<(u64, f64)>, out_id: *mut u64, out_weight: *mut f64) -> bool {
+        let Some((id, weight)) = selected else { return false };
+        *out_id = id;
+        if !out_weight.is_null() {
+            *out_weight = weight;
+        }
+        true
+    }
+
+    /// Writes a batch of selected items to the outputs, if the draw succeeded. `out_ids`
+    /// has been checked.
+    unsafe fn write_selection(
+        selected: Option<Vec<(u64, f64)>>,
+        out_ids: *mut u64,
+        out_weights: *mut f64,
+    ) -> bool {
+        let Some(selected) = selected else { return false };
+        let ids = slice::from_raw_parts_mut(out_ids, selected.len());
+        for (slot, &(id, _)) in ids.iter_mut().zip(&selected) {
+            *slot = id;
+        }
+        if !out_weights.is_null() {
+            let weights = slice::from_raw_parts_mut(out_weights, selected.len());
+            for (slot, &(_, weight)) in weights.iter_mut().zip(&selected) {
+                *slot = weight;
+            }
+        }
+        true
+    }
+
+    /// Selects an item by weight without removal, writing its ID and bin weight to the
+    /// outputs. Returns `false`, and writes nothing, if the index is empty or `index` or
+    /// `out_id` is `NULL`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index, and `out_id` must be `NULL` or writable.
+    /// `out_weight` may be `NULL`.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_select(index: *mut DigitBinIndex, out_id: *mut u64, out_weight: *mut f64) -> bool {
+        let Some(index) = index.as_mut().filter(|_| !out_id.is_null()) else { return false };
+        guard(false, || write_selected(index.select(), out_id, out_weight))
+    }
+
+    /// Selects and removes an item by weight, like `dbi_select`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index, and `out_id` must be `NULL` or writable.
+    /// `out_weight` may be `NULL`.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_select_and_remove(
+        index: *mut DigitBinIndex,
+        out_id: *mut u64,
+        out_weight: *mut f64,
+    ) -> bool {
+        let Some(index) = index.as_mut().filter(|_| !out_id.is_null()) else { return false };
+        guard(false, || write_selected(index.select_and_remove(), out_id, out_weight))
+    }
+
+    /// Takes one copy by weight, like `dbi_select_and_remove`, and also writes the number of
+    /// copies of its ID that remain.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index, and `out_id` must be `NULL` or writable.
+    /// `out_weight` and `out_remaining` may be `NULL`.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_select_and_decrement(
+        index: *mut DigitBinIndex,
+        out_id: *mut u64,
+        out_weight: *mut f64,
+        out_remaining: *mut u64,
+    ) -> bool {
+        let Some(index) = index.as_mut().filter(|_| !out_id.is_null()) else { return false };
+        guard(false, || {
+            let Some((id, weight, remaining)) = index.select_and_decrement() else { return false };
+            if !out_remaining.is_null() {
+                *out_remaining = remaining;
+            }
+            write_selected(Some((id, weight)), out_id, out_weight)
+        })
+    }
+
+    /// Selects `n` items by weight without removal, writing their IDs and bin weights to
+    /// the outputs. Returns `false`, and writes nothing, if the index holds fewer than `n`
+    /// items or `index` or `out_ids` is `NULL`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index, and `out_ids` must be `NULL` or have room
+    /// for `n` IDs. `out_weights` may be `NULL`, or must have room for `n` weights.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_select_many(
+        index: *mut DigitBinIndex,
+        n: u64,
+        out_ids: *mut u64,
+        out_weights: *mut f64,
+    ) -> bool {
+        let Some(index) = index.as_mut().filter(|_| !out_ids.is_null()) else { return false };
+        guard(false, || write_selection(index.select_many(n), out_ids, out_weights))
+    }
+
+    /// Selects and removes `n` unique items by weight, like `dbi_select_many`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index, and `out_ids` must be `NULL` or have room
+    /// for `n` IDs. `out_weights` may be `NULL`, or must have room for `n` weights.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_select_many_and_remove(
+        index: *mut DigitBinIndex,
+        n: u64,
+        out_ids: *mut u64,
+        out_weights: *mut f64,
+    ) -> bool {
+        let Some(index) = index.as_mut().filter(|_| !out_ids.is_null()) else { return false };
+        guard(false, || write_selection(index.select_many_and_remove(n), out_ids, out_weights))
+    }
+
+    /// Returns the number of items, or 0 for `NULL`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_count(index: *const DigitBinIndex) -> u64 {
+        let Some(index) = index.as_ref() else { return 0 };
+        guard(0, || index.count())
+    }
+
+    /// Returns the sum of the bin weights of all items, or 0 for `NULL`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be `NULL` or a live index.
+    #[no_mangle]
+    pub unsafe extern "C" fn dbi_total_weight(index: *const DigitBinIndex) -> f64 {
+        let Some(index) = index.as_ref() else { return 0.0 };
+        guard(0.0, || index.total_weight())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bin weight of an ID, found by walking the items, for indexes without a registry.
+    fn stored_weight(index: &DigitBinIndex, id: u64) -> Option<f64> {
+        index.iter().find(|&(stored, _)| stored == id).map(|(_, weight)| weight)
+    }
+
+    #[test]
+    fn test_select_and_remove() {
+        let mut index = DigitBinIndex::with_precision(3);
+        index.add(1, 0.085);
+        index.add(2, 0.205);
+        index.add(3, 0.346);
+        index.add(4, 0.364);
+        index.print_stats();
+        println!("Initial state: {} individuals, total weight = {}", index.count(), index.total_weight());    
+        if let Some((id, weight)) = index.select_and_remove() {
+            println!("Selected ID: {} with weight: {}", id, weight);
+        }
+        assert!(
+            index.count() == 3,
+            "The count is now {} and not 3 as expected",
+            index.count()
+        );
+        println!("Intermediate state: {} individuals, total weight = {}", index.count(), index.total_weight()); 
+        if let Some(selection) = index.select_many_and_remove(2) {
+            println!("Selection: {:?}", selection);
+        }
+        assert!(
+            index.count() == 1,
+            "The count is now {} and not 1 as expected",
+            index.count()
+        );
+        println!("Final state: {} individuals, total weight = {}", index.count(), index.total_weight()); 
+    }
+
+    #[test]
+    fn test_counted_cohorts() {
+        let mut index = DigitBinIndex::counted(3);
+        assert_eq!(index.add_with_count(1, 0.1, 1_000_000), 1_000_000);
+        assert_eq!(index.add_with_count(2, 0.1, 3), 3);
+        assert_eq!(index.add_with_count(3, 0.4, 2), 2);
+        assert_eq!(index.count(), 1_000_005);
+
+        // Cohort 1 dominates the shared 0.1 bin, so draws from that bin are almost always it.
+        let mut remaining_of_one = 1_000_000;
+        for _ in 0..100 {
+            let (id, _, remaining) = index.select_and_decrement().unwrap();
+            if id == 1 {
+                remaining_of_one -= 1;
+                assert_eq!(remaining, remaining_of_one);
+            }
+        }
+        assert_eq!(index.count(), 1_000_005 - 100);
+
+        // Removing by ID takes one copy at a time.
+        let before = index.count();
+        assert!(index.remove(1, 0.1));
+        assert_eq!(index.count(), before - 1);
+
+        // Set-based backends store at most one copy per ID.
+        let mut medium = DigitBinIndex::medium(3);
+        assert_eq!(medium.add_with_count(1, 0.1, 5), 1);
+        assert_eq!(medium.add_with_count(1, 0.1, 5), 0);
+        assert_eq!(medium.count(), 1);
+
+        // Emptied cohorts leave the bin without losing track of the ones moved into their place.
+        let mut bin = CountedBin::default();
+        bin.insert_range(0..4);
+        bin.insert_count(2, 5);
+        assert_eq!(bin.remove_all(0), 1);
+        assert!(bin.remove(1));
+        assert_eq!((bin.count_of(2), bin.count_of(3)), (6, 1));
+        assert_eq!(bin.remove_all(3), 1);
+        assert_eq!(bin.insert_count(0, 2), 2);
+        assert_eq!((bin.count_of(0), bin.count_of(1), bin.count_of(2), bin.len()), (2, 0, 6, 8));
+
+        // The prefix sums find the same copy as walking the entries, through every kind of churn.
+        let mut rng = rand::rng();
+        let mut bin = CountedBin::default();
+        bin.insert_range(0..37);
+        for step in 0..2_000u64 {
+            match step % 4 {
+                0 => { bin.insert_count(rng.random_range(0..60), rng.random_range(1..9)); }
+                1 => { bin.remove(rng.random_range(0..60)); }
+                2 => { bin.get_random_and_remove(&mut rng); }
+                _ => { bin.remove_all(rng.random_range(0..60)); }
+            }
+            let walked: Vec<u64> = bin.ids().collect();
+            assert_eq!(walked.len(), bin.len());
+            assert!(walked.iter().enumerate().all(|(position, &id)| bin.nth_id(position as u64) == Some(id)));
+        }
+    }
+
+    #[test]
+    fn test_merge_from_policies() {
+        let base = [(1, 0.1), (2, 0.2), (3, 0.3)];
+
+        let mut skip = DigitBinIndex::new();
+        skip.add_many(&base);
+        let report = skip.merge_from(vec![(1, 0.5), (2, 0.2), (3, 0.3)], ConflictPolicy::Skip);
+        assert_eq!(report, MergeReport { unchanged: 3, ..Default::default() });
+        assert!((skip.total_weight() - 0.6).abs() < 1e-9);
+
+        let mut sum = DigitBinIndex::new();
+        sum.add_many(&base);
+        // ID 3 would reach 1.0 and is rejected; the duplicate 0.1 entries for ID 5 add up.
+        let report = sum.merge_from(vec![(1, 0.1), (3, 0.7), (5, 0.1), (5, 0.1)], ConflictPolicy::Sum);
+        assert_eq!(report, MergeReport { added: 1, updated: 1, removed: 1, unchanged: 0, rejected: 1 });
+        assert_eq!(sum.count(), 2);
+        assert!((sum.total_weight() - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_merge_from_ids_in_several_bins() {
+        for registry in [false, true] {
+            let mut index = DigitBinIndex::small(2);
+            if registry {
+                index.enable_id_registry();
+            }
+            index.add(1, 0.2);
+            index.add(1, 0.5);
+            index.add(2, 0.3);
+            index.add(2, 0.6);
+            index.mark();
+            let report = index.merge_from(vec![(1, 0.7)], ConflictPolicy::Replace);
+            assert_eq!((report.updated, report.removed), (1, 1));
+            assert_eq!(index.iter().collect::<Vec<_>>(), vec![(1, 0.7), (1, 0.7)]);
+            assert_eq!(index.removed_since_mark().unwrap().bins.iter().map(|bin| bin.count).sum::<u64>(), 2);
+            assert!(index.check_mass().is_ok());
+        }
+
+        let mut cohorts = DigitBinIndex::counted(3);
+        cohorts.add_with_count(1, 0.25, 1_000_000);
+        cohorts.add_with_count(2, 0.5, 10);
+        let report = cohorts.merge_from(vec![(2, 0.125)], ConflictPolicy::Replace);
+        assert_eq!((report.updated, report.removed), (1, 1));
+        assert_eq!(cohorts.count(), 10);
+        assert_eq!(cohorts.bin_count(0.125), 10);
+        assert!(cohorts.check_mass().is_ok());
+    }
+
+    #[test]
+    fn test_merge_from_at_memory_budget() {
+        let mut index = DigitBinIndex::with_precision(2);
+        index.add(1, 0.25);
+        index.add(2, 0.5);
+        index.set_memory_budget(Some(index.memory_usage()));
+
+        // 0.75 needs new nodes, so ID 1 stays where it is; the 0.5 bin exists, so ID 3 can join it.
+        let report = index.merge_from(vec![(1, 0.75), (2, 0.5), (3, 0.5)], ConflictPolicy::Replace);
+        assert_eq!((report.rejected, report.unchanged, report.added), (1, 1, 1));
+        assert_eq!(stored_weight(&index, 1), Some(0.25));
+        assert_eq!(index.count(), 3);
+        assert!(index.check_mass().is_ok());
+    }
+
+    #[test]
+    fn test_shared_sampler_concurrent_draws() {
+        let mut index = DigitBinIndex::new();
+        index.add(1, 0.1);
+        index.add(2, 0.9);
+        let sampler = SharedSampler::new(index);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let sampler = sampler.clone();
+                std::thread::spawn(move || (0..2500).filter(|_| sampler.select().unwrap().0 == 2).count())
+            })
+            .collect();
+        let heavy: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        let ratio = heavy as f64 / 10_000.0;
+        assert!((ratio - 0.9).abs() < 0.03, "heavy item drawn {:.3} of the time", ratio);
+        assert_eq!(sampler.count(), 2);
+
+        let mut rebuilt = DigitBinIndex::new();
+        rebuilt.add(3, 0.4);
+        let previous = sampler.swap(rebuilt);
+        assert_eq!(previous.count(), 2);
+        assert_eq!(sampler.select(), Some((3, 0.4)));
+        assert_eq!(sampler.count(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_index_keeps_counts_consistent() {
+        let index = ConcurrentDigitBinIndex::with_config(Config::new().precision(2), 4);
+        let drawn: Vec<Vec<(u64, f64)>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4u64)
+                .map(|worker| {
+                    let index = &index;
+                    scope.spawn(move || {
+                        for i in 0..2000 {
+                            index.add(worker * 10_000 + i, if i % 2 == 0 { 0.1 } else { 0.9 });
+                        }
+                        (0..1000).map(|_| index.select_and_remove().unwrap()).collect()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+        let drawn: Vec<(u64, f64)> = drawn.into_iter().flatten().collect();
+        let unique: std::collections::HashSet<u64> = drawn.iter().map(|&(id, _)| id).collect();
+        assert_eq!(unique.len(), 4000);
+        assert_eq!(index.count(), 4000);
+        // The heavy half should dominate the early draws.
+        assert!(drawn.iter().filter(|&&(_, weight)| weight == 0.9).count() > 3000);
+
+        let expected = 8000.0 * 0.5 - drawn.iter().map(|&(_, weight)| weight).sum::<f64>();
+        assert!((index.total_weight() - expected).abs() < 1e-6);
+        let shards = index.into_shards();
+        assert_eq!(shards.len(), 4);
+        assert_eq!(shards.iter().map(DigitBinIndex::count).sum::<u64>(), 4000);
+        assert!(shards.iter().all(|shard| shard.count() > 500));
+
+        // A shard that publishes mass but cannot be drawn from ends the draw instead of
+        // being retried forever.
+        let index = ConcurrentDigitBinIndex::new(4);
+        (0..100).for_each(|id| { index.add(id, 0.5); });
+        let mut attempts = 0;
+        assert_eq!(index.draw(&mut WyRand::seed_from_u64(1), |_, _| { attempts += 1; None }), None);
+        assert!(attempts <= 4);
+    }
+
+    #[test]
+    fn test_allocator_is_sticky_and_weighted() {
+        let allocator = Allocator::new();
+        assert_eq!(allocator.assign(1), None);
+        allocator.set_arm(10, 1.0);
+        allocator.set_arm(20, 3.0);
+
+        let first: Vec<Option<u64>> = (0..1000).map(|user| allocator.assign(user)).collect();
+        let again: Vec<Option<u64>> = (0..1000).map(|user| allocator.assign(user)).collect();
+        assert_eq!(first, again);
+        let heavy = first.iter().filter(|&&arm| arm == Some(20)).count();
+        assert!((650..850).contains(&heavy));
+
+        // Counters survive a weight change; the new weights take effect immediately.
+        allocator.set_arm(20, 0.0001);
+        assert_eq!(allocator.arms(), vec![(10, 1.0), (20, 0.0001)]);
+        let stats = allocator.stats();
+        assert_eq!(stats.iter().map(|s| s.assignments).sum::<u64>(), 2000);
+        let light = (1000..3000).filter(|&user| allocator.assign(user) == Some(20)).count();
+        assert!(light < 10);
+
+        assert!(allocator.remove_arm(10));
+        assert!(!allocator.remove_arm(10));
+        assert!((0..100).all(|user| allocator.assign(user) == Some(20)));
+        allocator.set_arm(20, -1.0);
+        assert!(allocator.arms().is_empty());
+    }
+
+    #[test]
+    fn test_generational_handles_reject_stale_references() {
+        let mut index = GenerationalIndex::with_precision(3);
+        let a = index.insert(0.2).unwrap();
+        let b = index.insert(0.4).unwrap();
+        assert!(index.insert(1.5).is_none());
+
+        let (selected, _) = index.select_and_remove().unwrap();
+        assert!(!index.contains(selected));
+        let survivor = if selected == a { b } else { a };
+
+        // The freed slot goes to the next item, with a new generation.
+        let recycled = index.insert(0.3).unwrap();
+        assert_eq!(recycled.slot, selected.slot);
+        assert_ne!(recycled.generation, selected.generation);
+        assert!(!index.remove(selected));
+        assert_eq!(index.count(), 2);
+
+        assert!(index.remove(survivor));
+        assert_eq!(index.select(), Some((recycled, 0.3)));
+    }
+
+    #[test]
+    fn test_from_counts_matches_individual_adds() {
+        let histogram = [(0.1, 20_000), (0.25, 0), (1.5, 10), (0.3, 10_000)];
+        let index = DigitBinIndex::from_counts(1, &histogram);
+        assert!(matches!(index, DigitBinIndex::Medium(_)));
+
+        let mut expected = DigitBinIndex::medium(1);
+        for id in 0..20_000 {
+            expected.add(id, 0.1);
+        }
+        // The invalid 1.5 entry reserves IDs 20000..20010.
+        for id in 20_010..30_010 {
+            expected.add(id, 0.3);
+        }
+        assert_eq!(index.count(), expected.count());
+        assert!((index.total_weight() - expected.total_weight()).abs() < 1e-9);
+        let contains = |id| stored_weight(&index, id).is_some();
+        assert!(contains(0) && contains(19_999));
+        assert!(!contains(20_000) && !contains(20_009));
+        assert!(contains(20_010) && contains(30_009));
+        assert!(!contains(30_010));
+
+        // Counts beyond the u32 ID space go to the Large backend instead of truncating IDs.
+        let wide = DigitBinIndex::from_counts(1, &[(0.1, 0), (0.5, 0), (1.5, u32::MAX as u64), (0.2, 3)]);
+        assert!(matches!(wide, DigitBinIndex::Large(_)));
+        assert!(stored_weight(&wide, u32::MAX as u64).is_some() && stored_weight(&wide, u32::MAX as u64 + 2).is_some());
+        assert_eq!(wide.count(), 3);
+
+        let mut small = DigitBinIndex::from_counts(3, &[(0.5, 2)]);
+        let mut drawn = small.select_many_and_remove(2).unwrap();
+        drawn.sort_unstable_by_key(|&(id, _)| id);
+        assert_eq!(drawn, vec![(0, 0.5), (1, 0.5)]);
+    }
+
+    #[test]
+    fn test_add_range_across_backends() {
+        for mut index in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::large(3), DigitBinIndex::counted(3)] {
+            assert_eq!(index.add_range(10..20, 0.5), 10);
+            assert_eq!(index.add_range(0..5, 1.5), 0);
+            assert_eq!(index.count(), 10);
+            assert!((index.total_weight() - 5.0).abs() < 1e-9);
+            assert!(index.remove(15, 0.5));
+            assert!(!index.remove(20, 0.5));
+        }
+
+        let mut set = DigitBinIndex::medium(3);
+        set.add(12, 0.5);
+        assert_eq!(set.add_range(10..20, 0.5), 9);
+        assert_eq!(set.count(), 10);
+    }
+
+    #[test]
+    fn test_next_scheduled_is_proportional() {
+        let mut index = DigitBinIndex::with_precision(2);
+        index.add(1, 0.1);
+        index.add(2, 0.2);
+        index.add(3, 0.2);
+        index.add(4, 0.5);
+
+        // One full cycle has a total weight of 100 units of 0.01; every item gets its share.
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..100 {
+            *counts.entry(index.next_scheduled().unwrap().0).or_insert(0) += 1;
+        }
+        assert_eq!(counts[&1], 10);
+        assert_eq!(counts[&2], 20);
+        assert_eq!(counts[&3], 20);
+        assert_eq!(counts[&4], 50);
+
+        // The schedule adapts to removals.
+        assert!(index.remove(4, 0.5));
+        for _ in 0..10 {
+            assert_ne!(index.next_scheduled().unwrap().0, 4);
+        }
+        assert_eq!(DigitBinIndex::new().next_scheduled(), None);
+
+        // Successive visits to one bin walk its items in order, however large the bin.
+        let mut index = DigitBinIndex::with_precision(3);
+        index.add_range(0..50_000, 0.125);
+        let picks: Vec<u64> = (0..5).map(|_| index.next_scheduled().unwrap().0).collect();
+        assert_eq!(picks, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_select_with_quotas() {
+        let mut index = DigitBinIndex::with_precision(2);
+        for id in 0..100 {
+            index.add(id, if id % 2 == 0 { 0.1 } else { 0.9 });
+        }
+        let low: RoaringTreemap = (0..50).collect();
+        let high: RoaringTreemap = (50..100).collect();
+
+        let overlapping: RoaringTreemap = (40..60).collect();
+        assert_eq!(
+            index.select_with_quotas(&[(low.clone(), 1), (overlapping, 1)]),
+            Err(DigitBinIndexError::OverlappingGroups { id: 40 })
+        );
+        assert_eq!(
+            index.select_with_quotas(&[(low.clone(), 51)]),
+            Err(DigitBinIndexError::InfeasibleQuota { group: 0, requested: 51, available: 50 })
+        );
+        assert_eq!(index.count(), 100);
+
+        let winners = index.select_with_quotas(&[(low.clone(), 10), (high.clone(), 40)]).unwrap();
+        assert_eq!(winners[0].len(), 10);
+        assert_eq!(winners[1].len(), 40);
+        assert!(winners[0].iter().all(|&(id, _)| low.contains(id)));
+        assert!(winners[1].iter().all(|&(id, _)| high.contains(id)));
+        for &(id, weight) in winners.iter().flatten() {
+            assert_eq!(weight, if id % 2 == 0 { 0.1 } else { 0.9 });
+            assert!(!index.remove(id, weight));
+        }
+        assert_eq!(index.count(), 50);
+    }
+
+    #[test]
+    fn test_prune_bins() {
+        let mut index = DigitBinIndex::counted(2);
+        index.add_with_count(1, 0.25, 3);
+        index.add(2, 0.25);
+        index.add(3, 0.5);
+        index.add(4, 0.75);
+
+        let mut seen = Vec::new();
+        let removed = index.prune_bins(|weight, count| {
+            seen.push((weight, count));
+            weight > 0.4
+        });
+        assert_eq!(seen, vec![(0.25, 4), (0.5, 1), (0.75, 1)]);
+        assert_eq!(removed, vec![(3, 0.5), (4, 0.75)]);
+        assert_eq!(index.count(), 4);
+        assert!((index.total_weight() - 1.0).abs() < 1e-9);
+
+        // Pruned bins are skipped afterwards and can be refilled.
+        assert!(index.prune_bins(|weight, _| weight > 0.4).is_empty());
+        index.add(5, 0.5);
+        assert_eq!(index.count(), 5);
+    }
+
+    #[test]
+    fn test_coarsen_to_matches_direct_build() {
+        let items: Vec<(u64, f64)> = (1..2000).map(|id| (id, (id % 997) as f64 / 1000.0 + 0.0005)).collect();
+        for (mut index, mut direct) in [
+            (DigitBinIndex::small(4), DigitBinIndex::small(2)),
+            (DigitBinIndex::medium(4), DigitBinIndex::medium(2)),
+            (DigitBinIndex::counted(4), DigitBinIndex::counted(2)),
+        ] {
+            index.add_many(&items);
+            direct.add_many(&items);
+            let dropped = index.coarsen_to(2);
+            assert!(dropped.iter().all(|&(_, weight)| weight < 0.01));
+            assert_eq!(index.count() + dropped.len() as u64, items.len() as u64);
+            assert_eq!(index.count(), direct.count());
+            assert!((index.total_weight() - direct.total_weight()).abs() < 1e-6);
+            assert_eq!(index.precision(), 2);
+
+            let drawn = index.select_many_and_remove(index.count()).unwrap();
+            assert_eq!(drawn.len(), direct.count() as usize);
+            assert_eq!(index.total_weight(), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_plan_then_apply() {
+        let mut index = DigitBinIndex::with_precision(2);
+        for id in 0..50 {
+            index.add(id, 0.01 * (id + 1) as f64);
+        }
+        let plan = index.plan_select_many(20).unwrap();
+        assert_eq!(index.count(), 50);
+        let mut ids: Vec<u64> = plan.draws.iter().map(|d| d.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 20);
+        assert!(index.plan_select_many(51).is_none());
+
+        let mut other = DigitBinIndex::with_precision(3);
+        assert_eq!(
+            other.apply(&plan),
+            Err(DigitBinIndexError::PrecisionMismatch { expected: 2, found: 3 })
+        );
+
+        // A plan touching an item removed in the meantime is rejected as a whole.
+        let mut changed = index.clone();
+        let first = plan.draws[0];
+        assert!(changed.remove(first.id, first.weight));
+        assert_eq!(changed.apply(&plan), Err(DigitBinIndexError::StalePlan { id: first.id }));
+        assert_eq!(changed.count(), 49);
+
+        let removed = index.apply(&plan).unwrap();
+        assert_eq!(removed.len(), 20);
+        assert_eq!(index.count(), 30);
+        assert_eq!(index.apply(&plan).unwrap_err(), DigitBinIndexError::StalePlan { id: plan.draws[0].id });
+    }
+
+    #[test]
+    fn test_borrowed_iteration_and_drain() {
+        let items = [(5, 0.3), (1, 0.1), (9, 0.3), (4, 0.8)];
+        for mut index in [DigitBinIndex::small(2), DigitBinIndex::medium(2), DigitBinIndex::large(2), DigitBinIndex::counted(2)] {
+            index.add_many(&items);
+            let summary: Vec<(f64, u64)> = index.bins().map(|(weight, count, _)| (weight, count)).collect();
+            assert_eq!(summary, vec![(0.1, 1), (0.3, 2), (0.8, 1)]);
+
+            let mut listed: Vec<(u64, f64)> = (&index).into_iter().collect();
+            listed.sort_by_key(|&(id, _)| id);
+            assert_eq!(listed, vec![(1, 0.1), (4, 0.8), (5, 0.3), (9, 0.3)]);
+
+            let drained = index.drain();
+            assert_eq!(drained.len(), 4);
+            assert_eq!(index.count(), 0);
+            assert_eq!(index.total_weight(), 0.0);
+            assert_eq!(index.iter().count(), 0);
+            index.add(2, 0.5);
+            assert_eq!(index.iter().collect::<Vec<_>>(), vec![(2, 0.5)]);
+        }
+    }
+
+    #[test]
+    fn test_iter_exact_size() {
+        for mut index in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::large(3), DigitBinIndex::counted(3)] {
+            index.add_many(&[(1, 0.125), (2, 0.5), (3, 0.5)]);
+            // Bitmap backends store a single copy, the others all three.
+            let copies = index.add_with_count(7, 0.25, 3) as usize;
+            let mut items = index.iter();
+            assert_eq!(items.len(), 3 + copies);
+            for expected in (0..3 + copies).rev() {
+                assert!(items.next().is_some());
+                assert_eq!(items.len(), expected);
+            }
+            assert_eq!(items.next(), None);
+            assert_eq!(index.iter().filter(|&(id, _)| id == 7).count(), copies);
+        }
+    }
+
+    #[test]
+    fn test_state_hash_is_layout_independent() {
+        let items: Vec<(u64, f64)> = (0..500).map(|i| (i, ((i * 37) % 99 + 1) as f64 / 100.0)).collect();
+        let mut reversed = items.clone();
+        reversed.reverse();
+        let mut hashes = Vec::new();
+        for (mut forward, mut backward) in [
+            (DigitBinIndex::small(2), DigitBinIndex::large(2)),
+            (DigitBinIndex::medium(2), DigitBinIndex::counted(2)),
+        ] {
+            forward.add_many(&items);
+            for &(id, weight) in &reversed {
+                backward.add(id, weight);
+            }
+            hashes.push(forward.state_hash());
+            hashes.push(backward.state_hash());
+        }
+        assert!(hashes.windows(2).all(|w| w[0] == w[1]));
+
+        // Precision, membership, and bin assignment all change the hash.
+        let mut index = DigitBinIndex::small(2);
+        index.add_many(&items);
+        assert_ne!(index.state_hash(), DigitBinIndex::small(3).state_hash());
+        assert_ne!(DigitBinIndex::small(2).state_hash(), DigitBinIndex::small(3).state_hash());
+        index.remove(7, items[7].1);
+        let removed = index.state_hash();
+        assert_ne!(removed, hashes[0]);
+        index.add(7, 0.99);
+        assert_ne!(index.state_hash(), hashes[0]);
+        assert_ne!(index.state_hash(), removed);
+    }
+
+    #[test]
+    fn test_with_config_matches_shorthand_constructors() {
+        let backend = |index: &DigitBinIndex| std::mem::discriminant(index);
+        for precision in [1, 3, 6] {
+            for capacity in [10, 10_000_000, u64::MAX] {
+                let config = Config::new().precision(precision).capacity(capacity);
+                let shorthand = DigitBinIndex::with_precision_and_capacity(precision, capacity);
+                let configured = DigitBinIndex::with_config(config);
+                assert_eq!(backend(&configured), backend(&shorthand));
+                assert_eq!(configured.precision(), precision);
+            }
+        }
+        assert_eq!(backend(&DigitBinIndex::new()), backend(&DigitBinIndex::small(3)));
+        assert_eq!(backend(&DigitBinIndex::with_config(Config::new().backend(Backend::Counted))), backend(&DigitBinIndex::counted(3)));
+
+        let mut index = DigitBinIndex::with_config(Config::new().precision(1).memory_budget(Some(0)).id_registry(true));
+        assert!(index.has_id_registry());
+        assert!(index.try_add(1, 0.5).is_err());
+    }
+
+    #[test]
+    fn test_bulk_build_matches_individual_adds() {
+        let items: Vec<(u64, f64)> = (0..5000).map(|i| (i, ((i * 7919) % 1000 + 1) as f64 / 1001.0)).collect();
+        for registry in [false, true] {
+            for mut looped in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::large(3), DigitBinIndex::counted(3)] {
+                let mut bulk = looped.clone();
+                if registry {
+                    bulk.enable_id_registry();
+                }
+                for &(id, weight) in &items {
+                    looped.add(id, weight);
+                }
+                bulk.extend(items.iter().copied());
+                assert_eq!(bulk.count(), looped.count());
+                assert_eq!(bulk.total_weight(), looped.total_weight());
+                assert_eq!(bulk.state_hash(), looped.state_hash());
+                assert_eq!(bulk.remove_by_id(4321), looped.remove_by_id(4321));
+            }
+        }
+        let collected: DigitBinIndex = items.iter().copied().collect();
+        let mut looped = DigitBinIndex::new();
+        items.iter().for_each(|&(id, weight)| {
+            looped.add(id, weight);
+        });
+        assert_eq!(collected.state_hash(), looped.state_hash());
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let mut index = DigitBinIndex::with_precision(3);
+        index.add(1, 0.111);
+        let usage = index.memory_usage();
+        index.set_memory_budget(Some(usage));
+        assert_eq!(index.memory_budget(), Some(usage));
+
+        // Existing bins keep accepting items; new bins are refused by every insertion path.
+        assert!(index.try_add(2, 0.111).is_ok());
+        let err = index.try_add(3, 0.999).unwrap_err();
+        assert!(matches!(err, DigitBinIndexError::MemoryBudgetExceeded { budget, .. } if budget == usage));
+        assert_eq!(index.add_with_count(4, 0.5, 1), 0);
+        assert_eq!(index.add_range(10..20, 0.5), 0);
+        index.add_many(&[(5, 0.5), (6, 0.111)]);
+        let report = index.merge_from(vec![(1, 0.111), (2, 0.111), (6, 0.111), (7, 0.7)], ConflictPolicy::Replace);
+        assert_eq!(report.rejected, 1);
+        assert_eq!(index.count(), 3);
+        assert_eq!(index.memory_usage(), usage);
+
+        // Coarsening frees room for new bins.
+        index.coarsen_to(1);
+        assert!(index.memory_usage() < usage);
+        assert!(index.try_add(8, 0.9).is_ok());
+
+        index.set_memory_budget(None);
+        assert!(index.try_add(9, 0.5).is_ok());
+        assert_eq!(index.count(), 5);
+    }
+
+    #[test]
+    fn test_select_audited_is_verifiable() {
+        let mut index = DigitBinIndex::with_precision(2);
+        index.add_many(&[(1, 0.13), (2, 0.13), (3, 0.42), (4, 0.07)]);
+        let before = index.clone();
+
+        let audit = index.select_audited(&RoaringTreemap::new(), false).unwrap();
+        assert_eq!(audit.total_mass, 75);
+        assert!(!audit.removed);
+
+        // Replaying the target against the pre-draw state reproduces the bin path.
+        let expected_bin = match audit.target {
+            0..7 => vec![0, 7],
+            7..33 => vec![1, 3],
+            _ => vec![4, 2],
+        };
+        assert_eq!(audit.path, expected_bin);
+        let (_, bin_count, members) = before
+            .bins()
+            .find(|&(weight, _, _)| (weight * 100.0).round() as u64 == audit.bin_mass / audit.bin_count)
+            .unwrap();
+        assert_eq!(bin_count, audit.bin_count);
+        assert_eq!(members.clone().nth(audit.position as usize), Some(audit.id));
+        assert_eq!(index.count(), 4);
+
+        let everything: RoaringTreemap = (1..=4).collect();
+        assert_eq!(index.select_audited(&everything, true), None);
+        assert_eq!(index.count(), 4);
+    }
+
+    #[test]
+    fn test_select_audited_under_heavy_exclusion() {
+        for mut index in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::large(3), DigitBinIndex::counted(3)] {
+            index.add_range(0..50_000, 0.9);
+            index.add(50_000, 0.001);
+            index.add_range(50_001..50_003, 0.9);
+            let excluded: RoaringTreemap = (0..50_000).chain([50_001]).collect();
+            let members: Vec<u64> = index.bins().find(|&(weight, _, _)| weight > 0.5).unwrap().2.collect();
+            for _ in 0..20 {
+                // Excluded items hold nearly all of the mass, yet every draw finds an eligible one.
+                let audit = index.select_audited(&excluded, false).unwrap();
+                assert_eq!(audit.total_mass, 901);
+                assert!(!excluded.contains(audit.id));
+                if audit.id == 50_002 {
+                    assert_eq!((audit.path.clone(), audit.bin_mass, audit.bin_count), (vec![9, 0, 0], 900, 1));
+                    assert_eq!(members[audit.position as usize], 50_002);
+                } else {
+                    assert_eq!((audit.id, audit.position, audit.bin_count), (50_000, 0, 1));
+                }
+            }
+            let everything: RoaringTreemap = (0..50_003).collect();
+            assert_eq!(index.select_audited(&everything, true), None);
         }
     }
 
-    /// Returns the precision (number of decimal places) used for binning.
-    pub fn precision(&self) -> u8 {
-        match self {
-            DigitBinIndex::Small(idx) => idx.precision,
-            DigitBinIndex::Medium(idx) => idx.precision,
-            DigitBinIndex::Large(idx) => idx.precision,
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let mut index = DigitBinIndex::medium(3);
+        index.add_range(0..5_000, 0.25);
+        index.add(9_999, 0.5);
+        let json = serde_json::to_string(&index).unwrap();
+        // The tree is written nested, child by child, not as the arena it is stored in.
+        assert!(json.contains(r#""root":{"content":{"DigitIndex":[null,null,{"content":{"DigitIndex":["#));
+        let mut restored: DigitBinIndex = serde_json::from_str(&json).unwrap();
+        assert!(matches!(restored, DigitBinIndex::Medium(_)));
+        assert_eq!(restored.count(), index.count());
+        assert_eq!(restored.total_weight(), index.total_weight());
+        assert_eq!(restored.memory_usage(), index.memory_usage());
+        assert!(restored.remove(9_999, 0.5));
+        assert_eq!(restored.select_many_and_remove(5_000).unwrap().len(), 5_000);
+
+        let mut counted = DigitBinIndex::counted(3);
+        counted.add_with_count(1, 0.5, 1_000);
+        counted.add_range(2..10, 0.5);
+        let mut restored: DigitBinIndex = serde_json::from_str(&serde_json::to_string(&counted).unwrap()).unwrap();
+        assert_eq!(restored.count(), 1_008);
+        assert!(restored.remove(1, 0.5));
+        assert_eq!(restored.select_many_and_remove(1_007).unwrap().len(), 1_007);
+
+        let plan = index.plan_select_many(3).unwrap();
+        let plan: DrawPlan = serde_json::from_str(&serde_json::to_string(&plan).unwrap()).unwrap();
+        assert_eq!(index.apply(&plan).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_update_weight() {
+        for mut index in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::counted(3)] {
+            index.add_many(&[(1, 0.123), (2, 0.129), (3, 0.5)]);
+
+            // Shared prefix, different leaf.
+            assert!(index.update_weight(1, 0.123, 0.125));
+            // Different top-level digit.
+            assert!(index.update_weight(2, 0.129, 0.9));
+            // Same bin.
+            assert!(index.update_weight(3, 0.5, 0.5));
+            assert_eq!(index.count(), 3);
+            assert!((index.total_weight() - 1.525).abs() < 1e-9);
+
+            // A wrong old weight or an invalid new weight changes nothing.
+            assert!(!index.update_weight(1, 0.123, 0.2));
+            assert!(!index.update_weight(3, 0.5, 1.5));
+            assert!(!index.update_weight(7, 0.5, 0.2));
+            assert!((index.total_weight() - 1.525).abs() < 1e-9);
+
+            let mut drawn = index.select_many_and_remove(3).unwrap();
+            drawn.sort_unstable_by_key(|&(id, _)| id);
+            assert_eq!(drawn, vec![(1, 0.125), (2, 0.9), (3, 0.5)]);
         }
-    }    
-}
 
-/// A data structure that organizes weighted items into bins based on their
-/// decimal digits to enable fast weighted random selection and updates.
-///
-/// This structure is a specialized radix tree optimized for sequential sampling
-/// (like in Wallenius' distribution). It makes a deliberate engineering trade-off:
-/// it sacrifices a small, controllable amount of precision by binning items,
-/// but in return, it achieves O(P) performance for its core operations, where P
-/// is the configured precision. This is significantly faster than the O(log N)
-/// performance of general-purpose structures like a Fenwick Tree for its
-/// ideal use case.
-///
-/// # Type Parameters
-///
-/// * `B` - The bin container type for leaf nodes. Must implement the [`DigitBin`] trait.
-///   Common choices are [`Vec<u32>`] for maximum speed with small bins, or [`RoaringBitmap`]
-///   for memory efficiency with large, sparse bins.
-///
-/// # Examples
-///
-/// ```
-/// use digit_bin_index::DigitBinIndexGeneric;
-/// // Use Vec<u32> for leaf bins
-/// let mut index = DigitBinIndexGeneric::<Vec<u32>>::new();
-/// // Or use RoaringBitmap for leaf bins
-/// // let mut index = DigitBinIndexGeneric::<roaring::RoaringBitmap>::new();
-/// ```
-#[derive(Debug, Clone)]
-pub struct DigitBinIndexGeneric<B: DigitBin> {
-    /// The root node of the tree.
-    pub root: Node<B>,
-    /// The precision (number of decimal places) used for binning.
-    pub precision: u8,
-    /// The scaling factor (10^precision) as f64 for conversions.
-    scale: f64,
-}
+        // Moving into a set bin that already holds the ID collapses the duplicate.
+        let mut set = DigitBinIndex::medium(1);
+        set.add(1, 0.1);
+        set.add(1, 0.2);
+        assert!(set.update_weight(1, 0.1, 0.2));
+        assert_eq!(set.count(), 1);
+        assert!((set.total_weight() - 0.2).abs() < 1e-9);
+    }
 
-impl<B: DigitBin> Default for DigitBinIndexGeneric<B> {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn test_lookup_and_remove_by_id() {
+        let items: Vec<(u64, f64)> = (0..200).map(|i| (i, ((i * 13) % 97 + 1) as f64 / 100.0)).collect();
+        for registry in [false, true] {
+            for mut index in [DigitBinIndex::small(2), DigitBinIndex::medium(2), DigitBinIndex::large(2), DigitBinIndex::counted(2)] {
+                if registry {
+                    index.enable_id_registry();
+                }
+                index.add_many(&items[..100]);
+                index.add_range(100..150, 0.5);
+                for &(id, weight) in &items[150..] {
+                    index.add(id, weight);
+                }
+                index.add_with_count(500, 0.75, 2);
+                assert!(index.update_weight(3, items[3].1, 0.11));
+                index.remove(4, items[4].1);
+
+                assert_eq!(index.remove_by_id(3), Some(0.11));
+                assert_eq!(index.remove_by_id(4), None);
+                assert_eq!(index.remove_by_id(120), Some(0.5));
+                assert_eq!(index.remove_by_id(500), Some(0.75));
+                let (drawn, _) = index.select_and_remove().unwrap();
+                assert_eq!(index.remove_by_id(drawn), None);
+
+                assert!(!index.contains(drawn) && !index.contains(4));
+                assert_eq!(index.get_weight(3), None);
+                let remaining: Vec<(u64, f64)> = index.iter().collect();
+                for (id, weight) in remaining {
+                    assert!(index.contains(id));
+                    assert_eq!(index.get_weight(id), Some(weight));
+                    assert_eq!(index.remove_by_id(id), Some(weight));
+                }
+                assert_eq!(index.count(), 0);
+            }
+        }
     }
-}
 
-impl<B: DigitBin> DigitBinIndexGeneric<B> {
-    #[must_use]
-    pub fn new() -> Self {
-        Self::with_precision(DEFAULT_PRECISION)
+    #[test]
+    fn test_seeded_selection_is_reproducible() {
+        use rand::rngs::StdRng;
+
+        let mut base = DigitBinIndex::with_precision(3);
+        for id in 0..500 {
+            base.add(id, (id % 999 + 1) as f64 / 1000.0);
+        }
+        let run = |seed: u64| {
+            let mut index = base.clone();
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut trace = Vec::new();
+            trace.push(index.select_with_rng(&mut rng));
+            trace.push(index.select_and_remove_with_rng(&mut rng));
+            trace.extend(index.select_many_with_rng(5, &mut rng).unwrap().into_iter().map(Some));
+            trace.extend(index.select_many_and_remove_with_rng(50, &mut rng).unwrap().into_iter().map(Some));
+            (trace, index.count())
+        };
+        let (trace, remaining) = run(2024);
+        assert_eq!(remaining, 449);
+        assert_eq!(run(2024), (trace.clone(), remaining));
+        assert_ne!(run(2025).0, trace);
     }
 
-    #[must_use]
-    pub fn with_precision(precision: u8) -> Self {
-        assert!(precision > 0, "Precision must be at least 1.");
-        assert!(precision <= MAX_PRECISION as u8, "Precision cannot be larger than {}.", MAX_PRECISION);
-        Self {
-            root: Node::new_internal(),
-            precision,
-            scale: 10f64.powi(precision as i32),
-        }        
+    #[test]
+    fn test_scheduled_weight_changes() {
+        for registry in [false, true] {
+            let mut index = DigitBinIndex::with_config(Config::new().precision(2).id_registry(registry));
+            index.add_many(&[(1, 0.1), (2, 0.2), (3, 0.3)]);
+            index.at_tick(10, 1, 0.9);
+            index.at_tick(5, 2, 0.4);
+            index.at_tick(5, 2, 0.6); // same tick: applied in registration order
+            index.at_tick(7, 3, -1.0);
+            index.at_tick(7, 9, 0.0); // nothing to remove
+            assert_eq!(index.pending_changes(), 5);
+
+            assert_eq!(index.advance_to(7), 3);
+            assert_eq!(index.get_weight(2), Some(0.6));
+            assert!(!index.contains(3));
+            assert_eq!(index.pending_changes(), 1);
+            assert_eq!(index.clock(), 7);
+
+            // Overdue changes are picked up, and the clock does not go back.
+            index.at_tick(1, 4, 0.5);
+            assert_eq!(index.advance_to(3), 1);
+            assert_eq!(index.clock(), 7);
+            assert_eq!(index.advance_to(u64::MAX), 1);
+            assert_eq!(index.pending_changes(), 0);
+            assert_eq!(index.iter().collect::<Vec<_>>(), vec![(4, 0.5), (2, 0.6), (1, 0.9)]);
+            assert!((index.total_weight() - 2.0).abs() < 1e-9);
+        }
     }
 
-    /// Converts a f64 weight to an array of digits [0-9] for the given precision and the scaled u64 value.
-    /// Returns None if the weight is invalid (non-positive or zero after scaling).
-    fn weight_to_digits(&self, weight: f64, digits: &mut [u8; MAX_PRECISION]) -> Option<u64> {
-        if weight <= 0.0 || weight >= 1.0 {
-            return None;
+    #[test]
+    fn test_u64_ids_survive_the_full_api() {
+        let base = u32::MAX as u64 + 1;
+        let items: Vec<(u64, f64)> = (0..1000).map(|i| (base + i * 1_000_003, 0.001 + (i % 9) as f64 / 10.0)).collect();
+        let collected: DigitBinIndex = items.iter().copied().collect();
+        assert!(matches!(collected, DigitBinIndex::Large(_)));
+
+        for mut index in [DigitBinIndex::large(3), DigitBinIndex::counted(3), collected] {
+            if index.count() == 0 {
+                index.add_many(&items);
+            }
+            index.enable_id_registry();
+            assert!(items.iter().all(|&(id, _)| index.contains(id)));
+            let (id, _) = index.select().unwrap();
+            assert!(id >= base);
+            let many = index.select_many_and_remove(100).unwrap();
+            assert!(many.iter().all(|&(id, _)| id >= base));
+            let (id, weight) = index.select_and_remove().unwrap();
+            assert!(!index.contains(id) && id >= base);
+            let (id, _) = items.iter().find(|&&(id, _)| index.contains(id)).unwrap();
+            assert!(index.remove_by_id(*id).is_some());
+            assert_eq!(index.count(), 898);
+            index.add(*id, weight);
+            assert!(index.iter().all(|(id, _)| id >= base));
         }
+    }
 
-        let scaled = (weight * self.scale) as u64;
-        if scaled == 0 {
-            return None;
+    #[test]
+    fn test_merge_replica_converges() {
+        let mut base = DigitBinIndex::small(2);
+        base.add_many(&[(1, 0.1), (2, 0.2), (3, 0.3), (4, 0.4), (5, 0.5)]);
+        base.add_with_count(6, 0.6, 2);
+
+        let mut a = base.clone();
+        a.add(10, 0.1); // add on one side
+        a.add(12, 0.3); // added on both sides, differently
+        a.remove(1, 0.1); // removal vs reweight
+        a.update_weight(3, 0.3, 0.35); // same change on both sides
+        a.update_weight(4, 0.4, 0.8); // conflicting reweights
+        a.remove(6, 0.6); // one copy fewer
+
+        let mut b = base.clone();
+        b.add(11, 0.2);
+        b.add(12, 0.4);
+        b.update_weight(1, 0.1, 0.9);
+        b.update_weight(3, 0.3, 0.35);
+        b.update_weight(4, 0.4, 0.2);
+        b.remove(5, 0.5);
+
+        for (policy, expected) in [
+            (ReplicaPolicy::Heavier, vec![(10, 0.1), (2, 0.2), (11, 0.2), (3, 0.35), (12, 0.4), (6, 0.6), (4, 0.8), (1, 0.9)]),
+            (ReplicaPolicy::Lighter, vec![(10, 0.1), (2, 0.2), (4, 0.2), (11, 0.2), (12, 0.3), (3, 0.35), (6, 0.6)]),
+        ] {
+            let (mut ab, mut ba) = (a.clone(), b.clone());
+            let report = ab.merge_replica(&base, &b, policy).unwrap();
+            ba.merge_replica(&base, &a, policy).unwrap();
+            assert_eq!(ab.state_hash(), ba.state_hash());
+            let mut items: Vec<(u64, f64)> = ab.iter().collect();
+            items.sort_by(|x, y| x.1.total_cmp(&y.1).then(x.0.cmp(&y.0)));
+            items.dedup();
+            assert_eq!(items, expected);
+            let mut conflicts: Vec<u64> = report.conflicts.iter().map(|c| c.id).collect();
+            conflicts.sort_unstable();
+            assert_eq!(conflicts, vec![1, 4, 12]);
         }
 
-        let mut temp = scaled;
-        for i in (0..self.precision as usize).rev() {
-            digits[i] = (temp % 10) as u8;
-            temp /= 10;
+        let mut ours = a.clone();
+        ours.merge_replica(&base, &b, ReplicaPolicy::Ours).unwrap();
+        assert_eq!(stored_weight(&ours, 4), Some(0.8));
+        assert!(stored_weight(&ours, 1).is_none() && stored_weight(&ours, 5).is_none());
+        assert_eq!(ours.iter().filter(|&(id, _)| id == 6).count(), 1);
+        assert_eq!(
+            ours.merge_replica(&DigitBinIndex::small(3), &b, ReplicaPolicy::Ours),
+            Err(DigitBinIndexError::PrecisionMismatch { expected: 3, found: 2 })
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_select_many_matches_sequential() {
+        let items: Vec<(u64, f64)> = (0..50_000).map(|id| (id, if id % 10 == 0 { 0.9 } else { 0.1 })).collect();
+        for mut index in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::large(3), DigitBinIndex::counted(3)] {
+            index.add_many(&items);
+            let drawn = index.par_select_many(10_000).unwrap();
+            assert_eq!(drawn.len(), 10_000);
+            // 5,000 heavy items carry half the mass, so most of them are drawn.
+            let heavy = drawn.iter().filter(|&&(_, weight)| weight == 0.9).count();
+            assert!(heavy > 3_000, "only {heavy} heavy items drawn");
+
+            let removed = index.par_select_many_and_remove(10_000).unwrap();
+            let unique: std::collections::HashSet<u64> = removed.iter().map(|&(id, _)| id).collect();
+            assert_eq!(unique.len(), 10_000);
+            assert_eq!(index.count(), 40_000);
+            assert!((index.total_weight() - (9_000.0 - removed.iter().map(|&(_, w)| w).sum::<f64>())).abs() < 1e-6);
+            assert!(removed.iter().all(|&(id, _)| !index.contains(id)));
+            assert!(index.par_select_many(40_001).is_none());
         }
-        Some(scaled)
     }
 
-    // --- Standard Functions ---
+    #[test]
+    fn test_time_sliced_jobs() {
+        let mut index = DigitBinIndex::with_precision(3);
+        let mut load = BulkLoad::new((0..1_000).map(|id| (id, 0.25)).collect());
+        assert_eq!(load.resume(&mut index, 400), Slice::Paused);
+        assert_eq!((index.count(), load.remaining()), (400, 600));
+        assert_eq!(load.resume(&mut index, 400), Slice::Paused);
+        assert_eq!(load.resume(&mut index, 400), Slice::Finished(()));
+        assert_eq!(index.count(), 1_000);
 
-    pub fn add(&mut self, individual_id: u64, weight: f64) {
-        let mut digits = [0u8; MAX_PRECISION];
-        if let Some(scaled) = self.weight_to_digits(weight, &mut digits) {
-            Self::add_recurse(&mut self.root, individual_id, scaled, &digits, 1, self.precision)
+        let mut draw = BatchDraw::select_many_and_remove(300);
+        assert_eq!(draw.resume(&mut index, 200), Some(Slice::Paused));
+        assert_eq!(index.count(), 800);
+        // Items added between slices can be drawn by later slices.
+        index.add(5_000, 0.25);
+        let Some(Slice::Finished(drawn)) = draw.resume(&mut index, 200) else { panic!("draw did not finish") };
+        assert_eq!(drawn.len(), 300);
+        assert_eq!(drawn.iter().map(|&(id, _)| id).collect::<std::collections::HashSet<_>>().len(), 300);
+        assert_eq!(index.count(), 701);
+
+        let mut too_many = BatchDraw::select_many(800);
+        assert_eq!(too_many.resume(&mut index, 1_000), None);
+        assert_eq!(too_many.remaining(), 800);
+        assert_eq!(too_many.resume(&mut index, 500), Some(Slice::Paused));
+    }
+
+    #[test]
+    fn test_frequency_index() {
+        let mut index = FrequencyIndex::new(5_000_000);
+        assert_eq!(index.frequency_of(1), None);
+        index.enable_id_registry();
+        assert_eq!((index.index().precision(), index.resolution()), (7, 1));
+        assert!(index.add(1, 4_999_999));
+        assert!(index.add(2, 1));
+        assert!(!index.add(3, 0));
+        assert!(!index.add(4, 10_000_000));
+        assert_eq!(index.total_frequency(), 5_000_000);
+        assert_eq!(index.frequency_of(1), Some(4_999_999));
+        assert!(index.remove(2, 1));
+        assert_eq!(index.select_and_remove(), Some((1, 4_999_999)));
+        assert_eq!(index.count(), 0);
+
+        // Beyond 9 digits, frequencies are binned by their leading digits.
+        let mut index = FrequencyIndex::new(u64::MAX);
+        assert_eq!(index.resolution(), 100_000_000_000);
+        for id in 0..1_000 {
+            index.add(id, 12_345_678_912_345_678_912);
         }
+        assert_eq!(index.total_frequency(), 1_000 * 12_345_678_900_000_000_000);
+        let drawn = index.select_many_and_remove(10).unwrap();
+        assert!(drawn.iter().all(|&(_, frequency)| frequency == 12_345_678_900_000_000_000));
     }
 
-    /// Recursive private method to handle adding individuals.
-    fn add_recurse(
-        node: &mut Node<B>,
-        individual_id: u64,
-        scaled: u64, // Scaled weight as u64
-        digits: &[u8; MAX_PRECISION],
-        current_depth: u8,
-        max_depth: u8,
-    ) {
-        node.content_count += 1;
-        node.accumulated_value += scaled;
+    #[test]
+    fn test_digit_bin_map() {
+        let mut map = DigitBinMap::new(Config::new().precision(2).backend(Backend::Medium));
+        assert!(map.insert(1, 0.123, "a".to_string()));
+        assert!(map.insert(2, 0.5, "b".to_string()));
+        assert!(!map.insert(3, 1.5, "c".to_string()));
+        assert_eq!((map.count(), map.get(3)), (2, None));
 
-        if current_depth > max_depth {
-            if let NodeContent::DigitIndex(_) = &node.content {
-                node.content = NodeContent::Bin(B::default());
-            }
-            if let NodeContent::Bin(bin) = &mut node.content {
-                bin.insert(individual_id);
+        // Re-inserting moves the ID and replaces its value; a failed move changes nothing.
+        assert!(map.insert(1, 0.9, "A".to_string()));
+        assert!(!map.set_weight(1, 0.001));
+        assert!(!map.set_weight(7, 0.5));
+        assert_eq!((map.weight(1), map.get(1).map(String::as_str)), (Some(0.9), Some("A")));
+        assert_eq!(stored_weight(map.index(), 1), Some(0.9));
+        assert!(map.set_weight(2, 0.25));
+        map.get_mut(2).unwrap().push('!');
+
+        let (id, weight, value) = map.select().unwrap();
+        assert_eq!((map.weight(id), map.get(id)), (Some(weight), Some(value)));
+        let mut drained = map.select_many_and_remove(2).unwrap();
+        drained.sort_by_key(|&(id, ..)| id);
+        assert_eq!(drained, vec![(1, 0.9, "A".to_string()), (2, 0.25, "b!".to_string())]);
+        assert_eq!((map.count(), map.iter().count(), map.remove(1)), (0, 0, None));
+    }
+
+    #[test]
+    fn test_select_many_with_replacement() {
+        let mut index = DigitBinIndex::counted(2);
+        index.add_with_count(1, 0.5, 3);
+        index.add(2, 0.5);
+        index.add(3, 0.01);
+        assert_eq!(DigitBinIndex::new().select_many_with_replacement(1), None);
+        assert_eq!(index.select_many_with_replacement(0), Some(Vec::new()));
+
+        let mut rng = WyRand::seed_from_u64(11);
+        let drawn = index.select_many_with_replacement_with_rng(20_000, &mut rng).unwrap();
+        assert_eq!(drawn.len(), 20_000);
+        assert_eq!(index.count(), 5);
+        // Three copies of item 1 against one of item 2 in the same bin.
+        let ones = drawn.iter().filter(|&&(id, _)| id == 1).count() as f64;
+        let twos = drawn.iter().filter(|&&(id, _)| id == 2).count() as f64;
+        assert!((ones / twos - 3.0).abs() < 0.3, "ratio {}", ones / twos);
+        assert!(drawn.iter().all(|&(id, weight)| weight == if id == 3 { 0.01 } else { 0.5 }));
+    }
+
+    #[test]
+    fn test_merge_indexes() {
+        let items: Vec<(u64, f64)> = (0..3_000).map(|id| (id, ((id * 7) % 999 + 1) as f64 / 1000.0)).collect();
+        let mut whole = DigitBinIndex::with_precision(3);
+        whole.add_many(&items);
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut merged = build(3);
+            for chunk in items.chunks(1_000) {
+                let mut shard = build(3);
+                shard.add_many(chunk);
+                merged.merge(shard).unwrap();
             }
-            return;
+            assert_eq!(merged.state_hash(), whole.state_hash());
         }
 
-        let digit = digits[current_depth as usize - 1] as usize;
-        if let NodeContent::DigitIndex(children) = &mut node.content {
-            // Get the child, creating it if it doesn't exist.
-            let child_node = children[digit].get_or_insert_with(Node::new_internal);
-            Self::add_recurse(child_node, individual_id, scaled, digits, current_depth + 1, max_depth);
+        // Mixed backends fall back to insertion, and the registry follows the merge.
+        let mut index = DigitBinIndex::with_config(Config::new().id_registry(true));
+        index.add(1, 0.5);
+        let mut other = DigitBinIndex::counted(3);
+        other.add_with_count(2, 0.25, 3);
+        index.merge(other).unwrap();
+        assert_eq!(index.count(), 4);
+        assert_eq!(index.remove_by_id(2), Some(0.25));
+        assert_eq!(index.merge(DigitBinIndex::with_precision(4)), Err(DigitBinIndexError::PrecisionMismatch { expected: 4, found: 3 }));
+    }
+
+    #[test]
+    fn test_exact_weights_round_trip() {
+        let mut index = DigitBinIndex::with_config(Config::new().precision(2).exact_weights(true));
+        assert!(index.has_id_registry() && index.has_exact_weights());
+        // 0.29 truncates to the 0.28 bin; the same ID in two bins keeps both weights.
+        index.add(1, 0.29);
+        index.add(1, 0.5123);
+        index.add_many(&[(2, 0.2049), (3, 0.2001)]);
+        index.add_range(10..12, 0.777);
+        let mut seen: Vec<(u64, f64)> = index.clone().select_many_and_remove(6).unwrap();
+        seen.sort_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+        assert_eq!(seen, vec![(1, 0.29), (1, 0.5123), (2, 0.2049), (3, 0.2001), (10, 0.777), (11, 0.777)]);
+
+        assert!(index.remove(1, 0.5123));
+        assert_eq!(index.get_weight(1), Some(0.29));
+        assert!(index.update_weight(2, 0.2049, 0.9876));
+        // Coarsening rebuilds the registry; items left in their bins keep their weights.
+        index.coarsen_to(1);
+        assert_eq!(index.get_weight(2), Some(0.9));
+        let mut rest = Vec::new();
+        while let Some(item) = index.select_and_remove() {
+            rest.push(item);
         }
+        assert_eq!(rest.len(), 5);
+        assert_eq!(index.count(), 0);
     }
 
-    /// Adds multiple items to the index in a highly optimized batch operation.
-    ///
-    /// This method is significantly faster than calling `add` in a loop for large
-    /// collections of items. It works by first pre-processing the input items,
-    /// grouping them by their shared weight path (e.g., all items with weight 0.123...).
-    /// It then traverses the tree once per group, rather than once per item,
-    /// drastically reducing function call overhead and improving CPU cache performance
-    /// by performing aggregated updates at each node.
-    ///
-    /// # Arguments
-    ///
-    /// * `items` - A slice of `(individual_id, weight)` tuples to add to the index.
-    ///
-    pub fn add_many(&mut self, items: &[(u64, f64)]) {
-        if items.is_empty() {
-            return;
+    #[test]
+    fn test_consuming_iteration_after_partial_draw() {
+        let items: Vec<(u64, f64)> = (0..2_000).map(|id| (id, ((id % 97) + 1) as f64 / 100.0)).collect();
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(2);
+            index.add_many(&items);
+            index.mark();
+            let drawn = index.select_many_and_remove(500).unwrap();
+            let expected = index.iter().collect::<Vec<_>>();
+
+            let rest = index.clone().into_iter();
+            assert_eq!(rest.len(), 1_500);
+            assert_eq!(rest.collect::<Vec<_>>(), expected);
+
+            let mut drained = index.drain();
+            assert_eq!(index.count(), 0);
+            index.add(9_999, 0.5);
+            assert_eq!(drained.next(), expected.first().copied());
+            assert_eq!(drained.len(), 1_499);
+            let removed = index.removed_since_mark().unwrap();
+            assert_eq!(removed.ids.len(), drawn.len() as u64 + 1_500);
         }
+    }
 
-        let mut digits = [0u8; MAX_PRECISION];
-        for &(id, weight) in items {
-            if let Some(scaled) = self.weight_to_digits(weight, &mut digits) {
-                Self::add_recurse(&mut self.root, id, scaled, &digits, 1, self.precision)
-            } 
+    #[test]
+    fn test_stratified_bootstrap() {
+        let mut index = DigitBinIndex::counted(2);
+        index.add_with_count(1, 0.2, 3);
+        index.add(2, 0.6);
+        index.add(3, 0.4);
+        let a: RoaringTreemap = [1, 2].into_iter().collect();
+        let b: RoaringTreemap = [3].into_iter().collect();
+        let mut rng = WyRand::seed_from_u64(5);
+
+        let resamples = index.stratified_bootstrap(&[(a.clone(), 20_000), (b.clone(), 5)], &mut rng).unwrap();
+        // Three copies at 0.2 weigh as much as one item at 0.6.
+        let ones = resamples[0].iter().filter(|&&(id, _)| id == 1).count() as f64 / 20_000.0;
+        assert!((ones - 0.5).abs() < 0.02, "share {ones}");
+        assert_eq!(resamples[1], vec![(3, 0.4); 5]);
+        assert_eq!(index.count(), 5);
+
+        let empty: RoaringTreemap = [9].into_iter().collect();
+        assert_eq!(index.stratified_bootstrap(&[(empty.clone(), 0)], &mut rng), Ok(vec![vec![]]));
+        assert_eq!(
+            index.stratified_bootstrap(&[(a.clone(), 1), (empty, 1)], &mut rng),
+            Err(DigitBinIndexError::InfeasibleQuota { group: 1, requested: 1, available: 0 })
+        );
+        assert_eq!(
+            index.stratified_bootstrap(&[(a.clone(), 1), (&a | &b, 1)], &mut rng),
+            Err(DigitBinIndexError::OverlappingGroups { id: 1 })
+        );
+    }
+
+    #[test]
+    fn test_suggest_batch_size() {
+        let mut index = DigitBinIndex::counted(2);
+        assert_eq!(index.suggest_batch_size(0.05), 0);
+        index.add_with_count(1, 0.5, 1_000);
+        assert_eq!(index.suggest_batch_size(0.05), 50);
+        assert_eq!(index.suggest_batch_size(2.0), 1_000);
+        assert_eq!(index.suggest_batch_size(0.0), 1);
+        // 1,000 items at 0.5 and 1,000 at 0.01: n_eff = 510² / (250 + 0.1) ≈ 1040.
+        index.add_with_count(2, 0.01, 1_000);
+        assert_eq!(index.suggest_batch_size(0.1), 103);
+    }
+
+    #[test]
+    fn test_stats_track_structure() {
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(3);
+            index.add_many(&[(1, 0.111), (2, 0.112), (3, 0.112), (4, 0.9)]);
+            let stats = index.stats();
+            assert_eq!(stats.nodes_per_depth, vec![1, 2, 2, 3]);
+            assert_eq!((stats.occupied_bins, stats.empty_bins, stats.largest_bin), (3, 0, 2));
+            assert_eq!(stats.bin_size_histogram, vec![2, 1]);
+            assert!(stats.bin_bytes > 0);
+
+            assert!(index.remove(4, 0.9));
+            let stats = index.stats();
+            assert_eq!(stats.items, 3);
+            assert_eq!(stats.occupied_bins, 2);
+            assert_eq!(stats.occupied_bins + stats.empty_bins, stats.nodes_per_depth[3]);
         }
     }
 
-    pub fn remove(&mut self, individual_id: u64, weight: f64) -> bool{
-        let mut digits = [0u8; MAX_PRECISION];
-        if let Some(scaled) = self.weight_to_digits(weight, &mut digits) {
-            return Self::remove_recurse(&mut self.root, individual_id, scaled, &digits, 1, self.precision);
+    #[test]
+    fn test_cached_index_stays_in_sync() {
+        let weights: std::collections::HashMap<u64, f64> = [(1, 0.123), (2, 0.0001), (3, 0.456), (4, 1.5)].into_iter().collect();
+        let mut cached = CachedIndex::from_weights(Config::new().precision(2).backend(Backend::Medium), weights);
+        // Weights the index cannot hold are dropped from the map as well.
+        assert_eq!(cached.count(), 2);
+        assert_eq!(cached.weights().len(), 2);
+        assert_eq!(cached.weight(2), None);
+
+        assert!(cached.insert(1, 0.987));
+        assert!(!cached.insert(3, 0.0));
+        assert_eq!(cached.weight(3), Some(0.456));
+        assert!(cached.insert(5, 0.3));
+        assert_eq!(cached.count(), 3);
+        assert!((cached.index().total_weight() - (0.98 + 0.45 + 0.3)).abs() < 1e-9);
+
+        let mut drawn = cached.select_many_and_remove(3).unwrap();
+        drawn.sort_by_key(|&(id, _)| id);
+        assert_eq!(drawn, vec![(1, 0.987), (3, 0.456), (5, 0.3)]);
+        let (index, weights) = cached.into_parts();
+        assert_eq!((index.count(), weights.len()), (0, 0));
+    }
+
+    #[test]
+    fn test_rebin_to_precision() {
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(2);
+            // 0.57 * 100 is just below 57 in floating point, so those items land in the 0.56 bin.
+            index.add_many(&[(1, 0.29), (2, 0.57), (3, 0.57)]);
+            let before = index.iter().collect::<Vec<_>>();
+            index.at_tick(3, 1, 0.1234);
+            index.rebin_to_precision(4);
+            // Without exact weights the items keep their bin weights, exactly.
+            assert_eq!(index.precision(), 4);
+            assert_eq!(index.iter().collect::<Vec<_>>(), before);
+            assert_eq!(index.remove_by_id(2), Some(0.56));
+            assert_eq!(index.advance_to(3), 1);
+            assert_eq!(index.get_weight(1), Some(0.1234));
+            assert_eq!(index.rebin_to_precision(1), vec![]);
+            assert_eq!(index.iter().collect::<Vec<_>>(), vec![(1, 0.1), (3, 0.5)]);
         }
-        false
+
+        let mut index = DigitBinIndex::with_config(Config::new().precision(1).exact_weights(true));
+        index.add_many(&[(1, 0.123), (2, 0.456)]);
+        index.add(2, 0.9);
+        index.rebin_to_precision(2);
+        assert_eq!(index.iter().collect::<Vec<_>>(), vec![(1, 0.12), (2, 0.45), (2, 0.9)]);
+        assert_eq!(index.get_weight(1), Some(0.123));
+        assert!(index.has_exact_weights());
     }
 
-    /// Recursive private method to handle removing individuals.
-    fn remove_recurse(
-        node: &mut Node<B>,
-        individual_id: u64,
-        scaled: u64,
-        digits: &[u8; MAX_PRECISION],
-        current_depth: u8,
-        max_depth: u8,
-    ) -> bool {
-        if current_depth > max_depth {
-            if let NodeContent::Bin(bin) = &mut node.content {
-                let orig_len = bin.len();
-                bin.remove(individual_id);
-                if bin.len() < orig_len {
-                    node.content_count -= 1;
-                    node.accumulated_value -= scaled;
-                    return true;
-                }
-            }
-            return false;
+    #[test]
+    fn test_tie_break() {
+        use rand::rngs::StdRng;
+        use std::collections::HashSet;
+
+        // Shares of the mass: ID 1 [0, 25), ID 3 [25, 51), ID 2 [51, 101). The boundary at
+        // 25 is between two leaves of one parent, the one at 51 between two top-level children.
+        let mut index = DigitBinIndex::with_config(Config::new().precision(2).tie_break(TieBreak::FirstChild));
+        index.add_many(&[(1, 0.25), (2, 0.5), (3, 0.26)]);
+        let mut rng = StdRng::seed_from_u64(3);
+        let owners = |index: &DigitBinIndex, rng: &mut StdRng| {
+            [0, 25, 51, 101].map(|target| index.select_at(target, rng).map(|(id, _)| id))
+        };
+        assert_eq!(owners(&index, &mut rng), [None, Some(1), Some(3), Some(2)]);
+        index.set_tie_break(TieBreak::LastChild);
+        assert_eq!(owners(&index, &mut rng), [Some(1), Some(3), Some(2), None]);
+
+        index.set_tie_break(TieBreak::Random);
+        let mut sides = HashSet::new();
+        for _ in 0..100 {
+            assert_eq!(index.select_at(0, &mut rng), Some((1, 0.25)));
+            assert_eq!(index.select_at(101, &mut rng), Some((2, 0.5)));
+            sides.insert(index.select_at(25, &mut rng).unwrap().0);
         }
+        assert_eq!(sides, HashSet::from([1, 3]));
 
-        let digit = digits[current_depth as usize - 1] as usize;
-        if let NodeContent::DigitIndex(children) = &mut node.content {
-            // Check if the child at 'digit' exists and get a mutable reference to it.
-            if let Some(child_node) = children[digit].as_mut() {
-                // If it exists, recurse. If the recursion returns true (success)...
-                if Self::remove_recurse(child_node, individual_id, scaled, digits, current_depth + 1, max_depth) {
-                    // ...then update this node's stats and propagate the success upwards.
-                    node.content_count -= 1;
-                    node.accumulated_value -= scaled;
-                    return true;
-                }
+        // `FirstChild` matches a Fenwick-style search for the first prefix sum reaching a
+        // target drawn from 1..=total, draw for draw.
+        index.set_tie_break(TieBreak::FirstChild);
+        for seed in 0..200 {
+            let (id, _) = index.select_with_rng(&mut StdRng::seed_from_u64(seed)).unwrap();
+            let target = StdRng::seed_from_u64(seed).random_range(1..=101u64);
+            let expected = [(25, 1), (51, 3), (101, 2)].into_iter().find(|&(end, _)| target <= end).unwrap().1;
+            assert_eq!(id, expected);
+        }
+        let mut drained = Vec::new();
+        while let Some((id, _)) = index.select_and_remove_with_rng(&mut rng) {
+            drained.push(id);
+        }
+        drained.sort();
+        assert_eq!(drained, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_groups() {
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(2);
+            for id in 0..40 {
+                index.add_with_group(id, 0.25, (id % 2) as u16);
             }
+            index.add_range(100..110, 0.5);
+            assert!(!index.add_with_group(200, 1.5, 0));
+            assert_eq!(index.group_of(200), None);
+            assert_eq!((index.group_count(0), index.group_count(1)), (20, 20));
+            assert!((index.group_weight(1) - 5.0).abs() < 1e-9);
+
+            // Every removal path keeps the groups in step.
+            index.remove(0, 0.25);
+            index.remove_by_id(2);
+            index.select_and_remove();
+            index.select_many_and_remove(5);
+            let counts = (0..40u64).fold([0u64; 2], |mut counts, id| {
+                counts[(id % 2) as usize] += index.contains(id) as u64;
+                counts
+            });
+            assert_eq!([index.group_count(0), index.group_count(1)], counts);
+            assert_eq!(index.group_of(0), None);
+
+            // Moving an ID moves its copies, and weight updates follow it.
+            index.add_with_group(1, 0.25, 0);
+            assert!(index.update_weight(1, 0.25, 0.75));
+            assert_eq!(index.group_of(1), Some(0));
+            let before = index.group_weight(0);
+            assert_eq!(index.select_from_group_and_remove(7), None);
+
+            let drawn = index.select_many_stratified(&[(0, 3), (1, 2)]).unwrap();
+            assert!(drawn[0].iter().all(|&(id, _)| id % 2 == 0 || id == 1));
+            assert!(drawn[1].iter().all(|&(id, _)| id % 2 == 1 && id != 1));
+            let removed: f64 = drawn[0].iter().map(|&(_, weight)| weight).sum();
+            assert!((index.group_weight(0) - (before - removed)).abs() < 1e-9);
+            let available = index.group_count(1);
+            assert_eq!(
+                index.select_many_stratified(&[(1, available), (1, 1)]),
+                Err(DigitBinIndexError::InfeasibleQuota { group: 1, requested: 1, available: 0 })
+            );
+
+            // Tags survive a coarsening, which rebuilds the registry.
+            let kept = (0..40).find(|&id| index.contains(id)).unwrap();
+            let group = index.group_of(kept);
+            assert!(group.is_some());
+            index.coarsen_to(1);
+            assert_eq!(index.group_of(kept), group);
+            let ungrouped = (100..110).filter(|&id| index.contains(id)).count() as u64;
+            assert_eq!(index.group_count(0) + index.group_count(1) + ungrouped, index.count());
+            index.drain().for_each(drop);
+            assert_eq!((index.group_count(0), index.group_of(1)), (0, None));
         }
-        false
     }
 
-    /// Removes multiple items from the index in a highly optimized batch operation.
-    ///
-    /// This method is significantly faster than calling `remove` in a loop. It
-    /// groups the items to be removed by their weight path and traverses the tree
-    /// only once per group, performing aggregated updates on the way up.
-    ///
-    /// The `(id, weight)` pairs must match items that are currently in the index.
-    /// If a given pair is not found, it is silently ignored.
-    ///
-    /// # Arguments
-    ///
-    /// * `items` - A slice of `(id, weight)` tuples to remove from the index.
-    ///
-    pub fn remove_many(&mut self, items: &[(u64, f64)]) -> bool {
-        if items.is_empty() {
-            return false;
+    #[test]
+    fn test_id_lookups_use_the_registry() {
+        for mut index in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::counted(3)] {
+            index.add_many(&[(1, 0.25), (2, 0.5), (3, 0.75)]);
+            // Without the registry the lookups report nothing instead of scanning every item.
+            assert!(!index.contains(1));
+            assert_eq!(index.get_weight(1), None);
+            // Removing by ID turns the registry on, and the lookups answer from then on.
+            assert_eq!(index.remove_by_id(2), Some(0.5));
+            assert!(index.has_id_registry());
+            assert!(index.contains(1) && !index.contains(2));
+            assert_eq!(index.get_weight(3), Some(0.75));
         }
 
-        let mut digits = [0u8; MAX_PRECISION];
-        let mut success = true;
-        for &(id, weight) in items {
-            if let Some(scaled) = self.weight_to_digits(weight, &mut digits) {
-                success &= Self::remove_recurse(&mut self.root, id, scaled, &digits, 1, self.precision)
-            } else {
-                success &= false;                
+        let mut index = DigitBinIndex::new();
+        index.add(1, 0.5);
+        index.at_tick(1, 1, 0.25);
+        assert!(index.has_id_registry());
+        assert_eq!(index.advance_to(1), 1);
+        assert_eq!(index.get_weight(1), Some(0.25));
+    }
+
+    #[test]
+    fn test_clone_with_overrides() {
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut baseline = build(3);
+            baseline.add_many(&[(1, 0.1), (2, 0.2), (3, 0.3)]);
+            baseline.add_with_count(4, 0.4, 2);
+            let overrides = [(1, 0.9), (4, 0.05), (5, 0.5), (2, 1.5), (3, 0.7), (3, 0.6)];
+            for registry in [false, true] {
+                if registry {
+                    baseline.enable_id_registry();
+                }
+                let scenario = baseline.clone_with_overrides(&overrides);
+                let copies = scenario.count() - 3; // 2 with Counted bins, 1 with set-like bins
+                assert_eq!(stored_weight(&scenario, 1), Some(0.9));
+                assert_eq!(stored_weight(&scenario, 2), Some(0.2));
+                assert_eq!(stored_weight(&scenario, 3), Some(0.6));
+                assert_eq!(stored_weight(&scenario, 5), None);
+                assert!((scenario.total_weight() - (0.9 + 0.2 + 0.6 + 0.05 * copies as f64)).abs() < 1e-9);
+                assert_eq!(stored_weight(&baseline, 1), Some(0.1));
+                assert_eq!(scenario.has_id_registry(), registry);
             }
         }
-        success
+
+        let mut baseline = DigitBinIndex::with_config(Config::new().precision(1).exact_weights(true));
+        baseline.add(1, 0.25);
+        let mut scenario = baseline.clone_with_overrides(&[(1, 0.27)]);
+        assert_eq!(scenario.get_weight(1), Some(0.27));
+        assert_eq!(scenario.select_and_remove(), Some((1, 0.27)));
     }
 
-    // --- Selection Functions ---
+    #[test]
+    fn test_inconsistent_mass() {
+        let mut index = DigitBinIndex::with_precision(1);
+        index.add_many(&[(1, 0.2), (2, 0.5)]);
+        let DigitBinIndex::Small(inner) = &mut index else { unreachable!() };
+        // Zero out the count of the 0.5 bin, but not its mass.
+        let NodeContent::DigitIndex(block) = inner.root.content else { unreachable!() };
+        Arc::make_mut(&mut inner.arena).block_mut(block)[5].as_mut().unwrap().content_count = 0;
+        assert_eq!(
+            index.check_mass(),
+            Err(DigitBinIndexError::InconsistentMass { stored_count: 2, stored_mass: 7, actual_count: 1, actual_mass: 7 })
+        );
+
+        // Draws landing in the broken bin come back empty instead of dividing by zero.
+        for _ in 0..50 {
+            assert_ne!(index.select().map(|(id, _)| id), Some(2));
+            index.select_many_with_replacement(10);
+        }
+        let error = loop {
+            if let Err(error) = index.try_select() {
+                break error;
+            }
+        };
+        assert!(matches!(error, DigitBinIndexError::InconsistentMass { .. }));
 
-    pub fn select(&mut self) -> Option<(u64, f64)> {
-        self.select_and_optionally_remove(false)
-    }
+        // A massless root is caught up front, by every draw.
+        let DigitBinIndex::Small(inner) = &mut index else { unreachable!() };
+        inner.root.accumulated_value = 0;
+        inner.set_tie_break(TieBreak::FirstChild);
+        assert_eq!(index.select(), None);
+        assert_eq!(index.select_and_remove(), None);
+        assert_eq!(index.select_many(1), None);
+        assert_eq!(index.select_many_with_replacement(1), None);
+        assert!(index.try_select_and_remove().is_err());
 
-    pub fn select_many(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
-        self.select_many_and_optionally_remove(num_to_draw, false)
+        index.set_auto_repair(true);
+        assert!(index.try_select_and_remove().unwrap().is_some());
+        assert_eq!(index.check_mass(), Ok(()));
+        assert!(!index.repair_mass());
+        assert_eq!(index.count(), 1);
     }
 
-    pub fn select_and_remove(&mut self) -> Option<(u64, f64)> {
-        self.select_and_optionally_remove(true)
-    }
+    #[test]
+    fn test_select_and_reweight() {
+        use rand::rngs::StdRng;
 
-    // Wrapper function to handle both select and select_and_remove
-    pub fn select_and_optionally_remove(&mut self, with_removal: bool) -> Option<(u64, f64)> {
-        if self.root.content_count == 0 {
-            return None;
+        // A two-colour Pólya urn: the drawn colour's weight doubles.
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut urn = build(4);
+            urn.enable_exact_weights();
+            urn.add_many(&[(1, 0.001), (2, 0.001)]);
+            let mut rng = StdRng::seed_from_u64(11);
+            for _ in 0..8 {
+                let before = urn.clone();
+                let (id, weight) = urn.select_and_reweight_with_rng(2.0, &mut rng).unwrap();
+                assert_eq!(urn.get_weight(id), Some(weight));
+                assert!((weight - 2.0 * before.get_weight(id).unwrap()).abs() < 1e-12);
+                assert!((urn.total_weight() - before.total_weight() - weight / 2.0).abs() < 1e-9);
+            }
+            assert_eq!(urn.count(), 2);
+            // Eight doublings in total, however they were shared.
+            let product = urn.get_weight(1).unwrap() * urn.get_weight(2).unwrap();
+            assert!((product - 0.001 * 0.001 * 256.0).abs() < 1e-12);
         }
-        let mut rng = WyRand::from_os_rng();
-        let random_target = rng.random_range(0u64..self.root.accumulated_value);
-        Self::select_and_optionally_remove_recurse(&mut self.root, random_target, 1, self.precision, &mut rng, with_removal, self.scale)
+
+        // Binned weights are multiplied as binned, and shrink into the smallest bins.
+        let mut index = DigitBinIndex::with_precision(2);
+        index.add(7, 0.57);
+        assert_eq!(index.select_and_reweight(0.5), Some((7, 0.28)));
+        assert_eq!(index.select_and_reweight(0.01), Some((7, 0.28)));
+        assert_eq!(DigitBinIndex::new().select_and_reweight(2.0), None);
     }
 
-    // Helper function
-    fn select_and_optionally_remove_recurse(
-        node: &mut Node<B>,
-        target: u64,
-        current_depth: u8,
-        max_depth: u8,
-        rng: &mut WyRand,
-        with_removal: bool,
-        scale: f64,
-    ) -> Option<(u64, f64)> {
-        // Base case: Bin node
-        if current_depth > max_depth {
-            if let NodeContent::Bin(bin) = &mut node.content {
-                if bin.is_empty() {
-                    return None;
-                }
-                let scaled_weight = node.accumulated_value / node.content_count as u64;
-                let weight = scaled_weight as f64 / scale;
-                let selected_id = if with_removal {
-                    bin.get_random_and_remove(rng)?
-                } else {
-                    bin.get_random(rng)?
-                };
-                if with_removal {
-                    node.content_count -= 1;
-                    node.accumulated_value -= scaled_weight;
-                }
-                return Some((selected_id, weight));
+    #[test]
+    fn test_heaviest_and_lightest() {
+        use rand::rngs::StdRng;
+
+        let mut rng = StdRng::seed_from_u64(5);
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(3);
+            for id in 0..500 {
+                index.add(id, rng.random_range(0.001..0.999));
             }
-            return None;
-        }
+            let ascending: Vec<(u64, f64)> = index.iter().collect();
+            let weights = |items: &[(u64, f64)]| items.iter().map(|&(_, w)| w).collect::<Vec<_>>();
 
-        // Recursive case: DigitIndex node
-        if let NodeContent::DigitIndex(children) = &mut node.content {
-            let mut cum: u64 = 0;
-            // The iterator now gives us a mutable reference to the Option.
-            for child_option in children.iter_mut() {
-                // We pattern match to see if a child Node exists.
-                if let Some(child) = child_option.as_mut() {
-                    // Now, 'child' is a &mut Node<B>, and we can proceed with the original logic.
-                    if child.accumulated_value == 0 {
-                        continue;
-                    }
-                    if target < cum + child.accumulated_value {
-                        if let Some((selected_id, weight)) = Self::select_and_optionally_remove_recurse(
-                            child,
-                            target - cum,
-                            current_depth + 1,
-                            max_depth,
-                            rng,
-                            with_removal,
-                            scale,
-                        ) {
-                            if with_removal {
-                                node.content_count -= 1;
-                                node.accumulated_value -= (weight * scale).round() as u64;
-                            }
-                            return Some((selected_id, weight));
-                        }
-                        // This path is taken if recursion fails, which implies an empty bin was selected.
-                        return None; 
-                    }
-                    cum += child.accumulated_value;
-                }
+            for k in [0, 1, 37, 500, 600] {
+                let light = index.lightest(k);
+                assert_eq!(weights(&light), weights(&ascending[..k.min(500)]));
+                assert!(light.iter().all(|&(id, w)| stored_weight(&index, id) == Some(w)));
+
+                let heavy = index.heaviest(k);
+                let top: Vec<(u64, f64)> = ascending.iter().rev().take(k).copied().collect();
+                assert_eq!(weights(&heavy), weights(&top));
+                assert!(heavy.iter().all(|&(id, w)| stored_weight(&index, id) == Some(w)));
             }
         }
-        None
-    } 
 
-    pub fn select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64)>> {
-        self.select_many_and_optionally_remove(num_to_draw, true)
+        // Copies are listed once per copy, and emptied bins are skipped.
+        let mut index = DigitBinIndex::counted(2);
+        index.add_with_count(1, 0.9, 3);
+        index.add(2, 0.8);
+        index.add(3, 0.5);
+        index.remove(3, 0.5);
+        assert_eq!(index.heaviest(4), vec![(1, 0.9), (1, 0.9), (1, 0.9), (2, 0.8)]);
+        assert_eq!(index.lightest(1), vec![(2, 0.8)]);
+        assert!(DigitBinIndex::new().heaviest(3).is_empty());
     }
 
-    // Wrapper function to handle both select_many and select_many_and_remove
-    pub fn select_many_and_optionally_remove(&mut self, num_to_draw: u64, with_removal: bool) -> Option<Vec<(u64, f64)>> {
-        if num_to_draw > self.count() || num_to_draw == 0 {
-            return if num_to_draw == 0 { Some(Vec::new()) } else { None };
-        }
-        let mut rng = WyRand::from_os_rng();
-        let mut selected: Vec<(u64, f64)> = Vec::with_capacity(num_to_draw as usize);
-        let total_accum = self.root.accumulated_value;
-        // Create a Uniform distribution for the range [0, total_accum)
-        let uniform = Uniform::new(0u64, total_accum).expect("Valid range for Uniform");  
-        // Generate num_to_draw random numbers using sample_iter
-        let passed_targets: Vec<u64> = uniform
-            .sample_iter(&mut rng)
-            .take(num_to_draw as usize)
-            .collect();
-        Self::select_many_and_optionally_remove_recurse(
-            &mut self.root,
-            total_accum,
-            &mut selected,
-            &mut rng,
-            1,
-            self.precision,
-            with_removal,
-            passed_targets,
-            self.scale,
-        );
-        if selected.len() == num_to_draw as usize {
-            Some(selected)
-        } else {
-            None // Should not happen if logic is correct
+    #[test]
+    fn test_select_in_range() {
+        use rand::rngs::StdRng;
+
+        let mut rng = StdRng::seed_from_u64(8);
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(3);
+            for id in 0..300 {
+                index.add(id, (id % 100 + 1) as f64 / 101.0);
+            }
+            let (min, max) = (0.2, 0.45);
+            let in_range: Vec<(u64, f64)> = index.iter().filter(|&(_, w)| (min..=max).contains(&w)).collect();
+            let mass: f64 = in_range.iter().map(|&(_, w)| w).sum();
+
+            // Draws stay in range, with frequencies proportional to weight.
+            let mut hits = std::collections::HashMap::new();
+            let draws = 60_000;
+            for _ in 0..draws {
+                let (id, weight) = index.select_in_range(min, max).unwrap();
+                assert!((min..=max).contains(&weight));
+                *hits.entry(id).or_insert(0u32) += 1;
+            }
+            for &(id, weight) in &in_range {
+                let expected = draws as f64 * weight / mass;
+                assert!((hits[&id] as f64 - expected).abs() < 5.0 * expected.sqrt() + 5.0);
+            }
+
+            // The removing variant empties exactly the range.
+            let generic = match &mut index {
+                DigitBinIndex::Small(generic) => generic.select_in_range_and_remove_with_rng(min, max, &mut rng),
+                DigitBinIndex::Medium(generic) => generic.select_in_range_and_remove_with_rng(min, max, &mut rng),
+                DigitBinIndex::Large(generic) => generic.select_in_range_and_remove_with_rng(min, max, &mut rng),
+                DigitBinIndex::Counted(generic) => generic.select_in_range_and_remove_with_rng(min, max, &mut rng),
+            };
+            assert!(generic.is_some());
+            while index.select_in_range_and_remove(min, max).is_some() {}
+            assert_eq!(index.count(), 300 - in_range.len() as u64);
+            assert!(index.iter().all(|(_, w)| !(min..=max).contains(&w)));
         }
+
+        // Bounds are inclusive, compared with binned weights, and may be degenerate.
+        let mut index = DigitBinIndex::with_precision(2);
+        index.add_many(&[(1, 0.07), (2, 0.575)]);
+        assert_eq!(index.select_in_range(0.07, 0.07), Some((1, 0.07)));
+        assert_eq!(index.select_in_range(0.57, 0.57), Some((2, 0.57)));
+        assert_eq!(index.select_in_range(0.08, 0.56), None);
+        assert_eq!(index.select_in_range(0.5, 0.1), None);
+        assert_eq!(index.select_in_range(f64::NAN, 1.0), None);
+        assert_eq!(index.select_in_range(-1.0, 2.0).map(|(id, _)| id < 3), Some(true));
     }
 
-    /// Recursive helper for batch selection and removal.
-    /// - node: Current subtree root.
-    /// - subtree_total: Accumulated value of this node (passed to avoid borrowing issues).
-    /// - selected: Mutable vec to collect (id, weight) from leaves.
-    /// - rng: Mutable RNG.
-    /// - current_depth: Current digit level.
-    /// - precision: The precision of the DigitBinIndex (passed explicitly).
-    /// - with_removal: Whether to remove selected items.
-    /// - passed_targets: Pre-computed relative targets from parent (in [0, subtree_total)).
-    /// - scale: The scaling factor for weight conversions.
-    fn select_many_and_optionally_remove_recurse(
-        node: &mut Node<B>,
-        subtree_total: u64,
-        selected: &mut Vec<(u64, f64)>,
-        rng: &mut WyRand,
-        current_depth: u8,
-        precision: u8,
-        with_removal: bool,
-        passed_targets: Vec<u64>,
-        scale: f64,
-    ) {
-        let original_target_count = passed_targets.len() as u64;
-        if original_target_count == 0 {
-            return;
-        }
+    #[test]
+    fn test_select_many_is_uniform_within_bins() {
+        use rand::rngs::StdRng;
 
-        // This base case (leaf node) logic does not change, as it doesn't interact
-        // with the DigitIndex.
-        if current_depth > precision {
-            if let NodeContent::Bin(bin) = &mut node.content {
-                let bin_scaled = if node.content_count > 0 {
-                    node.accumulated_value / node.content_count as u64
-                } else {
-                    0u64
-                };
-                let bin_weight = bin_scaled as f64 / scale;
-                let to_select = original_target_count.min(node.content_count);
-                let mut picked = 0u64;
-                while picked < to_select && !bin.is_empty() {
-                    let id = if with_removal {
-                        bin.get_random_and_remove(rng).unwrap()
-                    } else {
-                        bin.get_random(rng).unwrap()
-                    };
-                    selected.push((id, bin_weight));
-                    picked += 1;
-                }
-                if with_removal {
-                    node.content_count -= picked;
-                    node.accumulated_value -= bin_scaled * picked as u64;
+        // A single bin of 20 IDs: each batch draw of 5 should favour no ID.
+        let mut rng = StdRng::seed_from_u64(13);
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut hits = [0u32; 20];
+            let rounds = 4_000;
+            for _ in 0..rounds {
+                let mut index = build(2);
+                index.add_range(0..20, 0.5);
+                for (id, _) in index.select_many_and_remove_with_rng(5, &mut rng).unwrap() {
+                    hits[id as usize] += 1;
                 }
             }
-            return;
+            // Each ID is expected rounds / 4 = 1000 times; the lowest are not preferred.
+            assert!(hits.iter().all(|&h| (h as f64 - 1000.0).abs() < 120.0), "{hits:?}");
+            assert!(hits[..5].iter().sum::<u32>() < 5_300);
         }
+    }
 
-        // --- START OF MODIFIED LOGIC ---
-        if let NodeContent::DigitIndex(children) = &mut node.content {
-            // CHANGE: Use fixed-size arrays of length 10 instead of dynamically sized Vecs.
-            let mut child_assigned = [0u64; 10];
-            // Note: `Default::default()` works for arrays where the element type is `Default`.
-            let mut child_rel_targets: [Vec<u64>; 10] = Default::default();
-            let mut assigned = 0u64;
-
-            // --- Main assignment loop ---
-            for &target in &passed_targets {
-                let mut cum: u64 = 0;
-                let mut chosen_idx = None;
-                // CHANGE: Iterate over the array of Options.
-                for (i, child_option) in children.iter().enumerate() {
-                    // CHANGE: Only process existing children.
-                    if let Some(child) = child_option {
-                        if child.accumulated_value == 0 {
-                            continue;
-                        }
-                        if target < cum + child.accumulated_value {
-                            if child_assigned[i] + 1 <= child.content_count {
-                                chosen_idx = Some(i);
-                            }
-                            break;
-                        }
-                        cum += child.accumulated_value;
-                    }
-                }
-                if let Some(idx) = chosen_idx {
-                    child_assigned[idx] += 1;
-                    // We need to re-calculate `cum` up to the chosen index to get the relative target.
-                    let start_of_child_range: u64 = children[..idx].iter().filter_map(|c| c.as_ref()).map(|c| c.accumulated_value).sum();
-                    let rel_target = target - start_of_child_range;
-                    child_rel_targets[idx].push(rel_target);
-                    assigned += 1;
-                }
-            }
-
-            // --- Rejection sampling for any remaining targets ---
-            let remaining = original_target_count - assigned;
-            let mut additional_assigned = 0u64;
-            while additional_assigned < remaining {
-                let target = rng.random_range(0u64..subtree_total);
-                let mut cum: u64 = 0;
-                let mut chosen_idx = None;
-                // CHANGE: Same iteration pattern as the loop above.
-                for (i, child_option) in children.iter().enumerate() {
-                    if let Some(child) = child_option {
-                        if child.accumulated_value == 0 {
-                            continue;
-                        }
-                        if target < cum + child.accumulated_value {
-                            if child_assigned[i] + 1 <= child.content_count {
-                                chosen_idx = Some(i);
-                            }
-                            break;
-                        }
-                        cum += child.accumulated_value;
-                    }
-                }
-                if let Some(idx) = chosen_idx {
-                    child_assigned[idx] += 1;
-                    let start_of_child_range: u64 = children[..idx].iter().filter_map(|c| c.as_ref()).map(|c| c.accumulated_value).sum();
-                    let rel_target = target - start_of_child_range;
-                    child_rel_targets[idx].push(rel_target);
-                    additional_assigned += 1;
-                }
-            }
-            
-            // CHANGE: Store accumulated values in a fixed-size array for the recursive calls.
-            let child_accums: [u64; 10] = std::array::from_fn(|i| {
-                children[i].as_ref().map_or(0, |c| c.accumulated_value)
-            });
+    #[test]
+    fn test_result_api() {
+        assert_eq!(DigitBinIndex::try_with_precision(10).unwrap_err(), DigitBinIndexError::PrecisionOutOfRange { precision: 10 });
+        assert!(DigitBinIndex::try_with_config(Config::new().precision(0).backend(Backend::Large)).is_err());
 
-            // --- Recurse into children ---
-            // CHANGE: Iterate through mutable options.
-            for (i, child_option) in children.iter_mut().enumerate() {
-                let assign_count = child_assigned[i];
-                if assign_count > 0 {
-                    // We must have a child here if it was assigned targets.
-                    if let Some(child) = child_option {
-                        let rel_targets = std::mem::take(&mut child_rel_targets[i]);
-                        Self::select_many_and_optionally_remove_recurse(
-                            child,
-                            child_accums[i],
-                            selected,
-                            rng,
-                            current_depth + 1,
-                            precision,
-                            with_removal,
-                            rel_targets,
-                            scale,
-                        );
-                    }
-                }
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(2);
+            for weight in [0.0, 1.0, -0.5, f64::NAN, f64::INFINITY] {
+                assert!(matches!(index.try_add(1, weight), Err(DigitBinIndexError::InvalidWeight { .. })));
+                assert!(matches!(index.try_remove(1, weight), Err(DigitBinIndexError::InvalidWeight { .. })));
             }
+            assert_eq!(index.try_add(1, 0.009), Err(DigitBinIndexError::WeightBelowResolution { weight: 0.009, precision: 2 }));
+            assert_eq!(index.count(), 0);
 
-            if with_removal {
-                // --- Unwind: Update this node's stats ---
-                // CHANGE: Sum up counts and values from the existing children in the array.
-                node.content_count = children.iter().filter_map(|c| c.as_ref()).map(|c| c.content_count).sum();
-                node.accumulated_value = children.iter().filter_map(|c| c.as_ref()).map(|c| c.accumulated_value).sum();
+            assert_eq!(index.try_add(1, 0.42), Ok(()));
+            let repeated = index.try_add(1, 0.42);
+            if matches!(index, DigitBinIndex::Medium(_) | DigitBinIndex::Large(_)) {
+                assert_eq!(repeated, Err(DigitBinIndexError::DuplicateId { id: 1 }));
+                assert_eq!(index.count(), 1);
+            } else {
+                assert_eq!(repeated, Ok(()));
+                assert_eq!(index.count(), 2);
+                assert_eq!(index.try_remove(1, 0.42), Ok(()));
             }
+
+            let mismatch = index.try_remove(1, 0.7).unwrap_err();
+            assert_eq!(mismatch, DigitBinIndexError::WeightMismatch { id: 1, given: 0.7, stored: 0.42 });
+            assert_eq!(mismatch.to_string(), "ID 1 is stored with weight 0.42, not 0.7");
+            assert_eq!(index.try_remove(9, 0.42), Err(DigitBinIndexError::NotFound { id: 9 }));
+
+            index.enable_id_registry();
+            assert_eq!(index.try_remove(1, 0.429), Ok(()));
+            assert_eq!(index.try_remove(1, 0.42), Err(DigitBinIndexError::NotFound { id: 1 }));
+            assert_eq!(index.count(), 0);
         }
     }
 
-    pub fn count(&self) -> u64 {
-        self.root.content_count
-    }
+    #[test]
+    fn test_select_many_sequential() {
+        use rand::rngs::StdRng;
+        use std::collections::HashSet;
 
-    pub fn total_weight(&self) -> f64 {
-        self.root.accumulated_value as f64 / self.scale
-    }
+        // The share of heavy items matches repeated single draws, not the batch draw.
+        let mut rng = StdRng::seed_from_u64(17);
+        let fill = |index: &mut DigitBinIndex| {
+            index.add_range(0..1000, 0.1);
+            index.add_range(1000..2000, 0.2);
+        };
+        let heavy = |selected: &[(u64, f64)]| selected.iter().filter(|&&(id, _)| id >= 1000).count() as f64;
+        let (mut sequential, mut clocks) = (0.0, 0.0);
+        for _ in 0..100 {
+            let mut index = DigitBinIndex::with_precision(3);
+            fill(&mut index);
+            let drawn: Vec<(u64, f64)> = (0..1500).filter_map(|_| index.select_and_remove_with_rng(&mut rng)).collect();
+            sequential += heavy(&drawn);
 
-    /// Prints detailed statistics about the tree: node count, bin stats, and weight stats.
-    pub fn print_stats_generic(&self) {
-        // This struct holds all the metrics we want to collect.
-        struct Stats {
-            node_count: usize,
-            non_empty_node_count: usize,
-            internal_node_count: usize, // NEW: For branching factor
-            child_slots_used: usize,    // NEW: For branching factor
-            bin_count: usize,
-            empty_bin_count: usize,
-            total_bin_items: u64,
-            min_weight: Option<f64>,
-            max_weight: Option<f64>,
-            // We collect all bin sizes to calculate standard deviation later.
-            bin_sizes: Vec<usize>, 
-            // Memory estimates
-            mem_nodes: usize,
-            mem_bins: usize,
+            let mut index = DigitBinIndex::with_precision(3);
+            fill(&mut index);
+            let DigitBinIndex::Small(generic) = &mut index else { unreachable!() };
+            let drawn = generic.select_many_sequential_and_optionally_remove_with_rng(1500, true, &mut rng).unwrap();
+            clocks += heavy(&drawn);
+            assert_eq!(index.count(), 500);
+            assert!(index.check_mass().is_ok());
         }
+        assert!((clocks - sequential).abs() / 100.0 < 5.0, "{clocks} vs {sequential}");
 
-        fn traverse<B: DigitBin>(
-            node: &Node<B>,
-            stats: &mut Stats,
-            scale: f64,
-        ) {
-            stats.node_count += 1;
-            stats.mem_nodes += std::mem::size_of::<Node<B>>();
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(2);
+            index.enable_id_registry();
+            index.add_range(0..300, 0.5);
+            index.add_range(300..310, 0.01);
 
-            if node.content_count > 0 {
-                stats.non_empty_node_count += 1;
-            }
-            
-            match &node.content {
-                NodeContent::DigitIndex(children) => {
-                    // --- NEW: Calculate branching factor stats ---
-                    stats.internal_node_count += 1;
-                    let used_children = children.iter().filter(|c| c.is_some()).count();
-                    stats.child_slots_used += used_children;
-                    // --- END NEW ---
+            let kept = index.select_many_sequential(250).unwrap();
+            assert_eq!(kept.iter().map(|&(id, _)| id).collect::<HashSet<_>>().len(), 250);
+            assert_eq!(index.count(), 310);
 
-                    // Add memory for the heap-allocated array of 10 optional nodes.
-                    stats.mem_nodes += std::mem::size_of::<[Option<Node<B>>; 10]>();
-                    
-                    // Iterate over the options in the array
-                    for child_option in children.iter() {
-                        // Only recurse into the children that actually exist (are Some)
-                        if let Some(child) = child_option {
-                            traverse(child, stats, scale);
-                        }
-                    }
-                }
-                NodeContent::Bin(bin) => {
-                    stats.bin_count += 1;
-                    let bin_size = bin.len();
-                    stats.bin_sizes.push(bin_size);
-                    stats.total_bin_items += bin_size as u64;
+            let removed = index.select_many_sequential_and_remove(310).unwrap();
+            assert_eq!(removed.iter().map(|&(id, _)| id).collect::<HashSet<_>>().len(), 310);
+            assert_eq!((index.count(), index.total_weight()), (0, 0.0));
+            assert!(!index.contains(5));
+            assert_eq!(index.select_many_sequential(1), None);
+        }
+    }
 
-                    // Estimate memory for the bin's contents.
-                    // This is an approximation. For RoaringBitmap, `serialized_size()` would be more accurate.
-                    stats.mem_bins += bin_size * std::mem::size_of::<u32>();
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_ffi_roundtrip() {
+        use crate::ffi::*;
+        use std::ptr;
 
-                    if bin_size == 0 {
-                        stats.empty_bin_count += 1;
-                    } else {
-                        // All items in a bin share the same weight.
-                        let scaled_weight = node.accumulated_value / node.content_count;
-                        let weight = scaled_weight as f64 / scale;
-                        stats.min_weight = Some(stats.min_weight.map_or(weight, |min| min.min(weight)));
-                        stats.max_weight = Some(stats.max_weight.map_or(weight, |max| max.max(weight)));
-                    }
-                }
+        assert!(dbi_with_precision(0).is_null());
+        let index = dbi_with_precision(3);
+        unsafe {
+            for id in 0..10 {
+                assert!(dbi_add(index, id, 0.5));
             }
+            assert!(!dbi_add(index, 99, 1.5));
+            assert_eq!(dbi_count(index), 10);
+            assert!((dbi_total_weight(index) - 5.0).abs() < 1e-9);
+
+            let (mut id, mut weight) = (0u64, 0.0f64);
+            assert!(dbi_select(index, &mut id, &mut weight));
+            assert!(id < 10 && weight == 0.5);
+            assert!(dbi_select_and_remove(index, &mut id, ptr::null_mut()));
+            assert!(dbi_remove(index, (id + 1) % 10, 0.5));
+            assert_eq!(dbi_count(index), 8);
+
+            let mut ids = [0u64; 8];
+            let mut weights = [0.0f64; 8];
+            assert!(!dbi_select_many_and_remove(index, 9, ids.as_mut_ptr(), weights.as_mut_ptr()));
+            assert!(dbi_select_many(index, 3, ids.as_mut_ptr(), ptr::null_mut()));
+            assert!(dbi_select_many_and_remove(index, 8, ids.as_mut_ptr(), weights.as_mut_ptr()));
+            let unique: std::collections::HashSet<u64> = ids.iter().copied().collect();
+            assert_eq!(unique.len(), 8);
+            assert!(weights.iter().all(|&w| w == 0.5));
+            assert_eq!(dbi_count(index), 0);
+            assert!(!dbi_select(index, &mut id, &mut weight));
+
+            dbi_free(index);
+            dbi_free(ptr::null_mut());
+
+            assert!(dbi_counted(10).is_null());
+            let cohorts = dbi_counted(3);
+            assert_eq!(dbi_add_with_count(cohorts, 7, 0.25, 1_000_000), 1_000_000);
+            let mut remaining = 0u64;
+            assert!(dbi_select_and_decrement(cohorts, &mut id, &mut weight, &mut remaining));
+            assert_eq!((id, weight, remaining), (7, 0.25, 999_999));
+            assert!(dbi_select_and_decrement(cohorts, &mut id, ptr::null_mut(), ptr::null_mut()));
+            assert_eq!(dbi_count(cohorts), 999_998);
+            dbi_free(cohorts);
+
+            // NULL index and output pointers are refused before anything is drawn.
+            let index = dbi_new();
+            assert!(dbi_add(index, 1, 0.5));
+            assert!(!dbi_select(index, ptr::null_mut(), &mut weight));
+            assert!(!dbi_select_and_remove(index, ptr::null_mut(), ptr::null_mut()));
+            assert!(!dbi_select_and_decrement(index, ptr::null_mut(), ptr::null_mut(), ptr::null_mut()));
+            assert!(!dbi_select_many_and_remove(index, 1, ptr::null_mut(), ptr::null_mut()));
+            assert_eq!(dbi_count(index), 1);
+            assert!(!dbi_add(ptr::null_mut(), 2, 0.5));
+            assert_eq!(dbi_add_with_count(ptr::null_mut(), 2, 0.5, 3), 0);
+            assert!(!dbi_select(ptr::null_mut(), &mut id, &mut weight));
+            assert_eq!((dbi_count(ptr::null()), dbi_total_weight(ptr::null())), (0, 0.0));
+            dbi_free(index);
         }
+    }
 
-        let mut stats = Stats {
-            node_count: 0,
-            non_empty_node_count: 0,
-            internal_node_count: 0, // NEW
-            child_slots_used: 0,    // NEW
-            bin_count: 0,
-            empty_bin_count: 0,
-            total_bin_items: 0,
-            min_weight: None,
-            max_weight: None,
-            bin_sizes: Vec::new(),
-            mem_nodes: 0,
-            mem_bins: 0,
-        };
+    #[test]
+    fn test_snapshot_shares_untouched_bins() {
+        fn bin<B: DigitBin>(index: &DigitBinIndexGeneric<B>, scaled: u64) -> &Arc<B> {
+            match &index.leaf(scaled).unwrap().content {
+                NodeContent::Bin(bin) => bin,
+                NodeContent::DigitIndex(_) => unreachable!(),
+            }
+        }
 
-        traverse(&self.root, &mut stats, self.scale);
-        
-        // --- Calculations ---
-        let fill_ratio = if stats.node_count > 0 {
-            stats.non_empty_node_count as f64 / stats.node_count as f64 * 100.0
-        } else { 0.0 };
+        let mut population = DigitBinIndexGeneric::<Vec<u32>>::with_precision(2);
+        for id in 0..10_000 {
+            population.add(id, if id % 2 == 0 { 0.15 } else { 0.85 });
+        }
+        let mut branch = population.snapshot();
+        assert!(Arc::ptr_eq(&population.arena, &branch.arena));
 
-        // NEW: Calculate average branching factor
-        let avg_branching_factor = if stats.internal_node_count > 0 {
-            stats.child_slots_used as f64 / stats.internal_node_count as f64
-        } else { 0.0 };
-        
-        let avg_bin_size = if stats.bin_count > 0 {
-            stats.total_bin_items as f64 / stats.bin_count as f64
-        } else { 0.0 };
+        // Removing from the 0.85 bin copies the arena, but only that bin.
+        assert!(branch.remove(1, 0.85));
+        assert!(!Arc::ptr_eq(&population.arena, &branch.arena));
+        assert!(Arc::ptr_eq(bin(&population, 15), bin(&branch, 15)));
+        assert!(!Arc::ptr_eq(bin(&population, 85), bin(&branch, 85)));
 
-        let std_dev_bin_size = if stats.bin_count > 1 {
-            let variance = stats.bin_sizes.iter()
-                .map(|&size| (size as f64 - avg_bin_size).powi(2)) // Corrected: removed the `*`
-                .sum::<f64>() / (stats.bin_count - 1) as f64;
-            variance.sqrt()
-        } else { 0.0 };
+        // Read-only draws copy nothing, and the branches stay independent.
+        let mut shared = population.snapshot();
+        let mut rng = WyRand::seed_from_u64(7);
+        for _ in 0..100 {
+            shared.select_with_rng(&mut rng).unwrap();
+        }
+        shared.select_many_with_rng(100, &mut rng).unwrap();
+        assert!(Arc::ptr_eq(&population.arena, &shared.arena));
+        branch.select_many_and_remove(5_000).unwrap();
+        assert_eq!((population.count(), branch.count()), (10_000, 4_999));
+        population.enable_id_registry();
+        assert!(population.contains(1));
+        assert!(population.check_mass().is_ok() && branch.check_mass().is_ok());
+    }
 
-        // NEW: Calculate bin size quartiles
-        let (q1_bin_size, median_bin_size, q3_bin_size) = if !stats.bin_sizes.is_empty() {
-            let mut sorted_sizes = stats.bin_sizes.clone();
-            sorted_sizes.sort_unstable();
-            let q1 = sorted_sizes.get(sorted_sizes.len() / 4).cloned().unwrap_or(0);
-            let median = sorted_sizes.get(sorted_sizes.len() / 2).cloned().unwrap_or(0);
-            let q3 = sorted_sizes.get(sorted_sizes.len() * 3 / 4).cloned().unwrap_or(0);
-            (q1, median, q3)
-        } else {
-            (0, 0, 0)
-        };
-        
-        let total_mem_mb = (stats.mem_nodes + stats.mem_bins) as f64 / (1024.0 * 1024.0);
-        let nodes_mem_mb = stats.mem_nodes as f64 / (1024.0 * 1024.0);
-        let bins_mem_mb = stats.mem_bins as f64 / (1024.0 * 1024.0);
-        
-        // NEW: Calculate average weight
-        let avg_weight = if self.count() > 0 {
-            self.total_weight() / self.count() as f64
-        } else { 0.0 };
-        
+    #[test]
+    fn test_select_many_excluding() {
+        let mut index = DigitBinIndexGeneric::<Vec<u32>>::with_precision(2);
+        for (id, weight) in [(0, 0.9), (1, 0.1), (2, 0.3), (3, 0.6), (4, 0.6)] {
+            index.add(id, weight);
+        }
+        let excluded: RoaringTreemap = [0].into_iter().collect();
 
-        // --- Printing ---
-        println!("\n[Tree Structure]");
-        println!("- Total Nodes Created:  {}", stats.node_count);
-        println!("- Internal Nodes:       {}", stats.internal_node_count); // NEW
-        println!("- Avg Branching Factor: {:.2} / 10", avg_branching_factor); // NEW
-        println!("- Tree Fill Ratio:      {:.2}%", fill_ratio);
-        println!("- Max Depth:            {}", self.precision);
+        // Sequential draws without replacement: the first pick of two from
+        // {0.1, 0.3, 0.6, 0.6} (total 1.6) is ID 1 with probability 0.1 / 1.6.
+        let mut rng = WyRand::seed_from_u64(11);
+        let trials = 40_000;
+        let mut first_light = 0;
+        let mut drawn_light = 0;
+        for _ in 0..trials {
+            let selected = index.select_many_excluding_with_rng(2, &excluded, &mut rng).unwrap();
+            assert_eq!(selected.len(), 2);
+            assert_ne!(selected[0].0, selected[1].0);
+            assert!(selected.iter().all(|&(id, _)| id != 0));
+            first_light += (selected[0].0 == 1) as u32;
+            drawn_light += selected.iter().filter(|&&(id, _)| id == 1).count() as u32;
+        }
+        let first = first_light as f64 / trials as f64;
+        assert!((first - 0.1 / 1.6).abs() < 0.01, "first draw share of ID 1 was {first}");
+        // P(ID 1 in the pair) = P(first) + sum over other first picks of P(pick) * 0.1 / (1.6 - w).
+        let expected = 0.1 / 1.6 + 0.3 / 1.6 * 0.1 / 1.3 + 2.0 * (0.6 / 1.6 * 0.1 / 1.0);
+        let share = drawn_light as f64 / trials as f64;
+        assert!((share - expected).abs() < 0.01, "ID 1 was drawn in {share} of pairs, expected {expected}");
 
-        println!("\n[Memory (Estimated)]");
-        println!("- Tree Structure:       {:.2} MB", nodes_mem_mb);
-        println!("- Leaf Bins:            {:.2} MB", bins_mem_mb);
-        println!("- Total Estimated:      {:.2} MB", total_mem_mb);
- 
-        println!("\n[Items & Bins]");
-        println!("- Total Items:          {}", stats.total_bin_items);
-        println!("- Total Bins (Leaves):  {}", stats.bin_count);
-        println!("- Empty Bins:           {}", stats.empty_bin_count);
-        println!("- Avg Items per Bin:    {:.2}", avg_bin_size);
-        println!("- Std Dev of Bin Size:  {:.2}", std_dev_bin_size);
-        println!("- Bin Size (min/max):   {} / {}", stats.bin_sizes.iter().min().map_or(0, |v| *v), stats.bin_sizes.iter().max().map_or(0, |v| *v));
-        println!("- Bin Size (Q1/Med/Q3): {} / {} / {}", q1_bin_size, median_bin_size, q3_bin_size); // NEW
-        
-        println!("\n[Weights]");
-        println!("- Smallest Weight:      {}", stats.min_weight.map_or("-".to_string(), |v| format!("{:.prec$}", v, prec = self.precision as usize)));
-        println!("- Largest Weight:       {}", stats.max_weight.map_or("-".to_string(), |v| format!("{:.prec$}", v, prec = self.precision as usize)));
-        println!("- Average Weight:       {:.prec$}", avg_weight, prec = self.precision as usize); // NEW
+        assert_eq!(index.select_many_excluding(4, &excluded).map(|s| s.len()), Some(4));
+        assert_eq!(index.select_many_excluding(5, &excluded), None);
+        assert_eq!(index.count(), 5);
     }
-}
 
-#[cfg(feature = "python-bindings")]
-mod python {
-    use super::*;
-    use pyo3::prelude::*;
+    #[test]
+    fn test_sampler_matches_select_with_rng() {
+        fn draw_n<D: Distribution<(u64, f64)>>(distribution: D, rng: &mut WyRand, n: usize) -> Vec<(u64, f64)> {
+            distribution.sample_iter(rng).take(n).collect()
+        }
 
-    #[pyclass(name = "DigitBinIndex")]
-    struct PyDigitBinIndex {
-        index: DigitBinIndex,
+        let mut index = DigitBinIndex::with_precision(3);
+        index.add_many(&[(1, 0.125), (2, 0.5), (3, 0.75), (4, 0.333)]);
+        let sampled = draw_n(index.sampler().unwrap(), &mut WyRand::seed_from_u64(5), 1_000);
+        let mut rng = WyRand::seed_from_u64(5);
+        let selected: Vec<_> = (0..1_000).map(|_| index.select_with_rng(&mut rng).unwrap()).collect();
+        assert_eq!(sampled, selected);
+        assert_eq!(index.count(), 4);
     }
 
-    #[pymethods]
-    impl PyDigitBinIndex {
-        #[new]
-        fn new() -> Self {
-            PyDigitBinIndex {
-                index: DigitBinIndex::new(),
-            }
+    #[test]
+    fn test_compact_releases_empty_subtrees() {
+        let mut index = DigitBinIndexGeneric::<Vec<u32>>::with_precision(3);
+        for id in 0..5_000u64 {
+            index.add(id, 0.001 + (id % 999) as f64 / 1_000.0);
         }
+        let full = index.memory_usage();
+        let shared = index.snapshot();
 
-        /// Create a DigitBinIndex with a specific precision.
-        #[staticmethod]
-        fn with_precision(precision: u64) -> Self {
-            PyDigitBinIndex {
-                index: DigitBinIndex::with_precision(precision.try_into().unwrap()),
-            }
+        let mut rng = WyRand::seed_from_u64(3);
+        index.select_many_and_remove_with_rng(4_990, &mut rng).unwrap();
+        assert_eq!(index.memory_usage(), full);
+        let slots = index.arena.slots.len();
+        let released = index.compact();
+        assert!(released > 0);
+        assert!(index.arena.slots.len() < slots / 10);
+        assert_eq!(index.memory_usage(), full - released * index.node_bytes());
+        assert!(index.check_mass().is_ok());
+        assert_eq!(index.count(), 10);
+        assert_eq!(index.compact(), 0);
+
+        // The snapshot keeps its own tree, and pruned paths are rebuilt on demand.
+        assert_eq!(shared.count(), 5_000);
+        assert_eq!(shared.memory_usage(), full);
+        for id in 0..5_000u64 {
+            index.add(10_000 + id, 0.001 + (id % 999) as f64 / 1_000.0);
         }
+        assert_eq!(index.memory_usage(), full);
+        assert!(index.check_mass().is_ok());
+    }
 
-        /// Create a DigitBinIndex with a specific precision and expected capacity.
-        #[staticmethod]
-        fn with_precision_and_capacity(precision: u8, capacity: u64) -> Self {
-            PyDigitBinIndex {
-                index: DigitBinIndex::with_precision_and_capacity(precision, capacity),
-            }
-        }        
+    #[test]
+    fn test_scale_all_weights() {
+        let mut index = DigitBinIndexGeneric::<RoaringBitmap>::with_precision(3);
+        for id in 0..1_000u64 {
+            index.add(id, 0.001 * (1 + id % 999) as f64);
+        }
+        let mut expected = index.clone();
+        let dropped = index.scale_all_weights(0.7);
+        // Weights of 0.001 become 0.0007, below the resolution.
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped.iter().all(|&(id, weight)| id % 999 == 0 && weight == 0.001));
 
-        /// Create a DigitBinIndex with Vec<u32> bins and the specified precision.
-        #[staticmethod]
-        fn small(precision: u8) -> Self {
-            PyDigitBinIndex {
-                index: DigitBinIndex::small(precision),
+        // The same as removing every item and adding it back at its scaled bin weight.
+        for id in 0..1_000u64 {
+            let scaled = 1 + id % 999;
+            expected.remove(id, scaled as f64 / 1_000.0);
+            let rescaled = scaled * 7 / 10;
+            if rescaled > 0 {
+                expected.add(id, rescaled as f64 / 1_000.0);
             }
         }
+        assert_eq!(index.count(), 998);
+        assert_eq!(index.root.accumulated_value, expected.root.accumulated_value);
+        assert_eq!(index.state_hash(), expected.state_hash());
+        assert!(index.check_mass().is_ok());
 
-        /// Create a DigitBinIndex with RoaringBitmap bins and the specified precision.
-        #[staticmethod]
-        fn medium(precision: u8) -> Self {
-            PyDigitBinIndex {
-                index: DigitBinIndex::medium(precision),
+        // Exact weights are rescaled themselves, and move as a fresh `add` would bin them.
+        let mut index = DigitBinIndexGeneric::<Vec<u32>>::with_precision(2);
+        index.enable_exact_weights();
+        index.add(1, 0.257);
+        index.add(2, 0.9);
+        assert_eq!(index.scale_all_weights(2.0), vec![(2, 0.9)]);
+        assert_eq!(index.get_weight(1), Some(0.514));
+        assert_eq!(index.select_and_remove(), Some((1, 0.514)));
+        assert!(index.check_mass().is_ok());
+    }
+
+    #[test]
+    fn test_quantize_and_bin_inspection() {
+        let mut rng = WyRand::seed_from_u64(8);
+        for mut index in [DigitBinIndex::small(3), DigitBinIndex::medium(3), DigitBinIndex::large(3), DigitBinIndex::counted(3)] {
+            for id in 0..2_000u64 {
+                let weight: f64 = rng.random_range(0.0005..0.9995);
+                let mut single = DigitBinIndex::with_precision(3);
+                single.add(0, weight);
+                assert_eq!(index.quantize(weight), single.select().map(|(_, binned)| binned), "weight {weight}");
+                index.add(id, weight);
+            }
+            for (weight, count, members) in index.bins() {
+                assert_eq!(index.quantize(weight), Some(weight));
+                assert_eq!(index.bin_count(weight), count);
+                assert!(index.bin_members(weight).unwrap().eq(members));
             }
+            assert_eq!(index.quantize(1.0), None);
+            assert_eq!(index.bin_count(0.0001), 0);
+            assert!(index.bin_members(f64::NAN).is_none());
         }
+    }
 
-        /// Create a DigitBinIndex with RoaringTreemap bins and the specified precision.
-        #[staticmethod]
-        fn large(precision: u8) -> Self {
-            PyDigitBinIndex {
-                index: DigitBinIndex::large(precision),
+    #[test]
+    fn test_wallenius_inclusion_matches_simulation() {
+        let mut index = DigitBinIndex::with_precision(2);
+        index.add_range(0..40, 0.1);
+        index.add_range(40..60, 0.3);
+        index.add_range(60..70, 0.9);
+        let bins = crate::wallenius::inclusion_probabilities(&index, 30).unwrap();
+        assert_eq!(bins.len(), 3);
+        assert!((bins.iter().map(|bin| bin.expected).sum::<f64>() - 30.0).abs() < 1e-9);
+        assert!(crate::wallenius::inclusion_probabilities(&index, 71).is_none());
+        assert!(crate::wallenius::inclusion_probabilities(&index, 0).unwrap().iter().all(|bin| bin.probability == 0.0));
+        assert!(crate::wallenius::inclusion_probabilities(&index, 70).unwrap().iter().all(|bin| bin.probability == 1.0));
+
+        let mut rng = WyRand::seed_from_u64(11);
+        let runs = 2_000;
+        let mut drawn = [0u64; 3];
+        for _ in 0..runs {
+            let mut copy = index.clone();
+            for _ in 0..30 {
+                let (id, _) = copy.select_and_remove_with_rng(&mut rng).unwrap();
+                drawn[if id < 40 { 0 } else if id < 60 { 1 } else { 2 }] += 1;
             }
         }
+        for (bin, &count) in bins.iter().zip(&drawn) {
+            let simulated = count as f64 / runs as f64;
+            assert!((simulated - bin.expected).abs() < 0.02 * bin.count as f64, "{simulated} vs {}", bin.expected);
+        }
+    }
+
+    #[test]
+    fn test_select_many_ordered() {
+        // With one heavy item among light ones, its position in the draw order follows
+        // repeated single draws: first with probability 0.9 / (0.9 + 0.1) = 0.9.
+        let mut rng = WyRand::seed_from_u64(23);
+        let mut first = 0;
+        for _ in 0..2000 {
+            let mut index = DigitBinIndex::with_precision(3);
+            index.add_range(0..100, 0.001);
+            index.add(100, 0.9);
+            let DigitBinIndex::Small(generic) = &mut index else { unreachable!() };
+            let drawn = generic.select_many_ordered_and_optionally_remove_with_rng(20, true, &mut rng).unwrap();
+            assert_eq!(drawn.len(), 20);
+            first += (drawn[0].0 == 100) as u32;
+        }
+        assert!((1750..1850).contains(&first), "{first}");
 
-        fn add(&mut self, id: u64, weight: f64) {
-            self.index.add(id, weight)
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(2);
+            index.add_range(0..50, 0.1);
+            index.add_range(50..60, 0.5);
+            let kept = index.select_many_ordered(60).unwrap();
+            let mut ids: Vec<u64> = kept.iter().map(|&(id, _)| id).collect();
+            ids.sort_unstable();
+            assert_eq!(ids, (0..60).collect::<Vec<_>>());
+            assert_eq!(index.count(), 60);
+            assert_eq!(index.select_many_ordered_and_remove(25).unwrap().len(), 25);
+            assert_eq!(index.count(), 35);
+            assert!(index.check_mass().is_ok());
         }
+    }
 
-        fn add_many(&mut self, items: Vec<(u64, f64)>) {
-            self.index.add_many(&items);
+    #[test]
+    fn test_shuffle_iter() {
+        // The heavy item leads the permutation with probability 0.8 / (0.8 + 10 * 0.02) = 0.8.
+        let mut rng = WyRand::seed_from_u64(31);
+        let mut first = 0;
+        for _ in 0..2000 {
+            let mut index = DigitBinIndex::with_precision(2);
+            index.add_range(0..10, 0.02);
+            index.add(10, 0.8);
+            let mut shuffle = index.shuffle_iter(&mut rng);
+            assert_eq!(shuffle.len(), 11);
+            first += (shuffle.next().unwrap().0 == 10) as u32;
         }
+        assert!((1550..1650).contains(&first), "{first}");
 
-        fn remove(&mut self, id: u64, weight: f64) -> bool {
-            self.index.remove(id, weight)
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            let mut index = build(3);
+            index.add_range(0..200, 0.125);
+            index.add_range(200..300, 0.5);
+            let mut shuffle = index.shuffle_iter(WyRand::seed_from_u64(2));
+            let mut ids: Vec<u64> = shuffle.by_ref().take(100).map(|(id, _)| id).collect();
+            let rest = shuffle.into_inner();
+            assert_eq!(rest.count(), 200);
+            ids.extend(rest.shuffle_iter(WyRand::seed_from_u64(3)).map(|(id, _)| id));
+            ids.sort_unstable();
+            assert_eq!(ids, (0..300).collect::<Vec<_>>());
         }
+    }
 
-        fn remove_many(&mut self, items: Vec<(u64, f64)>) -> bool {
-            self.index.remove_many(&items)
-        }        
+    #[test]
+    fn test_remove_ids_and_grouped_remove_many() {
+        for build in [DigitBinIndex::small, DigitBinIndex::medium, DigitBinIndex::large, DigitBinIndex::counted] {
+            for registry in [false, true] {
+                let mut index = build(3);
+                if registry {
+                    index.enable_exact_weights();
+                }
+                index.add_range(0..1000, 0.125);
+                index.add_range(1000..2000, 0.5);
+                index.add_range(2000..2100, 0.999);
+                index.mark();
 
-        fn select(&mut self) -> Option<(u64, f64)> {
-            self.index.select()
+                let losers: RoaringTreemap = (0..2100).filter(|id| id % 3 == 0).chain([5000]).collect();
+                assert_eq!(index.remove_ids(&losers), 700);
+                assert_eq!(index.count(), 1400);
+                assert!(index.check_mass().is_ok());
+                assert!(stored_weight(&index, 999).is_none() && stored_weight(&index, 998).is_some());
+                assert_eq!(index.removed_since_mark().unwrap().ids.len(), 700);
+
+                let items: Vec<(u64, f64)> = (2000..2100).filter(|id| id % 3 != 0).map(|id| (id, 0.999)).collect();
+                assert!(index.remove_many(&items));
+                assert!(!index.remove_many(&[(1, 0.125), (2, 0.5)]));
+                assert!(!index.remove_many(&[(4, 1.5)]));
+                assert_eq!(index.count(), 1400 - items.len() as u64 - 1);
+                assert!(index.check_mass().is_ok());
+                assert_eq!(index.get_weight(2001), None);
+            }
         }
+    }
+
+    #[test]
+    fn test_plan_with_copies() {
+        let mut cohort = DigitBinIndex::counted(3);
+        cohort.add_with_count(1, 0.5, 10);
+        let plan = cohort.plan_select_many(2).unwrap();
+        assert!(plan.draws.iter().all(|draw| draw.id == 1 && draw.bin == 500));
+        assert_eq!(cohort.apply(&plan).unwrap(), vec![(1, 0.5); 2]);
+        assert_eq!(cohort.count(), 8);
+        assert_eq!(cohort.plan_select_many(8).unwrap().draws.len(), 8);
+
+        let mut duplicates = DigitBinIndex::small(2);
+        duplicates.add(7, 0.25);
+        duplicates.add(7, 0.25);
+        duplicates.add(8, 0.5);
+        let plan = duplicates.plan_select_many(3).unwrap();
+        assert_eq!(plan.draws.iter().filter(|draw| draw.id == 7).count(), 2);
+        assert_eq!(duplicates.apply(&plan).unwrap().len(), 3);
+        assert_eq!(duplicates.count(), 0);
+    }
 
-        fn select_many(&mut self, n: u64) -> Option<Vec<(u64, f64)>> {
-            self.index.select_many(n)
+    #[test]
+    fn test_u32_backends_refuse_wider_ids() {
+        let wide = u32::MAX as u64 + 5;
+        for mut index in [DigitBinIndex::small(2), DigitBinIndex::medium(2)] {
+            index.add(4, 0.5);
+            // A truncated ID would be 4.
+            assert!(!index.contains(wide));
+            assert!(!index.remove(wide, 0.5));
+            assert_eq!(index.try_add(wide, 0.5), Err(DigitBinIndexError::IdOutOfRange { id: wide, max: u32::MAX as u64 }));
+            assert_eq!(index.count(), 1);
+            // The infallible insertions skip such IDs rather than panic.
+            assert!(!index.add(wide, 0.5));
+            assert_eq!(index.add_with_count(wide, 0.5, 3), 0);
+            assert_eq!(index.add_range(u32::MAX as u64..wide, 0.5), 0);
+            index.add_many(&[(wide, 0.5), (5, 0.5)]);
+            index.extend([(wide + 1, 0.25)]);
+            assert_eq!(index.count(), 2);
         }
+    }
 
-        fn select_and_remove(&mut self) -> Option<(u64, f64)> {
-            self.index.select_and_remove()
+    #[test]
+    fn test_lottery_policies() {
+        let mut index = DigitBinIndex::small(2);
+        index.add_with_count(1, 0.9, 5);
+        index.add_many(&[(2, 0.1), (3, 0.1), (4, 0.1)]);
+
+        // One win per entrant, however many entries they hold.
+        let outcome = index.clone().lottery(4, LotteryRules::new());
+        let mut winners: Vec<u64> = outcome.winners.iter().map(|&(id, _)| id).collect();
+        winners.sort();
+        assert_eq!(winners, vec![1, 2, 3, 4]);
+
+        // Consuming entries lets the heavy entrant win with each of their copies.
+        let outcome = index.clone().lottery(8, LotteryRules::new().duplicates(DuplicatePolicy::MultipleWins).remove_winners(true));
+        assert_eq!(outcome.winners.iter().filter(|&&(id, _)| id == 1).count(), 5);
+        assert!(outcome.trail.iter().all(|draw| draw.audit.removed));
+
+        // Nobody eligible: the lottery gives up after the re-draw limit.
+        let outcome = index.lottery(1, LotteryRules::new().eligibility(|_, _| false).max_redraws(2));
+        assert!(outcome.exhausted && outcome.winners.is_empty());
+        assert_eq!(outcome.trail.len(), 3);
+        assert_eq!(index.count(), 8);
+
+        // Fewer entrants than winners requested.
+        let outcome = index.lottery(10, LotteryRules::new().exclude([1, 2]).remove_winners(true));
+        assert!(outcome.exhausted);
+        assert_eq!(outcome.winners.len(), 2);
+        assert_eq!(index.count(), 6);
+    }
+
+    #[test]
+    fn test_select_not_in_distribution() {
+        let mut index = DigitBinIndex::large(1);
+        index.add_many(&[(1, 0.1), (2, 0.3), (3, 0.3), (4, 0.6), (5, 0.6)]);
+        // Excluding one of each pair leaves masses 0.1 : 0.3 : 0.6.
+        let excluded: RoaringTreemap = [3, 5].into_iter().collect();
+
+        let mut counts = [0u32; 6];
+        for _ in 0..20_000 {
+            let (id, _) = index.select_not_in(&excluded).unwrap();
+            counts[id as usize] += 1;
+        }
+        assert_eq!(counts[3] + counts[5], 0);
+        for (id, expected) in [(1, 0.1), (2, 0.3), (4, 0.6)] {
+            let observed = counts[id] as f64 / 20_000.0;
+            assert!((observed - expected).abs() < 0.02, "ID {} drawn {:.3}", id, observed);
         }
 
-        fn select_many_and_remove(&mut self, n: u64) -> Option<Vec<(u64, f64)>> {
-            self.index.select_many_and_remove(n)
+        let mut drained = Vec::new();
+        while let Some((id, _)) = index.select_and_remove_not_in(&excluded) {
+            drained.push(id);
         }
+        drained.sort_unstable();
+        assert_eq!(drained, vec![1, 2, 4]);
+        assert_eq!(index.count(), 2);
+    }
 
-        fn total_weight(&self) -> f64 {
-            self.index.total_weight()
+    #[test]
+    fn test_removed_since_mark() {
+        let mut index = DigitBinIndex::with_precision(2);
+        index.add_many(&[(1, 0.1), (2, 0.1), (3, 0.3), (4, 0.5), (5, 0.7), (6, 0.9)]);
+        assert!(index.removed_since_mark().is_none());
+        index.remove(6, 0.9);
+
+        index.mark();
+        assert!(index.update_weight(1, 0.1, 0.2));
+        assert!(index.removed_since_mark().unwrap().ids.is_empty());
+
+        index.remove(2, 0.1);
+        let (picked, _) = index.select_and_remove().unwrap();
+        index.prune_bins(|weight, _| weight > 0.6);
+        let tick = index.removed_since_mark().unwrap();
+        assert!(tick.ids.contains(2) && tick.ids.contains(picked));
+        assert!(tick.ids.contains(5) || picked == 5);
+        let removed_count: u64 = tick.bins.iter().map(|b| b.count).sum();
+        assert_eq!(removed_count, tick.ids.len());
+        assert!(tick.bins.windows(2).all(|w| w[0].weight < w[1].weight));
+
+        // A new mark starts a fresh tick.
+        index.mark();
+        let rest: Vec<u64> = index.drain().map(|(id, _)| id).collect();
+        let tick = index.removed_since_mark().unwrap();
+        assert_eq!(tick.ids.iter().collect::<Vec<_>>(), {
+            let mut rest = rest;
+            rest.sort_unstable();
+            rest
+        });
+    }
+
+    #[test]
+    fn test_snapshot_version_probe_and_migrate() {
+        let mut index = DigitBinIndex::counted(2);
+        index.add_with_count(3, 0.5, 10);
+        let mut current = Vec::new();
+        index.save_to(&mut current).unwrap();
+        assert_eq!(snapshot::probe_version(current.as_slice()).unwrap(), snapshot::CURRENT_VERSION);
+        let mut bytes = Vec::new();
+        snapshot::write_version(&index, &mut bytes, 1).unwrap();
+        assert_eq!(snapshot::probe_version(bytes.as_slice()).unwrap(), 1);
+        assert_eq!(DigitBinIndex::load_from(bytes.as_slice()).unwrap().count(), 10);
+
+        let mut migrated = Vec::new();
+        assert_eq!(snapshot::migrate(bytes.as_slice(), &mut migrated).unwrap(), 1);
+        assert_eq!(snapshot::probe_version(migrated.as_slice()).unwrap(), snapshot::CURRENT_VERSION);
+        assert_eq!(DigitBinIndex::load_from(migrated.as_slice()).unwrap().count(), 10);
+        assert_eq!(migrated, current);
+
+        // Snapshots from a newer release are refused rather than misread.
+        let mut future = bytes.clone();
+        future[4] = 0xff;
+        assert_eq!(
+            snapshot::migrate(future.as_slice(), &mut Vec::new()),
+            Err(DigitBinIndexError::UnsupportedVersion { version: 0xff })
+        );
+        assert!(matches!(
+            snapshot::probe_version(&b"DBI"[..]),
+            Err(DigitBinIndexError::CorruptSnapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_streams_from_reader() {
+        // Hands out at most three bytes per call, like a slow socket.
+        struct Trickle<'a>(&'a [u8]);
+        impl std::io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(3).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
         }
+        let mut small = DigitBinIndex::small(2);
+        small.add_many(&[(4, 0.5), (4, 0.5), (9, 0.01)]);
+        let mut medium = DigitBinIndex::medium(4);
+        medium.add_range(0..50_000, 0.0101);
+        let mut counted = DigitBinIndex::counted(3);
+        counted.add_with_count(7, 0.125, 1_000_000);
+        for index in [small, medium, counted, DigitBinIndex::large(2)] {
+            let mut bytes = Vec::new();
+            index.write_to(&mut bytes).unwrap();
+            let restored = DigitBinIndex::read_from(Trickle(&bytes)).unwrap();
+            assert_eq!(restored.state_hash(), index.state_hash());
+            assert_eq!(DigitBinIndex::load_from_unverified(Trickle(&bytes)).unwrap().count(), index.count());
 
-        fn count(&self) -> u64 {
-            self.index.count()
+            let mut longer = bytes.clone();
+            longer.push(0);
+            assert!(DigitBinIndex::read_from(longer.as_slice()).is_err());
+            assert!(matches!(
+                DigitBinIndex::load_from_unverified(longer.as_slice()),
+                Err(DigitBinIndexError::CorruptSnapshot { .. })
+            ));
+            for cut in [1, 4, 5, bytes.len() / 2] {
+                assert!(DigitBinIndex::read_from(&bytes[..bytes.len() - cut]).is_err());
+                assert!(DigitBinIndex::load_from_unverified(&bytes[..bytes.len() - cut]).is_err());
+            }
         }
 
-        fn print_stats(&self) {
-            self.index.print_stats();
+        // A bin count that claims more bins than the section holds is caught by the checksums.
+        let mut index = DigitBinIndex::new();
+        index.add(1, 0.5);
+        let mut bytes = Vec::new();
+        index.write_to(&mut bytes).unwrap();
+        bytes[22] ^= 0xff;
+        assert!(matches!(
+            DigitBinIndex::read_from(bytes.as_slice()),
+            Err(DigitBinIndexError::ChecksumMismatch { section: "file", .. })
+        ));
+        assert!(matches!(
+            DigitBinIndex::load_from_unverified(bytes.as_slice()),
+            Err(DigitBinIndexError::CorruptSnapshot { .. })
+        ));
+
+        // Unchecked lengths claiming a terabyte end at the end of the input, not in an allocation.
+        let mut bytes = Vec::new();
+        index.write_to(&mut bytes).unwrap();
+        bytes[14..22].copy_from_slice(&u64::MAX.to_le_bytes());
+        bytes[38..46].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(DigitBinIndex::read_from(bytes.as_slice()).is_err());
+        assert!(matches!(
+            DigitBinIndex::load_from_unverified(bytes.as_slice()),
+            Err(DigitBinIndexError::CorruptSnapshot { .. })
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_refuses_corrupt_fields() {
+        // Recomputes every checksum, as a snapshot written with the corruption would carry them.
+        fn reseal(bytes: &mut [u8]) {
+            let n = bytes.len();
+            // Version 4 added the radix to the header.
+            let header = if u16::from_le_bytes([bytes[4], bytes[5]]) >= 4 { 10 } else { 8 };
+            let crc = crc32fast::hash(&bytes[..header]);
+            bytes[header..header + 4].copy_from_slice(&crc.to_le_bytes());
+            // The bins section, then the state section if there is one before the trailer.
+            let mut start = header + 4;
+            while start < n - 4 {
+                let len = u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap()) as usize;
+                let end = start + 8 + len;
+                let crc = crc32fast::hash(&bytes[start + 8..end]);
+                bytes[end..end + 4].copy_from_slice(&crc.to_le_bytes());
+                start = end + 4;
+            }
+            let crc = crc32fast::hash(&bytes[..n - 4]);
+            bytes[n - 4..].copy_from_slice(&crc.to_le_bytes());
         }
+        let refused = |bytes: &[u8]| {
+            assert!(matches!(DigitBinIndex::load_from(bytes), Err(DigitBinIndexError::CorruptSnapshot { .. })));
+            assert!(matches!(DigitBinIndex::load_from_unverified(bytes), Err(DigitBinIndexError::CorruptSnapshot { .. })));
+        };
+
+        // A cohort whose copies overflow the item count: the header (14), payload length,
+        // bin count, weight, bin length and ID come before the copies field.
+        let mut counted = DigitBinIndex::counted(3);
+        counted.add_with_count(7, 0.125, 3);
+        let mut bytes = Vec::new();
+        counted.save_to(&mut bytes).unwrap();
+        let copies = 14 + 8 + 8 + 8 + 8 + 8;
+        bytes[copies..copies + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(DigitBinIndex::load_from(bytes.as_slice()).is_err());
+        reseal(&mut bytes);
+        refused(&bytes);
+
+        // The same in a version 1 snapshot, where entries are inserted one by one.
+        let mut old = Vec::new();
+        snapshot::write_version(&counted, &mut old, 1).unwrap();
+        let copies = 12 + 8 + 8 + 8 + 8 + 8;
+        old[copies..copies + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        reseal(&mut old);
+        refused(&old);
+
+        // A 64-bit ID in a version 1 snapshot relabelled as the u32 Small backend.
+        let mut large = DigitBinIndex::large(2);
+        large.add(1 << 40, 0.5);
+        let mut old = Vec::new();
+        snapshot::write_version(&large, &mut old, 1).unwrap();
+        old[6] = 0;
+        reseal(&mut old);
+        refused(&old);
+
+        // Version 1 Small entries never carry more than one copy.
+        let mut small = DigitBinIndex::small(2);
+        small.add(3, 0.5);
+        let mut old = Vec::new();
+        snapshot::write_version(&small, &mut old, 1).unwrap();
+        let copies = 12 + 8 + 8 + 8 + 8 + 8;
+        old[copies..copies + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        reseal(&mut old);
+        refused(&old);
     }
 
-    #[pymodule]
-    fn digit_bin_index(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
-        m.add_class::<PyDigitBinIndex>()?;
-        Ok(())
+    // Builds an index with every kind of side state: settings, a schedule in progress,
+    // pending changes, tracked removals, exact weights and group tags.
+    fn index_with_state() -> DigitBinIndex {
+        let mut index = DigitBinIndex::small(3);
+        index.set_memory_budget(Some(1 << 20));
+        index.set_tie_break(TieBreak::LastChild);
+        index.set_auto_repair(true);
+        index.enable_exact_weights();
+        index.add_with_group(1, 0.1234, 7);
+        index.add_with_group(2, 0.5, 9);
+        index.add_many(&[(3, 0.25), (4, 0.75)]);
+        index.at_tick(5, 3, 0.5);
+        index.at_tick(9, 4, 0.125);
+        index.advance_to(2);
+        index.mark();
+        assert!(index.remove(4, 0.75));
+        index.add(4, 0.75);
+        for _ in 0..3 {
+            index.next_scheduled();
+        }
+        index
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn assert_same_state(restored: &mut DigitBinIndex, index: &mut DigitBinIndex) {
+        assert_eq!(restored.memory_budget(), index.memory_budget());
+        assert_eq!(restored.tie_break(), index.tie_break());
+        assert_eq!(restored.auto_repair(), index.auto_repair());
+        assert_eq!(restored.get_weight(1), Some(0.1234));
+        assert_eq!(restored.group_of(1), Some(7));
+        assert_eq!(restored.group_count(9), 1);
+        let removed = |i: &DigitBinIndex| i.removed_since_mark().map(|r| (r.ids, r.bins));
+        assert_eq!(removed(restored), removed(index));
+        let schedule = |i: &mut DigitBinIndex| (0..8).map(|_| i.next_scheduled()).collect::<Vec<_>>();
+        assert_eq!(schedule(restored), schedule(index));
+        assert_eq!(restored.advance_to(10), index.advance_to(10));
+        assert_eq!(restored.get_weight(3), Some(0.5));
+        assert_eq!(restored.get_weight(4), Some(0.125));
+    }
 
     #[test]
-    fn test_select_and_remove() {
-        let mut index = DigitBinIndex::with_precision(3);
-        index.add(1, 0.085);
-        index.add(2, 0.205);
-        index.add(3, 0.346);
-        index.add(4, 0.364);
-        index.print_stats();
-        println!("Initial state: {} individuals, total weight = {}", index.count(), index.total_weight());    
-        if let Some((id, weight)) = index.select_and_remove() {
-            println!("Selected ID: {} with weight: {}", id, weight);
-        }
-        assert!(
-            index.count() == 3,
-            "The count is now {} and not 3 as expected",
-            index.count()
-        );
-        println!("Intermediate state: {} individuals, total weight = {}", index.count(), index.total_weight()); 
-        if let Some(selection) = index.select_many_and_remove(2) {
-            println!("Selection: {:?}", selection);
+    fn test_snapshot_keeps_state() {
+        let mut index = index_with_state();
+        let mut bytes = Vec::new();
+        index.save_to(&mut bytes).unwrap();
+        let mut restored = DigitBinIndex::load_from(bytes.as_slice()).unwrap();
+        assert_same_state(&mut restored, &mut index);
+
+        // A state section that does not decode is refused.
+        let index = index_with_state();
+        let mut bytes = Vec::new();
+        index.save_to(&mut bytes).unwrap();
+        // The state payload follows the header (14) and the length, bins and checksum of
+        // the bins section; its first byte flags the memory budget.
+        let bins_len = u64::from_le_bytes(bytes[14..22].try_into().unwrap()) as usize;
+        let state = 14 + 8 + bins_len + 4 + 8;
+        bytes[state] = 7;
+        assert!(DigitBinIndex::load_from(bytes.as_slice()).is_err());
+        assert!(matches!(DigitBinIndex::load_from_unverified(bytes.as_slice()), Err(DigitBinIndexError::CorruptSnapshot { .. })));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_keeps_state() {
+        let mut index = index_with_state();
+        let json = serde_json::to_string(&index).unwrap();
+        let mut restored: DigitBinIndex = serde_json::from_str(&json).unwrap();
+        assert_same_state(&mut restored, &mut index);
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip_and_checksums() {
+        let mut counted = DigitBinIndex::counted(3);
+        counted.add_with_count(7, 0.125, 1_000_000);
+        counted.add(8, 0.5);
+        let mut medium = DigitBinIndex::medium(4);
+        medium.add_range(0..10_000, 0.0101);
+        let mut small = DigitBinIndex::small(2);
+        small.add_many(&[(4, 0.5), (4, 0.5), (u32::MAX as u64, 0.99)]);
+        let mut large = DigitBinIndex::large(9);
+        large.add_many(&[(u64::MAX, 0.000000001), (1 << 40, 0.5)]);
+        for index in [counted, medium, small, large, DigitBinIndex::small(2)] {
+            let mut bytes = Vec::new();
+            index.save_to(&mut bytes).unwrap();
+            let mut old = Vec::new();
+            snapshot::write_version(&index, &mut old, 1).unwrap();
+            for bytes in [bytes, old] {
+                let restored = DigitBinIndex::load_from(bytes.as_slice()).unwrap();
+                assert_eq!(std::mem::discriminant(&restored), std::mem::discriminant(&index));
+                assert_eq!(restored.precision(), index.precision());
+                assert_eq!(restored.count(), index.count());
+                assert_eq!(restored.total_weight(), index.total_weight());
+                assert_eq!(restored.state_hash(), index.state_hash());
+                let bins = |i: &DigitBinIndex| i.bins().map(|(w, c, m)| (w, c, m.collect::<Vec<_>>())).collect::<Vec<_>>();
+                assert_eq!(bins(&restored), bins(&index));
+            }
         }
-        assert!(
-            index.count() == 1,
-            "The count is now {} and not 1 as expected",
-            index.count()
-        );
-        println!("Final state: {} individuals, total weight = {}", index.count(), index.total_weight()); 
+
+        // Compact bins: roaring bins take a fraction of the 16 bytes per ID of version 1.
+        let mut medium = DigitBinIndex::medium(4);
+        medium.add_range(0..100_000, 0.0101);
+        let (mut bytes, mut old) = (Vec::new(), Vec::new());
+        medium.save_to(&mut bytes).unwrap();
+        snapshot::write_version(&medium, &mut old, 1).unwrap();
+        assert!(bytes.len() * 50 < old.len(), "{} vs {}", bytes.len(), old.len());
+        // A bin whose contents do not decode is refused even without checksums: flip the
+        // first byte after the header (14), payload length (8), bin count, weight and length.
+        let bin_start = 14 + 8 + 8 + 8 + 8;
+        bytes[bin_start] ^= 0xff;
+        assert!(matches!(
+            DigitBinIndex::load_from_unverified(bytes.as_slice()),
+            Err(DigitBinIndexError::CorruptSnapshot { .. })
+        ));
+
+        let mut index = DigitBinIndex::new();
+        index.add_many(&[(1, 0.1), (2, 0.2)]);
+        let mut bytes = Vec::new();
+        index.save_to(&mut bytes).unwrap();
+
+        // Any flipped bit is caught by the whole-file checksum.
+        let mut corrupted = bytes.clone();
+        corrupted[46] ^= 0x01;
+        assert!(matches!(
+            DigitBinIndex::load_from(corrupted.as_slice()),
+            Err(DigitBinIndexError::ChecksumMismatch { section: "file", .. })
+        ));
+        // Skipping verification loads the silently wrong data.
+        assert!(DigitBinIndex::load_from_unverified(corrupted.as_slice()).is_ok());
+
+        assert!(matches!(
+            DigitBinIndex::load_from(&bytes[..bytes.len() - 3]),
+            Err(DigitBinIndexError::ChecksumMismatch { .. }) | Err(DigitBinIndexError::CorruptSnapshot { .. })
+        ));
+        assert!(matches!(
+            DigitBinIndex::load_from(&b"nope"[..]),
+            Err(DigitBinIndexError::ChecksumMismatch { .. }) | Err(DigitBinIndexError::CorruptSnapshot { .. })
+        ));
     }
 
     #[test]
@@ -1596,6 +11675,77 @@ mod tests {
             avg_high_risk, fishers_mean
         );
     }
+
+    #[test]
+    fn test_radix_builds_the_same_index() {
+        let build = |radix: u16| {
+            let mut index = DigitBinIndex::with_config(Config::new().precision(4).radix(radix));
+            for id in 0..2_000u64 {
+                index.add(id, ((id * 37) % 9_999 + 1) as f64 / 10_000.0);
+            }
+            index
+        };
+        let bins = |i: &DigitBinIndex| i.bins().map(|(w, c, m)| (w, c, m.collect::<Vec<_>>())).collect::<Vec<_>>();
+        let decimal = build(10);
+        for radix in [2, 3, 16, 256] {
+            let mut index = build(radix);
+            assert_eq!(index.radix(), radix);
+            assert_eq!(index.count(), decimal.count());
+            assert_eq!(index.total_weight(), decimal.total_weight());
+            assert_eq!(bins(&index), bins(&decimal));
+            assert!(index.check_mass().is_ok());
+            // 9999 needs 14 binary digits, 9 ternary, 4 hexadecimal and 2 in base 256.
+            let depth = match radix { 2 => 14, 3 => 9, 16 => 4, _ => 2 };
+            assert_eq!(index.stats().nodes_per_depth.len(), depth + 1);
+            assert_eq!(index.stats().radix, radix);
+
+            // Draws walk the bins in the same weight order, so a seed draws the same items.
+            let mut expected = decimal.clone();
+            let (mut a, mut b) = (WyRand::seed_from_u64(radix as u64), WyRand::seed_from_u64(radix as u64));
+            assert_eq!(index.select_with_rng(&mut a), expected.select_with_rng(&mut b));
+            assert_eq!(index.select_in_range(0.25, 0.25), expected.select_in_range(0.25, 0.25));
+            assert!(index.update_weight(5, 0.0186, 0.5));
+            assert!(expected.update_weight(5, 0.0186, 0.5));
+            assert!(index.remove(6, 0.0223));
+            assert!(expected.remove(6, 0.0223));
+            assert_eq!(index.heaviest(3), expected.heaviest(3));
+            assert_eq!(bins(&index), bins(&expected));
+
+            // Coarsening keeps the radix, and merging relays the other tree out in it.
+            let mut coarse = index.clone();
+            assert_eq!(coarse.coarsen_to(2), expected.clone().coarsen_to(2));
+            assert_eq!(coarse.radix(), radix);
+            assert!(coarse.check_mass().is_ok());
+            index.merge(build(10)).unwrap();
+            assert_eq!(index.count(), 2 * decimal.count() - 1);
+            assert!(index.check_mass().is_ok());
+
+            // Snapshots carry the radix.
+            let mut bytes = Vec::new();
+            index.save_to(&mut bytes).unwrap();
+            let restored = DigitBinIndex::load_from(bytes.as_slice()).unwrap();
+            assert_eq!(restored.radix(), radix);
+            assert_eq!(bins(&restored), bins(&index));
+            #[cfg(feature = "serde")]
+            {
+                let restored: DigitBinIndex = serde_json::from_str(&serde_json::to_string(&index).unwrap()).unwrap();
+                assert_eq!(restored.radix(), radix);
+                assert_eq!(bins(&restored), bins(&index));
+            }
+
+            let mut rng = WyRand::seed_from_u64(7);
+            let drawn = index.select_many_and_remove_with_rng(index.count(), &mut rng).unwrap();
+            assert_eq!(drawn.len() as u64, 2 * decimal.count() - 1);
+            assert_eq!(index.count(), 0);
+        }
+        assert_eq!(
+            DigitBinIndex::try_with_config(Config::new().radix(257)).unwrap_err(),
+            DigitBinIndexError::RadixOutOfRange { radix: 257 }
+        );
+        // A radix other than 10 needs a snapshot format that records it.
+        let mut bytes = Vec::new();
+        assert!(snapshot::write_version(&build(16), &mut bytes, 3).is_err());
+    }
 }
 
 #[cfg(test)]
@@ -1605,11 +11755,11 @@ fn test_weight_to_digits() {
     let index = DigitBinIndexGeneric::<Vec<u32>>::with_precision(3);
 
     // Test valid weight
-    let mut digits = [0u8; MAX_PRECISION];
+    let mut digits = [0u8; MAX_DEPTH];
     if let Some(scaled) = index.weight_to_digits(0.123, &mut digits) {    
         assert_eq!(scaled, 123);
         assert_eq!(digits[0..3], [1, 2, 3]);
-        assert_eq!(digits[3..], [0; 6]); // Remaining digits should be zero-padded
+        assert_eq!(digits[3..], [0; MAX_DEPTH - 3]); // Remaining digits should be zero-padded
     } else {
         panic!("Expected Some for valid weight");
     }
@@ -0,0 +1,143 @@
+//! Rules and results for [`DigitBinIndex::lottery`](crate::DigitBinIndex::lottery).
+//!
+//! A lottery draws `k` winners by weight, one audited draw at a time. The
+//! [`LotteryRules`] decide who may be drawn at all (forced exclusions), whether an
+//! entrant holding several entries may win more than once, which drawn entrants
+//! are ineligible and must be re-drawn, and whether winning entries are consumed.
+//! Every draw, including the ones that were re-drawn, ends up in the returned
+//! audit trail.
+
+use roaring::RoaringTreemap;
+
+use crate::DrawAudit;
+
+/// How an entrant with several entries (copies, or entries in several bins) is treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// An entrant wins at most once: after winning, all of their entries are excluded.
+    #[default]
+    SingleWin,
+    /// Every entry can win. Unless winners are removed, the same entry can win again.
+    MultipleWins,
+}
+
+/// The rules of a lottery.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{DuplicatePolicy, LotteryRules};
+///
+/// let rules = LotteryRules::new()
+///     .duplicates(DuplicatePolicy::SingleWin)
+///     .exclude([13, 42])
+///     .eligibility(|id, _weight| id % 2 == 0)
+///     .max_redraws(100)
+///     .remove_winners(true);
+/// ```
+pub struct LotteryRules<'a> {
+    pub(crate) duplicates: DuplicatePolicy,
+    pub(crate) excluded: RoaringTreemap,
+    pub(crate) eligible: Option<Box<dyn FnMut(u64, f64) -> bool + 'a>>,
+    pub(crate) max_redraws: u64,
+    pub(crate) remove_winners: bool,
+}
+
+impl<'a> LotteryRules<'a> {
+    /// One win per entrant, no exclusions, everyone eligible, at most 1,000 re-draws,
+    /// and winners stay in the index.
+    #[must_use]
+    pub fn new() -> Self {
+        LotteryRules {
+            duplicates: DuplicatePolicy::default(),
+            excluded: RoaringTreemap::new(),
+            eligible: None,
+            max_redraws: 1_000,
+            remove_winners: false,
+        }
+    }
+
+    /// Sets how entrants with several entries are treated.
+    #[must_use]
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Excludes entrants from the draw altogether. Can be called repeatedly.
+    #[must_use]
+    pub fn exclude(mut self, ids: impl IntoIterator<Item = u64>) -> Self {
+        self.excluded.extend(ids);
+        self
+    }
+
+    /// Sets a check run on every drawn `(id, weight)`. Entrants for which it returns
+    /// `false` are recorded as ineligible, excluded, and re-drawn.
+    #[must_use]
+    pub fn eligibility(mut self, eligible: impl FnMut(u64, f64) -> bool + 'a) -> Self {
+        self.eligible = Some(Box::new(eligible));
+        self
+    }
+
+    /// Caps the number of ineligible draws before the lottery gives up.
+    #[must_use]
+    pub fn max_redraws(mut self, max_redraws: u64) -> Self {
+        self.max_redraws = max_redraws;
+        self
+    }
+
+    /// Whether each winning entry is removed from the index.
+    #[must_use]
+    pub fn remove_winners(mut self, remove: bool) -> Self {
+        self.remove_winners = remove;
+        self
+    }
+}
+
+impl Default for LotteryRules<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for LotteryRules<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LotteryRules")
+            .field("duplicates", &self.duplicates)
+            .field("excluded", &self.excluded)
+            .field("eligibility", &self.eligible.is_some())
+            .field("max_redraws", &self.max_redraws)
+            .field("remove_winners", &self.remove_winners)
+            .finish()
+    }
+}
+
+/// What happened to one drawn entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LotteryDecision {
+    Won,
+    /// The eligibility check rejected the entrant, who was excluded and re-drawn.
+    Ineligible,
+}
+
+/// One draw of a lottery: the audit record and the decision taken on it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LotteryDraw {
+    pub audit: DrawAudit,
+    pub decision: LotteryDecision,
+}
+
+/// The result of a lottery.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LotteryOutcome {
+    /// The winning `(id, weight)` entries, in the order they were drawn.
+    pub winners: Vec<(u64, f64)>,
+    /// Every draw, winning or not, in order.
+    pub trail: Vec<LotteryDraw>,
+    /// Whether the lottery stopped short of `k` winners, because no eligible entry was
+    /// left or the re-draw limit was reached.
+    pub exhausted: bool,
+}
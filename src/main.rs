@@ -0,0 +1,175 @@
+//! Command-line sampling with a `DigitBinIndex`.
+//!
+//! Reads `id,weight` rows from a CSV file or stdin, draws from them, and writes the drawn
+//! `id,weight` rows as CSV, in draw order for the sequential mode. For example:
+//!
+//! ```text
+//! digit-bin-index sample --input weights.csv --draws 1000 --mode wallenius --seed 42 --precision 4
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+use digit_bin_index::DigitBinIndex;
+use rand::{Rng, SeedableRng};
+use wyrand::WyRand;
+
+const USAGE: &str = "\
+Usage: digit-bin-index sample --draws <N> [options]
+
+Draws N unique items, without replacement, from id,weight rows.
+
+Options:
+  --input <FILE>       Read id,weight rows from FILE instead of stdin. A header row is skipped.
+  --output <FILE>      Write the drawn id,weight rows to FILE instead of stdout.
+  --draws <N>          The number of items to draw.
+  --mode <MODE>        wallenius: one item at a time, reported in draw order (default).
+                       fisher: all items at once, as in select_many_and_remove.
+  --seed <SEED>        Seed the random number generator, for reproducible draws.
+  --precision <P>      The number of decimal places weights are binned by, 1 to 9 (default 3).
+  -h, --help           Print this help.";
+
+/// How the draws are made.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    /// Repeated single draws with removal, each seeing the removals before it.
+    Wallenius,
+    /// One batch draw with removal.
+    Fisher,
+}
+
+#[derive(Debug)]
+struct Options {
+    input: Option<String>,
+    output: Option<String>,
+    draws: u64,
+    mode: Mode,
+    seed: Option<u64>,
+    precision: u8,
+}
+
+/// Why the tool stopped: bad arguments are reported with the usage text.
+enum Failure {
+    Usage(String),
+    Run(String),
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, Failure> {
+    match args.next().as_deref() {
+        Some("sample") => {}
+        Some("-h" | "--help") | None => return Ok(None),
+        Some(other) => return Err(Failure::Usage(format!("unknown command '{other}'"))),
+    }
+    let mut options = Options { input: None, output: None, draws: 0, mode: Mode::Wallenius, seed: None, precision: 3 };
+    let mut draws = None;
+    while let Some(flag) = args.next() {
+        if flag == "-h" || flag == "--help" {
+            return Ok(None);
+        }
+        let value = args.next().ok_or_else(|| Failure::Usage(format!("{flag} needs a value")))?;
+        let invalid = || Failure::Usage(format!("invalid value '{value}' for {flag}"));
+        match flag.as_str() {
+            "--input" => options.input = Some(value),
+            "--output" => options.output = Some(value),
+            "--draws" => draws = Some(value.parse().map_err(|_| invalid())?),
+            "--mode" => {
+                options.mode = match value.as_str() {
+                    "wallenius" => Mode::Wallenius,
+                    "fisher" => Mode::Fisher,
+                    _ => return Err(invalid()),
+                }
+            }
+            "--seed" => options.seed = Some(value.parse().map_err(|_| invalid())?),
+            "--precision" => options.precision = value.parse().map_err(|_| invalid())?,
+            _ => return Err(Failure::Usage(format!("unknown option '{flag}'"))),
+        }
+    }
+    options.draws = draws.ok_or_else(|| Failure::Usage("--draws is required".to_string()))?;
+    Ok(Some(options))
+}
+
+/// Adds the `id,weight` rows of `reader` to `index`. Blank rows are skipped, and so is a
+/// first row that does not parse, as a header.
+fn load(index: &mut DigitBinIndex, reader: impl BufRead) -> Result<(), String> {
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| format!("cannot read input: {err}"))?;
+        let row = line.trim();
+        if row.is_empty() {
+            continue;
+        }
+        let parsed = row.split_once(',').and_then(|(id, weight)| Some((id.trim().parse::<u64>().ok()?, weight.trim().parse::<f64>().ok()?)));
+        let Some((id, weight)) = parsed else {
+            if number == 0 {
+                continue;
+            }
+            return Err(format!("line {}: expected 'id,weight', found '{row}'", number + 1));
+        };
+        index.try_add(id, weight).map_err(|err| format!("line {}: {err}", number + 1))?;
+    }
+    Ok(())
+}
+
+fn draw<R: Rng>(index: &mut DigitBinIndex, options: &Options, rng: &mut R) -> Result<Vec<(u64, f64)>, String> {
+    if options.draws > index.count() {
+        return Err(format!("cannot draw {} items from {}", options.draws, index.count()));
+    }
+    let drawn = match options.mode {
+        Mode::Wallenius => (0..options.draws).map(|_| index.select_and_remove_with_rng(rng)).collect(),
+        Mode::Fisher => index.select_many_and_remove_with_rng(options.draws, rng),
+    };
+    drawn.ok_or_else(|| "the remaining items have no weight to draw by".to_string())
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut index = DigitBinIndex::try_with_precision(options.precision).map_err(|err| err.to_string())?;
+    // Report the weights as given, not the bins they were truncated to.
+    index.enable_exact_weights();
+    match &options.input {
+        Some(path) => {
+            let file = File::open(path).map_err(|err| format!("cannot open '{path}': {err}"))?;
+            load(&mut index, BufReader::new(file))?;
+        }
+        None => load(&mut index, io::stdin().lock())?,
+    }
+
+    let drawn = match options.seed {
+        Some(seed) => draw(&mut index, options, &mut WyRand::seed_from_u64(seed))?,
+        None => draw(&mut index, options, &mut WyRand::from_os_rng())?,
+    };
+
+    let mut output: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(File::create(path).map_err(|err| format!("cannot create '{path}': {err}"))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut output = BufWriter::new(&mut output);
+    let written: io::Result<()> = (|| {
+        writeln!(output, "id,weight")?;
+        for (id, weight) in drawn {
+            writeln!(output, "{id},{weight}")?;
+        }
+        output.flush()
+    })();
+    written.map_err(|err| format!("cannot write output: {err}"))
+}
+
+fn main() -> ExitCode {
+    let result = parse_options(std::env::args().skip(1)).and_then(|options| match options {
+        Some(options) => run(&options).map_err(Failure::Run),
+        None => {
+            println!("{USAGE}");
+            Ok(())
+        }
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(message)) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(Failure::Run(message)) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
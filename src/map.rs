@@ -0,0 +1,144 @@
+//! An index that carries a value for every ID.
+//!
+//! In agent-based models a drawn ID is usually joined straight back to the
+//! agent's state. [`DigitBinMap`] keeps that state next to the index, keyed by
+//! ID together with the ID's exact weight, so a draw returns the value directly
+//! and removing an item needs only its ID. Adding, moving and removing go
+//! through the map, so the index and the values cannot drift apart. Each ID
+//! holds one weight and one value at a time.
+
+use std::collections::HashMap;
+
+use rand::SeedableRng;
+use wyrand::WyRand;
+
+use crate::{Config, DigitBinIndex};
+
+/// A `DigitBinIndex` whose items carry a value of type `V`.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{Config, DigitBinMap};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Agent { age: u32 }
+///
+/// let mut agents = DigitBinMap::new(Config::new().precision(2));
+/// agents.insert(1, 0.2, Agent { age: 30 });
+/// agents.insert(2, 0.7, Agent { age: 45 });
+/// agents.get_mut(1).unwrap().age += 1;
+///
+/// assert_eq!(agents.remove(2), Some((0.7, Agent { age: 45 })));
+/// assert_eq!(agents.select_and_remove(), Some((1, 0.2, Agent { age: 31 })));
+/// assert_eq!(agents.count(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DigitBinMap<V> {
+    index: DigitBinIndex,
+    entries: HashMap<u64, (f64, V)>,
+}
+
+impl<V> DigitBinMap<V> {
+    /// Creates an empty map built from `config`.
+    pub fn new(config: Config) -> Self {
+        DigitBinMap { index: DigitBinIndex::with_config(config), entries: HashMap::new() }
+    }
+
+    /// Adds an ID with its weight and value, or replaces both if the ID is already present.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the index cannot store the weight, in which case nothing changes.
+    pub fn insert(&mut self, id: u64, weight: f64, value: V) -> bool {
+        let stored = match self.entries.get(&id) {
+            Some(&(old, _)) => self.index.update_weight(id, old, weight),
+            None => self.index.add_with_count(id, weight, 1) > 0,
+        };
+        if stored {
+            self.entries.insert(id, (weight, value));
+        }
+        stored
+    }
+
+    /// Changes the weight of an ID and keeps its value.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the ID is absent or the index cannot store the weight.
+    pub fn set_weight(&mut self, id: u64, weight: f64) -> bool {
+        let Some((old, _)) = self.entries.get_mut(&id) else { return false };
+        let stored = self.index.update_weight(id, *old, weight);
+        if stored {
+            *old = weight;
+        }
+        stored
+    }
+
+    /// Removes an ID and returns its exact weight and value, or `None` if it was not present.
+    pub fn remove(&mut self, id: u64) -> Option<(f64, V)> {
+        let (weight, value) = self.entries.remove(&id)?;
+        self.index.remove(id, weight);
+        Some((weight, value))
+    }
+
+    /// Returns the value of an ID.
+    pub fn get(&self, id: u64) -> Option<&V> {
+        self.entries.get(&id).map(|(_, value)| value)
+    }
+
+    /// Returns the value of an ID for modification. The weight is changed with
+    /// [`set_weight`](Self::set_weight).
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut V> {
+        self.entries.get_mut(&id).map(|(_, value)| value)
+    }
+
+    /// Returns the exact weight of an ID.
+    pub fn weight(&self, id: u64) -> Option<f64> {
+        self.entries.get(&id).map(|&(weight, _)| weight)
+    }
+
+    /// Selects an ID by weight without removal, returning its exact weight and its value.
+    pub fn select(&self) -> Option<(u64, f64, &V)> {
+        let (id, _) = self.index.select_with_rng(&mut WyRand::from_os_rng())?;
+        let (weight, value) = &self.entries[&id];
+        Some((id, *weight, value))
+    }
+
+    /// Selects and removes an ID by weight, returning its exact weight and its value.
+    pub fn select_and_remove(&mut self) -> Option<(u64, f64, V)> {
+        let (id, _) = self.index.select_and_remove()?;
+        let (weight, value) = self.entries.remove(&id).expect("selected IDs are in the map");
+        Some((id, weight, value))
+    }
+
+    /// Selects and removes `num_to_draw` unique IDs by weight, returning their exact
+    /// weights and values. See [`DigitBinIndex::select_many_and_remove`].
+    pub fn select_many_and_remove(&mut self, num_to_draw: u64) -> Option<Vec<(u64, f64, V)>> {
+        let selected = self.index.select_many_and_remove(num_to_draw)?;
+        Some(
+            selected
+                .into_iter()
+                .map(|(id, _)| {
+                    let (weight, value) = self.entries.remove(&id).expect("selected IDs are in the map");
+                    (id, weight, value)
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the number of IDs.
+    pub fn count(&self) -> u64 {
+        self.index.count()
+    }
+
+    /// Returns an iterator over the IDs with their exact weights and values, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, f64, &V)> {
+        self.entries.iter().map(|(&id, (weight, value))| (id, *weight, value))
+    }
+
+    /// Returns the index, for draws and queries that do not change it.
+    pub fn index(&self) -> &DigitBinIndex {
+        &self.index
+    }
+}
@@ -0,0 +1,54 @@
+//! The index as a [`Distribution`] from the `rand` crate.
+//!
+//! [`Sampler`] borrows a non-empty index and draws from it without removal, so
+//! it plugs into anything written against `rand`: `sample_iter`, generic code
+//! taking `impl Distribution<T>`, or a distribution stored next to others. The
+//! RNG is always supplied by the caller.
+
+use rand::distr::Distribution;
+use rand::Rng;
+
+use crate::DigitBinIndex;
+
+/// A borrowed, non-empty index that samples `(id, weight)` pairs by weight.
+///
+/// Created with [`DigitBinIndex::sampler`]. Each sample is a
+/// [`select_with_rng`](DigitBinIndex::select_with_rng) draw.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::DigitBinIndex;
+/// use rand::distr::Distribution;
+/// use rand::SeedableRng;
+/// use wyrand::WyRand;
+///
+/// let mut index = DigitBinIndex::new();
+/// index.add(1, 0.2);
+/// index.add(2, 0.6);
+///
+/// let mut rng = WyRand::seed_from_u64(42);
+/// let ones = index.sampler().unwrap().sample_iter(&mut rng).take(10_000).filter(|&(id, _)| id == 1).count();
+/// assert!((ones as f64 / 10_000.0 - 0.25).abs() < 0.03);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Sampler<'a> {
+    index: &'a DigitBinIndex,
+}
+
+impl<'a> Sampler<'a> {
+    pub(crate) fn new(index: &'a DigitBinIndex) -> Option<Self> {
+        (index.count() > 0).then_some(Sampler { index })
+    }
+
+    /// Returns the index being sampled.
+    pub fn index(&self) -> &'a DigitBinIndex {
+        self.index
+    }
+}
+
+impl Distribution<(u64, f64)> for Sampler<'_> {
+    fn sample<R: Rng + ?Sized>(&self, mut rng: &mut R) -> (u64, f64) {
+        self.index.select_with_rng(&mut rng).expect("a sampler only borrows a non-empty index")
+    }
+}
@@ -0,0 +1,127 @@
+//! A read-only, thread-shared sampling handle.
+//!
+//! `SharedSampler` publishes a finished `DigitBinIndex` through an `ArcSwap` so
+//! that any number of threads can draw from it concurrently without locking.
+//! Each thread uses its own `WyRand`, seeded from the OS on first use. Mutation
+//! is not possible through the handle; to pick up new weights, build a fresh
+//! index and [`swap`](SharedSampler::swap) it in. Readers never block and
+//! every draw sees either the old or the new index in full.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use rand::SeedableRng;
+use wyrand::WyRand;
+
+use crate::DigitBinIndex;
+
+thread_local! {
+    static THREAD_RNG: RefCell<WyRand> = RefCell::new(WyRand::from_os_rng());
+}
+
+/// A cheaply cloneable, read-only handle for concurrent non-destructive selection.
+///
+/// Clones share the same published index, so a [`swap`](Self::swap) through any
+/// clone is seen by all of them.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{DigitBinIndex, SharedSampler};
+/// use std::thread;
+///
+/// let mut index = DigitBinIndex::new();
+/// index.add(1, 0.2);
+/// index.add(2, 0.8);
+/// let sampler = SharedSampler::new(index);
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let sampler = sampler.clone();
+///         thread::spawn(move || sampler.select().unwrap().0)
+///     })
+///     .collect();
+/// for handle in handles {
+///     let id = handle.join().unwrap();
+///     assert!(id == 1 || id == 2);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedSampler {
+    index: Arc<ArcSwap<DigitBinIndex>>,
+}
+
+impl SharedSampler {
+    /// Freezes an index into a shared sampler.
+    pub fn new(index: DigitBinIndex) -> Self {
+        Self::from(Arc::new(index))
+    }
+
+    /// Selects a single item without removal, using this thread's RNG.
+    pub fn select(&self) -> Option<(u64, f64)> {
+        let index = self.index.load();
+        THREAD_RNG.with(|rng| index.select_with_rng(&mut *rng.borrow_mut()))
+    }
+
+    /// Selects a single item without removal, using the given RNG.
+    pub fn select_with_rng<R: rand::Rng>(&self, rng: &mut R) -> Option<(u64, f64)> {
+        self.index.load().select_with_rng(rng)
+    }
+
+    /// Returns the currently published index for read-only inspection.
+    pub fn index(&self) -> Arc<DigitBinIndex> {
+        self.index.load_full()
+    }
+
+    /// Atomically publishes a rebuilt index to all readers.
+    ///
+    /// Draws already in flight finish against the previous index; every draw
+    /// that starts afterwards sees the new one.
+    ///
+    /// # Returns
+    ///
+    /// The previously published index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use digit_bin_index::{DigitBinIndex, SharedSampler};
+    ///
+    /// let mut index = DigitBinIndex::new();
+    /// index.add(1, 0.5);
+    /// let sampler = SharedSampler::new(index);
+    /// let reader = sampler.clone();
+    ///
+    /// let mut rebuilt = DigitBinIndex::new();
+    /// rebuilt.add(2, 0.5);
+    /// let previous = sampler.swap(rebuilt);
+    /// assert_eq!(previous.count(), 1);
+    /// assert_eq!(reader.select(), Some((2, 0.5)));
+    /// ```
+    pub fn swap(&self, new_index: DigitBinIndex) -> Arc<DigitBinIndex> {
+        self.index.swap(Arc::new(new_index))
+    }
+
+    /// Returns the number of items in the underlying index.
+    pub fn count(&self) -> u64 {
+        self.index.load().count()
+    }
+
+    /// Returns the total weight of the underlying index.
+    pub fn total_weight(&self) -> f64 {
+        self.index.load().total_weight()
+    }
+}
+
+impl From<DigitBinIndex> for SharedSampler {
+    fn from(index: DigitBinIndex) -> Self {
+        Self::new(index)
+    }
+}
+
+impl From<Arc<DigitBinIndex>> for SharedSampler {
+    fn from(index: Arc<DigitBinIndex>) -> Self {
+        Self { index: Arc::new(ArcSwap::new(index)) }
+    }
+}
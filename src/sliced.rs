@@ -0,0 +1,160 @@
+//! Time-sliced versions of long-running operations.
+//!
+//! Loading millions of items or drawing a huge batch can take long enough to stall
+//! a host that has a frame or tick deadline. The jobs here do the same work in
+//! bounded slices: each call to `resume` handles at most `budget` items and then
+//! returns, keeping its progress in the job so the next call continues where the
+//! last one stopped. The index stays fully usable between slices.
+
+use rand::SeedableRng;
+use wyrand::WyRand;
+
+use crate::DigitBinIndex;
+
+/// The result of running one slice of a job.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slice<T> {
+    /// The budget ran out; call `resume` again to continue.
+    Paused,
+    /// The job is complete.
+    Finished(T),
+}
+
+impl<T> Slice<T> {
+    /// Returns `true` if the job is complete.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Slice::Finished(_))
+    }
+}
+
+/// A bulk load of `(id, weight)` items, done in slices with `add_many`.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{BulkLoad, DigitBinIndex, Slice};
+///
+/// let mut index = DigitBinIndex::with_precision(3);
+/// let mut load = BulkLoad::new((0..10_000).map(|id| (id, 0.5)).collect());
+/// let mut slices = 1;
+/// while load.resume(&mut index, 1_000) == Slice::Paused {
+///     slices += 1; // run the rest of the tick here
+/// }
+/// assert_eq!(slices, 10);
+/// assert_eq!(index.count(), 10_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BulkLoad {
+    items: Vec<(u64, f64)>,
+    next: usize,
+}
+
+impl BulkLoad {
+    /// Prepares a load of `items`. Nothing is added until [`resume`](Self::resume) is called.
+    pub fn new(items: Vec<(u64, f64)>) -> Self {
+        BulkLoad { items, next: 0 }
+    }
+
+    /// Returns the number of items not yet added.
+    pub fn remaining(&self) -> usize {
+        self.items.len() - self.next
+    }
+
+    /// Adds up to `budget` more items to `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is 0.
+    pub fn resume(&mut self, index: &mut DigitBinIndex, budget: usize) -> Slice<()> {
+        assert!(budget > 0, "A slice needs a budget of at least one item.");
+        let end = self.items.len().min(self.next + budget);
+        index.add_many(&self.items[self.next..end]);
+        self.next = end;
+        if self.remaining() == 0 { Slice::Finished(()) } else { Slice::Paused }
+    }
+}
+
+/// A large batch draw, done in slices.
+///
+/// Each slice is a batch draw of up to `budget` items from what the index holds at
+/// that moment, so a removing draw sees the removals of earlier slices and its
+/// items are unique across slices. Without removal, each slice draws independently.
+/// Changes made to the index between slices are seen by the following slices.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{BatchDraw, DigitBinIndex, Slice};
+///
+/// let mut index = DigitBinIndex::with_precision(3);
+/// index.add_range(0..10_000, 0.5);
+///
+/// let mut draw = BatchDraw::select_many_and_remove(2_500);
+/// let drawn = loop {
+///     match draw.resume(&mut index, 1_000).unwrap() {
+///         Slice::Paused => continue, // run the rest of the tick here
+///         Slice::Finished(drawn) => break drawn,
+///     }
+/// };
+/// assert_eq!(drawn.len(), 2_500);
+/// assert_eq!(index.count(), 7_500);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BatchDraw {
+    remaining: u64,
+    with_removal: bool,
+    drawn: Vec<(u64, f64)>,
+    rng: WyRand,
+}
+
+impl BatchDraw {
+    /// Prepares a draw of `num_to_draw` items without removal, like
+    /// [`select_many`](DigitBinIndex::select_many).
+    pub fn select_many(num_to_draw: u64) -> Self {
+        Self::new(num_to_draw, false)
+    }
+
+    /// Prepares a draw of `num_to_draw` items with removal, like
+    /// [`select_many_and_remove`](DigitBinIndex::select_many_and_remove).
+    pub fn select_many_and_remove(num_to_draw: u64) -> Self {
+        Self::new(num_to_draw, true)
+    }
+
+    fn new(num_to_draw: u64, with_removal: bool) -> Self {
+        BatchDraw {
+            remaining: num_to_draw,
+            with_removal,
+            drawn: Vec::with_capacity(num_to_draw as usize),
+            rng: WyRand::from_os_rng(),
+        }
+    }
+
+    /// Returns the number of items not yet drawn.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Draws up to `budget` more items from `index`.
+    ///
+    /// # Returns
+    ///
+    /// [`Slice::Finished`] with all drawn items once the draw is complete, or `None`
+    /// if the index holds fewer items than this slice needs. A failed slice draws
+    /// nothing, so it can be retried after adding items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is 0.
+    pub fn resume(&mut self, index: &mut DigitBinIndex, budget: u64) -> Option<Slice<Vec<(u64, f64)>>> {
+        assert!(budget > 0, "A slice needs a budget of at least one item.");
+        let batch = self.remaining.min(budget);
+        let drawn = if self.with_removal {
+            index.select_many_and_remove_with_rng(batch, &mut self.rng)?
+        } else {
+            index.select_many_with_rng(batch, &mut self.rng)?
+        };
+        self.drawn.extend(drawn);
+        self.remaining -= batch;
+        Some(if self.remaining == 0 { Slice::Finished(std::mem::take(&mut self.drawn)) } else { Slice::Paused })
+    }
+}
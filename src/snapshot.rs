@@ -0,0 +1,464 @@
+//! Binary snapshot format with integrity checks.
+//!
+//! Snapshots are written with [`DigitBinIndex::save_to`] and read back with
+//! [`DigitBinIndex::load_from`]. This module adds version probing and migration
+//! so that checkpoints written by older releases can still be opened.
+//!
+//! Layout (all integers little-endian):
+//!
+//! ```text
+//! header   magic "DBIX" | version u16 | backend u8 | precision u8 | radix u16 | crc32 u32
+//! bins     payload length u64 | payload | crc32 u32
+//! state    payload length u64 | payload | crc32 u32    (version 3 and later)
+//! trailer  crc32 u32 over every preceding byte
+//! ```
+//!
+//! The radix is in the header from version 4 on; older snapshots load with radix 10.
+//!
+//! The bins payload is a bin count u64 followed, for each non-empty bin, by its
+//! scaled weight u64 and its members:
+//!
+//! * Version 1: an entry count u64 and that many `(id u64, copies u64)` pairs.
+//! * Version 2: a byte length u64 and the bin in its own compact form
+//!   ([`DigitBin::encode`]): `u32` IDs for `Small`, the portable roaring format
+//!   for `Medium` and `Large`, and `(id, copies)` pairs for `Counted`. A loaded
+//!   bin is placed into the tree whole, so loading costs little more than
+//!   reading the file.
+//!
+//! The state payload holds everything else that shapes how the index behaves: its
+//! settings (memory budget, tie-break rule, auto-repair), the `next_scheduled` state,
+//! the clock and pending `at_tick` changes, tracked removals, and, if the ID registry
+//! is on, exact weights and group tags. The registry itself is rebuilt from the items.
+//! Snapshots of versions 1 and 2 have no state section and load with the defaults. Up to
+//! version 3 the state stores each round-robin slot's width as a `u8`, from version 4 on
+//! as a `u16`, since a radix of 256 gives 256 children.
+//!
+//! Snapshots are read as a stream, one bin at a time, with the checksums computed
+//! along the way: loading holds the index being built and a single encoded bin,
+//! never a copy of the whole file. Since a bin is placed before the section checksum
+//! can be compared, every field is checked as it is decoded (IDs against the backend,
+//! copy counts, and the totals the tree accumulates), so a corrupt snapshot is refused
+//! with an error, verified or not, instead of panicking.
+
+use std::io::{BufReader, ErrorKind, Read, Write};
+
+use roaring::{RoaringBitmap, RoaringTreemap};
+
+use crate::state::IndexState;
+use crate::{CountedBin, DigitBin, DigitBinIndex, DigitBinIndexError, DigitBinIndexGeneric, DEFAULT_RADIX, MAX_DEPTH, MAX_PRECISION, MAX_RADIX, MIN_RADIX};
+
+const MAGIC: &[u8; 4] = b"DBIX";
+/// The snapshot format version written by this build.
+pub const CURRENT_VERSION: u16 = 4;
+/// The header up to its checksum, without the radix that version 4 added.
+const HEADER_LEN: usize = 8;
+
+const SMALL: u8 = 0;
+const MEDIUM: u8 = 1;
+const LARGE: u8 = 2;
+const COUNTED: u8 = 3;
+
+pub(crate) fn write<W: Write>(index: &DigitBinIndex, writer: W) -> Result<(), DigitBinIndexError> {
+    write_version(index, writer, CURRENT_VERSION)
+}
+
+/// Writes a snapshot in the layout of an older `version`, for compatibility tests.
+pub(crate) fn write_version<W: Write>(index: &DigitBinIndex, mut writer: W, version: u16) -> Result<(), DigitBinIndexError> {
+    let (backend, payload, state) = match index {
+        DigitBinIndex::Small(index) => (SMALL, encode_bins(index, version), IndexState::of(index)),
+        DigitBinIndex::Medium(index) => (MEDIUM, encode_bins(index, version), IndexState::of(index)),
+        DigitBinIndex::Large(index) => (LARGE, encode_bins(index, version), IndexState::of(index)),
+        DigitBinIndex::Counted(index) => (COUNTED, encode_bins(index, version), IndexState::of(index)),
+    };
+    if version < 4 && index.radix() != DEFAULT_RADIX {
+        return Err(DigitBinIndexError::UnsupportedVersion { version });
+    }
+    let mut header = Vec::with_capacity(HEADER_LEN + 14);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&version.to_le_bytes());
+    header.push(backend);
+    header.push(index.precision());
+    if version >= 4 {
+        header.extend_from_slice(&index.radix().to_le_bytes());
+    }
+    let header_crc = crc32fast::hash(&header);
+    header.extend_from_slice(&header_crc.to_le_bytes());
+    header.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    let payload_crc = crc32fast::hash(&payload).to_le_bytes();
+    let mut parts = vec![header, payload, payload_crc.to_vec()];
+    if version >= 3 {
+        let mut state_payload = Vec::new();
+        state.encode(&mut state_payload, version);
+        let state_crc = crc32fast::hash(&state_payload).to_le_bytes();
+        parts.extend([(state_payload.len() as u64).to_le_bytes().to_vec(), state_payload, state_crc.to_vec()]);
+    }
+    let mut file = crc32fast::Hasher::new();
+    for part in &parts {
+        file.update(part);
+        writer.write_all(part)?;
+    }
+    writer.write_all(&file.finalize().to_le_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn read<R: Read>(reader: R, verify: bool) -> Result<DigitBinIndex, DigitBinIndexError> {
+    let mut stream = Stream::new(BufReader::new(reader));
+    let result = read_sections(&mut stream, verify);
+    if matches!(result, Err(DigitBinIndexError::Io { .. })) {
+        return result;
+    }
+    // Whatever follows the bins section should be the four-byte trailer.
+    if verify {
+        // The whole-file checksum covers every byte, so a mismatch explains any other failure.
+        let rest = stream.drain()?;
+        if let Some(stored) = stream.trailer() {
+            check("file", stored, stream.file.clone().finalize())?;
+        }
+        let index = result?;
+        return match rest {
+            4 => Ok(index),
+            0..4 => Err(corrupt("file is truncated")),
+            _ => Err(corrupt("trailing bytes after snapshot")),
+        };
+    }
+    let index = result?;
+    stream.fill(&mut [0; 4])?;
+    if stream.drain()? > 0 {
+        return Err(corrupt("trailing bytes after snapshot"));
+    }
+    Ok(index)
+}
+
+/// Reads the header, the bins and the state sections, stopping before the trailer.
+fn read_sections<R: Read>(stream: &mut Stream<R>, verify: bool) -> Result<DigitBinIndex, DigitBinIndexError> {
+    let mut header = vec![0u8; HEADER_LEN];
+    stream.fill(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(corrupt("not a DigitBinIndex snapshot"));
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    if !(1..=CURRENT_VERSION).contains(&version) {
+        return Err(DigitBinIndexError::UnsupportedVersion { version });
+    }
+    let (backend, precision) = (header[6], header[7]);
+    let radix = if version >= 4 {
+        let mut radix = [0u8; 2];
+        stream.fill(&mut radix)?;
+        header.extend_from_slice(&radix);
+        u16::from_le_bytes(radix)
+    } else {
+        DEFAULT_RADIX
+    };
+    let header_crc = stream.u32()?;
+    if verify {
+        check("header", header_crc, crc32fast::hash(&header))?;
+    }
+    if precision == 0 || precision as usize > MAX_PRECISION {
+        return Err(corrupt("precision out of range"));
+    }
+    if !(MIN_RADIX..=MAX_RADIX).contains(&radix) {
+        return Err(corrupt("radix out of range"));
+    }
+
+    let payload_len = stream.u64()?;
+    stream.section = Some((crc32fast::Hasher::new(), payload_len));
+    let mut index = match backend {
+        SMALL => DigitBinIndex::Small(decode_bins::<Vec<u32>, R>(precision, radix, stream, version, false)?),
+        MEDIUM => DigitBinIndex::Medium(decode_bins::<RoaringBitmap, R>(precision, radix, stream, version, false)?),
+        LARGE => DigitBinIndex::Large(decode_bins::<RoaringTreemap, R>(precision, radix, stream, version, false)?),
+        COUNTED => DigitBinIndex::Counted(decode_bins::<CountedBin, R>(precision, radix, stream, version, true)?),
+        _ => return Err(corrupt("unknown backend")),
+    };
+    let (payload_hash, _) = stream.section.take().expect("the bins section is open");
+    let payload_crc = stream.u32()?;
+    if verify {
+        check("bins", payload_crc, payload_hash.finalize())?;
+    }
+    if version < 3 {
+        return Ok(index);
+    }
+
+    let state_len = stream.u64()?;
+    stream.section = Some((crc32fast::Hasher::new(), state_len));
+    let state_payload = stream.take(usize::try_from(state_len).map_err(|_| corrupt("state is too large"))?)?;
+    let (state_hash, _) = stream.section.take().expect("the state section is open");
+    let state_crc = stream.u32()?;
+    if verify {
+        check("state", state_crc, state_hash.finalize())?;
+    }
+    let state = IndexState::decode(&state_payload, version).ok_or_else(|| corrupt("malformed state"))?;
+    match &mut index {
+        DigitBinIndex::Small(index) => state.restore(index)?,
+        DigitBinIndex::Medium(index) => state.restore(index)?,
+        DigitBinIndex::Large(index) => state.restore(index)?,
+        DigitBinIndex::Counted(index) => state.restore(index)?,
+    }
+    Ok(index)
+}
+
+/// Reads the format version of a snapshot without decoding it.
+///
+/// Only the first six bytes are consumed.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{snapshot, DigitBinIndex};
+///
+/// let mut bytes = Vec::new();
+/// DigitBinIndex::new().save_to(&mut bytes).unwrap();
+/// assert_eq!(snapshot::probe_version(bytes.as_slice()).unwrap(), snapshot::CURRENT_VERSION);
+/// ```
+pub fn probe_version<R: Read>(mut reader: R) -> Result<u16, DigitBinIndexError> {
+    let mut prefix = [0u8; 6];
+    reader.read_exact(&mut prefix).map_err(|_| corrupt("file is truncated"))?;
+    if &prefix[..4] != MAGIC {
+        return Err(corrupt("not a DigitBinIndex snapshot"));
+    }
+    Ok(u16::from_le_bytes([prefix[4], prefix[5]]))
+}
+
+/// Upgrades a snapshot of any supported version to [`CURRENT_VERSION`].
+///
+/// The snapshot is fully verified while being read, so a successful migration also
+/// guarantees an intact output.
+///
+/// # Returns
+///
+/// The version of the snapshot that was read.
+///
+/// # Examples
+///
+/// ```
+/// use digit_bin_index::{snapshot, DigitBinIndex};
+///
+/// let mut index = DigitBinIndex::new();
+/// index.add(1, 0.5);
+/// let mut old = Vec::new();
+/// index.save_to(&mut old).unwrap();
+///
+/// let mut upgraded = Vec::new();
+/// assert_eq!(snapshot::migrate(old.as_slice(), &mut upgraded).unwrap(), snapshot::CURRENT_VERSION);
+/// assert_eq!(DigitBinIndex::load_from(upgraded.as_slice()).unwrap().count(), 1);
+/// ```
+pub fn migrate<R: Read, W: Write>(mut reader: R, writer: W) -> Result<u16, DigitBinIndexError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let version = probe_version(bytes.as_slice())?;
+    let index = match version {
+        1..=CURRENT_VERSION => read(bytes.as_slice(), true)?,
+        _ => return Err(DigitBinIndexError::UnsupportedVersion { version }),
+    };
+    write(&index, writer)?;
+    Ok(version)
+}
+
+fn encode_bins<B: DigitBin>(index: &DigitBinIndexGeneric<B>, version: u16) -> Vec<u8> {
+    let mut bins = 0u64;
+    let mut out = vec![0; 8];
+    index.for_each_bin(|scaled, bin| {
+        if bin.is_empty() {
+            return;
+        }
+        bins += 1;
+        out.extend_from_slice(&scaled.to_le_bytes());
+        if version == 1 {
+            let mut entries = Vec::new();
+            bin.for_each_count(|id, copies| entries.push((id, copies)));
+            out.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+            for (id, copies) in entries {
+                out.extend_from_slice(&id.to_le_bytes());
+                out.extend_from_slice(&copies.to_le_bytes());
+            }
+        } else {
+            let start = out.len();
+            out.extend_from_slice(&[0; 8]);
+            bin.encode(&mut out);
+            let len = (out.len() - start - 8) as u64;
+            out[start..start + 8].copy_from_slice(&len.to_le_bytes());
+        }
+    });
+    out[..8].copy_from_slice(&bins.to_le_bytes());
+    out
+}
+
+/// Decodes the bins section. Every field is checked before it reaches the tree, so a
+/// corrupt snapshot is refused with an error whether or not the checksums are verified.
+/// `multiplicity` allows more than one copy per version 1 entry, which only the counted
+/// backend writes.
+fn decode_bins<B: DigitBin, R: Read>(
+    precision: u8,
+    radix: u16,
+    stream: &mut Stream<R>,
+    version: u16,
+    multiplicity: bool,
+) -> Result<DigitBinIndexGeneric<B>, DigitBinIndexError> {
+    let mut index = DigitBinIndexGeneric::<B>::with_precision_and_radix(precision, radix);
+    let bin_count = stream.u64()?;
+    let mut digits = [0u8; MAX_DEPTH];
+    // The root's item count and mass bound every node's, so checking them rules out overflow.
+    let (mut items, mut mass) = (0u64, 0u64);
+    let mut tally = |scaled: u64, copies: u64| -> Result<(), DigitBinIndexError> {
+        items = items.checked_add(copies).ok_or_else(|| corrupt("item count overflows"))?;
+        mass = scaled.checked_mul(copies).and_then(|bin| mass.checked_add(bin)).ok_or_else(|| corrupt("total weight overflows"))?;
+        Ok(())
+    };
+    for _ in 0..bin_count {
+        let scaled = stream.u64()?;
+        if !index.is_valid_scaled(scaled) {
+            return Err(corrupt("bin weight out of range"));
+        }
+        index.scaled_to_digits(scaled, &mut digits);
+        if version == 1 {
+            let entry_count = stream.u64()?;
+            let mut entries = Vec::new();
+            for _ in 0..entry_count {
+                let (id, copies) = (stream.u64()?, stream.u64()?);
+                if id > B::MAX_ID {
+                    return Err(corrupt("ID out of range"));
+                }
+                if copies == 0 || (copies > 1 && !multiplicity) {
+                    return Err(corrupt("invalid copy count"));
+                }
+                tally(scaled, copies)?;
+                entries.push((id, copies));
+            }
+            index.insert_at(scaled, &digits, |bin| entries.iter().map(|&(id, copies)| bin.insert_count(id, copies)).sum())?;
+        } else {
+            let len = usize::try_from(stream.u64()?).map_err(|_| corrupt("bin is too large"))?;
+            let loaded = B::decode(&stream.take(len)?).ok_or_else(|| corrupt("malformed bin"))?;
+            if loaded.is_empty() {
+                return Err(corrupt("empty bin"));
+            }
+            tally(scaled, loaded.len() as u64)?;
+            index.insert_at(scaled, &digits, |bin| {
+                let stored = loaded.len() as u64;
+                bin.merge(loaded);
+                stored
+            })?;
+        }
+    }
+    if stream.section.as_ref().is_some_and(|&(_, left)| left > 0) {
+        return Err(corrupt("trailing bytes in bins section"));
+    }
+    Ok(index)
+}
+
+fn check(section: &'static str, expected: u32, found: u32) -> Result<(), DigitBinIndexError> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(DigitBinIndexError::ChecksumMismatch { section, expected, found })
+    }
+}
+
+fn corrupt(reason: &str) -> DigitBinIndexError {
+    DigitBinIndexError::CorruptSnapshot { reason: reason.to_string() }
+}
+
+/// A snapshot being read front to back.
+///
+/// Every byte goes into the whole-file checksum, which trails the input by four bytes so
+/// that at the end it covers everything but the trailer. While the bins section is open,
+/// its bytes also go into the section's checksum, and reads past its end are refused.
+struct Stream<R> {
+    reader: R,
+    file: crc32fast::Hasher,
+    /// The last (up to) four bytes read, right-aligned, not yet in `file`.
+    lag: [u8; 4],
+    consumed: u64,
+    /// The open section's checksum so far and its remaining length.
+    section: Option<(crc32fast::Hasher, u64)>,
+}
+
+impl<R: Read> Stream<R> {
+    fn new(reader: R) -> Self {
+        Stream { reader, file: crc32fast::Hasher::new(), lag: [0; 4], consumed: 0, section: None }
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), DigitBinIndexError> {
+        if self.section.as_ref().is_some_and(|&(_, left)| buf.len() as u64 > left) {
+            return Err(corrupt("file is truncated"));
+        }
+        self.reader.read_exact(buf).map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => corrupt("file is truncated"),
+            _ => err.into(),
+        })?;
+        if let Some((hasher, left)) = &mut self.section {
+            hasher.update(buf);
+            *left -= buf.len() as u64;
+        }
+        self.hash(buf);
+        Ok(())
+    }
+
+    /// Reads `len` bytes; within a section, only as many as the section has left.
+    ///
+    /// The bytes are read in chunks and the buffer grows with what actually arrives, so a
+    /// corrupt length ends in an error at the end of the input, not in a huge allocation.
+    fn take(&mut self, len: usize) -> Result<Vec<u8>, DigitBinIndexError> {
+        if self.section.as_ref().is_some_and(|&(_, left)| len as u64 > left) {
+            return Err(corrupt("file is truncated"));
+        }
+        let mut bytes = Vec::new();
+        let mut chunk = [0u8; 8192];
+        while bytes.len() < len {
+            let n = (len - bytes.len()).min(chunk.len());
+            self.fill(&mut chunk[..n])?;
+            bytes.extend_from_slice(&chunk[..n]);
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, DigitBinIndexError> {
+        let mut bytes = [0; 4];
+        self.fill(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, DigitBinIndexError> {
+        let mut bytes = [0; 8];
+        self.fill(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads to the end of the input and returns the number of bytes that were left.
+    fn drain(&mut self) -> Result<u64, DigitBinIndexError> {
+        let mut chunk = [0u8; 8192];
+        let mut drained = 0;
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => return Ok(drained),
+                Ok(n) => {
+                    self.hash(&chunk[..n]);
+                    drained += n as u64;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// The last four bytes read, as the stored whole-file checksum, if there were four.
+    fn trailer(&self) -> Option<u32> {
+        (self.consumed >= 4).then(|| u32::from_le_bytes(self.lag))
+    }
+
+    fn hash(&mut self, bytes: &[u8]) {
+        let held = self.consumed.min(4) as usize;
+        if bytes.len() >= 4 {
+            self.file.update(&self.lag[4 - held..]);
+            self.file.update(&bytes[..bytes.len() - 4]);
+            self.lag.copy_from_slice(&bytes[bytes.len() - 4..]);
+        } else {
+            let mut joined = [0u8; 8];
+            let total = held + bytes.len();
+            joined[..held].copy_from_slice(&self.lag[4 - held..]);
+            joined[held..total].copy_from_slice(bytes);
+            let keep = total.min(4);
+            self.file.update(&joined[..total - keep]);
+            self.lag[4 - keep..].copy_from_slice(&joined[total - keep..total]);
+        }
+        self.consumed += bytes.len() as u64;
+    }
+}
@@ -0,0 +1,387 @@
+//! The bookkeeping an index keeps besides its items.
+//!
+//! Settings, the `next_scheduled` state, pending `at_tick` changes, tracked removals,
+//! exact weights and group tags all change how a restored index behaves, so snapshots,
+//! serde and pickles carry them as an [`IndexState`]. The ID registry's map from IDs
+//! to bins and the group pools are derived from the items and rebuilt on restore.
+
+use crate::{DigitBin, DigitBinIndexError, DigitBinIndexGeneric, Groups, IdRegistry, RemovalTracker, TieBreak};
+
+/// The removed IDs, and `(weight bits, copies)` per bin, since the last mark.
+type Removals = (Vec<u64>, Vec<(u64, u64)>);
+/// Exact weights as `(id, scaled weight, weight)`, and group tags as `(id, group)`.
+type Registry = (Option<Vec<(u64, u64, f64)>>, Option<Vec<(u64, u16)>>);
+
+/// Everything of an index but its items and precision, in a plain, portable form.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct IndexState {
+    memory_budget: Option<u64>,
+    tie_break: TieBreak,
+    auto_repair: bool,
+    clock: u64,
+    /// `(depth, digit prefix, cursor, children's current weights)` per round-robin slot.
+    schedule: Vec<(u8, u64, u64, Vec<i128>)>,
+    /// `(tick, id, new weight)` per registered change.
+    timeline: Vec<(u64, u64, f64)>,
+    removals: Option<Removals>,
+    registry: Option<Registry>,
+}
+
+/// Round-robin weights are bounded by a few times the total mass, which is a `u64`; a
+/// restored weight beyond this bound could overflow later updates.
+const MAX_CURRENT: i128 = (u64::MAX as i128) << 8;
+
+impl IndexState {
+    pub(crate) fn of<B: DigitBin>(index: &DigitBinIndexGeneric<B>) -> Self {
+        IndexState {
+            memory_budget: index.memory_budget.map(|budget| budget as u64),
+            tie_break: index.tie_break,
+            auto_repair: index.auto_repair,
+            clock: index.clock,
+            schedule: index
+                .schedule
+                .iter()
+                .map(|(&(depth, prefix), slot)| (depth, prefix, slot.cursor, slot.current.to_vec()))
+                .collect(),
+            timeline: index
+                .timeline
+                .iter()
+                .flat_map(|(&tick, changes)| changes.iter().map(move |&(id, weight)| (tick, id, weight)))
+                .collect(),
+            removals: index.removals.as_ref().map(|tracker| {
+                (tracker.ids.iter().collect(), tracker.bins.iter().map(|(&bits, &copies)| (bits, copies)).collect())
+            }),
+            registry: index.registry.as_ref().map(|registry| {
+                (
+                    registry.exact.as_ref().map(|exact| exact.iter().map(|(&(id, scaled), &weight)| (id, scaled, weight)).collect()),
+                    registry.groups.as_ref().map(|groups| groups.of.iter().map(|(&id, &group)| (id, group)).collect()),
+                )
+            }),
+        }
+    }
+
+    /// Applies the state to an index holding the restored items.
+    pub(crate) fn restore<B: DigitBin>(self, index: &mut DigitBinIndexGeneric<B>) -> Result<(), DigitBinIndexError> {
+        let corrupt = |reason: &str| DigitBinIndexError::CorruptSnapshot { reason: reason.to_string() };
+        index.memory_budget = self.memory_budget.map(|budget| usize::try_from(budget).unwrap_or(usize::MAX));
+        index.tie_break = self.tie_break;
+        index.auto_repair = self.auto_repair;
+        index.clock = self.clock;
+        index.schedule.clear();
+        for (depth, prefix, cursor, current) in self.schedule {
+            if current.len() > index.radix as usize || current.iter().any(|weight| weight.unsigned_abs() > MAX_CURRENT as u128) {
+                return Err(corrupt("invalid schedule"));
+            }
+            let slot = index.schedule.entry((depth, prefix)).or_default();
+            slot.current = current;
+            slot.cursor = cursor;
+        }
+        index.timeline.clear();
+        for (tick, id, weight) in self.timeline {
+            index.timeline.entry(tick).or_default().push((id, weight));
+        }
+        index.removals = self.removals.map(|(ids, bins)| RemovalTracker { ids: ids.into_iter().collect(), bins: bins.into_iter().collect() });
+        index.registry = None;
+        if let Some((exact, groups)) = self.registry {
+            // Enabling the registry fills in the bins and group pools of the restored items.
+            index.registry = Some(IdRegistry {
+                exact: exact.map(|exact| exact.into_iter().map(|(id, scaled, weight)| ((id, scaled), weight)).collect()),
+                groups: groups.map(|of| Groups::new(index.precision, index.radix, of.into_iter().collect())),
+                ..IdRegistry::default()
+            });
+            index.enable_id_registry();
+        }
+        Ok(())
+    }
+
+    /// Appends the state in the little-endian layout of the snapshot's state section.
+    /// Slot widths are a `u8` before snapshot version 4 and a `u16` from then on.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>, version: u16) {
+        match self.memory_budget {
+            Some(budget) => {
+                out.push(1);
+                out.extend_from_slice(&budget.to_le_bytes());
+            }
+            None => out.push(0),
+        }
+        out.push(match self.tie_break {
+            TieBreak::FirstChild => 0,
+            TieBreak::LastChild => 1,
+            TieBreak::Random => 2,
+        });
+        out.push(self.auto_repair as u8);
+        out.extend_from_slice(&self.clock.to_le_bytes());
+        out.extend_from_slice(&(self.schedule.len() as u64).to_le_bytes());
+        for (depth, prefix, cursor, current) in &self.schedule {
+            out.push(*depth);
+            out.extend_from_slice(&prefix.to_le_bytes());
+            out.extend_from_slice(&cursor.to_le_bytes());
+            if version >= 4 {
+                out.extend_from_slice(&(current.len() as u16).to_le_bytes());
+            } else {
+                out.push(current.len() as u8);
+            }
+            current.iter().for_each(|weight| out.extend_from_slice(&weight.to_le_bytes()));
+        }
+        out.extend_from_slice(&(self.timeline.len() as u64).to_le_bytes());
+        for (tick, id, weight) in &self.timeline {
+            for field in [*tick, *id, weight.to_bits()] {
+                out.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        out.push(self.removals.is_some() as u8);
+        if let Some((ids, bins)) = &self.removals {
+            out.extend_from_slice(&(ids.len() as u64).to_le_bytes());
+            ids.iter().for_each(|id| out.extend_from_slice(&id.to_le_bytes()));
+            out.extend_from_slice(&(bins.len() as u64).to_le_bytes());
+            for (bits, copies) in bins {
+                out.extend_from_slice(&bits.to_le_bytes());
+                out.extend_from_slice(&copies.to_le_bytes());
+            }
+        }
+        out.push(self.registry.is_some() as u8);
+        if let Some((exact, groups)) = &self.registry {
+            out.push(exact.is_some() as u8);
+            if let Some(exact) = exact {
+                out.extend_from_slice(&(exact.len() as u64).to_le_bytes());
+                for (id, scaled, weight) in exact {
+                    for field in [*id, *scaled, weight.to_bits()] {
+                        out.extend_from_slice(&field.to_le_bytes());
+                    }
+                }
+            }
+            out.push(groups.is_some() as u8);
+            if let Some(groups) = groups {
+                out.extend_from_slice(&(groups.len() as u64).to_le_bytes());
+                for (id, group) in groups {
+                    out.extend_from_slice(&id.to_le_bytes());
+                    out.extend_from_slice(&group.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    /// Reads the state written by [`encode`](Self::encode), or returns `None` if the
+    /// bytes are malformed or not used up exactly.
+    pub(crate) fn decode(bytes: &[u8], version: u16) -> Option<Self> {
+        let mut input = Input(bytes);
+        let input = &mut input;
+        let memory_budget = input.option(|input| input.u64())?;
+        let tie_break = match input.u8()? {
+            0 => TieBreak::FirstChild,
+            1 => TieBreak::LastChild,
+            2 => TieBreak::Random,
+            _ => return None,
+        };
+        let auto_repair = input.flag()?;
+        let clock = input.u64()?;
+        let schedule = input.list(|input| {
+            let (depth, prefix, cursor) = (input.u8()?, input.u64()?, input.u64()?);
+            let width = if version >= 4 { input.u16()? } else { u16::from(input.u8()?) };
+            let current = (0..width).map(|_| input.i128()).collect::<Option<_>>()?;
+            Some((depth, prefix, cursor, current))
+        })?;
+        let timeline = input.list(|input| Some((input.u64()?, input.u64()?, f64::from_bits(input.u64()?))))?;
+        let removals = input.option(|input| {
+            let ids = input.list(|input| input.u64())?;
+            let bins = input.list(|input| Some((input.u64()?, input.u64()?)))?;
+            Some((ids, bins))
+        })?;
+        let registry = input.option(|input| {
+            let exact = input.option(|input| input.list(|input| Some((input.u64()?, input.u64()?, f64::from_bits(input.u64()?)))))?;
+            let groups = input.option(|input| input.list(|input| Some((input.u64()?, input.u16()?))))?;
+            Some((exact, groups))
+        })?;
+        input.0.is_empty().then_some(IndexState { memory_budget, tie_break, auto_repair, clock, schedule, timeline, removals, registry })
+    }
+}
+
+/// The unread rest of an encoded state.
+struct Input<'a>(&'a [u8]);
+
+impl Input<'_> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[byte]| byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    fn i128(&mut self) -> Option<i128> {
+        self.bytes().map(i128::from_le_bytes)
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        if self.flag()? { read(self).map(Some) } else { Some(None) }
+    }
+
+    /// Reads a length-prefixed list. The list grows with the items actually read, so a
+    /// corrupt length runs out of input instead of allocating.
+    fn list<T>(&mut self, mut read: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.u64()?;
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(read(self)?);
+        }
+        Some(items)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use std::sync::Arc;
+
+    use super::IndexState;
+    use crate::{Arena, DigitBin, DigitBinIndexGeneric, Node, NodeContent, DEFAULT_RADIX, MAX_PRECISION, MAX_RADIX, MIN_RADIX};
+
+    /// The serialized form of an index: the tree, its precision and radix, and its state.
+    #[derive(Serialize)]
+    #[serde(rename = "DigitBinIndexGeneric")]
+    struct Saved<'a, B: DigitBin> {
+        root: SavedNode<'a, B>,
+        precision: u8,
+        radix: u16,
+        state: IndexState,
+    }
+
+    /// A node with its children written out in place, so the serialized tree is nested
+    /// rather than a copy of the arena.
+    #[derive(Serialize)]
+    #[serde(rename = "Node")]
+    struct SavedNode<'a, B: DigitBin> {
+        content: SavedContent<'a, B>,
+        accumulated_value: u64,
+        content_count: u64,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "NodeContent")]
+    enum SavedContent<'a, B: DigitBin> {
+        DigitIndex(SavedChildren<'a, B>),
+        Bin(&'a B),
+    }
+
+    struct SavedChildren<'a, B: DigitBin> {
+        children: &'a [Option<Node<B>>],
+        arena: &'a Arena<B>,
+    }
+
+    impl<'a, B: DigitBin> SavedNode<'a, B> {
+        fn of(node: &'a Node<B>, arena: &'a Arena<B>) -> Self {
+            let content = match &node.content {
+                &NodeContent::DigitIndex(block) => SavedContent::DigitIndex(SavedChildren { children: arena.block(block), arena }),
+                NodeContent::Bin(bin) => SavedContent::Bin(bin.as_ref()),
+            };
+            SavedNode { content, accumulated_value: node.accumulated_value, content_count: node.content_count }
+        }
+    }
+
+    impl<B: DigitBin + Serialize> Serialize for SavedChildren<'_, B> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.children.iter().map(|child| child.as_ref().map(|child| SavedNode::of(child, self.arena))))
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "Node")]
+    struct LoadedNode<B: DigitBin> {
+        content: LoadedContent<B>,
+        accumulated_value: u64,
+        content_count: u64,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "NodeContent")]
+    enum LoadedContent<B: DigitBin> {
+        DigitIndex(Vec<Option<LoadedNode<B>>>),
+        Bin(B),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "DigitBinIndexGeneric")]
+    struct Loaded<B: DigitBin> {
+        root: LoadedNode<B>,
+        precision: u8,
+        #[serde(default = "default_radix")]
+        radix: u16,
+        #[serde(default)]
+        state: IndexState,
+    }
+
+    fn default_radix() -> u16 {
+        DEFAULT_RADIX
+    }
+
+    /// Moves a loaded subtree into the arena, or returns `None` unless every internal node
+    /// has one slot per digit and the bins sit exactly `depth` levels down, so digit lookups
+    /// stay in bounds.
+    fn plant<B: DigitBin>(node: LoadedNode<B>, arena: &mut Arena<B>, depth: u8) -> Option<Node<B>> {
+        let content = match node.content {
+            LoadedContent::Bin(bin) if depth == 0 => NodeContent::Bin(Arc::new(bin)),
+            LoadedContent::DigitIndex(children) if depth > 0 && children.len() == arena.width => {
+                let block = arena.alloc();
+                for (digit, child) in children.into_iter().enumerate() {
+                    if let Some(child) = child {
+                        let child = plant(child, arena, depth - 1)?;
+                        arena.put(block, digit, child);
+                    }
+                }
+                NodeContent::DigitIndex(block)
+            }
+            _ => return None,
+        };
+        Some(Node { content, accumulated_value: node.accumulated_value, content_count: node.content_count })
+    }
+
+    impl<B: DigitBin + Serialize> Serialize for DigitBinIndexGeneric<B> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let root = SavedNode::of(&self.root, &self.arena);
+            Saved { root, precision: self.precision, radix: self.radix, state: IndexState::of(self) }.serialize(serializer)
+        }
+    }
+
+    impl<'de, B: DigitBin + Deserialize<'de>> Deserialize<'de> for DigitBinIndexGeneric<B> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let loaded = Loaded::<B>::deserialize(deserializer)?;
+            if loaded.precision == 0 || loaded.precision as usize > MAX_PRECISION {
+                return Err(D::Error::custom("precision out of range"));
+            }
+            if !(MIN_RADIX..=MAX_RADIX).contains(&loaded.radix) {
+                return Err(D::Error::custom("radix out of range"));
+            }
+            let mut index = DigitBinIndexGeneric::with_precision_and_radix(loaded.precision, loaded.radix);
+            let mut arena = Arena::new(index.radix);
+            let Some(root) = plant(loaded.root, &mut arena, index.depth) else {
+                return Err(D::Error::custom("tree does not match the radix and precision"));
+            };
+            index.root = root;
+            index.arena = Arc::new(arena);
+            index.recount_nodes();
+            loaded.state.restore(&mut index).map_err(D::Error::custom)?;
+            Ok(index)
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Expected outcomes of sequential draws, computed instead of simulated.
+//!
+//! Drawing `n` items one after another with [`DigitBinIndex::select_and_remove`]
+//! follows Wallenius' noncentral hypergeometric distribution over the bins. Its
+//! exact moments need a numerical integral per bin combination, but the mean has
+//! a well-known and accurate approximation (Manly 1974, Fog 2008): there is one
+//! `θ` in `(0, 1)` such that an item of weight `w` is drawn with probability
+//! `1 - θ^w`, where `θ` is fixed by requiring the expected draws to add up to `n`.
+//! [`inclusion_probabilities`] solves for `θ` over the current bins, so
+//! simulation output can be checked against theory without replicate runs.
+//!
+//! ```
+//! use digit_bin_index::{wallenius, DigitBinIndex};
+//!
+//! let mut index = DigitBinIndex::with_precision(2);
+//! index.add_range(0..100, 0.1);
+//! index.add_range(100..200, 0.4);
+//!
+//! let bins = wallenius::inclusion_probabilities(&index, 50).unwrap();
+//! let expected: f64 = bins.iter().map(|bin| bin.expected).sum();
+//! assert!((expected - 50.0).abs() < 1e-6);
+//! // Heavier items are more likely to be drawn, but far from four times as likely.
+//! assert!(bins[1].probability > bins[0].probability);
+//! assert!(bins[1].probability < 4.0 * bins[0].probability);
+//! ```
+
+use crate::DigitBinIndex;
+
+/// The expected outcome for one bin of `n` sequential draws.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BinInclusion {
+    /// The bin's per-item weight.
+    pub weight: f64,
+    /// The number of items in the bin.
+    pub count: u64,
+    /// The probability that a given item of the bin is among the draws.
+    pub probability: f64,
+    /// The expected number of draws from the bin, `count * probability`.
+    pub expected: f64,
+}
+
+/// Approximates, for every non-empty bin, the probability that an item is among
+/// `num_to_draw` sequential draws without replacement.
+///
+/// # Returns
+///
+/// One entry per non-empty bin, in ascending weight order, or `None` if `num_to_draw`
+/// exceeds the item count.
+pub fn inclusion_probabilities(index: &DigitBinIndex, num_to_draw: u64) -> Option<Vec<BinInclusion>> {
+    let bins: Vec<(f64, u64)> = index.bins().map(|(weight, count, _)| (weight, count)).collect();
+    let total: u64 = bins.iter().map(|&(_, count)| count).sum();
+    if num_to_draw > total {
+        return None;
+    }
+
+    // Work with ln θ = -t: the expected draws, sum of m (1 - e^(-t w)), grow with t.
+    let expected = |t: f64| -> f64 { bins.iter().map(|&(w, m)| m as f64 * -(-t * w).exp_m1()).sum() };
+    let n = num_to_draw as f64;
+    let t = if num_to_draw == 0 {
+        0.0
+    } else if num_to_draw == total {
+        f64::INFINITY
+    } else {
+        let mut high = 1.0;
+        while expected(high) < n {
+            high *= 2.0;
+        }
+        let mut low = 0.0;
+        for _ in 0..200 {
+            let mid = 0.5 * (low + high);
+            if expected(mid) < n {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        0.5 * (low + high)
+    };
+
+    Some(
+        bins.into_iter()
+            .map(|(weight, count)| {
+                let probability = if t.is_infinite() { 1.0 } else { -(-t * weight).exp_m1() };
+                BinInclusion { weight, count, probability, expected: count as f64 * probability }
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,76 @@
+"""Pickle round trips of the Python bindings.
+
+Build the extension first, e.g. `maturin develop --features python-bindings`, then run
+`python -m unittest tests/test_pickle.py`.
+"""
+
+import copy
+import pickle
+import unittest
+
+from digit_bin_index import DigitBinIndex
+
+
+def roundtrip(index):
+    return pickle.loads(pickle.dumps(index))
+
+
+class PickleTest(unittest.TestCase):
+    def test_items_survive(self):
+        index = DigitBinIndex.with_precision(3)
+        for i in range(100):
+            index.add(i, 0.001 * (i + 1))
+        restored = roundtrip(index)
+        self.assertEqual(len(restored), 100)
+        self.assertEqual(repr(restored), repr(index))
+        self.assertEqual(restored.bin_members(0.05), [49])
+
+    def test_registry_and_exact_weights_survive(self):
+        index = DigitBinIndex.with_precision(2)
+        index.enable_exact_weights()
+        index.add(1, 0.1234)
+        index.add(2, 0.5)
+        restored = roundtrip(index)
+        self.assertTrue(restored.has_exact_weights())
+        self.assertEqual(restored.get_weight(1), 0.1234)
+        self.assertEqual(restored.remove_by_id(2), 0.5)
+        self.assertEqual(len(restored), 1)
+
+    def test_groups_survive(self):
+        index = DigitBinIndex.with_precision(2)
+        index.add_with_group(1, 0.25, 7)
+        index.add_with_group(2, 0.5, 7)
+        index.add_with_group(3, 0.75, 8)
+        restored = roundtrip(index)
+        self.assertAlmostEqual(restored.group_weight(7), 0.75)
+        self.assertEqual(restored.select_from_group(8), (3, 0.75))
+
+    def test_schedule_continues(self):
+        index = DigitBinIndex.with_precision(1)
+        index.add_range(0, 3, 0.1)
+        index.add_range(3, 5, 0.3)
+        for _ in range(4):
+            index.next_scheduled()
+        restored = roundtrip(index)
+        self.assertEqual([restored.next_scheduled() for _ in range(10)], [index.next_scheduled() for _ in range(10)])
+
+    def test_pending_weight_changes_survive(self):
+        index = DigitBinIndex.with_precision(1)
+        index.add(1, 0.2)
+        index.advance_to(3)
+        index.at_tick(5, 1, 0.7)
+        restored = roundtrip(index)
+        self.assertEqual(restored.advance_to(4), 0)
+        self.assertEqual(restored.advance_to(5), 1)
+        self.assertEqual(restored.get_weight(1), 0.7)
+
+    def test_deepcopy_is_independent(self):
+        index = DigitBinIndex.with_precision(1)
+        index.add(1, 0.2)
+        clone = copy.deepcopy(index)
+        clone.add(2, 0.3)
+        self.assertEqual((len(index), len(clone)), (1, 2))
+
+
+if __name__ == "__main__":
+    unittest.main()